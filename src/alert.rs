@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result, bail};
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use crate::client::{Target, bare_host};
+use crate::config_file::{AlertsConfig, AppConfig};
+use crate::get_number;
+use crate::logger::rfc3339;
+use crate::notify::Dispatcher;
+use crate::output;
+use crate::reboot;
+use crate::rules::{self, Metric, short_duration};
+use crate::status::TEMP_CRIT;
+use crate::temperature;
+
+/// The hottest reading at or above `TEMP_CRIT`, as (sensor, °C)
+pub fn crit_reading(info: &Value) -> Option<(&'static str, f64)> {
+    [("core", "temp"), ("vr", "vrTemp")]
+        .into_iter()
+        .filter_map(|(sensor, key)| get_number(info, key).map(|t| (sensor, t)))
+        .filter(|(_, t)| *t >= TEMP_CRIT)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// Fill in `%host%`, `%device%`, `%temp%`, `%vrtemp%`, `%sensor%` and
+/// `%hashrate%`. Missing readings become empty strings.
+pub fn expand(template: &str, target: &Target, info: &Value, sensor: &str) -> String {
+    let num = |key| get_number(info, key).map(|v| v.to_string()).unwrap_or_default();
+    template
+        .replace("%host%", bare_host(&target.host))
+        .replace("%device%", target.name())
+        .replace("%temp%", &num("temp"))
+        .replace("%vrtemp%", &num("vrTemp"))
+        .replace("%sensor%", sensor)
+        .replace("%hashrate%", &num("hashRate"))
+}
+
+/// Run the --on-crit hook through the shell if `info` is over the critical
+/// temperature. A hook that can't start or exits non-zero is reported on
+/// stderr with its output; it never fails the command it is attached to.
+pub fn on_crit(hook: &str, target: &Target, info: &Value) {
+    let Some((sensor, _)) = crit_reading(info) else {
+        return;
+    };
+    let command = expand(hook, target, info, sensor);
+    tracing::info!(device = target.name(), sensor, command, "running --on-crit hook");
+    if let Err(e) = run_hook(&command) {
+        eprintln!("Warning: --on-crit: {e:#}");
+        tracing::error!(device = target.name(), error = format!("{e:#}"), "--on-crit hook failed");
+    }
+}
+
+/// `on_crit` for repeated polls: fires only when the miner crosses into
+/// critical, tracked in `was_crit`, rather than on every hot reading
+pub fn on_crit_once(hook: &str, target: &Target, info: &Value, was_crit: &mut bool) {
+    let crit = crit_reading(info).is_some();
+    if crit && !*was_crit {
+        on_crit(hook, target, info);
+    }
+    *was_crit = crit;
+}
+
+/// How urgent an alert is; notifiers that have priorities use it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// Things `[alerts]` can watch for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Condition {
+    TempAbove,
+    HashrateBelow,
+    Unreachable,
+    RejectRateAbove,
+    FallbackPool,
+    UnexpectedReboot,
+    /// An `[alerts.rules]` entry on this metric
+    Rule(Metric),
+}
+
+impl Condition {
+    /// Name in event payloads, matching the config key
+    pub fn name(self) -> &'static str {
+        match self {
+            Condition::TempAbove => "temp_above",
+            Condition::HashrateBelow => "hashrate_below",
+            Condition::Unreachable => "unreachable_polls",
+            Condition::RejectRateAbove => "reject_rate_above",
+            Condition::FallbackPool => "fallback_pool",
+            Condition::UnexpectedReboot => "unexpected_reboot",
+            Condition::Rule(metric) => metric.name(),
+        }
+    }
+
+    /// Overheating and unreachable miners need attention first
+    fn severity(self) -> Severity {
+        match self {
+            Condition::TempAbove | Condition::Unreachable => Severity::Critical,
+            _ => Severity::Warning,
+        }
+    }
+
+    /// What is measured, for human-readable messages
+    fn label(self) -> &'static str {
+        match self {
+            Condition::TempAbove => "Temperature",
+            Condition::HashrateBelow => "Hashrate",
+            Condition::Unreachable => "Failed polls",
+            Condition::RejectRateAbove => "Reject rate",
+            Condition::FallbackPool => "Fallback pool",
+            Condition::UnexpectedReboot => "Unexpected reboot",
+            Condition::Rule(metric) => metric.label(),
+        }
+    }
+
+    /// The metric message values are read as, for the ones that have one
+    fn metric(self) -> Option<Metric> {
+        match self {
+            Condition::TempAbove => Some(Metric::Temp),
+            Condition::HashrateBelow => Some(Metric::Hashrate),
+            Condition::RejectRateAbove => Some(Metric::RejectRate),
+            Condition::Rule(metric) => Some(metric),
+            Condition::Unreachable | Condition::FallbackPool | Condition::UnexpectedReboot => None,
+        }
+    }
+
+    /// Unit of the values in messages: temperatures in --temp-unit
+    fn unit(self) -> &'static str {
+        match self {
+            Condition::TempAbove => temperature::unit().metric_unit(Metric::Temp),
+            Condition::HashrateBelow => " GH/s",
+            Condition::RejectRateAbove => "%",
+            Condition::UnexpectedReboot => " s",
+            Condition::Unreachable | Condition::FallbackPool => "",
+            Condition::Rule(metric) => temperature::unit().metric_unit(metric),
+        }
+    }
+
+    /// The reading this condition compares, when the miner reported it
+    pub fn reading(self, info: &Value) -> Option<f64> {
+        match self {
+            Condition::TempAbove => {
+                let temps = [get_number(info, "temp"), get_number(info, "vrTemp")];
+                temps.into_iter().flatten().reduce(f64::max)
+            }
+            Condition::HashrateBelow => get_number(info, "hashRate"),
+            Condition::RejectRateAbove => {
+                let accepted = get_number(info, "sharesAccepted")?;
+                let rejected = get_number(info, "sharesRejected")?;
+                let total = accepted + rejected;
+                (total > 0.0).then(|| rejected / total * 100.0)
+            }
+            // Reported as a bool or 0/1 depending on the firmware
+            Condition::FallbackPool => {
+                let v = info.get("isUsingFallbackStratum")?;
+                v.as_bool().or_else(|| v.as_f64().map(|n| n != 0.0)).map(f64::from)
+            }
+            Condition::Rule(metric) => metric.reading(info),
+            Condition::Unreachable | Condition::UnexpectedReboot => None,
+        }
+    }
+
+    fn is_bad(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Condition::HashrateBelow => value < threshold,
+            Condition::FallbackPool => value >= 1.0,
+            _ => value > threshold,
+        }
+    }
+}
+
+/// A condition starting or ending on one device
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlertEvent {
+    pub device: String,
+    pub condition: Condition,
+    /// The `[alerts.rules]` entry behind the event, if any
+    pub rule: Option<String>,
+    pub severity: Severity,
+    /// true when the condition started, false on recovery
+    pub firing: bool,
+    pub value: Option<f64>,
+    pub threshold: Option<f64>,
+    /// When the condition started
+    pub since: SystemTime,
+    pub at: SystemTime,
+}
+
+impl AlertEvent {
+    /// The rule name, or the [alerts] key for the built-in conditions
+    pub fn name(&self) -> &str {
+        self.rule.as_deref().unwrap_or(self.condition.name())
+    }
+
+    /// JSON body sent to webhooks
+    pub fn payload(&self) -> Value {
+        json!({
+            "device": self.device,
+            "condition": self.name(),
+            "severity": self.severity.name(),
+            "state": if self.firing { "firing" } else { "resolved" },
+            "value": self.value,
+            "threshold": self.threshold,
+            "since": rfc3339(self.since),
+            "timestamp": rfc3339(self.at),
+        })
+    }
+}
+
+impl AlertEvent {
+    /// One-line headline, ex: "gamma: Temperature alert" / "gamma: Temperature recovered"
+    /// (or the rule's name in place of the label)
+    pub fn title(&self) -> String {
+        let state = if self.firing { "alert" } else { "recovered" };
+        format!("{}: {} {state}", self.device, self.rule.as_deref().unwrap_or(self.condition.label()))
+    }
+
+    /// The value against the threshold, ex: "70.5 °C (threshold 68 °C)".
+    /// Temperatures are in --temp-unit; the event JSON keeps °C.
+    pub fn detail(&self) -> String {
+        let unit = self.condition.unit();
+        let shown = |v: f64| match self.condition.metric() {
+            Some(metric) => round2(temperature::unit().metric_value(metric, v)),
+            None => round2(v),
+        };
+        match self.condition {
+            Condition::FallbackPool if self.firing => "Mining on the fallback pool".to_string(),
+            Condition::FallbackPool => "Back on the primary pool".to_string(),
+            Condition::Unreachable if !self.firing => "Reachable again".to_string(),
+            Condition::UnexpectedReboot => match self.value {
+                Some(up) => format!("Rebooted after {} up", short_duration(Duration::from_secs_f64(up.max(0.0)))),
+                None => "Rebooted".to_string(),
+            },
+            _ => {
+                let value = self.value.map(|v| format!("{}{unit}", shown(v))).unwrap_or_else(|| "-".into());
+                match self.threshold {
+                    Some(t) => format!("{value} (threshold {}{unit})", shown(t)),
+                    None => value,
+                }
+            }
+        }
+    }
+}
+
+fn round2(v: f64) -> f64 {
+    (v * 100.0).round() / 100.0
+}
+
+/// Debounced `[alerts]` conditions across polls: an event when a device
+/// enters a bad state and one when it leaves it, nothing in between
+pub struct Monitor {
+    checks: Vec<(Condition, f64)>,
+    unreachable_after: Option<u32>,
+    /// Conditions currently firing, with when they started
+    firing: HashMap<(String, Condition), SystemTime>,
+    /// Failed polls in a row per device
+    failures: HashMap<String, u32>,
+    /// Uptime per device, with `unexpected_reboot` on
+    reboots: Option<reboot::Tracker>,
+}
+
+impl Monitor {
+    pub fn new(cfg: &AlertsConfig) -> Self {
+        let checks = [
+            (Condition::TempAbove, cfg.temp_above),
+            (Condition::HashrateBelow, cfg.hashrate_below),
+            (Condition::RejectRateAbove, cfg.reject_rate_above),
+            (Condition::FallbackPool, cfg.fallback_pool.then_some(1.0)),
+        ];
+        Monitor {
+            checks: checks.into_iter().filter_map(|(c, t)| Some((c, t?))).collect(),
+            unreachable_after: cfg.unreachable_polls.filter(|n| *n > 0),
+            firing: HashMap::new(),
+            failures: HashMap::new(),
+            reboots: cfg.unexpected_reboot.then(|| reboot::Tracker::new(reboot::log_path())),
+        }
+    }
+
+    /// Feed one poll of `device` (`None` when it couldn't be reached)
+    pub fn observe(&mut self, device: &str, info: Option<&Value>, at: SystemTime) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        let Some(info) = info else {
+            let failures = self.failures.entry(device.to_string()).or_insert(0);
+            *failures += 1;
+            if let Some(after) = self.unreachable_after
+                && *failures == after
+            {
+                let value = Some(f64::from(*failures));
+                let threshold = Some(f64::from(after));
+                events.extend(self.transition(device, Condition::Unreachable, true, value, threshold, at));
+            }
+            return events;
+        };
+
+        self.failures.remove(device);
+        let threshold = self.unreachable_after.map(f64::from);
+        events.extend(self.transition(device, Condition::Unreachable, false, Some(0.0), threshold, at));
+        if let Some(reboot) = self.reboots.as_mut().and_then(|t| t.observe(device, info, at)) {
+            if reboot.requested {
+                tracing::info!(device, "rebooted after a restart we sent");
+            } else {
+                // Nothing to recover from, so it fires without entering `firing`
+                events.push(AlertEvent {
+                    device: device.to_string(),
+                    condition: Condition::UnexpectedReboot,
+                    rule: None,
+                    severity: Condition::UnexpectedReboot.severity(),
+                    firing: true,
+                    value: Some(reboot.uptime_before),
+                    threshold: None,
+                    since: reboot.booted,
+                    at,
+                });
+            }
+        }
+        for (condition, threshold) in self.checks.clone() {
+            // A reading the miner didn't report leaves the state as it was
+            if let Some(value) = condition.reading(info) {
+                let bad = condition.is_bad(value, threshold);
+                events.extend(self.transition(device, condition, bad, Some(value), Some(threshold), at));
+            }
+        }
+        events
+    }
+
+    fn transition(
+        &mut self,
+        device: &str,
+        condition: Condition,
+        bad: bool,
+        value: Option<f64>,
+        threshold: Option<f64>,
+        at: SystemTime,
+    ) -> Option<AlertEvent> {
+        let key = (device.to_string(), condition);
+        let since = match (bad, self.firing.get(&key)) {
+            (true, None) => {
+                self.firing.insert(key, at);
+                at
+            }
+            (false, Some(_)) => self.firing.remove(&key)?,
+            _ => return None,
+        };
+        Some(AlertEvent {
+            device: device.to_string(),
+            condition,
+            rule: None,
+            severity: condition.severity(),
+            firing: bad,
+            value,
+            threshold,
+            since,
+            at,
+        })
+    }
+}
+
+/// `alerts` subcommands
+#[derive(Subcommand, Debug)]
+pub enum AlertsCommand {
+    /// Send a sample alert through every configured notifier
+    Test,
+    /// Where each [alerts.rules] entry stands, as last evaluated by a
+    /// running `watch` or `fleet watch`
+    Status {
+        /// Print the states as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+pub fn run(cmd: &AlertsCommand, cfg: &AppConfig) -> Result<()> {
+    match cmd {
+        AlertsCommand::Test => send_test(cfg),
+        AlertsCommand::Status { json } => show_status(cfg, *json),
+    }
+}
+
+fn show_status(cfg: &AppConfig, json: bool) -> Result<()> {
+    let configured: Vec<&String> = cfg.alerts.iter().flat_map(|a| a.rules.keys()).collect();
+    if configured.is_empty() {
+        bail!("No rules configured; add [alerts.rules.NAME] tables with a `when` condition");
+    }
+    // Validates the rules too, so a typo shows up here rather than in the watcher
+    rules::Engine::from_config(cfg)?;
+    let path = rules::state_path().context("Could not determine the config directory")?;
+    let Some(mut states) = rules::load_states(&path)? else {
+        println!("No rule has been evaluated yet; `watch` and `fleet watch` record their state for this command");
+        return Ok(());
+    };
+    // Drop rules removed from the config since the watcher saved them
+    states.retain(|s| configured.contains(&&s.rule));
+
+    if json {
+        println!("{}", output::json(&states)?);
+        return Ok(());
+    }
+    let now = UNIX_EPOCH.elapsed().map(|d| d.as_secs()).unwrap_or(0);
+    let ago = |t: u64| rules::short_duration(Duration::from_secs(now.saturating_sub(t)));
+    let rows: Vec<[String; 6]> = states
+        .iter()
+        .map(|s| {
+            [
+                s.rule.clone(),
+                s.device.clone(),
+                s.severity.name().to_string(),
+                s.state.name().to_string(),
+                ago(s.since),
+                s.value.clone().unwrap_or_else(|| "-".to_string()),
+            ]
+        })
+        .collect();
+    let header = ["RULE", "DEVICE", "SEVERITY", "STATE", "FOR", "VALUE"];
+    let widths: Vec<usize> =
+        (0..header.len()).map(|i| rows.iter().map(|r| r[i].chars().count()).max().unwrap_or(0).max(header[i].len())).collect();
+    let line = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells.iter().zip(&widths).map(|(c, w)| format!("{c:<w$}")).collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+    line(header.to_vec());
+    for row in &rows {
+        line(row.iter().map(String::as_str).collect());
+    }
+    if let Some(updated) = states.iter().map(|s| s.updated).max() {
+        println!("\nLast evaluated {} ago", ago(updated));
+    }
+    Ok(())
+}
+
+/// A made-up overheat on the first configured device, delivered
+/// synchronously so every notifier's outcome can be reported
+fn send_test(cfg: &AppConfig) -> Result<()> {
+    let alerts = cfg.alerts.as_ref().context("No [alerts] table in the config")?;
+    let out = Dispatcher::new(alerts)?;
+    if out.is_empty() {
+        bail!("No notifiers configured; set webhook or add [[alerts.notify]] entries under [alerts]");
+    }
+
+    let now = SystemTime::now();
+    let threshold = alerts.temp_above.unwrap_or(TEMP_CRIT);
+    let event = AlertEvent {
+        device: cfg.devices.keys().next().cloned().unwrap_or_else(|| "test".to_string()),
+        condition: Condition::TempAbove,
+        rule: None,
+        severity: Severity::Critical,
+        firing: true,
+        value: Some(threshold + 2.0),
+        threshold: Some(threshold),
+        since: now,
+        at: now,
+    };
+
+    let mut failed = 0;
+    for (kind, outcome) in out.send_now(&event) {
+        match outcome {
+            Ok(()) => println!("{kind:<8} sent"),
+            Err(e) => {
+                failed += 1;
+                println!("{kind:<8} failed ({e:#})");
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} notifier(s) failed");
+    }
+    Ok(())
+}
+
+fn run_hook(command: &str) -> Result<()> {
+    #[cfg(windows)]
+    let output = Command::new("cmd").args(["/C", command]).output();
+    #[cfg(not(windows))]
+    let output = Command::new("sh").args(["-c", command]).output();
+
+    let output = output.with_context(|| format!("Failed to run hook `{command}`"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match stderr.trim() {
+            "" => bail!("Hook `{command}` failed ({})", output.status),
+            msg => bail!("Hook `{command}` failed ({}): {msg}", output.status),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn target() -> Target {
+        Target { alias: Some("gamma".into()), host: "http://192.168.1.50".into(), client: reqwest::Client::new() }
+    }
+
+    #[test]
+    fn hottest_critical_sensor_wins() {
+        assert_eq!(crit_reading(&json!({"temp": 61.0, "vrTemp": 55.0})), None);
+        assert_eq!(crit_reading(&json!({"temp": 71.5, "vrTemp": 55.0})), Some(("core", 71.5)));
+        assert_eq!(crit_reading(&json!({"temp": 72.0, "vrTemp": 80.0})), Some(("vr", 80.0)));
+    }
+
+    #[test]
+    fn placeholders_are_substituted() {
+        let info = json!({"temp": 71.5, "hashRate": 480.2});
+        assert_eq!(
+            expand("notify %device% %host% %sensor% %temp%C %vrtemp% %hashrate%", &target(), &info, "core"),
+            "notify gamma 192.168.1.50 core 71.5C  480.2"
+        );
+    }
+
+    #[test]
+    fn alerts_fire_once_and_recover_once() {
+        let cfg = AlertsConfig { temp_above: Some(68.0), unreachable_polls: Some(2), ..Default::default() };
+        let mut monitor = Monitor::new(&cfg);
+        let t = |s| SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(s);
+
+        assert!(monitor.observe("gamma", Some(&json!({"temp": 60})), t(0)).is_empty());
+        let fired = monitor.observe("gamma", Some(&json!({"temp": 65, "vrTemp": 70})), t(10));
+        assert_eq!(fired.len(), 1);
+        assert!(fired[0].firing);
+        assert_eq!((fired[0].value, fired[0].threshold), (Some(70.0), Some(68.0)));
+        // Still hot: no repeat
+        assert!(monitor.observe("gamma", Some(&json!({"temp": 71})), t(20)).is_empty());
+        let resolved = monitor.observe("gamma", Some(&json!({"temp": 60})), t(30));
+        assert_eq!(resolved.len(), 1);
+        assert!(!resolved[0].firing);
+        assert_eq!(resolved[0].since, t(10));
+        assert_eq!(resolved[0].payload()["state"], "resolved");
+
+        // Unreachable only after two failures in a row
+        assert!(monitor.observe("gamma", None, t(40)).is_empty());
+        let down = monitor.observe("gamma", None, t(50));
+        assert_eq!(down[0].condition, Condition::Unreachable);
+        assert!(monitor.observe("gamma", None, t(60)).is_empty());
+        let up = monitor.observe("gamma", Some(&json!({"temp": 60})), t(70));
+        assert_eq!((up.len(), up[0].firing), (1, false));
+    }
+
+    #[test]
+    fn reject_rate_and_fallback_readings() {
+        let info = json!({"sharesAccepted": 990, "sharesRejected": 10, "isUsingFallbackStratum": 1});
+        assert_eq!(Condition::RejectRateAbove.reading(&info), Some(1.0));
+        assert_eq!(Condition::FallbackPool.reading(&info), Some(1.0));
+        assert_eq!(Condition::FallbackPool.reading(&json!({"isUsingFallbackStratum": false})), Some(0.0));
+        assert_eq!(Condition::RejectRateAbove.reading(&json!({"sharesAccepted": 0, "sharesRejected": 0})), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_hook_reports_stderr() {
+        let err = run_hook("echo nope >&2; exit 3").unwrap_err().to_string();
+        assert!(err.contains("nope"), "{err}");
+        run_hook("true").unwrap();
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use crate::rules::short_duration;
+
+/// Windows `watch` and `daemon` average over unless told otherwise
+pub const DEFAULT_WINDOWS: [Duration; 2] = [Duration::from_secs(600), Duration::from_secs(3600)];
+
+/// Time-weighted rolling averages of one reading over several windows.
+/// Each stretch between two successive readings counts for as long as it
+/// lasted (the trapezoid between them), so uneven poll intervals don't skew
+/// the result. A failed poll breaks the series: the time around it counts
+/// for nothing rather than being bridged.
+pub struct Rolling {
+    windows: Vec<Duration>,
+    samples: VecDeque<(Instant, Option<f64>)>,
+}
+
+impl Rolling {
+    pub fn new(windows: &[Duration]) -> Self {
+        Rolling { windows: windows.to_vec(), samples: VecDeque::new() }
+    }
+
+    /// Add a poll; `None` for one that failed
+    pub fn push(&mut self, at: Instant, value: Option<f64>) {
+        self.samples.push_back((at, value));
+        // Keep one sample from before the longest window, to clip against
+        let longest = self.windows.iter().max().copied().unwrap_or_default();
+        while self.samples.len() > 2 && at.saturating_duration_since(self.samples[1].0) > longest {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Average over the `window` ending at the latest poll. Until two
+    /// readings in a row exist there is no stretch to weigh, so it is the
+    /// plain mean of the readings in the window.
+    pub fn average(&self, window: Duration) -> Option<f64> {
+        let end = self.samples.back()?.0;
+        let start = end.checked_sub(window).unwrap_or(self.samples.front()?.0);
+        let (mut area, mut covered) = (0.0, 0.0);
+        for (&(t0, v0), &(t1, v1)) in self.samples.iter().zip(self.samples.iter().skip(1)) {
+            let (Some(v0), Some(v1)) = (v0, v1) else { continue };
+            if t1 <= start || t1 <= t0 {
+                continue;
+            }
+            // Clip a stretch that starts before the window
+            let (t0, v0) = if t0 < start {
+                let f = (start - t0).as_secs_f64() / (t1 - t0).as_secs_f64();
+                (start, v0 + (v1 - v0) * f)
+            } else {
+                (t0, v0)
+            };
+            let secs = (t1 - t0).as_secs_f64();
+            area += (v0 + v1) / 2.0 * secs;
+            covered += secs;
+        }
+        if covered > 0.0 {
+            return Some(area / covered);
+        }
+        let values: Vec<f64> = self.samples.iter().filter(|(t, _)| *t >= start).filter_map(|(_, v)| *v).collect();
+        (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+    }
+
+    /// (window, average) for every configured window with data
+    pub fn averages(&self) -> Vec<(Duration, f64)> {
+        self.windows.iter().filter_map(|&w| self.average(w).map(|avg| (w, avg))).collect()
+    }
+}
+
+/// `10m avg 511.8, 1h avg 509.2`
+pub fn describe(averages: &[(Duration, f64)], decimals: usize) -> String {
+    averages
+        .iter()
+        .map(|(window, avg)| format!("{} avg {avg:.decimals$}", short_duration(*window)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(points: &[(u64, Option<f64>)]) -> Rolling {
+        let t0 = Instant::now();
+        let mut r = Rolling::new(&[Duration::from_secs(60), Duration::from_secs(600)]);
+        for &(s, v) in points {
+            r.push(t0 + Duration::from_secs(s), v);
+        }
+        r
+    }
+
+    #[test]
+    fn weighs_by_time_not_by_sample_count() {
+        // 500 for 9 minutes, then a burst of fast polls at 600
+        let mut points = vec![(0, Some(500.0)), (540, Some(500.0))];
+        points.extend((0..10).map(|i| (541 + i, Some(600.0))));
+        let r = stream(&points);
+        let avg = r.average(Duration::from_secs(600)).unwrap();
+        // 540s at 500, the 1s ramp at 550, 9s at 600
+        let expected = (540.0 * 500.0 + 550.0 + 9.0 * 600.0) / 550.0;
+        assert!((avg - expected).abs() < 1e-9, "{avg} vs {expected}");
+        // A plain mean of the 12 samples would be ~583
+        assert!(avg < 510.0);
+    }
+
+    #[test]
+    fn clips_to_the_window_and_skips_failed_polls() {
+        let r = stream(&[(0, Some(100.0)), (60, Some(200.0)), (90, None), (120, Some(400.0)), (150, Some(400.0))]);
+        // The last minute: 200 and the failed poll (skipped), then 120-150 at 400
+        assert_eq!(r.average(Duration::from_secs(60)), Some(400.0));
+        // All of it: 0-60 ramps 100 -> 200 (avg 150), 120-150 at 400
+        let avg = r.average(Duration::from_secs(600)).unwrap();
+        assert!((avg - (150.0 * 60.0 + 400.0 * 30.0) / 90.0).abs() < 1e-9);
+        // Window starting mid-ramp: 30-60 averages 175
+        let partial = stream(&[(0, Some(100.0)), (60, Some(200.0))]);
+        assert_eq!(partial.average(Duration::from_secs(30)), Some(175.0));
+    }
+
+    #[test]
+    fn early_readings_and_pruning() {
+        assert_eq!(stream(&[]).average(Duration::from_secs(60)), None);
+        assert_eq!(stream(&[(0, Some(512.0))]).average(Duration::from_secs(60)), Some(512.0));
+        assert_eq!(stream(&[(0, None)]).average(Duration::from_secs(60)), None);
+
+        let long: Vec<_> = (0..200).map(|i| (i * 10, Some(i as f64))).collect();
+        let r = stream(&long);
+        assert!(r.samples.len() <= 62, "{}", r.samples.len());
+        assert_eq!(describe(&r.averages(), 1), "1m avg 196.0, 10m avg 169.0");
+    }
+}
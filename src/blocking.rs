@@ -0,0 +1,57 @@
+//! The same client for programs without an async runtime. Each call is run
+//! to completion on a small runtime the client owns, so don't use it from
+//! inside another one.
+
+use std::sync::Arc;
+use serde_json::Value;
+use tokio::runtime::{Builder, Runtime};
+use crate::{Error, SystemInfo};
+
+/// [`crate::BitaxeClient`], waited on
+#[derive(Clone, Debug)]
+pub struct BitaxeClient {
+    inner: crate::BitaxeClient,
+    runtime: Arc<Runtime>,
+}
+
+impl BitaxeClient {
+    /// With `DEFAULT_TIMEOUT` and no credentials
+    pub fn new(base_url: &str) -> Result<Self, Error> {
+        BitaxeClient::wrap(crate::BitaxeClient::new(base_url)?)
+    }
+
+    /// With an async client set up elsewhere (timeouts, basic auth headers, ...)
+    pub fn with_client(http: reqwest::Client, base_url: &str) -> Result<Self, Error> {
+        BitaxeClient::wrap(crate::BitaxeClient::with_client(http, base_url))
+    }
+
+    fn wrap(inner: crate::BitaxeClient) -> Result<Self, Error> {
+        let runtime = Builder::new_current_thread().enable_all().build().map_err(Error::Runtime)?;
+        Ok(BitaxeClient { inner, runtime: Arc::new(runtime) })
+    }
+
+    pub fn base_url(&self) -> &str {
+        self.inner.base_url()
+    }
+
+    /// See [`crate::BitaxeClient::system_info`]
+    pub fn system_info(&self) -> Result<SystemInfo, Error> {
+        self.runtime.block_on(self.inner.system_info())
+    }
+
+    pub fn system_info_json(&self) -> Result<Value, Error> {
+        self.runtime.block_on(self.inner.system_info_json())
+    }
+
+    pub fn restart(&self) -> Result<(), Error> {
+        self.runtime.block_on(self.inner.restart())
+    }
+
+    pub fn patch_settings(&self, settings: &Value) -> Result<(), Error> {
+        self.runtime.block_on(self.inner.patch_settings(settings))
+    }
+
+    pub fn reset_session(&self) -> Result<(), Error> {
+        self.runtime.block_on(self.inner.reset_session())
+    }
+}
@@ -0,0 +1,418 @@
+use anyhow::{Context, Result, bail};
+use bitaxe_api::SystemInfo;
+use clap::Args;
+use crate::client::Target;
+use crate::fleet::{DeviceResult, FleetOpts, fetch_fleet};
+use crate::rules::{Expr, Metric};
+use crate::status;
+use crate::temperature;
+use crate::threshold::{Level, ThresholdArgs};
+
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+    /// Metric to check (temp, hashrate, power, ... as in [alerts.rules]),
+    /// optionally with its own thresholds: temp:65:70, hashrate:450:400:invert.
+    /// Repeat to check several; the worst state wins.
+    #[arg(long, required_unless_present_any = ["warn", "crit"], value_name = "NAME[:WARN:CRIT[:invert]]")]
+    metric: Vec<String>,
+
+    /// Warning threshold for metrics given without their own (temperatures in --temp-unit)
+    #[arg(short, long, value_name = "N", allow_hyphen_values = true)]
+    warning: Option<f64>,
+
+    /// Critical threshold for metrics given without their own
+    #[arg(short, long, value_name = "N", allow_hyphen_values = true)]
+    critical: Option<f64>,
+
+    /// Lower is worse (ex: hashrate): warn below -w and go critical below -c
+    #[arg(long)]
+    invert: bool,
+
+    /// Expressions instead of (or besides) --metric: WARNING or CRITICAL
+    /// when one holds, ex: --crit "hashRate<400" --warn "reject_rate>1"
+    #[command(flatten)]
+    thresholds: ThresholdArgs,
+
+    #[command(flatten)]
+    pub opts: FleetOpts,
+}
+
+/// Exit with a plugin status code; the status line is already printed
+#[derive(Debug)]
+pub struct PluginExit(pub u8);
+
+impl std::fmt::Display for PluginExit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "exit status {}", self.0)
+    }
+}
+
+impl std::error::Error for PluginExit {}
+
+/// A usage error on `check` (a missing -w, a threshold that isn't a number)
+/// as an UNKNOWN status line: Nagios would read clap's exit code 2 as CRITICAL
+pub fn usage_error(e: &clap::Error) -> PluginExit {
+    println!("BITAXE {} - {}", ServiceState::Unknown.name(), usage_line(e));
+    PluginExit(ServiceState::Unknown.code())
+}
+
+/// The first paragraph of clap's message on one line, without "error: "
+fn usage_line(e: &clap::Error) -> String {
+    let text = e.render().to_string();
+    let first = text.split("\n\n").next().unwrap_or_default();
+    let first = first.trim_start_matches("error: ");
+    first.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Nagios service states, ordered so the worst one wins when combining:
+/// a critical reading beats an unreachable device, which beats a warning
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum ServiceState {
+    Ok,
+    Warning,
+    Unknown,
+    Critical,
+}
+
+impl ServiceState {
+    fn name(self) -> &'static str {
+        match self {
+            ServiceState::Ok => "OK",
+            ServiceState::Warning => "WARNING",
+            ServiceState::Unknown => "UNKNOWN",
+            ServiceState::Critical => "CRITICAL",
+        }
+    }
+
+    /// Plugin exit code: 0 OK, 1 WARNING, 2 CRITICAL, 3 UNKNOWN
+    fn code(self) -> u8 {
+        match self {
+            ServiceState::Ok => 0,
+            ServiceState::Warning => 1,
+            ServiceState::Critical => 2,
+            ServiceState::Unknown => 3,
+        }
+    }
+}
+
+impl From<Option<Level>> for ServiceState {
+    fn from(level: Option<Level>) -> Self {
+        match level {
+            None => ServiceState::Ok,
+            Some(Level::Warn) => ServiceState::Warning,
+            Some(Level::Crit) => ServiceState::Critical,
+        }
+    }
+}
+
+/// One metric with its thresholds
+#[derive(Debug, PartialEq)]
+struct Check {
+    metric: Metric,
+    warning: Option<f64>,
+    critical: Option<f64>,
+    invert: bool,
+}
+
+impl Check {
+    /// `NAME[:WARN:CRIT[:invert]]`; a bare name takes -w, -c and --invert
+    fn parse(spec: &str, args: &CheckArgs) -> Result<Self> {
+        let mut parts = spec.split(':');
+        let metric = Metric::parse(parts.next().unwrap_or_default())?;
+        if metric == Metric::FailedPolls {
+            bail!("failed_polls only applies to [alerts.rules]; an unreachable miner is UNKNOWN here");
+        }
+        if metric.needs_history() {
+            bail!("{} takes several polls to work out; it only applies to [alerts.rules]", metric.name());
+        }
+        let rest: Vec<&str> = parts.collect();
+        let threshold = |s: &str| s.parse::<f64>().with_context(|| format!("invalid threshold '{s}' in --metric {spec}"));
+        let check = match rest.as_slice() {
+            [] => Check { metric, warning: args.warning, critical: args.critical, invert: args.invert },
+            [w, c] => Check { metric, warning: Some(threshold(w)?), critical: Some(threshold(c)?), invert: false },
+            [w, c, "invert"] => Check { metric, warning: Some(threshold(w)?), critical: Some(threshold(c)?), invert: true },
+            _ => bail!("invalid --metric {spec} (expected NAME, NAME:WARN:CRIT or NAME:WARN:CRIT:invert)"),
+        };
+        if let (Some(w), Some(c)) = (check.warning, check.critical) {
+            if !check.invert && w > c {
+                bail!("{}: warning {w} is above critical {c}; if lower is worse, use invert", metric.name());
+            }
+            if check.invert && w < c {
+                bail!("{}: with invert, warning {w} has to be above critical {c}", metric.name());
+            }
+        }
+        Ok(check)
+    }
+
+    /// A value equal to a threshold is still within it, as with Nagios ranges
+    fn state(&self, value: f64) -> ServiceState {
+        let beyond = |t: Option<f64>| t.is_some_and(|t| if self.invert { value < t } else { value > t });
+        if beyond(self.critical) {
+            ServiceState::Critical
+        } else if beyond(self.warning) {
+            ServiceState::Warning
+        } else {
+            ServiceState::Ok
+        }
+    }
+}
+
+/// Print the plugin status line and exit with its code. Anything that
+/// stops the check from running, like no devices to check, is UNKNOWN.
+pub fn run(args: &CheckArgs, targets: Result<Vec<Target>>) -> Result<()> {
+    let outcome = targets.and_then(|targets| {
+        let checks = args.metric.iter().map(|spec| Check::parse(spec, args)).collect::<Result<Vec<_>>>()?;
+        Ok(report(&checks, &args.thresholds, &fetch_fleet(&targets, &args.opts)))
+    });
+    let (state, text) = outcome.unwrap_or_else(|e| (ServiceState::Unknown, format!("{e:#}")));
+    println!("BITAXE {} - {text}", state.name());
+    match state {
+        ServiceState::Ok => Ok(()),
+        state => Err(PluginExit(state.code()).into()),
+    }
+}
+
+/// The combined state and the text after "BITAXE STATE - ", perfdata included
+fn report(checks: &[Check], thresholds: &ThresholdArgs, results: &[DeviceResult]) -> (ServiceState, String) {
+    let named = results.len() > 1;
+    let mut worst = ServiceState::Ok;
+    let mut devices = Vec::new();
+    let mut perfdata = Vec::new();
+    for r in results {
+        let mut items = Vec::new();
+        match &r.info {
+            Err(e) => {
+                worst = worst.max(ServiceState::Unknown);
+                items.push(format!("unreachable ({e})"));
+            }
+            Ok(info) => {
+                // Thresholds and readings in --temp-unit, as they were typed
+                let shown = temperature::unit().convert(info);
+                for check in checks {
+                    let name = check.metric.name();
+                    let Some(value) = check.metric.reading(&shown) else {
+                        worst = worst.max(ServiceState::Unknown);
+                        items.push(format!("{name} not reported"));
+                        continue;
+                    };
+                    let value = (value * 100.0).round() / 100.0;
+                    let state = check.state(value);
+                    worst = worst.max(state);
+                    let mut item = format!("{name} {value}{}", short_unit(check.metric));
+                    if state != ServiceState::Ok {
+                        item += &format!(" ({})", state.name());
+                    }
+                    items.push(item);
+
+                    let label = if named { perf_label(&format!("{}_{name}", r.name)) } else { name.to_string() };
+                    let mut perf = format!("{label}={value}{}", perf_uom(check.metric));
+                    if check.warning.is_some() || check.critical.is_some() {
+                        let t = |t: Option<f64>| t.map(|t| t.to_string()).unwrap_or_default();
+                        perf += &format!(";{};{}", t(check.warning), t(check.critical));
+                    }
+                    perfdata.push(perf);
+                }
+                // Without --metric this is the verdict `status` prints as its footer,
+                // temperature limits included; with it, -w/-c decide instead of them
+                let verdict = if checks.is_empty() {
+                    status::verdict(&SystemInfo::from(info), info, thresholds)
+                } else {
+                    thresholds.verdict(info)
+                };
+                worst = worst.max(ServiceState::from(verdict.level));
+                // One item per metric the expressions name, at its worst breach
+                let mut metrics: Vec<Metric> = Vec::new();
+                for expr in thresholds.crit.iter().chain(&thresholds.warn) {
+                    if !metrics.contains(&expr.metric) {
+                        metrics.push(expr.metric);
+                    }
+                }
+                for metric in metrics {
+                    let name = metric.name();
+                    let Some(value) = metric.reading(&shown) else {
+                        worst = worst.max(ServiceState::Unknown);
+                        items.push(format!("{name} not reported"));
+                        continue;
+                    };
+                    let value = (value * 100.0).round() / 100.0;
+                    let mut item = format!("{name} {value}{}", short_unit(metric));
+                    if let Some(breach) = thresholds.worst(metric, info) {
+                        item += &format!(" ({}: {})", ServiceState::from(Some(breach.level)).name(), breach.expr);
+                    }
+                    items.push(item);
+                    let label = if named { perf_label(&format!("{}_{name}", r.name)) } else { name.to_string() };
+                    perfdata.push(format!("{label}={value}{}", perf_uom(metric)));
+                }
+                // Expressions are named above; what's left is the built-in limits
+                let exprs: Vec<String> = thresholds.crit.iter().chain(&thresholds.warn).map(Expr::to_string).collect();
+                items.extend(verdict.reasons.into_iter().filter(|reason| !exprs.contains(reason)));
+            }
+        }
+        let items = items.join(", ");
+        devices.push(if named { format!("{}: {items}", r.name) } else { items });
+    }
+
+    let mut text = devices.join("; ");
+    if !perfdata.is_empty() {
+        text += " | ";
+        text += &perfdata.join(" ");
+    }
+    (worst, text)
+}
+
+/// Unit for the status text, ex: 58.2C, 512GH/s
+fn short_unit(metric: Metric) -> String {
+    temperature::unit().metric_unit(metric).trim().replace('°', "")
+}
+
+/// The few units perfdata understands
+fn perf_uom(metric: Metric) -> &'static str {
+    match metric {
+        Metric::RejectRate => "%",
+        Metric::Uptime => "s",
+        _ => "",
+    }
+}
+
+/// Labels with spaces, quotes or `=` are single-quoted (quotes doubled)
+fn perf_label(label: &str) -> String {
+    if label.contains([' ', '\'', '=']) {
+        format!("'{}'", label.replace('\'', "''"))
+    } else {
+        label.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::fleet::result;
+
+    fn args(warning: Option<f64>, critical: Option<f64>, invert: bool) -> CheckArgs {
+        CheckArgs {
+            metric: Vec::new(),
+            warning,
+            critical,
+            invert,
+            thresholds: ThresholdArgs::default(),
+            opts: FleetOpts { concurrency: 1, tag: Vec::new(), any_tag: false },
+        }
+    }
+
+    #[test]
+    fn usage_errors_fit_on_the_status_line() {
+        let usage = |args: &[&str]| {
+            let e = CheckArgs::augment_args(clap::Command::new("check")).try_get_matches_from(args).unwrap_err();
+            usage_line(&e)
+        };
+        assert_eq!(
+            usage(&["check"]),
+            "the following required arguments were not provided: --metric <NAME[:WARN:CRIT[:invert]]>"
+        );
+        let bad = usage(&["check", "--metric", "temp", "-w", "abc"]);
+        assert_eq!(bad, "invalid value 'abc' for '--warning <N>': invalid float literal");
+    }
+
+    #[test]
+    fn parses_specs_and_flags() {
+        let flags = args(Some(65.0), Some(70.0), false);
+        let temp = Check::parse("temp", &flags).unwrap();
+        assert_eq!((temp.metric, temp.warning, temp.critical, temp.invert), (Metric::Temp, Some(65.0), Some(70.0), false));
+        let hash = Check::parse("hashrate:450:400:invert", &flags).unwrap();
+        assert_eq!((hash.warning, hash.critical, hash.invert), (Some(450.0), Some(400.0), true));
+        assert_eq!(Check::parse("wifi_rssi:-70:-80:invert", &flags).unwrap().critical, Some(-80.0));
+
+        assert!(Check::parse("hashrate:450:400", &flags).unwrap_err().to_string().contains("use invert"));
+        assert!(Check::parse("temp:65", &flags).is_err());
+        assert!(Check::parse("tmp", &flags).is_err());
+        assert!(Check::parse("failed_polls", &flags).is_err());
+    }
+
+    #[test]
+    fn thresholds_follow_the_nagios_convention() {
+        let temp = Check::parse("temp:65:70", &args(None, None, false)).unwrap();
+        assert_eq!(temp.state(65.0), ServiceState::Ok);
+        assert_eq!(temp.state(65.1), ServiceState::Warning);
+        assert_eq!(temp.state(70.5), ServiceState::Critical);
+        let hash = Check::parse("hashrate", &args(Some(450.0), Some(400.0), true)).unwrap();
+        assert_eq!(hash.state(500.0), ServiceState::Ok);
+        assert_eq!(hash.state(420.0), ServiceState::Warning);
+        assert_eq!(hash.state(399.0), ServiceState::Critical);
+        assert_eq!((ServiceState::Unknown.code(), ServiceState::Critical.code()), (3, 2));
+    }
+
+    #[test]
+    fn status_line_with_perfdata() {
+        let none = args(None, None, false);
+        let checks = [Check::parse("temp:65:70", &none).unwrap(), Check::parse("hashrate:450:400:invert", &none).unwrap()];
+        let gamma = result("gamma", Ok(json!({"temp": 58.2, "hashRate": 512.345})));
+        let (state, text) = report(&checks, &none.thresholds, &[gamma]);
+        assert_eq!(state, ServiceState::Ok);
+        assert_eq!(text, "temp 58.2C, hashrate 512.35GH/s | temp=58.2;65;70 hashrate=512.35;450;400");
+
+        let fleet = [
+            result("gamma", Ok(json!({"temp": 66, "hashRate": 380}))),
+            result("my shed", Err("timed out".into())),
+        ];
+        let (state, text) = report(&checks, &none.thresholds, &fleet);
+        // Critical hashrate beats the unreachable device and the warm one
+        assert_eq!(state, ServiceState::Critical);
+        assert_eq!(
+            text,
+            "gamma: temp 66C (WARNING), hashrate 380GH/s (CRITICAL); my shed: unreachable (timed out) \
+             | gamma_temp=66;65;70 gamma_hashrate=380;450;400"
+        );
+
+        let fan = [Check::parse("fan_rpm", &none).unwrap()];
+        let (state, text) = report(&fan, &none.thresholds, &[result("gamma", Ok(json!({})))]);
+        assert_eq!((state, text.as_str()), (ServiceState::Unknown, "fan_rpm not reported"));
+        assert_eq!(perf_label("my shed_temp"), "'my shed_temp'");
+    }
+
+    #[test]
+    fn expressions_set_the_state() {
+        let expr = |s| Expr::parse(s).unwrap();
+        let thresholds = ThresholdArgs {
+            warn: vec![expr("reject_rate>1"), expr("hashRate<450")],
+            crit: vec![expr("hashRate<400")],
+        };
+        let info = json!({"hashRate": 420, "sharesAccepted": 990, "sharesRejected": 10});
+        let (state, text) = report(&[], &thresholds, &[result("gamma", Ok(info))]);
+        assert_eq!(state, ServiceState::Warning);
+        assert_eq!(text, "hashrate 420GH/s (WARNING: hashrate < 450), reject_rate 1% | hashrate=420 reject_rate=1%");
+
+        // Critical beats the reject rate the miner didn't report
+        let (state, text) = report(&[], &thresholds, &[result("gamma", Ok(json!({"hashRate": 380})))]);
+        assert_eq!(state, ServiceState::Critical);
+        assert_eq!(text, "hashrate 380GH/s (CRITICAL: hashrate < 400), reject_rate not reported | hashrate=380");
+    }
+
+    #[test]
+    fn agrees_with_the_status_footer() {
+        let thresholds = ThresholdArgs { warn: vec![Expr::parse("hashRate<450").unwrap()], crit: Vec::new() };
+        for (reading, state) in [
+            (json!({"temp": 72.0, "hashRate": 500}), ServiceState::Critical),
+            (json!({"temp": 60.0, "vrTemp": 66.0, "hashRate": 500}), ServiceState::Warning),
+            (json!({"temp": 60.0, "hashRate": 420}), ServiceState::Warning),
+            (json!({"temp": 60.0, "hashRate": 500}), ServiceState::Ok),
+        ] {
+            let footer = status::verdict(&SystemInfo::from(&reading), &reading, &thresholds);
+            assert_eq!(ServiceState::from(footer.level), state, "{reading}");
+            assert_eq!(report(&[], &thresholds, &[result("gamma", Ok(reading.clone()))]).0, state, "{reading}");
+        }
+        let (_, text) = report(&[], &ThresholdArgs::default(), &[result("gamma", Ok(json!({"temp": 72.0})))]);
+        assert_eq!(text, "overheat");
+    }
+
+    #[test]
+    fn metric_thresholds_replace_the_temperature_limits() {
+        let hot = [result("gamma", Ok(json!({"temp": 72.0, "vrTemp": 75.0, "hashRate": 500})))];
+        let temp = Check::parse("temp", &args(Some(80.0), Some(90.0), false)).unwrap();
+        let (state, text) = report(&[temp], &ThresholdArgs::default(), &hot);
+        assert_eq!((state, text.as_str()), (ServiceState::Ok, "temp 72C | temp=72;80;90"));
+        let hash = Check::parse("hashrate", &args(Some(450.0), Some(400.0), true)).unwrap();
+        let (state, text) = report(&[hash], &ThresholdArgs::default(), &hot);
+        assert_eq!((state, text.as_str()), (ServiceState::Ok, "hashrate 500GH/s | hashrate=500;450;400"));
+    }
+}
@@ -0,0 +1,218 @@
+use std::time::Duration;
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use reqwest::Client;
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue};
+use crate::config_file::DeviceConfig;
+
+/// Whole-request timeout when neither --timeout, BITAXE_TIMEOUT, the device
+/// nor the config sets one
+pub const DEFAULT_TIMEOUT: Duration = bitaxe_api::DEFAULT_TIMEOUT;
+
+/// Timeout of a `discover` probe without --probe-timeout: most addresses of
+/// a scan never answer, so it is kept well below a normal request's
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Network flags from the command line. Set ones win over the per-device
+/// values in the config; unset ones fall back to them.
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    /// --timeout, else BITAXE_TIMEOUT
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    /// Top-level `timeout` from the config, below a device's `timeout_secs`
+    pub config_timeout: Option<Duration>,
+    /// --api-base: path inserted between host and /api/...
+    pub api_base: Option<String>,
+    /// Top-level `api_base` from the config, below a device's `path_prefix`
+    pub config_api_base: Option<String>,
+}
+
+/// What a client is built from once precedence has been applied
+#[derive(Debug, PartialEq)]
+pub struct ClientSettings {
+    pub timeout: Duration,
+    pub connect_timeout: Option<Duration>,
+    /// Basic auth (username, password) for miners behind a proxy
+    pub auth: Option<(String, Option<String>)>,
+}
+
+/// A miner to talk to: its alias (for configured devices), base URL and
+/// the client set up for it
+#[derive(Clone)]
+pub struct Target {
+    pub alias: Option<String>,
+    pub host: String,
+    pub client: Client,
+}
+
+impl Target {
+    /// What output calls the device: the alias, or the host without scheme
+    pub fn name(&self) -> &str {
+        match &self.alias {
+            Some(alias) => alias,
+            None => bare_host(&self.host),
+        }
+    }
+}
+
+impl ClientOptions {
+    /// Flag (or env) > device value > config value > built-in default
+    pub fn settings(&self, dev: Option<&DeviceConfig>) -> ClientSettings {
+        let timeout = self
+            .timeout
+            .or_else(|| dev.and_then(|d| d.timeout_secs).map(Duration::from_secs))
+            .or(self.config_timeout)
+            .unwrap_or(DEFAULT_TIMEOUT);
+        let auth = dev.and_then(|d| d.username.clone().map(|user| (user, d.password.clone())));
+        ClientSettings { timeout, connect_timeout: self.connect_timeout, auth }
+    }
+
+    /// `discover` probes: --probe-timeout, else `DEFAULT_PROBE_TIMEOUT`
+    /// lowered to the request timeout (flag, env or config) when that is shorter
+    pub fn probe_timeout(&self, flag: Option<Duration>) -> Duration {
+        match (flag, self.timeout.or(self.config_timeout)) {
+            (Some(probe), _) => probe,
+            (None, Some(timeout)) => timeout.min(DEFAULT_PROBE_TIMEOUT),
+            (None, None) => DEFAULT_PROBE_TIMEOUT,
+        }
+    }
+
+    pub fn build(&self, dev: Option<&DeviceConfig>) -> Result<Client> {
+        let settings = self.settings(dev);
+        let mut builder = Client::builder().timeout(settings.timeout).default_headers(settings.headers()?);
+        if let Some(connect) = settings.connect_timeout {
+            builder = builder.connect_timeout(connect);
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Base path for a host: --api-base > device `path_prefix` > config `api_base`
+    pub fn base_path<'a>(&'a self, dev: Option<&'a DeviceConfig>) -> Option<&'a str> {
+        self.api_base
+            .as_deref()
+            .or_else(|| dev.and_then(|d| d.path_prefix.as_deref()))
+            .or(self.config_api_base.as_deref())
+    }
+
+    /// The target for a configured device, with its base path applied
+    pub fn device_target(&self, name: &str, dev: &DeviceConfig) -> Result<Target> {
+        let host = join_base(&dev.host, self.base_path(Some(dev)));
+        Ok(Target { alias: Some(name.to_string()), host, client: self.build(Some(dev))? })
+    }
+
+    /// A host given directly (--host, BITAXE_URL, ...): no per-device settings
+    pub fn host_target(&self, host: &str) -> Result<Target> {
+        let url = join_base(host, self.base_path(None));
+        Ok(Target { alias: None, host: url, client: self.build(None)? })
+    }
+}
+
+impl ClientSettings {
+    /// Basic auth, when there is any
+    fn headers(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        if let Some((user, password)) = &self.auth {
+            let credentials = STANDARD.encode(format!("{user}:{}", password.as_deref().unwrap_or("")));
+            let mut value = HeaderValue::from_str(&format!("Basic {credentials}")).context("Invalid credentials")?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+        Ok(headers)
+    }
+}
+
+/// `http://10.0.0.2/` -> `10.0.0.2`
+pub fn bare_host(host: &str) -> &str {
+    let host = host.split_once("://").map_or(host, |(_, rest)| rest);
+    host.trim_end_matches('/')
+}
+
+/// Host with an optional base path, without doubled slashes:
+/// `http://proxy:8080/` + `/bitaxe/` -> `http://proxy:8080/bitaxe`
+pub fn join_base(host: &str, base: Option<&str>) -> String {
+    let host = host.trim_end_matches('/');
+    match base.map(|b| b.trim_matches('/')) {
+        Some(base) if !base.is_empty() => format!("{host}/{base}"),
+        _ => host.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device() -> DeviceConfig {
+        DeviceConfig {
+            host: "http://proxy:8080/".to_string(),
+            hostname: None,
+            asic_model: None,
+            tags: Vec::new(),
+            timeout_secs: Some(15),
+            username: Some("admin".to_string()),
+            password: Some("secret".to_string()),
+            path_prefix: Some("/bitaxe/".to_string()),
+        }
+    }
+
+    #[test]
+    fn defaults_without_flags_or_device() {
+        let s = ClientOptions::default().settings(None);
+        assert_eq!(s, ClientSettings { timeout: DEFAULT_TIMEOUT, connect_timeout: None, auth: None });
+    }
+
+    #[test]
+    fn device_values_apply_when_flags_are_unset() {
+        let s = ClientOptions::default().settings(Some(&device()));
+        assert_eq!(s.timeout, Duration::from_secs(15));
+        assert_eq!(s.auth, Some(("admin".to_string(), Some("secret".to_string()))));
+    }
+
+    #[test]
+    fn flags_override_device_values() {
+        let (timeout, connect_timeout) = (Some(Duration::from_millis(2500)), Some(Duration::from_secs(1)));
+        let opts = ClientOptions { timeout, connect_timeout, ..Default::default() };
+        let s = opts.settings(Some(&device()));
+        assert_eq!(s.timeout, Duration::from_millis(2500));
+        assert_eq!(s.connect_timeout, Some(Duration::from_secs(1)));
+        // No flag for credentials: the device's still apply
+        assert!(s.auth.is_some());
+    }
+
+    #[test]
+    fn config_timeout_is_below_the_device() {
+        let opts = ClientOptions { config_timeout: Some(Duration::from_secs(30)), ..Default::default() };
+        assert_eq!(opts.settings(None).timeout, Duration::from_secs(30));
+        assert_eq!(opts.settings(Some(&device())).timeout, Duration::from_secs(15));
+    }
+
+    #[test]
+    fn probes_stay_short_unless_asked() {
+        let ms = Duration::from_millis;
+        assert_eq!(ClientOptions::default().probe_timeout(None), DEFAULT_PROBE_TIMEOUT);
+        let slow = ClientOptions { timeout: Some(ms(30_000)), ..Default::default() };
+        assert_eq!(slow.probe_timeout(None), DEFAULT_PROBE_TIMEOUT);
+        let fast = ClientOptions { config_timeout: Some(ms(500)), ..Default::default() };
+        assert_eq!(fast.probe_timeout(None), ms(500));
+        assert_eq!(fast.probe_timeout(Some(ms(5000))), ms(5000));
+    }
+
+    #[test]
+    fn base_path_is_joined_with_single_slashes() {
+        assert_eq!(join_base("http://proxy:8080/", Some("/bitaxe/")), "http://proxy:8080/bitaxe");
+        assert_eq!(join_base("http://proxy:8080", Some("a/b")), "http://proxy:8080/a/b");
+        assert_eq!(join_base("http://proxy:8080/", None), "http://proxy:8080");
+        assert_eq!(join_base("http://proxy:8080", Some("/")), "http://proxy:8080");
+    }
+
+    #[test]
+    fn base_path_precedence() {
+        let dev = device();
+        let mut opts = ClientOptions { config_api_base: Some("/cfg".to_string()), ..Default::default() };
+        assert_eq!(opts.base_path(None), Some("/cfg"));
+        assert_eq!(opts.base_path(Some(&dev)), Some("/bitaxe/"));
+        opts.api_base = Some("/flag".to_string());
+        assert_eq!(opts.base_path(Some(&dev)), Some("/flag"));
+    }
+}
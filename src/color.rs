@@ -0,0 +1,47 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use clap::ValueEnum;
+
+/// When to emit ANSI colors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Color when stdout is a terminal and NO_COLOR is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+pub const BOLD: &str = "1";
+pub const DIM: &str = "2";
+pub const RED: &str = "31";
+pub const GREEN: &str = "32";
+pub const YELLOW: &str = "33";
+
+/// The single place that decides whether output is colored.
+/// `--color always` wins over everything, then `--no-color`, then the
+/// NO_COLOR convention (any non-empty value), then TTY detection.
+pub fn should_colorize(choice: ColorChoice, no_color_flag: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            if no_color_flag {
+                return false;
+            }
+            if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+                return false;
+            }
+            io::stdout().is_terminal()
+        }
+    }
+}
+
+/// Wrap `text` in an ANSI SGR sequence when coloring is enabled
+pub fn paint(enabled: bool, code: &str, text: &str) -> String {
+    if enabled {
+        format!("\x1b[{code}m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
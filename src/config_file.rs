@@ -0,0 +1,540 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use toml_edit::{DocumentMut, Item, Table};
+use crate::alert::Severity;
+use crate::statsd::TagFormat;
+use crate::temperature::TempUnit;
+
+/// Config structure (matches config.toml)
+#[derive(Debug, Default, Deserialize)]
+pub struct AppConfig {
+    pub host: Option<String>,
+    /// Path AxeOS is mounted under behind a proxy, ex: "/bitaxe"
+    pub api_base: Option<String>,
+    /// Minimum gap between requests to one miner, ex: "500ms"
+    pub request_spacing: Option<String>,
+    /// Request timeout for every miner, ex: "10s"; below a device's
+    /// `timeout_secs`, above the 5s default
+    pub timeout: Option<String>,
+    /// How long `log --db` keeps samples before pruning them, ex: "90d"
+    #[cfg(feature = "sqlite")]
+    pub history_retention: Option<String>,
+    /// Command run when a temperature reaches the critical threshold
+    /// (same placeholders as --on-crit; off unless set)
+    pub on_crit: Option<String>,
+    /// Device used when no --device flag is given
+    pub default_device: Option<String>,
+    /// Named devices, ex: [devices.gamma] host = "http://192.168.1.50"
+    #[serde(default)]
+    pub devices: BTreeMap<String, DeviceConfig>,
+    /// Named tuning presets, ex: [profiles.max] frequency = 575
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Defaults for `push influx`
+    pub influx: Option<InfluxConfig>,
+    /// Broker connection for `mqtt`
+    pub mqtt: Option<MqttConfig>,
+    /// Defaults for `push statsd` and its `daemon` sink
+    pub statsd: Option<StatsdConfig>,
+    /// Defaults for `push graphite` and its `daemon` sink
+    pub graphite: Option<GraphiteConfig>,
+    /// Conditions `watch` and `fleet watch` report, and where to
+    pub alerts: Option<AlertsConfig>,
+    /// Which outputs `daemon` feeds
+    pub daemon: Option<DaemonConfig>,
+    /// When `watchdog` restarts a stuck miner
+    pub watchdog: Option<WatchdogConfig>,
+    /// Decimal places `status` and `watch` print readings with, and the
+    /// temperature unit
+    pub display: Option<DisplayConfig>,
+    /// How reads from the miners are retried
+    pub retry: Option<RetryConfig>,
+}
+
+/// The [retry] table; --retries overrides `retries`
+#[derive(Debug, Default, Deserialize)]
+pub struct RetryConfig {
+    /// Repeats after the first try (default 1)
+    pub retries: Option<u32>,
+    /// Pause before the first retry, doubled for each next one (default "500ms")
+    pub backoff: Option<String>,
+    /// Longest pause (default "10s")
+    pub max_backoff: Option<String>,
+}
+
+/// The [display] table: decimal places per kind of reading and the unit
+/// temperatures are shown in (not to be confused with the miner's screen,
+/// see `display`)
+#[derive(Debug, Default, Deserialize)]
+pub struct DisplayConfig {
+    /// GH/s (default 2)
+    pub hashrate_decimals: Option<usize>,
+    /// °C for core and VR (default 1)
+    pub temp_decimals: Option<usize>,
+    /// W (default 2)
+    pub power_decimals: Option<usize>,
+    /// Input V (default 2)
+    pub voltage_decimals: Option<usize>,
+    /// "c" or "f" (default c); --temp-unit overrides it
+    pub temp_unit: Option<TempUnit>,
+}
+
+/// The [daemon] table: the poll interval and the sinks each poll goes to
+#[derive(Debug, Default, Deserialize)]
+pub struct DaemonConfig {
+    /// ex: "30s" (the default)
+    pub interval: Option<String>,
+    /// Append CSV rows (as `log --format csv`) to this file
+    pub csv: Option<PathBuf>,
+    /// Record to history.db next to the config file
+    #[serde(default)]
+    pub history: bool,
+    /// Record to this database instead (implies `history`)
+    pub history_path: Option<PathBuf>,
+    /// Publish to the broker in [mqtt]
+    #[serde(default)]
+    pub mqtt: bool,
+    /// Serve Prometheus metrics on this address, ex: "127.0.0.1:9100"
+    pub exporter: Option<String>,
+    /// Send to the StatsD server in [statsd]
+    #[serde(default)]
+    pub statsd: bool,
+    /// Write to the carbon listener in [graphite]
+    #[serde(default)]
+    pub graphite: bool,
+    /// Check [alerts]; on whenever that table exists unless set to false
+    pub alerts: Option<bool>,
+    /// Restart stuck miners as set in [watchdog]
+    #[serde(default)]
+    pub watchdog: bool,
+    /// Rolling hashrate averages in the cycle log, ex: ["10m", "1h"] (the default)
+    pub averages: Option<Vec<String>>,
+}
+
+/// The [watchdog] table: defaults for `watchdog` and its `daemon` sink
+#[derive(Debug, Default, Deserialize)]
+pub struct WatchdogConfig {
+    /// GH/s; polls below it are bad (default 1)
+    pub hashrate_below: Option<f64>,
+    /// Bad polls in a row before a restart (default 10)
+    pub polls: Option<u32>,
+    /// ex: "15m" (the default), least time between restarts of one miner
+    pub cooldown: Option<String>,
+    /// Whether a poll without new accepted shares is bad (default true)
+    pub shares: Option<bool>,
+    /// Log restarts instead of sending them
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// The [influx] table: InfluxDB v2 write target
+#[derive(Debug, Default, Deserialize)]
+pub struct InfluxConfig {
+    pub url: Option<String>,
+    pub org: Option<String>,
+    pub bucket: Option<String>,
+    pub token: Option<String>,
+}
+
+/// The [statsd] table: where `push statsd` sends, and how metrics are named
+#[derive(Debug, Default, Deserialize)]
+pub struct StatsdConfig {
+    /// ex: "127.0.0.1:8125" (the default)
+    pub addr: Option<String>,
+    /// First metric name segment (default "bitaxe")
+    pub prefix: Option<String>,
+    /// "prefix" (the default) or "datadog"
+    pub tag_format: Option<TagFormat>,
+}
+
+/// The [graphite] table: carbon's plaintext listener and the metric path
+#[derive(Debug, Default, Deserialize)]
+pub struct GraphiteConfig {
+    /// ex: "graphite.lan:2003"; default "127.0.0.1:2003"
+    pub addr: Option<String>,
+    /// First path segment (default "bitaxe")
+    pub prefix: Option<String>,
+}
+
+/// The [mqtt] table: broker, credentials and TLS files for `mqtt`
+#[derive(Debug, Default, Deserialize)]
+pub struct MqttConfig {
+    /// ex: "mqtt://homelab:1883" or "mqtts://homelab:8883"
+    pub broker: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Defaults to bitaxe-cli-<pid>
+    pub client_id: Option<String>,
+    pub qos: Option<u8>,
+    /// First topic level, "bitaxe" unless set
+    pub topic_prefix: Option<String>,
+    /// PEM CA bundle for mqtts:// (default: the system roots)
+    pub ca_file: Option<PathBuf>,
+    /// PEM client certificate and key for brokers that require them
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    /// Always publish per-metric topics, as with `mqtt --per-metric`
+    #[serde(default)]
+    pub per_metric: bool,
+    /// Always publish Home Assistant discovery configs, as with `mqtt --ha-discovery`
+    #[serde(default)]
+    pub ha_discovery: bool,
+}
+
+/// The [alerts] table. Every threshold is optional; unset ones aren't checked.
+#[derive(Debug, Default, Deserialize)]
+pub struct AlertsConfig {
+    /// URL that gets a JSON POST when a condition fires or recovers
+    /// (shorthand for a `type = "webhook"` notifier)
+    pub webhook: Option<String>,
+    /// Where events go, ex: [[alerts.notify]] type = "discord" url = "..."
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub notify: Vec<NotifierConfig>,
+    /// °C, checked against the hotter of the core and VR sensors
+    pub temp_above: Option<f64>,
+    /// GH/s
+    pub hashrate_below: Option<f64>,
+    /// Failed polls in a row before a device counts as unreachable
+    pub unreachable_polls: Option<u32>,
+    /// Percent of shares rejected since boot
+    pub reject_rate_above: Option<f64>,
+    /// Alert while the miner is mining on its fallback pool
+    #[serde(default)]
+    pub fallback_pool: bool,
+    /// Alert when a miner's uptime goes backwards, unless this CLI had just
+    /// restarted it
+    #[serde(default)]
+    pub unexpected_reboot: bool,
+    /// Named rules, ex: [alerts.rules.overheat] when = "temp > 68 for 2m"
+    #[serde(default)]
+    pub rules: BTreeMap<String, RuleConfig>,
+}
+
+/// One `[alerts.rules.NAME]` table
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RuleConfig {
+    /// `METRIC OP THRESHOLD [for DURATION]`, ex: "hashrate < 400 for 5m"
+    pub when: String,
+    /// info, warning (default) or critical
+    #[serde(default)]
+    pub severity: Severity,
+    /// Once firing, how far back past the threshold the value has to go
+    /// before the rule resolves
+    #[serde(default)]
+    pub hysteresis: f64,
+    /// Only these devices (default: all)
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// Only devices with one of these tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// One `[alerts.notify]` entry
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotifierConfig {
+    /// The raw event JSON
+    Webhook { url: String },
+    /// A Discord channel webhook
+    Discord { url: String },
+    /// A Slack incoming webhook
+    Slack { url: String },
+    /// A push notification through an ntfy server
+    Ntfy {
+        #[serde(default = "default_ntfy_server")]
+        server: String,
+        topic: String,
+        /// Access token for protected topics
+        token: Option<String>,
+    },
+    /// A native notification on this machine (needs the `desktop` feature)
+    Desktop,
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".to_string()
+}
+
+/// Accept a single table as well as an array of them
+fn one_or_many<'de, D, T>(de: D) -> Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+    Ok(match OneOrMany::deserialize(de)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
+}
+
+/// One entry of the [devices] table
+#[derive(Debug, Deserialize)]
+pub struct DeviceConfig {
+    pub host: String,
+    /// Hostname reported by the miner when it was added (metadata only)
+    pub hostname: Option<String>,
+    /// ASIC model reported by the miner when it was added (metadata only)
+    pub asic_model: Option<String>,
+    /// Free-form labels used to target subsets with --tag
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Request timeout for this device (--timeout still wins)
+    pub timeout_secs: Option<u64>,
+    /// Basic auth for a miner behind a reverse proxy
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Path the proxy mounts AxeOS under, ex: "/bitaxe"
+    pub path_prefix: Option<String>,
+}
+
+/// One entry of the [profiles] table; unset fields are left alone on apply
+#[derive(Debug, Deserialize)]
+pub struct Profile {
+    /// ASIC frequency in MHz
+    pub frequency: Option<f64>,
+    /// ASIC core voltage in mV
+    pub core_voltage: Option<f64>,
+    /// Fan speed in percent
+    pub fanspeed: Option<f64>,
+    pub autofanspeed: Option<bool>,
+}
+
+/// Config file names probed in the config directory, in order
+const CONFIG_NAMES: &[&str] = &["config.toml", "config.yaml", "config.yml", "config.json"];
+
+/// Path given with --config, which replaces the probing
+static EXPLICIT_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use `path` instead of the default location; only the first call has an effect
+pub fn set_config_path(path: PathBuf) {
+    let _ = EXPLICIT_PATH.set(path);
+}
+
+/// Try to load the config file (TOML, YAML or JSON) if it exists.
+/// An explicit --config path must exist and have a known extension.
+pub fn load_config() -> Result<AppConfig> {
+    let mut builder = config::Config::builder();
+
+    if let Some(path) = EXPLICIT_PATH.get() {
+        let known = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| ["toml", "yaml", "yml", "json"].contains(&e.to_ascii_lowercase().as_str()));
+        if !known {
+            bail!("Can't tell the format of {}: use a .toml, .yaml or .json file", path.display());
+        }
+        if !path.exists() {
+            bail!("Config file {} not found", path.display());
+        }
+    }
+    if let Some(path) = config_path()
+        && path.exists()
+    {
+        // The format follows the file extension
+        builder = builder.add_source(config::File::from(path));
+    }
+
+    // If there are no sources, this still builds an empty config,
+    // and deserialization into AppConfig (all fields Option) is fine.
+    // A file that doesn't parse is an error rather than an empty config.
+    let mut raw: config::Value = builder.build()?.try_deserialize()?;
+    expand_env(&mut raw, "")?;
+    let app_cfg: AppConfig = raw.try_deserialize()?;
+    Ok(app_cfg)
+}
+
+/// Replace `${NAME}` in every string value with the environment variable,
+/// so hosts and passwords can stay out of a shared config file. `$${` is a
+/// literal `${`. `key` is the dotted path so far, for the error message.
+fn expand_env(value: &mut config::Value, key: &str) -> Result<()> {
+    match &mut value.kind {
+        config::ValueKind::String(s) if s.contains("${") => {
+            *s = expand(s, |name| env::var(name).ok()).with_context(|| format!("In config value `{key}`"))?;
+        }
+        config::ValueKind::Table(table) => {
+            for (name, v) in table.iter_mut() {
+                let key = if key.is_empty() { name.clone() } else { format!("{key}.{name}") };
+                expand_env(v, &key)?;
+            }
+        }
+        config::ValueKind::Array(items) => {
+            for (i, v) in items.iter_mut().enumerate() {
+                expand_env(v, &format!("{key}[{i}]"))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// A config value refers to an environment variable that isn't set
+#[derive(Debug)]
+pub struct UnsetVar(String);
+
+impl std::fmt::Display for UnsetVar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Environment variable {} is not set", self.0)
+    }
+}
+
+impl std::error::Error for UnsetVar {}
+
+/// `${NAME}` expansion for one string, looking names up with `var`
+fn expand(s: &str, var: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(at) = rest.find('$') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            out.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let Some(end) = after.find('}') else {
+                bail!("`${{` without a closing `}}` in '{s}'");
+            };
+            let name = &after[..end];
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                bail!("'{name}' in '{s}' isn't an environment variable name");
+            }
+            match var(name) {
+                Some(value) => out.push_str(&value),
+                None => return Err(UnsetVar(name.to_string()).into()),
+            }
+            rest = &after[end + 1..];
+        } else {
+            out.push('$');
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// The config file: the --config path, else the first of config.toml,
+/// config.yaml, config.yml and config.json that exists in
+/// ~/.config/bitaxe-cli (config.toml when there is none yet)
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = EXPLICIT_PATH.get() {
+        return Some(path.clone());
+    }
+    // Cross-platform home dir (HOME on Linux/Mac, USERPROFILE on Windows)
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+    let dir = PathBuf::from(home).join(".config").join("bitaxe-cli");
+    let found = CONFIG_NAMES.iter().map(|name| dir.join(name)).find(|p| p.exists());
+    Some(found.unwrap_or_else(|| dir.join(CONFIG_NAMES[0])))
+}
+
+/// Find a named device, listing the configured names when it is missing
+pub fn lookup_device<'a>(cfg: &'a AppConfig, name: &str) -> Result<&'a DeviceConfig> {
+    if let Some(dev) = cfg.devices.get(name) {
+        return Ok(dev);
+    }
+
+    if cfg.devices.is_empty() {
+        bail!("Unknown device '{name}': no [devices] are configured in ~/.config/bitaxe-cli/config.toml");
+    }
+    let names: Vec<&str> = cfg.devices.keys().map(String::as_str).collect();
+    bail!("Unknown device '{name}'. Configured devices: {}", names.join(", "));
+}
+
+/// The config file as an editable TOML document.
+/// Editing through toml_edit keeps comments and keys we don't know about.
+pub struct ConfigDoc {
+    path: PathBuf,
+    doc: DocumentMut,
+}
+
+impl ConfigDoc {
+    /// Open the config file for editing (an empty document when it doesn't exist yet)
+    pub fn open() -> Result<Self> {
+        let path = config_path().context("Could not determine home directory for the config file")?;
+        if !path.extension().is_some_and(|e| e.eq_ignore_ascii_case("toml")) {
+            bail!("Only TOML config files can be edited by bitaxe-cli; change {} by hand", path.display());
+        }
+        let doc = if path.exists() {
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            text.parse::<DocumentMut>()
+                .with_context(|| format!("Failed to parse {}", path.display()))?
+        } else {
+            DocumentMut::new()
+        };
+        Ok(Self { path, doc })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The [devices] table, created when missing
+    pub fn devices_mut(&mut self) -> Result<&mut Table> {
+        let item = self.doc.entry("devices").or_insert_with(|| {
+            let mut t = Table::new();
+            t.set_implicit(true);
+            Item::Table(t)
+        });
+        item.as_table_mut().context("`devices` in the config file is not a table")
+    }
+
+    pub fn default_device(&self) -> Option<&str> {
+        self.doc.get("default_device").and_then(|v| v.as_str())
+    }
+
+    pub fn set_default_device(&mut self, name: Option<&str>) {
+        match name {
+            Some(name) => {
+                self.doc["default_device"] = toml_edit::value(name);
+            }
+            None => {
+                self.doc.remove("default_device");
+            }
+        }
+    }
+
+    /// Write to a temp file next to the config and rename it into place,
+    /// so a crash never leaves a half-written config behind.
+    pub fn save(&self) -> Result<()> {
+        let dir = self.path.parent().context("Config path has no parent directory")?;
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        let tmp = self.path.with_extension("toml.tmp");
+        {
+            let mut f = File::create(&tmp).with_context(|| format!("Failed to create {}", tmp.display()))?;
+            f.write_all(self.doc.to_string().as_bytes())?;
+            f.sync_all()?;
+        }
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_references_are_expanded() {
+        let var = |name: &str| (name == "BITAXE_GARAGE").then(|| "192.168.1.50".to_string());
+        assert_eq!(expand("http://${BITAXE_GARAGE}:80", var).unwrap(), "http://192.168.1.50:80");
+        assert_eq!(expand("pa$$word $5 $${HOME}", var).unwrap(), "pa$$word $5 ${HOME}");
+        let unset = expand("${BITAXE_ATTIC}", var).unwrap_err().to_string();
+        assert_eq!(unset, "Environment variable BITAXE_ATTIC is not set");
+        assert!(expand("${BITAXE_GARAGE", var).unwrap_err().to_string().contains("without a closing"));
+        assert!(expand("${}", var).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use anyhow::{Context, Result, anyhow, bail};
+use clap::Args;
+use tungstenite::handshake::HandshakeError;
+use tungstenite::http::{StatusCode, Uri};
+use tungstenite::{Message, WebSocket};
+use crate::client::{DEFAULT_TIMEOUT, Target};
+use crate::interrupt::Interrupt;
+
+/// How often a blocked read wakes up to look for Ctrl-C
+const POLL: Duration = Duration::from_millis(250);
+/// A quiet socket is pinged after this long, so a dead link shows up
+const IDLE_PING: Duration = Duration::from_secs(30);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// `logs`: follow the miner's console as AxeOS streams it on /api/ws
+#[derive(Args, Debug)]
+pub struct ConsoleArgs {
+    /// Only print lines containing this text (ANSI colors are ignored when matching)
+    #[arg(long, value_name = "PATTERN")]
+    grep: Option<String>,
+
+    /// Also append the printed lines to this file, without colors
+    #[arg(long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// Where lines go: stdout, and the --output file
+struct Sink {
+    grep: Option<String>,
+    colorize: bool,
+    file: Option<BufWriter<File>>,
+}
+
+impl Sink {
+    fn message(&mut self, text: &str) -> Result<()> {
+        let mut out = io::stdout().lock();
+        for line in text.lines() {
+            let line = line.trim_end_matches('\r');
+            let plain = strip_ansi(line);
+            if !matches(self.grep.as_deref(), &plain) {
+                continue;
+            }
+            writeln!(out, "{}", if self.colorize { line } else { &plain })?;
+            if let Some(file) = &mut self.file {
+                writeln!(file, "{plain}")?;
+            }
+        }
+        out.flush()?;
+        self.flush()
+    }
+
+    /// A line of our own, printed whatever --grep says
+    fn marker(&mut self, text: &str) -> Result<()> {
+        println!("{text}");
+        if let Some(file) = &mut self.file {
+            writeln!(file, "{text}")?;
+        }
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if let Some(file) = &mut self.file {
+            file.flush().context("Couldn't write the --output file")?;
+        }
+        Ok(())
+    }
+}
+
+/// Print log lines until Ctrl-C. If the miner can't be reached at the
+/// start this fails; once streaming, a dropped socket is reopened with
+/// backoff (1s doubling to 30s) and a `-- reconnected --` line.
+pub fn run(args: &ConsoleArgs, target: &Target, colorize: bool) -> Result<()> {
+    let url = ws_url(&target.host)?;
+    let file = match &args.output {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Couldn't open {}", path.display()))?;
+            Some(BufWriter::new(file))
+        }
+        None => None,
+    };
+    let mut sink = Sink { grep: args.grep.clone(), colorize, file };
+    let interrupt = Interrupt::install()?;
+
+    let mut socket = connect(&url)?;
+    eprintln!("Streaming {url} (Ctrl-C to stop)");
+    loop {
+        let mut lost = match stream(&mut socket, &mut sink, &interrupt) {
+            Ok(()) => {
+                // Ctrl-C: say goodbye, but don't wait on a miner that's gone
+                let _ = socket.close(None).and_then(|_| socket.flush());
+                sink.flush()?;
+                return interrupt.finish();
+            }
+            Err(e) => e,
+        };
+        tracing::warn!(host = target.host, error = format!("{lost:#}"), "log stream lost");
+
+        let mut backoff = MIN_BACKOFF;
+        socket = loop {
+            eprintln!("-- connection lost: {lost:#}; reconnecting in {}s --", backoff.as_secs());
+            if !interrupt.sleep(backoff) {
+                return interrupt.finish();
+            }
+            match connect(&url) {
+                Ok(socket) => break socket,
+                Err(e) => lost = e,
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        };
+        sink.marker("-- reconnected --")?;
+    }
+}
+
+/// Read messages until Ctrl-C (`Ok`) or the socket fails (`Err`)
+fn stream(socket: &mut WebSocket<TcpStream>, sink: &mut Sink, interrupt: &Interrupt) -> Result<()> {
+    let mut heard = Instant::now();
+    while !interrupt.is_set() {
+        match socket.read() {
+            Ok(message) => {
+                heard = Instant::now();
+                match message {
+                    Message::Text(text) => sink.message(&text)?,
+                    Message::Binary(bytes) => sink.message(&String::from_utf8_lossy(&bytes))?,
+                    Message::Close(_) => bail!("closed by the miner"),
+                    Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {}
+                }
+            }
+            // Woken by the read timeout or by Ctrl-C itself
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) =>
+            {
+                if heard.elapsed() >= IDLE_PING {
+                    socket.send(Message::Ping(Default::default()))?;
+                    heard = Instant::now();
+                }
+            }
+            // tungstenite repeats the cause in its messages; one copy will do
+            Err(e) => bail!("{e}"),
+        }
+    }
+    Ok(())
+}
+
+fn connect(url: &str) -> Result<WebSocket<TcpStream>> {
+    let uri: Uri = url.parse().with_context(|| format!("Invalid URL {url}"))?;
+    let host = uri.host().context("The URL has no host")?.trim_matches(['[', ']']);
+    let addr = (host, uri.port_u16().unwrap_or(80))
+        .to_socket_addrs()
+        .with_context(|| format!("Couldn't resolve {host}"))?
+        .next()
+        .with_context(|| format!("No address for {host}"))?;
+    let tcp = TcpStream::connect_timeout(&addr, DEFAULT_TIMEOUT).with_context(|| format!("Couldn't connect to {addr}"))?;
+    tcp.set_read_timeout(Some(DEFAULT_TIMEOUT))?;
+
+    let (socket, _) = tungstenite::client(url, tcp).map_err(|e| match e {
+        HandshakeError::Failure(tungstenite::Error::Http(resp)) if resp.status() == StatusCode::NOT_FOUND => {
+            anyhow!("{url} answered 404: this firmware doesn't stream its log over WebSocket")
+        }
+        HandshakeError::Failure(e) => anyhow!("WebSocket handshake with {url} failed: {e}"),
+        HandshakeError::Interrupted(_) => anyhow!("WebSocket handshake with {url} timed out"),
+    })?;
+    socket.get_ref().set_read_timeout(Some(POLL))?;
+    Ok(socket)
+}
+
+/// The log socket below the host's base path: `http://10.0.0.2` ->
+/// `ws://10.0.0.2/api/ws`
+fn ws_url(host: &str) -> Result<String> {
+    let host = host.trim_end_matches('/');
+    match host.split_once("://") {
+        Some(("http", rest)) => Ok(format!("ws://{rest}/api/ws")),
+        Some(("https", _)) => bail!("`logs` can't stream over TLS (wss) yet; point it at the miner's http:// address"),
+        Some((scheme, _)) => bail!("Unsupported scheme {scheme}:// for {host}"),
+        None => Ok(format!("ws://{host}/api/ws")),
+    }
+}
+
+fn matches(grep: Option<&str>, line: &str) -> bool {
+    grep.is_none_or(|pattern| line.contains(pattern))
+}
+
+/// ESP-IDF colors its log levels (`\x1b[0;32mI (123) wifi: ...\x1b[0m`)
+fn strip_ansi(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        // CSI: ESC [ parameters, then a final byte in @..~
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn socket_url_and_line_filtering() {
+        assert_eq!(ws_url("http://10.0.0.2").unwrap(), "ws://10.0.0.2/api/ws");
+        assert_eq!(ws_url("http://proxy:8080/bitaxe/").unwrap(), "ws://proxy:8080/bitaxe/api/ws");
+        assert!(ws_url("https://miner.lan").is_err());
+
+        let line = "\x1b[0;33mW (81234) stratum_task: Share rejected\x1b[0m";
+        assert_eq!(strip_ansi(line), "W (81234) stratum_task: Share rejected");
+        assert_eq!(strip_ansi("no colors"), "no colors");
+        assert!(matches(Some("rejected"), &strip_ansi(line)));
+        assert!(!matches(Some("0;33"), &strip_ansi(line)));
+        assert!(matches(None, "anything"));
+    }
+}
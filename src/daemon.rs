@@ -0,0 +1,524 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use crate::average::{self, Rolling};
+use crate::client::Target;
+use crate::config_file::{AppConfig, DaemonConfig, config_path};
+use crate::fleet::{DeviceResult, FleetOpts, fetch_fleet};
+use crate::graphite::{self, Carbon};
+#[cfg(feature = "sqlite")]
+use crate::history::{self, Failure, HistoryDb, Recorder, Sample};
+use crate::interrupt::Interrupt;
+use crate::logger::{LogFile, csv_row, header};
+use crate::notify::Alerts;
+use crate::shares::{self, Seed};
+use crate::statsd::Emitter;
+use crate::systemd::Notifier;
+use crate::watchdog::{Policy, Watchdog};
+use crate::{exporter, get_number, logging, mqtt, parse_duration, ratelimit, rules, systemd};
+
+#[derive(Args, Debug)]
+pub struct DaemonArgs {
+    /// Time between polls (default: `interval` in [daemon], then 30s)
+    #[arg(long, value_parser = parse_duration)]
+    interval: Option<Duration>,
+
+    /// Run one full cycle, close the sinks and exit
+    #[arg(long)]
+    once: bool,
+
+    /// Print a systemd service file for this binary and config, then exit
+    /// (ex: > /etc/systemd/system/bitaxe-cli.service)
+    #[arg(long, conflicts_with = "once")]
+    pub print_systemd_unit: bool,
+
+    #[command(flatten)]
+    pub opts: FleetOpts,
+}
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// An output fed with every poll of the fleet. New outputs implement this
+/// and get added in `sinks`; the poll loop doesn't know about any of them.
+pub trait Sink {
+    fn name(&self) -> &'static str;
+
+    /// Take one cycle's results; returns a short summary for the cycle log
+    fn write(&mut self, results: &[DeviceResult]) -> Result<String>;
+
+    /// Flush and let go on shutdown
+    fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Fans each cycle out to every sink. A failing sink is reported and
+/// skipped for that cycle, without holding up the others.
+pub struct Bus {
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+impl Bus {
+    pub fn new(sinks: Vec<Box<dyn Sink>>) -> Self {
+        Bus { sinks }
+    }
+
+    /// (sink, summary or error) for each sink, in order
+    pub fn publish(&mut self, results: &[DeviceResult]) -> Vec<(&'static str, Result<String>)> {
+        self.sinks.iter_mut().map(|sink| (sink.name(), sink.write(results))).collect()
+    }
+
+    /// Close every sink, reporting (but getting past) failures
+    pub fn close(&mut self) {
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.close() {
+                eprintln!("Warning: closing {}: {e:#}", sink.name());
+            }
+        }
+    }
+}
+
+/// CSV rows as `log --format csv` writes them
+struct CsvSink(LogFile);
+
+impl Sink for CsvSink {
+    fn name(&self) -> &'static str {
+        "csv"
+    }
+
+    fn write(&mut self, results: &[DeviceResult]) -> Result<String> {
+        for r in results {
+            self.0.append(&csv_row(r.fetched_at, &r.name, r.info.as_ref().map_err(String::clone)))?;
+        }
+        Ok(format!("{} rows", results.len()))
+    }
+}
+
+#[cfg(feature = "sqlite")]
+struct HistorySink(Recorder);
+
+#[cfg(feature = "sqlite")]
+impl Sink for HistorySink {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn write(&mut self, results: &[DeviceResult]) -> Result<String> {
+        let samples: Vec<Sample> = results
+            .iter()
+            .filter_map(|r| r.info.as_ref().ok().map(|info| Sample::new(&r.name, r.fetched_at, info)))
+            .collect();
+        let failures: Vec<Failure> = results.iter().filter_map(Failure::new).collect();
+        self.0.record(&samples, &failures)?;
+        Ok(format!("{} samples", samples.len()))
+    }
+}
+
+struct MqttSink(mqtt::Publisher);
+
+impl Sink for MqttSink {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    fn write(&mut self, results: &[DeviceResult]) -> Result<String> {
+        match self.0.publish(results) {
+            0 => Ok("published".to_string()),
+            dropped => bail!("broker unreachable, dropped {dropped} message(s)"),
+        }
+    }
+
+    /// Marks the bridge offline before disconnecting
+    fn close(&mut self) -> Result<()> {
+        self.0.close();
+        Ok(())
+    }
+}
+
+/// Gauges and share counters to StatsD, one datagram per device. Lost
+/// datagrams are counted so the cycle log shows them.
+struct StatsdSink(Emitter);
+
+impl Sink for StatsdSink {
+    fn name(&self) -> &'static str {
+        "statsd"
+    }
+
+    fn write(&mut self, results: &[DeviceResult]) -> Result<String> {
+        let (mut sent, mut failed, mut error) = (0, 0, None);
+        for r in results {
+            let Ok(info) = &r.info else { continue };
+            let lines = self.0.lines(&r.name, info);
+            match self.0.send(&lines) {
+                Ok(()) => sent += lines.len(),
+                Err(e) => {
+                    failed += 1;
+                    error = Some(e);
+                }
+            }
+        }
+        if let Some(e) = error {
+            bail!("{failed} datagram(s) not sent to {} ({} since start): {e}", self.0.addr(), self.0.failures);
+        }
+        Ok(match self.0.failures {
+            0 => format!("{sent} lines"),
+            lost => format!("{sent} lines ({lost} datagrams lost since start)"),
+        })
+    }
+}
+
+/// Every reading to carbon, one batch per cycle. While carbon is down the
+/// cycles in the backoff fail without trying.
+struct GraphiteSink(Carbon);
+
+impl Sink for GraphiteSink {
+    fn name(&self) -> &'static str {
+        "graphite"
+    }
+
+    fn write(&mut self, results: &[DeviceResult]) -> Result<String> {
+        let lines = graphite::lines(&self.0.prefix, results);
+        self.0.send(&lines)?;
+        Ok(format!("{} lines", lines.len()))
+    }
+}
+
+/// Stores the latest exposition for the /metrics listener on the runtime
+struct ExporterSink {
+    body: Arc<Mutex<String>>,
+    rules: rules::Engine,
+}
+
+impl Sink for ExporterSink {
+    fn name(&self) -> &'static str {
+        "exporter"
+    }
+
+    fn write(&mut self, results: &[DeviceResult]) -> Result<String> {
+        let text = exporter::exposition(results, &mut self.rules);
+        let mut body = self.body.lock().map_err(|_| anyhow::anyhow!("metrics listener panicked"))?;
+        *body = text;
+        Ok("updated".to_string())
+    }
+}
+
+struct AlertSink(Alerts);
+
+impl Sink for AlertSink {
+    fn name(&self) -> &'static str {
+        "alerts"
+    }
+
+    fn write(&mut self, results: &[DeviceResult]) -> Result<String> {
+        let events: usize = results.iter().map(|r| self.0.observe(&r.name, r.info.as_ref().ok())).sum();
+        Ok(format!("{events} events"))
+    }
+
+    /// Waits for notifications still being delivered
+    fn close(&mut self) -> Result<()> {
+        self.0.flush();
+        Ok(())
+    }
+}
+
+/// Restarts miners that stay stuck, as `watchdog` does
+struct WatchdogSink {
+    watchdog: Watchdog,
+    targets: Vec<Target>,
+}
+
+impl Sink for WatchdogSink {
+    fn name(&self) -> &'static str {
+        "watchdog"
+    }
+
+    fn write(&mut self, results: &[DeviceResult]) -> Result<String> {
+        let due = self.watchdog.act(&self.targets, results);
+        Ok(match (due, self.watchdog.policy().dry_run) {
+            (0, _) => "ok".to_string(),
+            (n, true) => format!("{n} restarts due (dry run)"),
+            (n, false) => format!("{n} restarts"),
+        })
+    }
+}
+
+/// The sinks [daemon] turns on, connected and ready
+fn sinks(
+    conf: &DaemonConfig,
+    cfg: &AppConfig,
+    targets: &[Target],
+    seeds: &[Seed],
+    interrupt: &Interrupt,
+) -> Result<Vec<Box<dyn Sink>>> {
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+    if let Some(path) = &conf.csv {
+        sinks.push(Box::new(CsvSink(LogFile::open(path, Some(header()))?)));
+    }
+    if conf.history || conf.history_path.is_some() {
+        #[cfg(feature = "sqlite")]
+        sinks.push(Box::new(HistorySink(Recorder::open(conf.history_path.as_deref(), cfg)?)));
+        #[cfg(not(feature = "sqlite"))]
+        bail!("history in [daemon] needs a build with the sqlite feature");
+    }
+    if conf.mqtt {
+        let mqtt = cfg.mqtt.as_ref();
+        let settings = mqtt::Settings::resolve(mqtt, None, None, None).context("mqtt in [daemon]")?;
+        sinks.push(Box::new(MqttSink(mqtt::Publisher::connect(mqtt, settings, interrupt)?)));
+    }
+    if conf.statsd {
+        sinks.push(Box::new(StatsdSink(Emitter::connect(cfg.statsd.as_ref(), None, None, None)?)));
+    }
+    if conf.graphite {
+        sinks.push(Box::new(GraphiteSink(Carbon::new(cfg.graphite.as_ref(), None, None))));
+    }
+    if let Some(listen) = &conf.exporter {
+        let mut rules = rules::Engine::from_config(cfg)?;
+        rules.seed_shares(seeds);
+        sinks.push(Box::new(ExporterSink { body: exporter::serve(listen)?, rules }));
+    }
+    if conf.alerts != Some(false)
+        && let Some(mut alerts) = Alerts::from_config(cfg)?
+    {
+        alerts.seed_shares(seeds);
+        // Kept next to the samples so `report` can list them
+        #[cfg(feature = "sqlite")]
+        if conf.history || conf.history_path.is_some() {
+            alerts.record_to(HistoryDb::open(&history::db_path(conf.history_path.as_deref())?)?);
+        }
+        sinks.push(Box::new(AlertSink(alerts)));
+    }
+    if conf.watchdog {
+        let watchdog = Watchdog::new(Policy::from_config(cfg.watchdog.as_ref())?);
+        sinks.push(Box::new(WatchdogSink { watchdog, targets: targets.to_vec() }));
+    }
+    Ok(sinks)
+}
+
+/// With history on, where each device's share counter stood when the
+/// daemon last ran, so the time since the last share carries over restarts
+#[cfg(feature = "sqlite")]
+fn share_seeds(conf: &DaemonConfig) -> Result<Vec<Seed>> {
+    if !conf.history && conf.history_path.is_none() {
+        return Ok(Vec::new());
+    }
+    HistoryDb::open(&history::db_path(conf.history_path.as_deref())?)?.last_shares()
+}
+
+/// --interval, then `interval` in [daemon], then the default
+fn interval(args: &DaemonArgs, conf: Option<&DaemonConfig>) -> Result<Duration> {
+    let interval = match (args.interval, conf.and_then(|c| c.interval.as_deref())) {
+        (Some(d), _) => d,
+        (None, Some(s)) => parse_duration(s).map_err(|e| anyhow::anyhow!("interval in [daemon]: {e}"))?,
+        (None, None) => DEFAULT_INTERVAL,
+    };
+    Ok(ratelimit::poll_interval(interval))
+}
+
+/// `averages` in [daemon], or the defaults
+fn windows(conf: &DaemonConfig) -> Result<Vec<Duration>> {
+    match &conf.averages {
+        Some(list) => list
+            .iter()
+            .map(|s| parse_duration(s).map_err(|e| anyhow::anyhow!("averages in [daemon]: {e}")))
+            .filter(|w| !matches!(w, Ok(w) if w.is_zero()))
+            .collect(),
+        None => Ok(average::DEFAULT_WINDOWS.to_vec()),
+    }
+}
+
+/// Fleet hashrate this cycle, and its rolling averages: the sum of each
+/// device's own, so a miner that's down for a while doesn't drag them down
+fn fleet_hashrate(rolling: &mut HashMap<String, Rolling>, windows: &[Duration], results: &[DeviceResult]) -> String {
+    let mut total = 0.0;
+    for r in results {
+        let hash = r.info.as_ref().ok().and_then(|i| get_number(i, "hashRate"));
+        total += hash.unwrap_or(0.0);
+        rolling.entry(r.name.clone()).or_insert_with(|| Rolling::new(windows)).push(Instant::now(), hash);
+    }
+    let mut sums: Vec<(Duration, f64)> = windows.iter().map(|&w| (w, 0.0)).collect();
+    for device in rolling.values() {
+        for (w, avg) in device.averages() {
+            if let Some(sum) = sums.iter_mut().find(|(sw, _)| *sw == w) {
+                sum.1 += avg;
+            }
+        }
+    }
+    if sums.is_empty() {
+        format!("{total:.1} GH/s")
+    } else {
+        format!("{total:.1} GH/s ({})", average::describe(&sums, 1))
+    }
+}
+
+/// `4.2 shares/min (ultra: last share 12m ago)`, naming the device that has gone
+/// longest without a share
+fn fleet_shares(tracker: &mut shares::Tracker, results: &[DeviceResult]) -> Option<String> {
+    let stats: Vec<(&str, shares::Stats)> = results
+        .iter()
+        .filter_map(|r| Some((r.name.as_str(), tracker.observe(&r.name, r.info.as_ref().ok()?, r.fetched_at)?)))
+        .collect();
+    let (device, worst) = stats.iter().max_by_key(|(_, s)| s.since_last)?;
+    let last = format!("{device}: last share {} ago", rules::short_duration(worst.since_last));
+    let rates: Vec<f64> = stats.iter().filter_map(|(_, s)| s.per_minute).collect();
+    if rates.is_empty() {
+        return Some(last);
+    }
+    Some(format!("{:.1} shares/min ({last})", rates.iter().sum::<f64>()))
+}
+
+/// `--print-systemd-unit`
+pub fn print_unit(args: &DaemonArgs, cfg: &AppConfig) -> Result<()> {
+    print!("{}", systemd::unit(config_path().as_deref(), interval(args, cfg.daemon.as_ref())?)?);
+    Ok(())
+}
+
+/// Poll every target each interval and hand the results to the configured
+/// sinks until SIGINT/SIGTERM (or after one cycle with --once). Stopping is
+/// the normal way out, so it closes the sinks and exits 0. Under systemd it
+/// reports ready after the first cycle that reached a device, and pings the
+/// watchdog after every cycle.
+pub fn run(args: &DaemonArgs, targets: &[Target], cfg: &AppConfig) -> Result<()> {
+    let conf = cfg.daemon.as_ref().context("No [daemon] table in the config; it lists the outputs to feed")?;
+    let interval = interval(args, Some(conf))?;
+    let windows = windows(conf)?;
+    let notifier = Notifier::from_env();
+    if let Some(watchdog) = notifier.watchdog()
+        && interval >= watchdog
+    {
+        eprintln!(
+            "Warning: polling every {} but WatchdogSec is {}; systemd will restart the daemon between cycles",
+            rules::short_duration(interval),
+            rules::short_duration(watchdog)
+        );
+    }
+    #[cfg(feature = "sqlite")]
+    let seeds = share_seeds(conf)?;
+    #[cfg(not(feature = "sqlite"))]
+    let seeds = Vec::new();
+    let interrupt = Interrupt::install()?;
+    let mut bus = Bus::new(sinks(conf, cfg, targets, &seeds, &interrupt)?);
+    if bus.sinks.is_empty() {
+        bail!("Nothing to do: enable csv, history, mqtt, statsd, graphite, exporter, alerts or watchdog in [daemon]");
+    }
+    let names = bus.sinks.iter().map(|s| s.name()).collect::<Vec<_>>().join(", ");
+    eprintln!("Polling {} device(s) every {} into {names}", targets.len(), rules::short_duration(interval));
+    tracing::info!(devices = targets.len(), interval = interval.as_secs_f64(), sinks = names, "daemon started");
+
+    let mut cycle: u64 = 0;
+    let mut ready = false;
+    let mut rolling: HashMap<String, Rolling> = HashMap::new();
+    let mut shares = shares::Tracker::new();
+    shares.seed(&seeds);
+    loop {
+        cycle += 1;
+        let started = Instant::now();
+        let results = fetch_fleet(targets, &args.opts);
+        for r in &results {
+            logging::poll(&r.name, r.info.as_ref().map_err(String::clone));
+        }
+        let reachable = results.iter().filter(|r| r.info.is_ok()).count();
+        let hashrate = fleet_hashrate(&mut rolling, &windows, &results);
+        let share_rate = fleet_shares(&mut shares, &results);
+        let mut summary = Vec::new();
+        for (sink, outcome) in bus.publish(&results) {
+            match outcome {
+                Ok(done) => summary.push(format!("{sink} {done}")),
+                Err(e) => {
+                    eprintln!("Warning: {sink}: {e:#}");
+                    tracing::error!(cycle, sink, error = format!("{e:#}"), "sink failed");
+                    summary.push(format!("{sink} failed"));
+                }
+            }
+        }
+        let took = started.elapsed();
+        let mut line = format!("Cycle {cycle}: {reachable}/{} reachable, {hashrate}", results.len());
+        if let Some(share_rate) = &share_rate {
+            line += &format!(", {share_rate}");
+        }
+        line += &format!("; {}", summary.join(", "));
+        eprintln!("{line}");
+        if !ready && reachable > 0 {
+            notifier.ready();
+            ready = true;
+        }
+        notifier.status(&line);
+        notifier.alive();
+        tracing::info!(
+            cycle,
+            devices = results.len(),
+            reachable,
+            hashrate,
+            shares = share_rate,
+            took_ms = took.as_millis() as u64,
+            sinks = summary.join(", "),
+            "cycle"
+        );
+
+        if args.once || !interrupt.sleep(interval.saturating_sub(took)) {
+            break;
+        }
+    }
+
+    eprintln!("Shutting down after {cycle} cycle(s)");
+    notifier.stopping();
+    bus.close();
+    tracing::info!(cycles = cycle, "daemon stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+    use serde_json::json;
+
+    /// Records what it saw in a shared log, optionally failing every write
+    struct Fake {
+        name: &'static str,
+        fail: bool,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Sink for Fake {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn write(&mut self, results: &[DeviceResult]) -> Result<String> {
+            if self.fail {
+                bail!("disk full");
+            }
+            self.log.lock().unwrap().push(format!("{} got {}", self.name, results.len()));
+            Ok(format!("{} rows", results.len()))
+        }
+
+        fn close(&mut self) -> Result<()> {
+            self.log.lock().unwrap().push(format!("{} closed", self.name));
+            if self.fail { bail!("still full") } else { Ok(()) }
+        }
+    }
+
+    #[test]
+    fn a_failing_sink_does_not_stop_the_others() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let fake = |name, fail| Box::new(Fake { name, fail, log: log.clone() }) as Box<dyn Sink>;
+        let mut bus = Bus::new(vec![fake("csv", true), fake("mqtt", false)]);
+        let results = [DeviceResult {
+            name: "gamma".into(),
+            host: String::new(),
+            info: Ok(json!({"hashRate": 500})),
+            fetched_at: SystemTime::now(),
+        }];
+
+        let outcomes = bus.publish(&results);
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(outcomes[0].0, "csv");
+        assert!(outcomes[0].1.is_err());
+        assert_eq!(outcomes[1].1.as_ref().unwrap(), "1 rows");
+
+        bus.close();
+        assert_eq!(*log.lock().unwrap(), ["mqtt got 1", "csv closed", "mqtt closed"]);
+    }
+}
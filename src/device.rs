@@ -0,0 +1,241 @@
+use anyhow::{Result, bail};
+use bitaxe_api::SystemInfo;
+use clap::Subcommand;
+use toml_edit::{Array, Item, Table, value};
+use crate::client::ClientOptions;
+use crate::config_file::{AppConfig, ConfigDoc};
+use crate::discover::Found;
+use crate::{fetch_info, runtime};
+
+/// `device` subcommands: manage the [devices] table in the config file
+#[derive(Subcommand, Debug)]
+pub enum DeviceCommand {
+    /// Add a named device
+    Add {
+        name: String,
+        /// Base URL of the miner (ex: http://192.168.1.50)
+        host: String,
+        /// Contact the miner and store its hostname and ASIC model
+        #[arg(long)]
+        probe: bool,
+    },
+
+    /// Remove a named device
+    Remove { name: String },
+
+    /// Rename a device
+    Rename { old: String, new: String },
+
+    /// Make a device the default when no --device flag is given
+    SetDefault { name: String },
+
+    /// List configured devices with an online/offline check
+    List,
+
+    /// Add or remove tags on a device
+    Tag {
+        #[command(subcommand)]
+        action: TagAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TagAction {
+    /// Add a tag to a device
+    Add { name: String, tag: String },
+
+    /// Remove a tag from a device
+    Remove { name: String, tag: String },
+}
+
+pub fn run(cmd: &DeviceCommand, cfg: &AppConfig, opts: &ClientOptions) -> Result<()> {
+    match cmd {
+        DeviceCommand::Add { name, host, probe } => add_device(opts, name, host, *probe),
+        DeviceCommand::Remove { name } => remove_device(name),
+        DeviceCommand::Rename { old, new } => rename_device(old, new),
+        DeviceCommand::SetDefault { name } => set_default(name),
+        DeviceCommand::List => list_devices(opts, cfg),
+        DeviceCommand::Tag { action: TagAction::Add { name, tag } } => edit_tag(name, tag, true),
+        DeviceCommand::Tag { action: TagAction::Remove { name, tag } } => edit_tag(name, tag, false),
+    }
+}
+
+fn add_device(opts: &ClientOptions, name: &str, host: &str, probe: bool) -> Result<()> {
+    if name.trim().is_empty() {
+        bail!("Device name cannot be empty");
+    }
+
+    let mut doc = ConfigDoc::open()?;
+    let devices = doc.devices_mut()?;
+    if devices.contains_key(name) {
+        bail!("Device '{name}' already exists; remove or rename it first");
+    }
+
+    let host = host.trim_end_matches('/');
+    let mut entry = Table::new();
+    entry["host"] = value(host);
+
+    if probe {
+        let info = SystemInfo::from(&runtime().block_on(fetch_info(&opts.build(None)?, host))?);
+        if let Some(hostname) = info.hostname {
+            entry["hostname"] = value(hostname);
+        }
+        if let Some(model) = info.asic_model {
+            entry["asic_model"] = value(model);
+        }
+    }
+
+    devices.insert(name, Item::Table(entry));
+    doc.save()?;
+    println!("Added device '{name}' ({host}) to {}", doc.path().display());
+    Ok(())
+}
+
+fn remove_device(name: &str) -> Result<()> {
+    let mut doc = ConfigDoc::open()?;
+    if doc.devices_mut()?.remove(name).is_none() {
+        bail!("Unknown device '{name}'");
+    }
+    if doc.default_device() == Some(name) {
+        doc.set_default_device(None);
+    }
+
+    doc.save()?;
+    println!("Removed device '{name}'");
+    Ok(())
+}
+
+fn rename_device(old: &str, new: &str) -> Result<()> {
+    if new.trim().is_empty() {
+        bail!("Device name cannot be empty");
+    }
+
+    let mut doc = ConfigDoc::open()?;
+    let devices = doc.devices_mut()?;
+    if devices.contains_key(new) {
+        bail!("Device '{new}' already exists");
+    }
+    let Some(entry) = devices.remove(old) else {
+        bail!("Unknown device '{old}'");
+    };
+    devices.insert(new, entry);
+    if doc.default_device() == Some(old) {
+        doc.set_default_device(Some(new));
+    }
+
+    doc.save()?;
+    println!("Renamed device '{old}' to '{new}'");
+    Ok(())
+}
+
+fn set_default(name: &str) -> Result<()> {
+    let mut doc = ConfigDoc::open()?;
+    if !doc.devices_mut()?.contains_key(name) {
+        bail!("Unknown device '{name}'");
+    }
+    doc.set_default_device(Some(name));
+
+    doc.save()?;
+    println!("Default device is now '{name}'");
+    Ok(())
+}
+
+fn edit_tag(name: &str, tag: &str, add: bool) -> Result<()> {
+    if tag.trim().is_empty() {
+        bail!("Tag cannot be empty");
+    }
+
+    let mut doc = ConfigDoc::open()?;
+    let Some(entry) = doc.devices_mut()?.get_mut(name).and_then(Item::as_table_like_mut) else {
+        bail!("Unknown device '{name}'");
+    };
+
+    let tags = entry.entry("tags").or_insert(value(Array::new()));
+    let Some(tags) = tags.as_array_mut() else {
+        bail!("`tags` of device '{name}' is not an array");
+    };
+    let pos = tags.iter().position(|t| t.as_str() == Some(tag));
+
+    match (add, pos) {
+        (true, Some(_)) => {
+            println!("Device '{name}' already has tag '{tag}'");
+            return Ok(());
+        }
+        (true, None) => tags.push(tag),
+        (false, Some(i)) => {
+            tags.remove(i);
+        }
+        (false, None) => bail!("Device '{name}' has no tag '{tag}'"),
+    }
+
+    doc.save()?;
+    if add {
+        println!("Tagged '{name}' with '{tag}'");
+    } else {
+        println!("Removed tag '{tag}' from '{name}'");
+    }
+    Ok(())
+}
+
+fn list_devices(opts: &ClientOptions, cfg: &AppConfig) -> Result<()> {
+    if cfg.devices.is_empty() {
+        println!("No devices configured. Add one with `bitaxe-cli device add <name> <host>`.");
+        return Ok(());
+    }
+
+    for (name, dev) in &cfg.devices {
+        let marker = if cfg.default_device.as_deref() == Some(name.as_str()) { "*" } else { " " };
+        let target = opts.device_target(name, dev)?;
+        let state = match runtime().block_on(fetch_info(&target.client, &target.host)) {
+            Ok(_) => "online",
+            Err(_) => "offline",
+        };
+
+        let mut meta = Vec::new();
+        if let Some(h) = &dev.hostname {
+            meta.push(h.as_str());
+        }
+        if let Some(m) = &dev.asic_model {
+            meta.push(m.as_str());
+        }
+        let meta = if meta.is_empty() { String::new() } else { format!("  ({})", meta.join(", ")) };
+        let tags = if dev.tags.is_empty() { String::new() } else { format!("  [{}]", dev.tags.join(", ")) };
+
+        println!("{marker} {name:<12} {:<28} {state}{meta}{tags}", dev.host);
+    }
+    Ok(())
+}
+
+/// Save discovered devices keyed by their hostname, skipping any whose name
+/// or host is already configured
+pub fn add_found(cfg: &AppConfig, found: &[Found]) -> Result<()> {
+    let mut doc = ConfigDoc::open()?;
+    let devices = doc.devices_mut()?;
+    let mut added = 0;
+
+    for f in found {
+        let name = f.hostname.clone().unwrap_or_else(|| f.host.clone());
+        let known_host = cfg.devices.values().any(|d| d.host.trim_end_matches('/') == f.host);
+        if known_host || devices.contains_key(&name) {
+            println!("Skipping {name} ({}): already configured", f.host);
+            continue;
+        }
+
+        let mut entry = Table::new();
+        entry["host"] = value(f.host.as_str());
+        if let Some(h) = &f.hostname {
+            entry["hostname"] = value(h.as_str());
+        }
+        if let Some(m) = &f.asic_model {
+            entry["asic_model"] = value(m.as_str());
+        }
+        devices.insert(&name, Item::Table(entry));
+        println!("Added device '{name}' ({})", f.host);
+        added += 1;
+    }
+
+    if added > 0 {
+        doc.save()?;
+    }
+    Ok(())
+}
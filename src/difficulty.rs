@@ -0,0 +1,102 @@
+pub use bitaxe_api::parse_difficulty as parse;
+use bitaxe_api::DIFFICULTY_SUFFIXES as SUFFIXES;
+use crate::fleet::format_uptime;
+
+/// Hashes needed on average per unit of share difficulty
+const HASHES_PER_DIFF: f64 = 4_294_967_296.0;
+
+/// `--target`, ex: 10G
+pub fn parse_target(s: &str) -> Result<f64, String> {
+    match parse(s) {
+        Some(d) if d > 0.0 => Ok(d),
+        _ => Err(format!("invalid difficulty '{s}' (ex: 500M, 10G, 1.5T)")),
+    }
+}
+
+/// Short form with the largest fitting suffix, ex: 5G, 1.25T
+pub fn format(d: f64) -> String {
+    let (value, suffix) = SUFFIXES
+        .iter()
+        .rev()
+        .find(|(_, mult)| d >= *mult)
+        .map(|(c, mult)| (d / mult, c.to_string()))
+        .unwrap_or((d, String::new()));
+    let value = format!("{value:.2}");
+    format!("{}{suffix}", value.trim_end_matches('0').trim_end_matches('.'))
+}
+
+/// Expected seconds until a share of at least `target` turns up at
+/// `hashrate` GH/s. Mining is memoryless, so this doesn't shrink as the
+/// best difficulty gets closer; it is the average wait from any moment.
+pub fn expected_secs(target: f64, hashrate: f64) -> Option<f64> {
+    (hashrate > 0.0).then(|| target * HASHES_PER_DIFF / (hashrate * 1e9))
+}
+
+/// ETA for humans: minutes to days, then years
+pub fn format_eta(secs: f64) -> String {
+    const YEAR: f64 = 365.25 * 86_400.0;
+    if secs < 60.0 {
+        "under a minute".to_string()
+    } else if secs >= YEAR {
+        let years = secs / YEAR;
+        if years >= 10.0 { format!("{years:.0} years") } else { format!("{years:.1} years") }
+    } else {
+        format_uptime(secs)
+    }
+}
+
+/// `best` as a percentage of `target`, with enough digits to show movement
+pub fn format_progress(best: f64, target: f64) -> String {
+    let pct = best / target * 100.0;
+    match pct {
+        p if p >= 100.0 => "target reached".to_string(),
+        p if p >= 10.0 => format!("{p:.0}%"),
+        p if p >= 1.0 => format!("{p:.1}%"),
+        p if p >= 0.01 => format!("{p:.2}%"),
+        _ => "<0.01%".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_suffixed_difficulties() {
+        assert_eq!(parse("1234"), Some(1234.0));
+        assert_eq!(parse("45.3M"), Some(45.3e6));
+        assert_eq!(parse("1.2G"), Some(1.2e9));
+        assert_eq!(parse("3.5 T"), Some(3.5e12));
+        assert_eq!(parse("2k"), Some(2000.0));
+        assert_eq!(parse("2K"), Some(2000.0));
+        assert_eq!(parse("10g"), Some(1e10));
+        assert_eq!(parse("1P"), Some(1e15));
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("G"), None);
+        assert_eq!(parse("12X"), None);
+        assert_eq!(parse("-5G"), None);
+        assert!(parse_target("0").is_err());
+    }
+
+    #[test]
+    fn formats_targets_and_progress() {
+        assert_eq!(format(5e9), "5G");
+        assert_eq!(format(1.25e12), "1.25T");
+        assert_eq!(format(999.0), "999");
+        assert_eq!(format_progress(1.2e9, 5e9), "24%");
+        assert_eq!(format_progress(45.3e6, 5e9), "0.91%");
+        assert_eq!(format_progress(6e9, 5e9), "target reached");
+        assert_eq!(format_progress(1.0, 5e9), "<0.01%");
+    }
+
+    #[test]
+    fn eta_from_hashrate() {
+        // 1M at 1 TH/s: 2^32 * 1e6 hashes at 1e12 per second
+        let secs = expected_secs(1e6, 1000.0).unwrap();
+        assert!((secs - 4294.967).abs() < 0.01, "{secs}");
+        assert_eq!(expected_secs(1e9, 0.0), None);
+        assert_eq!(format_eta(secs), "1h 11m");
+        assert_eq!(format_eta(30.0), "under a minute");
+        assert_eq!(format_eta(expected_secs(1e12, 500.0).unwrap()), "272 years");
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::BTreeSet;
+use std::io::{self, IsTerminal};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use anyhow::{Context, Result, bail};
+use bitaxe_api::SystemInfo;
+use clap::Args;
+use mdns_sd::{ServiceDaemon, ServiceEvent};
+use reqwest::Client;
+use serde_json::Value;
+use crate::client::ClientOptions;
+use crate::config_file::AppConfig;
+use crate::fleet::bounded_map;
+use crate::interrupt::Interrupt;
+use crate::{device, fetch_info, parse_duration, runtime};
+
+/// Service types browsed for candidates. AxeOS serves its UI over plain
+/// HTTP; the dedicated type is picked up by firmware that advertises one.
+const SERVICE_TYPES: &[&str] = &["_http._tcp.local.", "_axeos._tcp.local."];
+
+#[derive(Args, Debug)]
+pub struct DiscoverArgs {
+    /// How long to listen for mDNS announcements (ex: 5s)
+    #[arg(long, default_value = "5s", value_parser = parse_duration)]
+    duration: Duration,
+
+    /// Timeout for probing each candidate's /api/system/info [default: 2s,
+    /// or --timeout when that is shorter]
+    #[arg(long, value_parser = parse_duration)]
+    probe_timeout: Option<Duration>,
+
+    /// Add found devices to the config's [devices] table (keyed by hostname)
+    #[arg(long)]
+    add: bool,
+
+    /// Actively probe every address of an IPv4 network (ex: 192.168.1.0/24)
+    /// instead of listening for mDNS
+    #[arg(long, value_name = "CIDR")]
+    scan: Option<String>,
+
+    /// Parallel probes during --scan
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u16).range(1..))]
+    concurrency: u16,
+
+    /// Allow scanning networks larger than /22
+    #[arg(long)]
+    allow_large_scan: bool,
+}
+
+/// A host that answered with an AxeOS payload
+pub struct Found {
+    pub host: String,
+    pub hostname: Option<String>,
+    pub asic_model: Option<String>,
+    pub version: Option<String>,
+}
+
+pub fn run(args: &DiscoverArgs, cfg: &AppConfig, opts: &ClientOptions) -> Result<()> {
+    let probe_timeout = opts.probe_timeout(args.probe_timeout);
+    let found = match &args.scan {
+        Some(cidr) => {
+            let net = Ipv4Net::parse(cidr)?;
+            if net.prefix < MIN_SCAN_PREFIX && !args.allow_large_scan {
+                bail!(
+                    "{cidr} has {} addresses; refusing to scan wider than /{MIN_SCAN_PREFIX} without --allow-large-scan",
+                    net.hosts().len()
+                );
+            }
+            let hosts: Vec<IpAddr> = net.hosts().into_iter().map(IpAddr::V4).collect();
+            scan(&hosts, probe_timeout, args.concurrency as usize)?
+        }
+        None => {
+            let candidates = browse(args.duration)?;
+            probe_all(&candidates, probe_timeout)?
+        }
+    };
+    report(&found, cfg, args.add)
+}
+
+/// Collect (ip, port) pairs announced over mDNS during `duration`
+fn browse(duration: Duration) -> Result<BTreeSet<(IpAddr, u16)>> {
+    let daemon = ServiceDaemon::new()?;
+    let receivers = SERVICE_TYPES
+        .iter()
+        .map(|ty| daemon.browse(ty))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    eprintln!("Browsing mDNS for {}s...", duration.as_secs_f64());
+    let mut candidates = BTreeSet::new();
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        for rx in &receivers {
+            while let Ok(event) = rx.try_recv() {
+                if let ServiceEvent::ServiceResolved(svc) = event {
+                    let port = if svc.get_port() == 0 { 80 } else { svc.get_port() };
+                    for ip in svc.get_addresses_v4() {
+                        candidates.insert((IpAddr::V4(ip), port));
+                    }
+                }
+            }
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    // Shutdown failures only matter to the daemon thread we are done with
+    let _ = daemon.shutdown();
+    Ok(candidates)
+}
+
+/// Probe every candidate and keep those that look like AxeOS
+pub fn probe_all(candidates: &BTreeSet<(IpAddr, u16)>, timeout: Duration) -> Result<Vec<Found>> {
+    let client = Client::builder().timeout(timeout).build()?;
+    let urls: Vec<String> = candidates.iter().map(|(ip, port)| base_url(*ip, *port)).collect();
+
+    let probed = runtime().block_on(bounded_map(urls, 16, |url| probe(client.clone(), url)));
+    Ok(probed.into_iter().flatten().collect())
+}
+
+/// One candidate: `Some` when it answers with an AxeOS payload
+async fn probe(client: Client, url: String) -> Option<Found> {
+    let info = SystemInfo::from(&fetch_info(&client, &url).await.ok().filter(is_axeos)?);
+    Some(Found { host: url, hostname: info.hostname, asic_model: info.asic_model, version: info.version })
+}
+
+/// Networks wider than this prefix need --allow-large-scan
+const MIN_SCAN_PREFIX: u8 = 22;
+
+/// Probe every address with progress on stderr. Ctrl-C stops the scan
+/// and returns what was found so far.
+fn scan(hosts: &[IpAddr], timeout: Duration, concurrency: usize) -> Result<Vec<Found>> {
+    let client = Client::builder().timeout(timeout).build()?;
+    let interrupt = Interrupt::install()?;
+
+    let done = Arc::new(AtomicUsize::new(0));
+    let hits = Arc::new(AtomicUsize::new(0));
+    let total = hosts.len();
+    let progress = io::stderr().is_terminal();
+
+    let probe_one = |ip: IpAddr| {
+        let (client, interrupt, done, hits) = (client.clone(), interrupt.clone(), done.clone(), hits.clone());
+        async move {
+            if interrupt.is_set() {
+                return None;
+            }
+            let found = probe(client, base_url(ip, 80)).await;
+            if found.is_some() {
+                hits.fetch_add(1, Ordering::Relaxed);
+            }
+            let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+            if progress {
+                eprint!("\rScanned {n}/{total}, found {}", hits.load(Ordering::Relaxed));
+            }
+            found
+        }
+    };
+    let probed = runtime().block_on(bounded_map(hosts.to_vec(), concurrency, probe_one));
+    if progress {
+        eprintln!();
+    }
+
+    if interrupt.is_set() {
+        eprintln!("Scan interrupted; showing partial results.");
+    }
+    Ok(probed.into_iter().flatten().collect())
+}
+
+/// An IPv4 network in CIDR notation. Scanning works on plain address
+/// lists, so IPv6 support only needs its own network type.
+#[derive(Debug, PartialEq)]
+struct Ipv4Net {
+    addr: Ipv4Addr,
+    prefix: u8,
+}
+
+impl Ipv4Net {
+    fn parse(s: &str) -> Result<Self> {
+        let (addr, prefix) = s
+            .trim()
+            .split_once('/')
+            .with_context(|| format!("'{s}' is not in CIDR notation (ex: 192.168.1.0/24)"))?;
+        if addr.parse::<std::net::Ipv6Addr>().is_ok() {
+            bail!("IPv6 scanning is not supported yet");
+        }
+        let addr: Ipv4Addr = addr.parse().with_context(|| format!("'{addr}' is not an IPv4 address"))?;
+        let prefix: u8 = prefix
+            .parse()
+            .ok()
+            .filter(|p| *p <= 32)
+            .with_context(|| format!("'{prefix}' is not a prefix length between 0 and 32"))?;
+        Ok(Self { addr, prefix })
+    }
+
+    /// Usable host addresses: network and broadcast are skipped except for /31 and /32
+    fn hosts(&self) -> Vec<Ipv4Addr> {
+        let mask = if self.prefix == 0 { 0 } else { u32::MAX << (32 - self.prefix) };
+        let network = u32::from(self.addr) & mask;
+        let broadcast = network | !mask;
+
+        let (first, last) = if self.prefix >= 31 { (network, broadcast) } else { (network + 1, broadcast - 1) };
+        (first..=last).map(Ipv4Addr::from).collect()
+    }
+}
+
+pub fn base_url(ip: IpAddr, port: u16) -> String {
+    match (ip, port) {
+        (IpAddr::V4(v4), 80) => format!("http://{v4}"),
+        (IpAddr::V4(v4), p) => format!("http://{v4}:{p}"),
+        (IpAddr::V6(v6), 80) => format!("http://[{v6}]"),
+        (IpAddr::V6(v6), p) => format!("http://[{v6}]:{p}"),
+    }
+}
+
+/// Other HTTP services answer too; only accept payloads with AxeOS fields
+pub fn is_axeos(info: &Value) -> bool {
+    info.get("ASICModel").is_some() || (info.get("hashRate").is_some() && info.get("bestDiff").is_some())
+}
+
+/// Print what was found and optionally save it to the config
+pub fn report(found: &[Found], cfg: &AppConfig, add: bool) -> Result<()> {
+    if found.is_empty() {
+        println!("No Bitaxe devices found.");
+        return Ok(());
+    }
+
+    println!("{:<20} {:<28} {:<8} FIRMWARE", "NAME", "HOST", "ASIC");
+    for f in found {
+        println!(
+            "{:<20} {:<28} {:<8} {}",
+            f.hostname.as_deref().unwrap_or("-"),
+            f.host,
+            f.asic_model.as_deref().unwrap_or("-"),
+            f.version.as_deref().unwrap_or("-"),
+        );
+    }
+
+    if add {
+        device::add_found(cfg, found)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cidr() {
+        let net = Ipv4Net::parse("192.168.1.77/24").unwrap();
+        assert_eq!(net, Ipv4Net { addr: Ipv4Addr::new(192, 168, 1, 77), prefix: 24 });
+
+        assert!(Ipv4Net::parse("192.168.1.0").is_err());
+        assert!(Ipv4Net::parse("192.168.1.0/33").is_err());
+        assert!(Ipv4Net::parse("192.168.300.0/24").is_err());
+        assert!(Ipv4Net::parse("fe80::/64").is_err());
+    }
+
+    #[test]
+    fn host_ranges() {
+        let hosts = Ipv4Net::parse("10.0.0.9/24").unwrap().hosts();
+        assert_eq!(hosts.len(), 254);
+        assert_eq!(hosts[0], Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(hosts[253], Ipv4Addr::new(10, 0, 0, 254));
+
+        assert_eq!(Ipv4Net::parse("10.0.0.9/32").unwrap().hosts(), [Ipv4Addr::new(10, 0, 0, 9)]);
+        assert_eq!(Ipv4Net::parse("10.0.0.0/31").unwrap().hosts().len(), 2);
+        assert_eq!(Ipv4Net::parse("10.0.0.0/22").unwrap().hosts().len(), 1022);
+    }
+}
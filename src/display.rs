@@ -0,0 +1,139 @@
+use anyhow::{Result, bail};
+use clap::Args;
+use reqwest::Client;
+use serde_json::{Map, Value, json};
+use crate::{fetch_info, get_number, get_str, patch_system};
+
+#[derive(Args, Debug)]
+pub struct DisplayArgs {
+    /// Keep the screen on (it never sleeps)
+    #[arg(long, conflicts_with = "off")]
+    on: bool,
+
+    /// Turn the screen off
+    #[arg(long)]
+    off: bool,
+
+    /// Screen rotation: 0, 90, 180 or 270
+    #[arg(long, value_name = "DEG", value_parser = parse_rotation)]
+    rotate: Option<u16>,
+}
+
+fn parse_rotation(s: &str) -> Result<u16, String> {
+    match s.trim_end_matches('°').parse() {
+        Ok(deg @ (0 | 90 | 180 | 270)) => Ok(deg),
+        _ => Err(format!("invalid rotation '{s}' (use 0, 90, 180 or 270)")),
+    }
+}
+
+/// Without flags, show the display settings; otherwise PATCH the ones the
+/// firmware reports in /api/system/info
+pub async fn run(args: &DisplayArgs, client: &Client, host: &str) -> Result<()> {
+    let info = fetch_info(client, host).await?;
+    if !args.on && !args.off && args.rotate.is_none() {
+        let lines = describe(&info);
+        if lines.is_empty() {
+            bail!("Firmware {} doesn't report any display settings", version(&info));
+        }
+        for (label, value) in lines {
+            println!("{label:<10} {value}");
+        }
+        return Ok(());
+    }
+
+    let (body, summary) = changes(args, &info)?;
+    patch_system(client, host, &Value::Object(body)).await?;
+    println!("Display updated: {}", summary.join(", "));
+    Ok(())
+}
+
+/// The PATCH body for the requested changes, with a description of each.
+/// AxeOS keeps the screen awake for `displayTimeout` minutes (-1: always,
+/// 0: off); older builds can only flip it (`flipscreen`) rather than rotate.
+fn changes(args: &DisplayArgs, info: &Value) -> Result<(Map<String, Value>, Vec<String>)> {
+    let mut body = Map::new();
+    let mut summary = Vec::new();
+    if args.on || args.off {
+        if info.get("displayTimeout").is_none() {
+            bail!("Firmware {} can't turn the display on or off (no displayTimeout setting)", version(info));
+        }
+        body.insert("displayTimeout".into(), json!(if args.on { -1 } else { 0 }));
+        summary.push(if args.on { "screen on" } else { "screen off" }.to_string());
+    }
+    if let Some(deg) = args.rotate {
+        if info.get("rotation").is_some() {
+            body.insert("rotation".into(), json!(deg));
+        } else if info.get("flipscreen").is_some() {
+            if deg % 180 != 0 {
+                bail!("Firmware {} can only flip the display (--rotate 0 or 180)", version(info));
+            }
+            body.insert("flipscreen".into(), json!(u8::from(deg == 180)));
+        } else {
+            bail!("Firmware {} can't rotate the display (no rotation setting)", version(info));
+        }
+        summary.push(format!("rotation {deg}°"));
+    }
+    Ok((body, summary))
+}
+
+/// (label, value) for each display setting the miner reports
+fn describe(info: &Value) -> Vec<(&'static str, String)> {
+    let mut lines = Vec::new();
+    if let Some(model) = get_str(info, "display") {
+        lines.push(("Display", model.to_string()));
+    }
+    match (get_number(info, "rotation"), get_number(info, "flipscreen")) {
+        (Some(deg), _) => lines.push(("Rotation", format!("{deg}°"))),
+        (None, Some(flip)) => lines.push(("Rotation", if flip != 0.0 { "180°" } else { "0°" }.to_string())),
+        (None, None) => {}
+    }
+    if let Some(invert) = get_number(info, "invertscreen") {
+        lines.push(("Inverted", if invert != 0.0 { "yes" } else { "no" }.to_string()));
+    }
+    if let Some(minutes) = get_number(info, "displayTimeout") {
+        let state = match minutes {
+            m if m < 0.0 => "always on".to_string(),
+            0.0 => "off".to_string(),
+            m => format!("sleeps after {m} min"),
+        };
+        lines.push(("Screen", state));
+    }
+    lines
+}
+
+fn version(info: &Value) -> &str {
+    get_str(info, "version").unwrap_or("unknown")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(on: bool, off: bool, rotate: Option<u16>) -> DisplayArgs {
+        DisplayArgs { on, off, rotate }
+    }
+
+    #[test]
+    fn patches_the_fields_the_firmware_has() {
+        let info = json!({"displayTimeout": 5, "rotation": 0});
+        let (body, summary) = changes(&args(false, true, Some(90)), &info).unwrap();
+        assert_eq!(Value::Object(body), json!({"displayTimeout": 0, "rotation": 90}));
+        assert_eq!(summary, ["screen off", "rotation 90°"]);
+
+        // Older builds only flip
+        let (body, _) = changes(&args(true, false, Some(180)), &json!({"displayTimeout": 0, "flipscreen": 0})).unwrap();
+        assert_eq!(Value::Object(body), json!({"displayTimeout": -1, "flipscreen": 1}));
+    }
+
+    #[test]
+    fn unsupported_settings_are_reported() {
+        let info = json!({"flipscreen": 1, "version": "v2.0.0"});
+        let err = changes(&args(true, false, None), &info).unwrap_err().to_string();
+        assert!(err.contains("v2.0.0 can't turn the display on or off"), "{err}");
+        let err = changes(&args(false, false, Some(90)), &info).unwrap_err().to_string();
+        assert!(err.contains("can only flip"), "{err}");
+        assert!(changes(&args(false, false, Some(0)), &json!({})).is_err());
+        assert!(parse_rotation("45").is_err());
+        assert_eq!(parse_rotation("270°"), Ok(270));
+    }
+}
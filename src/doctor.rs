@@ -0,0 +1,139 @@
+use std::time::Instant;
+use anyhow::{Result, bail};
+use serde_json::Value;
+use crate::client::ClientOptions;
+use crate::color::{GREEN, RED, YELLOW, paint};
+use crate::config_file::{UnsetVar, config_path, load_config};
+use crate::schema::Drift;
+use crate::{Cli, resolve_targets, runtime};
+
+enum Outcome {
+    Pass,
+    /// Worth knowing, but the CLI still works
+    Warn,
+    Fail,
+}
+
+struct Report {
+    colorize: bool,
+}
+
+impl Report {
+    fn check(&self, outcome: Outcome, name: &str, detail: &str, hint: Option<&str>) {
+        let label = match outcome {
+            Outcome::Pass => paint(self.colorize, GREEN, "PASS"),
+            Outcome::Warn => paint(self.colorize, YELLOW, "WARN"),
+            Outcome::Fail => paint(self.colorize, RED, "FAIL"),
+        };
+        println!("{label}  {name:<14} {detail}");
+        if let Some(hint) = hint {
+            println!("      hint: {hint}");
+        }
+    }
+}
+
+/// Walk through config, host resolution and the API, stopping at the first
+/// critical failure since later checks depend on it
+pub fn run(cli: &Cli, opts: &ClientOptions, colorize: bool) -> Result<()> {
+    let report = Report { colorize };
+
+    let cfg = match (config_path(), load_config()) {
+        (Some(path), Ok(cfg)) if path.exists() => {
+            let detail = format!("{} ({} devices)", path.display(), cfg.devices.len());
+            report.check(Outcome::Pass, "Config file", &detail, None);
+            cfg
+        }
+        (path, Ok(cfg)) => {
+            let shown = path.map_or("~/.config/bitaxe-cli/config.toml".to_string(), |p| p.display().to_string());
+            report.check(
+                Outcome::Warn,
+                "Config file",
+                &format!("{shown} not found"),
+                Some("optional with --host or BITAXE_URL; `device add <name> <host>` creates it"),
+            );
+            cfg
+        }
+        (_, Err(e)) if e.downcast_ref::<UnsetVar>().is_some() => {
+            let hint = Some("export the variable, or write the value in");
+            report.check(Outcome::Fail, "Config file", &format!("{e:#}"), hint);
+            bail!("doctor found problems");
+        }
+        (_, Err(e)) => {
+            report.check(Outcome::Fail, "Config file", &format!("can't be parsed: {e:#}"), Some("fix the file syntax (TOML, YAML or JSON by extension)"));
+            bail!("doctor found problems");
+        }
+    };
+
+    let target = match resolve_targets(cli, &cfg, opts) {
+        Ok(targets) => {
+            let detail = match targets.len() {
+                1 => targets[0].host.clone(),
+                n => format!("{} (checking the first of {n})", targets[0].host),
+            };
+            report.check(Outcome::Pass, "Host", &detail, None);
+            targets.into_iter().next().expect("resolve_targets never returns an empty list")
+        }
+        Err(e) => {
+            report.check(
+                Outcome::Fail,
+                "Host",
+                &format!("{e:#}"),
+                Some("add a miner with `bitaxe-cli device add <name> <host>`"),
+            );
+            bail!("doctor found problems");
+        }
+    };
+
+    let url = format!("{}/api/system/info", target.host);
+    let start = Instant::now();
+    let resp = match runtime().block_on(target.client.get(&url).send()) {
+        Ok(resp) => {
+            let ms = start.elapsed().as_secs_f64() * 1000.0;
+            report.check(Outcome::Pass, "Reachable", &format!("answered in {ms:.0}ms"), None);
+            resp
+        }
+        Err(e) => {
+            let hint = if e.is_timeout() {
+                "is the miner powered on and on the same network?"
+            } else {
+                "check the address and port; AxeOS serves plain http://"
+            };
+            report.check(Outcome::Fail, "Reachable", &format!("{e}"), Some(hint));
+            bail!("doctor found problems");
+        }
+    };
+
+    let status = resp.status();
+    if !status.is_success() {
+        report.check(Outcome::Fail, "System info", &format!("HTTP {status}"), Some("is this an AxeOS device?"));
+        bail!("doctor found problems");
+    }
+    let info: Value = match runtime().block_on(resp.json()) {
+        Ok(info) => {
+            report.check(Outcome::Pass, "System info", "HTTP 200, valid JSON", None);
+            info
+        }
+        Err(e) => {
+            report.check(
+                Outcome::Fail,
+                "System info",
+                &format!("not JSON: {e}"),
+                Some("another web server may be answering at this address"),
+            );
+            bail!("doctor found problems");
+        }
+    };
+
+    let drift = Drift::of(&info);
+    if drift.missing.is_empty() {
+        report.check(Outcome::Pass, "Fields", "all expected fields present", None);
+    } else {
+        report.check(
+            Outcome::Warn,
+            "Fields",
+            &drift.describe(),
+            Some("older, newer or forked firmware; affected values show as N/A"),
+        );
+    }
+    Ok(())
+}
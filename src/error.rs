@@ -0,0 +1,277 @@
+use std::error::Error as _;
+use std::sync::atomic::{AtomicBool, Ordering};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use thiserror::Error;
+use crate::client::bare_host;
+use crate::retry::Failed;
+
+/// Exit code for generic failures
+pub const EXIT_FAILURE: u8 = 1;
+/// Exit code when no host could be resolved or the config file is unusable
+pub const EXIT_CONFIG: u8 = 3;
+/// Exit code when the miner couldn't be reached (connect/timeout/DNS errors)
+pub const EXIT_NETWORK: u8 = 4;
+/// Exit code when the miner answered with an HTTP error
+pub const EXIT_HTTP: u8 = 5;
+/// Exit code when the host answered with something that isn't AxeOS JSON
+pub const EXIT_NOT_BITAXE: u8 = 6;
+/// Exit code when the firmware doesn't have the endpoint
+pub const EXIT_UNSUPPORTED: u8 = 7;
+
+/// Set when the command line asks for `--json`, so failures come out as
+/// JSON on stderr too
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_json(on: bool) {
+    JSON.store(on, Ordering::Relaxed);
+}
+
+pub fn json_mode() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Failures worth telling apart. Each has its own exit code and, where
+/// there is something to try, a hint printed after the error.
+#[derive(Debug, Error)]
+pub enum BitaxeError {
+    #[error("Couldn't connect to {host}{}", tries(*attempts))]
+    Refused { host: String, attempts: u32, source: reqwest::Error },
+    #[error("{host} didn't answer in time{}", tries(*attempts))]
+    Timeout { host: String, attempts: u32, source: reqwest::Error },
+    #[error("Couldn't resolve {host}{}", tries(*attempts))]
+    Dns { host: String, attempts: u32, source: reqwest::Error },
+    #[error("Request to {host} failed{}", tries(*attempts))]
+    Transport { host: String, attempts: u32, source: reqwest::Error },
+    /// `what` names the operation ("Restart", "Update", ...)
+    #[error(
+        "{what} failed with status {status}{}{}",
+        tries(*attempts),
+        detail.as_ref().map(|d| format!(": {d}")).unwrap_or_default()
+    )]
+    Status { what: String, status: StatusCode, detail: Option<String>, attempts: u32 },
+    #[error("{host} responded but didn't return Bitaxe JSON — is this the right device?")]
+    NotBitaxe { host: String, source: bitaxe_api::Unexpected },
+    #[error("{what} is not supported on firmware {version}")]
+    Unsupported { what: &'static str, version: String },
+    /// Library failures without a better home here
+    #[error("Request to {host} failed")]
+    Api { host: String, source: bitaxe_api::Error },
+    #[error("No host configured. Use --host, set BITAXE_URL, or create ~/.config/bitaxe-cli/config.toml")]
+    NoHost,
+    /// Attached as context to the reason the config file didn't load
+    #[error("Couldn't load the config file")]
+    Config,
+}
+
+impl BitaxeError {
+    /// Sort a failed request to `host` by what went wrong
+    pub fn transport(host: &str, source: reqwest::Error) -> Self {
+        BitaxeError::retried(host, Failed { source, attempts: 1 })
+    }
+
+    /// `transport` for a request `retry::send` gave up on
+    pub fn retried(host: &str, failed: Failed) -> Self {
+        let Failed { source, attempts } = failed;
+        let host = bare_host(host).to_string();
+        if source.is_timeout() {
+            BitaxeError::Timeout { host, attempts, source }
+        } else if is_dns(&source) {
+            BitaxeError::Dns { host, attempts, source }
+        } else if source.is_connect() {
+            BitaxeError::Refused { host, attempts, source }
+        } else {
+            BitaxeError::Transport { host, attempts, source }
+        }
+    }
+
+    /// A library error for a call to `host`; `what` as for `Status`
+    pub fn from_api(host: &str, what: &str, e: bitaxe_api::Error) -> Self {
+        BitaxeError::api_retried(host, what, e, 1)
+    }
+
+    /// `from_api` for a call `retry::call` gave up on after `attempts` tries
+    pub fn api_retried(host: &str, what: &str, e: bitaxe_api::Error, attempts: u32) -> Self {
+        match e {
+            bitaxe_api::Error::Timeout { source, .. }
+            | bitaxe_api::Error::Connect { source, .. }
+            | bitaxe_api::Error::Http(source) => BitaxeError::retried(host, Failed { source, attempts }),
+            bitaxe_api::Error::Status { status, detail } => {
+                BitaxeError::Status { what: what.to_string(), status, detail, attempts }
+            }
+            bitaxe_api::Error::NotBitaxe(source) => {
+                BitaxeError::NotBitaxe { host: bare_host(host).to_string(), source }
+            }
+            source => BitaxeError::Api { host: bare_host(host).to_string(), source },
+        }
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            BitaxeError::Refused { .. }
+            | BitaxeError::Timeout { .. }
+            | BitaxeError::Dns { .. }
+            | BitaxeError::Transport { .. } => EXIT_NETWORK,
+            BitaxeError::Status { .. } => EXIT_HTTP,
+            BitaxeError::NotBitaxe { .. } => EXIT_NOT_BITAXE,
+            BitaxeError::Unsupported { .. }
+            | BitaxeError::Api { source: bitaxe_api::Error::Unsupported { .. }, .. } => EXIT_UNSUPPORTED,
+            BitaxeError::Api { .. } => EXIT_FAILURE,
+            BitaxeError::NoHost | BitaxeError::Config => EXIT_CONFIG,
+        }
+    }
+
+    /// Name in the `--json` error object
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BitaxeError::Refused { .. } => "connection_refused",
+            BitaxeError::Timeout { .. } => "timeout",
+            BitaxeError::Dns { .. } => "dns",
+            BitaxeError::Transport { .. } => "transport",
+            BitaxeError::Status { .. } => "http_status",
+            BitaxeError::NotBitaxe { .. } => "not_bitaxe",
+            BitaxeError::Unsupported { .. } => "unsupported",
+            BitaxeError::Api { .. } => "api",
+            BitaxeError::NoHost => "no_host",
+            BitaxeError::Config => "config",
+        }
+    }
+
+    /// What to try next, when there is something
+    pub fn hint(&self) -> Option<String> {
+        let hint = match self {
+            BitaxeError::Refused { .. } | BitaxeError::Transport { .. } => {
+                "is the host reachable? try `bitaxe-cli ping`".to_string()
+            }
+            BitaxeError::Timeout { .. } => {
+                "the miner may be busy or switched off; try `bitaxe-cli ping`, or allow more with --timeout".to_string()
+            }
+            BitaxeError::Dns { host, .. } => {
+                format!(
+                    "check the spelling of '{host}', or use the miner's IP address (`bitaxe-cli discover` finds it)"
+                )
+            }
+            BitaxeError::Status { status, .. } => match *status {
+                StatusCode::NOT_FOUND | StatusCode::NOT_IMPLEMENTED => {
+                    "the host speaks HTTP but has no AxeOS API there: check --host and --api-base, \
+                     or the firmware may be too old for this command"
+                        .to_string()
+                }
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                    "the miner, or a proxy in front of it, wants credentials: set `username` and `password` \
+                     for the device (`bitaxe-cli device`)"
+                        .to_string()
+                }
+                s if s.is_server_error() => "the miner had an internal error; try again, or restart it".to_string(),
+                _ => return None,
+            },
+            BitaxeError::NotBitaxe { .. } => {
+                "check that --host points at the miner and not a router or other device \
+                 (`bitaxe-cli discover` lists the miners on the network)"
+                    .to_string()
+            }
+            BitaxeError::Unsupported { version, .. } => {
+                format!("this needs a newer AxeOS than {version}; update the miner's firmware")
+            }
+            BitaxeError::Api { .. } => return None,
+            BitaxeError::NoHost => "add a miner with `bitaxe-cli device add <name> <host>`".to_string(),
+            BitaxeError::Config => "`bitaxe-cli doctor` checks the config file".to_string(),
+        };
+        Some(hint)
+    }
+}
+
+/// ` after 3 attempts` when retries were made
+fn tries(attempts: u32) -> String {
+    if attempts > 1 { format!(" after {attempts} attempts") } else { String::new() }
+}
+
+/// DNS failures are connect errors to reqwest; only the message of the
+/// underlying resolver error tells them apart
+fn is_dns(e: &reqwest::Error) -> bool {
+    let mut cause = e.source();
+    while let Some(c) = cause {
+        if c.to_string().contains("dns error") {
+            return true;
+        }
+        cause = c.source();
+    }
+    false
+}
+
+/// The structured failure behind `e`, if there is one
+pub fn find(e: &anyhow::Error) -> Option<&BitaxeError> {
+    e.downcast_ref::<BitaxeError>().or_else(|| e.chain().find_map(|c| c.downcast_ref::<BitaxeError>()))
+}
+
+/// Map an error to an exit code so scripts can tell failure modes apart.
+/// Requests made outside the paths that build a `BitaxeError` still count
+/// as network failures when reqwest says so.
+pub fn exit_code(e: &anyhow::Error) -> u8 {
+    if let Some(e) = find(e) {
+        return e.exit_code();
+    }
+    let network = e.chain().any(|cause| {
+        cause
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|re| re.is_connect() || re.is_timeout() || re.is_request())
+    });
+    if network { EXIT_NETWORK } else { EXIT_FAILURE }
+}
+
+/// What `--json` commands print on stderr instead of the text error
+pub fn json_object(e: &anyhow::Error) -> Value {
+    let found = find(e);
+    let causes: Vec<String> = e.chain().skip(1).map(|c| c.to_string()).collect();
+    json!({
+        "error": {
+            "kind": found.map_or("error", |f| f.kind()),
+            "message": e.to_string(),
+            "causes": causes,
+            "hint": found.and_then(|f| f.hint()),
+            "exitCode": exit_code(e),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    fn status(code: u16) -> BitaxeError {
+        let status = StatusCode::from_u16(code).unwrap();
+        BitaxeError::Status { what: "Request".into(), status, detail: None, attempts: 1 }
+    }
+
+    #[test]
+    fn statuses_get_their_own_hints() {
+        assert!(status(404).hint().unwrap().contains("firmware may be too old"));
+        assert!(status(401).hint().unwrap().contains("credentials"));
+        assert!(status(503).hint().unwrap().contains("internal error"));
+        assert_eq!(status(400).hint(), None);
+        assert_eq!(status(404).exit_code(), EXIT_HTTP);
+        let (what, status, detail) = ("Update".into(), StatusCode::BAD_REQUEST, Some("no".into()));
+        let detail = BitaxeError::Status { what, status, detail, attempts: 1 };
+        assert_eq!(detail.to_string(), "Update failed with status 400 Bad Request: no");
+        let (what, status) = ("Request".into(), StatusCode::BAD_GATEWAY);
+        let retried = BitaxeError::Status { what, status, detail: None, attempts: 3 };
+        assert_eq!(retried.to_string(), "Request failed with status 502 Bad Gateway after 3 attempts");
+    }
+
+    #[test]
+    fn found_under_context() {
+        let e = anyhow::Error::new(BitaxeError::NoHost).context("while resolving");
+        assert_eq!(exit_code(&e), EXIT_CONFIG);
+        let out = json_object(&Err::<(), _>(BitaxeError::Config).context("unused").unwrap_err());
+        assert_eq!(out["error"]["kind"], "config");
+        assert_eq!(out["error"]["exitCode"], EXIT_CONFIG);
+
+        let plain = anyhow::anyhow!("Invalid frequency");
+        assert_eq!(exit_code(&plain), EXIT_FAILURE);
+        let out = json_object(&plain);
+        assert_eq!(out, json!({"error": {
+            "kind": "error", "message": "Invalid frequency", "causes": [], "hint": null, "exitCode": 1,
+        }}));
+    }
+}
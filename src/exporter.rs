@@ -0,0 +1,479 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use crate::client::Target;
+use crate::config_file::AppConfig;
+use crate::fleet::{DeviceResult, FleetOpts, fetch_fleet, poll_fleet};
+use crate::rules::{self, RuleStatus, State};
+use crate::shares::Stats;
+use crate::{get_number, parse_duration, runtime};
+
+/// How long a scraper gets to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Args, Debug)]
+pub struct ExporterArgs {
+    /// Address to serve /metrics on
+    #[arg(long, default_value = "127.0.0.1:9100")]
+    listen: String,
+
+    /// Scrapes closer together than this reuse the previous poll
+    #[arg(long, default_value = "5s", value_parser = parse_duration)]
+    min_interval: Duration,
+
+    #[command(flatten)]
+    pub opts: FleetOpts,
+}
+
+#[derive(Args, Debug)]
+pub struct MetricsArgs {
+    /// File for node_exporter's textfile collector (default: stdout).
+    /// Written to a temp file and renamed, so it is never read half-written.
+    #[arg(long, value_name = "PATH")]
+    textfile: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub opts: FleetOpts,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Gauge,
+    Counter,
+}
+
+/// One exported metric family. Names and help strings are part of the
+/// interface people build dashboards on, so don't rename them.
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    kind: Kind,
+    /// (extra label, AxeOS key) per sample
+    samples: &'static [(Option<(&'static str, &'static str)>, &'static str)],
+}
+
+const FAMILIES: &[Family] = &[
+    Family {
+        name: "bitaxe_hashrate_ghs",
+        help: "Current hashrate in GH/s",
+        kind: Kind::Gauge,
+        samples: &[(None, "hashRate")],
+    },
+    Family {
+        name: "bitaxe_temperature_celsius",
+        help: "Temperature in degrees Celsius",
+        kind: Kind::Gauge,
+        samples: &[(Some(("sensor", "core")), "temp"), (Some(("sensor", "vr")), "vrTemp")],
+    },
+    Family {
+        name: "bitaxe_power_watts",
+        help: "Power draw in watts",
+        kind: Kind::Gauge,
+        samples: &[(None, "power")],
+    },
+    Family {
+        name: "bitaxe_frequency_mhz",
+        help: "ASIC frequency in MHz",
+        kind: Kind::Gauge,
+        samples: &[(None, "frequency")],
+    },
+    Family {
+        name: "bitaxe_shares_total",
+        help: "Shares submitted since boot",
+        kind: Kind::Counter,
+        samples: &[(Some(("result", "accepted")), "sharesAccepted"), (Some(("result", "rejected")), "sharesRejected")],
+    },
+    Family {
+        name: "bitaxe_uptime_seconds",
+        help: "Seconds since the miner booted",
+        kind: Kind::Gauge,
+        samples: &[(None, "uptimeSeconds")],
+    },
+    Family {
+        name: "bitaxe_wifi_rssi_dbm",
+        help: "WiFi signal strength in dBm",
+        kind: Kind::Gauge,
+        samples: &[(None, "wifiRSSI")],
+    },
+];
+
+/// Serve /metrics until the process is killed. Each scrape polls every
+/// target (at most once per --min-interval); an unreachable device shows up
+/// as `bitaxe_up 0` instead of failing the scrape. [alerts.rules] are
+/// evaluated on every poll and exported as `bitaxe_alert_state`; sending
+/// notifications is left to Alertmanager or a `watch`.
+pub fn run(args: &ExporterArgs, targets: &[Target], cfg: &AppConfig) -> Result<()> {
+    let engine = rules::Engine::from_config(cfg)?;
+    runtime().block_on(async {
+        let listener = TcpListener::bind(&args.listen)
+            .await
+            .with_context(|| format!("Failed to listen on {}", args.listen))?;
+        eprintln!("Serving metrics for {} device(s) on http://{}/metrics", targets.len(), listener.local_addr()?);
+        let scrapes = Arc::new(tokio::sync::Mutex::new(Scrapes { engine, cache: None }));
+        accept(listener, |stream| {
+            let (scrapes, targets) = (scrapes.clone(), targets.to_vec());
+            let (min_interval, concurrency) = (args.min_interval, args.opts.concurrency as usize);
+            // Scrapes queue up on the lock, so one that arrives mid-poll
+            // gets that poll's result
+            handle(stream, async move {
+                let mut scrapes = scrapes.lock().await;
+                if let Some((at, body)) = &scrapes.cache
+                    && at.elapsed() < min_interval
+                {
+                    return body.clone();
+                }
+                let body = exposition(&poll_fleet(targets, concurrency).await, &mut scrapes.engine);
+                scrapes.cache = Some((Instant::now(), body.clone()));
+                body
+            })
+        })
+        .await
+    })
+}
+
+/// What successive scrapes of `run` share
+struct Scrapes {
+    engine: rules::Engine,
+    cache: Option<(Instant, String)>,
+}
+
+/// Answer every connection on a task of its own, from what `handler` makes of it
+async fn accept<F>(listener: TcpListener, handler: impl Fn(TcpStream) -> F) -> Result<()>
+where
+    F: Future<Output = Result<()>> + Send + 'static,
+{
+    loop {
+        let Ok((stream, _)) = listener.accept().await else { continue };
+        let answer = handler(stream);
+        tokio::spawn(async move {
+            if let Err(e) = answer.await {
+                eprintln!("Warning: failed to answer a scrape: {e}");
+            }
+        });
+    }
+}
+
+/// Metrics for one poll, with the share rate, reboot count and alert rule states after
+/// feeding it to `engine`
+pub fn exposition(results: &[DeviceResult], engine: &mut rules::Engine) -> String {
+    for r in results {
+        engine.observe(&r.name, r.info.as_ref().ok(), r.fetched_at);
+    }
+    render(results)
+        + &render_shares(results, engine)
+        + &render_reboots(results, engine)
+        + &render_rules(&engine.states())
+}
+
+/// Serve /metrics from the runtime for a loop that polls on its own
+/// schedule (`daemon`); scrapes get whatever was last stored in the returned text
+pub fn serve(listen: &str) -> Result<Arc<Mutex<String>>> {
+    let listener = runtime().block_on(TcpListener::bind(listen));
+    let listener = listener.with_context(|| format!("Failed to listen on {listen}"))?;
+    eprintln!("Serving metrics on http://{}/metrics", listener.local_addr()?);
+    let body = Arc::new(Mutex::new(String::new()));
+    let shared = body.clone();
+    runtime().spawn(accept(listener, move |stream| {
+        let shared = shared.clone();
+        handle(stream, async move { shared.lock().map(|b| b.clone()).unwrap_or_default() })
+    }));
+    Ok(body)
+}
+
+/// `metrics`: poll once and write the exposition. Fails when no device
+/// answered, after writing, so the `bitaxe_up 0` lines still land.
+pub fn write_once(args: &MetricsArgs, targets: &[Target]) -> Result<()> {
+    let results = fetch_fleet(targets, &args.opts);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+    let mut text = render(&results);
+    text += "# HELP bitaxe_scrape_timestamp_seconds When this file was written (Unix time)\n";
+    text += "# TYPE bitaxe_scrape_timestamp_seconds gauge\n";
+    text += &format!("bitaxe_scrape_timestamp_seconds {now:.3}\n");
+
+    match &args.textfile {
+        Some(path) => write_atomic(path, &text)?,
+        None => print!("{text}"),
+    }
+    if results.iter().all(|r| r.info.is_err()) {
+        bail!("None of the {} device(s) could be reached", results.len());
+    }
+    Ok(())
+}
+
+/// Write next to `path` and rename over it. The temp name doesn't end in
+/// .prom, so the collector ignores it meanwhile.
+pub fn write_atomic(path: &Path, text: &str) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    {
+        let mut f = File::create(&tmp).with_context(|| format!("Failed to create {}", tmp.display()))?;
+        f.write_all(text.as_bytes())?;
+        f.sync_all()?;
+    }
+    fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+/// Answer one HTTP request; `metrics` is only awaited for GET /metrics
+async fn handle(mut stream: TcpStream, metrics: impl Future<Output = String>) -> Result<()> {
+    let (read, mut write) = stream.split();
+    let request = async {
+        let mut reader = BufReader::new(read);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await?;
+        // Skip the headers; nothing in them matters here
+        let mut line = String::new();
+        while reader.read_line(&mut line).await? > 2 {
+            line.clear();
+        }
+        Ok::<_, std::io::Error>(request_line)
+    };
+    let request_line = tokio::time::timeout(READ_TIMEOUT, request).await.context("Timed out reading the request")??;
+
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let path = path.split('?').next().unwrap_or("");
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", "text/plain; version=0.0.4; charset=utf-8", metrics.await),
+        ("GET", "/") => ("200 OK", "text/plain; charset=utf-8", "bitaxe-cli exporter: see /metrics\n".to_string()),
+        _ => ("404 Not Found", "text/plain; charset=utf-8", "not found\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    write.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Prometheus text exposition format for one poll of the fleet
+fn render(results: &[DeviceResult]) -> String {
+    let mut out = String::new();
+    out += "# HELP bitaxe_up Whether the last poll of the device succeeded\n";
+    out += "# TYPE bitaxe_up gauge\n";
+    for r in results {
+        out += &format!("bitaxe_up{{device=\"{}\"}} {}\n", escape_label(&r.name), u8::from(r.info.is_ok()));
+    }
+
+    let reachable: Vec<(&str, &Value)> = results.iter().filter_map(|r| Some((r.name.as_str(), r.info.as_ref().ok()?))).collect();
+    for family in FAMILIES {
+        let mut lines = Vec::new();
+        for (device, info) in &reachable {
+            for (label, key) in family.samples {
+                let Some(value) = get_number(info, key) else { continue };
+                let extra = label.map(|(k, v)| format!(",{k}=\"{v}\"")).unwrap_or_default();
+                lines.push(format!("{}{{device=\"{}\"{extra}}} {value}\n", family.name, escape_label(device)));
+            }
+        }
+        if lines.is_empty() {
+            continue;
+        }
+        let kind = match family.kind {
+            Kind::Gauge => "gauge",
+            Kind::Counter => "counter",
+        };
+        out += &format!("# HELP {} {}\n# TYPE {} {kind}\n", family.name, family.help, family.name);
+        out.extend(lines);
+    }
+    out
+}
+
+/// Gauges worked out over successive polls, for the devices that answered this one
+fn render_shares(results: &[DeviceResult], engine: &rules::Engine) -> String {
+    let stats: Vec<(&str, Stats)> = results
+        .iter()
+        .filter(|r| r.info.is_ok())
+        .filter_map(|r| Some((r.name.as_str(), engine.shares(&r.name)?)))
+        .collect();
+    let mut out = String::new();
+    let rates: Vec<(&str, f64)> = stats.iter().filter_map(|(d, s)| Some((*d, s.per_minute?))).collect();
+    if !rates.is_empty() {
+        out += "# HELP bitaxe_share_rate_per_minute Accepted shares per minute over the last 10 minutes\n";
+        out += "# TYPE bitaxe_share_rate_per_minute gauge\n";
+        for (device, rate) in rates {
+            out += &format!("bitaxe_share_rate_per_minute{{device=\"{}\"}} {rate}\n", escape_label(device));
+        }
+    }
+    if !stats.is_empty() {
+        out += "# HELP bitaxe_last_share_age_seconds Seconds since the accepted share count last went up\n";
+        out += "# TYPE bitaxe_last_share_age_seconds gauge\n";
+        for (device, s) in stats {
+            out += &format!(
+                "bitaxe_last_share_age_seconds{{device=\"{}\"}} {}\n",
+                escape_label(device),
+                s.since_last.as_secs()
+            );
+        }
+    }
+    out
+}
+
+/// Reboots nobody asked for since the exporter started, for the devices
+/// that answered this poll
+fn render_reboots(results: &[DeviceResult], engine: &rules::Engine) -> String {
+    let counts: Vec<(&str, u64)> = results
+        .iter()
+        .filter(|r| r.info.is_ok())
+        .filter_map(|r| Some((r.name.as_str(), engine.unexpected_reboots(&r.name)?)))
+        .collect();
+    if counts.is_empty() {
+        return String::new();
+    }
+    let mut out = String::new();
+    out += "# HELP bitaxe_reboots_total Reboots not requested by bitaxe-cli, seen by uptime going backwards\n";
+    out += "# TYPE bitaxe_reboots_total counter\n";
+    for (device, n) in counts {
+        out += &format!("bitaxe_reboots_total{{device=\"{}\"}} {n}\n", escape_label(device));
+    }
+    out
+}
+
+/// One 0/1/2 gauge per (rule, device) the engine has evaluated
+fn render_rules(states: &[RuleStatus]) -> String {
+    if states.is_empty() {
+        return String::new();
+    }
+    let mut out = String::new();
+    out += "# HELP bitaxe_alert_state Alert rule state: 0 inactive or resolved, 1 pending, 2 firing\n";
+    out += "# TYPE bitaxe_alert_state gauge\n";
+    for s in states {
+        let value = match s.state {
+            State::Inactive | State::Resolved => 0,
+            State::Pending => 1,
+            State::Firing => 2,
+        };
+        out += &format!(
+            "bitaxe_alert_state{{device=\"{}\",rule=\"{}\",severity=\"{}\"}} {value}\n",
+            escape_label(&s.device),
+            escape_label(&s.rule),
+            s.severity.name()
+        );
+    }
+    out
+}
+
+/// Label values escape backslashes, quotes and newlines
+fn escape_label(v: &str) -> String {
+    v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::alert::Severity;
+    use crate::fleet::result;
+
+    #[test]
+    fn metric_names_and_help_are_stable() {
+        let info = json!({
+            "hashRate": 512.5, "temp": 61, "vrTemp": 55.5, "power": 14.2, "frequency": 525,
+            "sharesAccepted": 1234, "sharesRejected": 5, "uptimeSeconds": 3600, "wifiRSSI": -55
+        });
+        let text = render(&[result("gamma", Ok(info))]);
+        assert_eq!(
+            text,
+            "# HELP bitaxe_up Whether the last poll of the device succeeded\n\
+             # TYPE bitaxe_up gauge\n\
+             bitaxe_up{device=\"gamma\"} 1\n\
+             # HELP bitaxe_hashrate_ghs Current hashrate in GH/s\n\
+             # TYPE bitaxe_hashrate_ghs gauge\n\
+             bitaxe_hashrate_ghs{device=\"gamma\"} 512.5\n\
+             # HELP bitaxe_temperature_celsius Temperature in degrees Celsius\n\
+             # TYPE bitaxe_temperature_celsius gauge\n\
+             bitaxe_temperature_celsius{device=\"gamma\",sensor=\"core\"} 61\n\
+             bitaxe_temperature_celsius{device=\"gamma\",sensor=\"vr\"} 55.5\n\
+             # HELP bitaxe_power_watts Power draw in watts\n\
+             # TYPE bitaxe_power_watts gauge\n\
+             bitaxe_power_watts{device=\"gamma\"} 14.2\n\
+             # HELP bitaxe_frequency_mhz ASIC frequency in MHz\n\
+             # TYPE bitaxe_frequency_mhz gauge\n\
+             bitaxe_frequency_mhz{device=\"gamma\"} 525\n\
+             # HELP bitaxe_shares_total Shares submitted since boot\n\
+             # TYPE bitaxe_shares_total counter\n\
+             bitaxe_shares_total{device=\"gamma\",result=\"accepted\"} 1234\n\
+             bitaxe_shares_total{device=\"gamma\",result=\"rejected\"} 5\n\
+             # HELP bitaxe_uptime_seconds Seconds since the miner booted\n\
+             # TYPE bitaxe_uptime_seconds gauge\n\
+             bitaxe_uptime_seconds{device=\"gamma\"} 3600\n\
+             # HELP bitaxe_wifi_rssi_dbm WiFi signal strength in dBm\n\
+             # TYPE bitaxe_wifi_rssi_dbm gauge\n\
+             bitaxe_wifi_rssi_dbm{device=\"gamma\"} -55\n"
+        );
+    }
+
+    #[test]
+    fn unreachable_devices_are_down_not_fatal() {
+        let text = render(&[result("dead", Err("timed out".into())), result("gamma", Ok(json!({"power": 14.0})))]);
+        assert!(text.contains("bitaxe_up{device=\"dead\"} 0\n"));
+        assert!(text.contains("bitaxe_up{device=\"gamma\"} 1\n"));
+        assert!(text.contains("bitaxe_power_watts{device=\"gamma\"} 14\n"));
+        assert!(!text.contains("device=\"dead\"} 14"));
+        // Families without any sample are left out entirely
+        assert!(!text.contains("bitaxe_hashrate_ghs"));
+    }
+
+    #[test]
+    fn rule_states_are_exported() {
+        let state = |rule: &str, state| RuleStatus {
+            rule: rule.into(),
+            device: "gamma".into(),
+            severity: Severity::Critical,
+            when: "temp > 68".into(),
+            state,
+            since: 0,
+            value: None,
+            updated: 0,
+        };
+        assert_eq!(render_rules(&[]), "");
+        assert_eq!(
+            render_rules(&[state("overheat", State::Firing), state("slow", State::Resolved)]),
+            "# HELP bitaxe_alert_state Alert rule state: 0 inactive or resolved, 1 pending, 2 firing\n\
+             # TYPE bitaxe_alert_state gauge\n\
+             bitaxe_alert_state{device=\"gamma\",rule=\"overheat\",severity=\"critical\"} 2\n\
+             bitaxe_alert_state{device=\"gamma\",rule=\"slow\",severity=\"critical\"} 0\n"
+        );
+    }
+
+    #[test]
+    fn share_rate_comes_from_successive_polls() {
+        let mut engine = rules::Engine::new(Vec::new(), Default::default());
+        let t0 = SystemTime::now();
+        let poll = |secs, accepted| DeviceResult {
+            fetched_at: t0 + Duration::from_secs(secs),
+            ..result("gamma", Ok(json!({"sharesAccepted": accepted})))
+        };
+        let first = exposition(&[poll(0, 10)], &mut engine);
+        assert!(!first.contains("bitaxe_share_rate_per_minute"));
+        assert!(first.contains("bitaxe_last_share_age_seconds{device=\"gamma\"} 0\n"));
+        let text = exposition(&[poll(120, 14)], &mut engine);
+        assert!(text.contains("bitaxe_share_rate_per_minute{device=\"gamma\"} 2\n"), "{text}");
+        let text = exposition(&[poll(180, 14)], &mut engine);
+        assert!(text.contains("bitaxe_last_share_age_seconds{device=\"gamma\"} 60\n"), "{text}");
+    }
+
+    #[test]
+    fn reboots_are_counted_from_uptime() {
+        let mut engine = rules::Engine::new(Vec::new(), Default::default());
+        let poll = |uptime| result("gamma", Ok(json!({"uptimeSeconds": uptime})));
+        assert!(exposition(&[poll(600)], &mut engine).contains("bitaxe_reboots_total{device=\"gamma\"} 0\n"));
+        exposition(&[poll(630)], &mut engine);
+        let text = exposition(&[poll(12)], &mut engine);
+        let counter = "# TYPE bitaxe_reboots_total counter\nbitaxe_reboots_total{device=\"gamma\"} 1\n";
+        assert!(text.contains(counter), "{text}");
+        assert!(!exposition(&[result("gamma", Err("timed out".into()))], &mut engine).contains("bitaxe_reboots_total"));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -0,0 +1,96 @@
+use serde_json::Value;
+
+/// One `--field KEY[=FORMAT]`, ex: `hashRate` or `hashRate=%.0f`
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldSpec {
+    pub key: String,
+    format: Format,
+}
+
+/// printf-style conversions accepted after `=`
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    /// As reported (numbers in plain decimal notation)
+    Raw,
+    /// `%.Nf`, and `%f` for 6 places
+    Fixed(usize),
+    /// `%d` / `%i`: rounded to a whole number
+    Int,
+}
+
+pub fn parse_field(s: &str) -> Result<FieldSpec, String> {
+    let (key, format) = match s.split_once('=') {
+        Some((key, spec)) => (key, parse_format(spec)?),
+        None => (s, Format::Raw),
+    };
+    if key.is_empty() {
+        return Err("missing key, ex: --field hashRate".into());
+    }
+    Ok(FieldSpec { key: key.to_string(), format })
+}
+
+fn parse_format(spec: &str) -> Result<Format, String> {
+    let bad = || format!("unsupported format '{spec}' (use %s, %d, %f or %.Nf)");
+    match spec {
+        "%s" => Ok(Format::Raw),
+        "%d" | "%i" => Ok(Format::Int),
+        "%f" => Ok(Format::Fixed(6)),
+        _ => {
+            let places = spec.strip_prefix("%.").and_then(|r| r.strip_suffix('f')).ok_or_else(bad)?;
+            places.parse().map(Format::Fixed).map_err(|_| bad())
+        }
+    }
+}
+
+impl FieldSpec {
+    /// The value as one line for scripts, `None` when the key is absent.
+    /// Numbers go through Rust's own formatting, never the locale: always
+    /// a `.` decimal point, no thousands separators, no exponent.
+    pub fn render(&self, info: &Value) -> Option<String> {
+        let value = info.get(&self.key)?;
+        let Some(n) = value.as_f64() else {
+            return Some(match value {
+                Value::String(s) => s.clone(),
+                Value::Null => String::new(),
+                other => other.to_string(),
+            });
+        };
+        Some(match self.format {
+            // Integers stay exact beyond f64's 53 bits
+            Format::Raw if value.is_i64() || value.is_u64() => value.to_string(),
+            Format::Raw => format!("{n}"),
+            Format::Fixed(places) => format!("{n:.places$}"),
+            Format::Int => format!("{:.0}", n.round()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(spec: &str, info: Value) -> Option<String> {
+        parse_field(spec).unwrap().render(&info)
+    }
+
+    #[test]
+    fn numbers_are_plain_decimal() {
+        assert_eq!(render("hashRate", json!({"hashRate": 1234567.5})).as_deref(), Some("1234567.5"));
+        assert_eq!(render("hashRate", json!({"hashRate": 1e21})).as_deref(), Some("1000000000000000000000"));
+        assert_eq!(render("uptime", json!({"uptime": 18446744073709551615u64})).as_deref(), Some("18446744073709551615"));
+        assert_eq!(render("hostname", json!({"hostname": "gamma"})).as_deref(), Some("gamma"));
+        assert_eq!(render("missing", json!({})), None);
+    }
+
+    #[test]
+    fn printf_style_formats() {
+        let info = json!({"hashRate": 512.567, "temp": 61});
+        assert_eq!(render("hashRate=%.0f", info.clone()).as_deref(), Some("513"));
+        assert_eq!(render("hashRate=%.1f", info.clone()).as_deref(), Some("512.6"));
+        assert_eq!(render("temp=%f", info.clone()).as_deref(), Some("61.000000"));
+        assert_eq!(render("hashRate=%d", info.clone()).as_deref(), Some("513"));
+        assert!(parse_field("hashRate=%x").is_err());
+        assert!(parse_field("=%d").is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use serde_json::Value;
+use crate::temperature;
+
+/// A numeric column of the fleet table: CLI name -> AxeOS key
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Field {
+    pub name: &'static str,
+    pub key: &'static str,
+}
+
+/// Named like the `rules::Metric` they read, so `--filter`, `--warn` and
+/// `check --metric` take the same words; `shares_accepted` has no metric
+pub const FIELDS: &[Field] = &[
+    Field { name: "hashrate", key: "hashRate" },
+    Field { name: "temp", key: "temp" },
+    Field { name: "vr_temp", key: "vrTemp" },
+    Field { name: "power", key: "power" },
+    Field { name: "frequency", key: "frequency" },
+    Field { name: "shares_accepted", key: "sharesAccepted" },
+    Field { name: "shares_rejected", key: "sharesRejected" },
+    Field { name: "uptime", key: "uptimeSeconds" },
+    Field { name: "wifi_rssi", key: "wifiRSSI" },
+];
+
+/// Names the fleet table used before it took the metric ones
+const ALIASES: &[(&str, &str)] =
+    &[("vrtemp", "vr_temp"), ("accepted", "shares_accepted"), ("rejected", "shares_rejected"), ("rssi", "wifi_rssi")];
+
+impl Field {
+    /// By name, old name, or the AxeOS key it is read from (`vrTemp`)
+    pub fn parse(name: &str) -> Option<Field> {
+        let name = ALIASES.iter().find(|(old, _)| old.eq_ignore_ascii_case(name)).map_or(name, |(_, new)| new);
+        FIELDS.iter().copied().find(|f| f.name.eq_ignore_ascii_case(name) || f.key == name)
+    }
+
+    /// The reading, temperatures in --temp-unit like the table shows them
+    pub fn value(&self, info: &Value) -> Option<f64> {
+        temperature::unit().reading(info, self.key)
+    }
+}
+
+/// What the fleet table is ordered by
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    /// The device name from the config
+    Name,
+    Field(Field),
+}
+
+/// Clap value parser for --sort: `name` or a numeric column
+pub fn parse_sort_key(s: &str) -> Result<SortKey, String> {
+    if s.trim().eq_ignore_ascii_case("name") {
+        return Ok(SortKey::Name);
+    }
+    let field = Field::parse(s.trim()).map(SortKey::Field);
+    field.ok_or_else(|| format!("unknown field '{s}' (known: name, {})", field_names()))
+}
+
+fn field_names() -> String {
+    FIELDS.iter().map(|f| f.name).collect::<Vec<_>>().join(", ")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Op {
+    fn apply(self, a: f64, b: f64) -> bool {
+        match self {
+            Op::Lt => a < b,
+            Op::Le => a <= b,
+            Op::Gt => a > b,
+            Op::Ge => a >= b,
+            Op::Eq => a == b,
+            Op::Ne => a != b,
+        }
+    }
+}
+
+/// One --filter expression: `online`, `offline`, or `<field> <op> <number>`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Online,
+    Offline,
+    Compare { field: Field, op: Op, value: f64 },
+}
+
+impl Filter {
+    /// `info` is `None` for unreachable devices, which never satisfy a comparison
+    pub fn matches(&self, info: Option<&Value>) -> bool {
+        match self {
+            Filter::Online => info.is_some(),
+            Filter::Offline => info.is_none(),
+            Filter::Compare { field, op, value } => info
+                .and_then(|i| field.value(i))
+                .is_some_and(|v| op.apply(v, *value)),
+        }
+    }
+}
+
+/// Clap value parser for --filter. Errors quote the expression and point
+/// a caret at the offending token.
+pub fn parse_filter(expr: &str) -> Result<Filter, String> {
+    let fail = |pos: usize, msg: String| -> String {
+        format!("{msg}\n  {expr}\n  {}^", " ".repeat(pos))
+    };
+
+    // Field name (or keyword)
+    let start = expr.len() - expr.trim_start().len();
+    let name_end = expr[start..]
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .map_or(expr.len(), |i| start + i);
+    let name = &expr[start..name_end];
+    if name.is_empty() {
+        return Err(fail(start, "expected a field name, `online` or `offline`".to_string()));
+    }
+
+    let rest = &expr[name_end..];
+    if rest.trim().is_empty() {
+        return match name.to_ascii_lowercase().as_str() {
+            "online" => Ok(Filter::Online),
+            "offline" => Ok(Filter::Offline),
+            _ if Field::parse(name).is_some() => {
+                Err(fail(name_end, format!("expected a comparison after '{name}'")))
+            }
+            _ => Err(fail(start, format!("unknown field '{name}' (known: {}, online, offline)", field_names()))),
+        };
+    }
+    let Some(field) = Field::parse(name) else {
+        return Err(fail(start, format!("unknown field '{name}' (known: {})", field_names())));
+    };
+
+    // Operator
+    let op_start = name_end + (rest.len() - rest.trim_start().len());
+    let op_end = expr[op_start..]
+        .find(|c: char| !"<>=!".contains(c))
+        .map_or(expr.len(), |i| op_start + i);
+    let op = match &expr[op_start..op_end] {
+        "<" => Op::Lt,
+        "<=" => Op::Le,
+        ">" => Op::Gt,
+        ">=" => Op::Ge,
+        "=" | "==" => Op::Eq,
+        "!=" => Op::Ne,
+        "" => return Err(fail(op_start, "expected one of < <= > >= == !=".to_string())),
+        other => return Err(fail(op_start, format!("unknown operator '{other}'"))),
+    };
+
+    // Number
+    let num = expr[op_end..].trim();
+    let num_start = op_end + (expr[op_end..].len() - expr[op_end..].trim_start().len());
+    if num.is_empty() {
+        return Err(fail(num_start, "expected a number".to_string()));
+    }
+    let value: f64 = num
+        .parse()
+        .map_err(|_| fail(num_start, format!("'{num}' is not a number")))?;
+
+    Ok(Filter::Compare { field, op, value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::rules::Metric;
+
+    #[test]
+    fn parses_comparisons() {
+        let f = parse_filter("temp>65").unwrap();
+        assert_eq!(f, Filter::Compare { field: Field::parse("temp").unwrap(), op: Op::Gt, value: 65.0 });
+
+        let f = parse_filter(" hashrate <= 400.5 ").unwrap();
+        assert_eq!(f, Filter::Compare { field: Field::parse("hashrate").unwrap(), op: Op::Le, value: 400.5 });
+    }
+
+    #[test]
+    fn fields_take_the_metric_names() {
+        for field in FIELDS.iter().filter(|f| f.name != "shares_accepted") {
+            let metric = Metric::parse(field.name).unwrap();
+            assert_eq!(metric.key(), Some(field.key), "{}", field.name);
+        }
+        let vr = Field::parse("vr_temp").unwrap();
+        assert_eq!(Field::parse("vrtemp"), Some(vr));
+        assert_eq!(Field::parse("vrTemp"), Some(vr));
+        assert_eq!(Field::parse("rssi").map(|f| f.name), Some("wifi_rssi"));
+        assert!(parse_filter("vr_temp>60").is_ok());
+    }
+
+    #[test]
+    fn parses_keywords() {
+        assert_eq!(parse_filter("offline").unwrap(), Filter::Offline);
+        assert_eq!(parse_filter("ONLINE").unwrap(), Filter::Online);
+    }
+
+    #[test]
+    fn errors_point_at_the_bad_token() {
+        let err = parse_filter("temp>>65").unwrap_err();
+        assert!(err.starts_with("unknown operator '>>'"), "{err}");
+        assert!(err.ends_with("\n  temp>>65\n      ^"), "{err}");
+
+        let err = parse_filter("tmp>65").unwrap_err();
+        assert!(err.ends_with("\n  tmp>65\n  ^"), "{err}");
+
+        let err = parse_filter("temp > hot").unwrap_err();
+        assert!(err.ends_with("\n  temp > hot\n         ^"), "{err}");
+
+        assert!(parse_filter("temp").is_err());
+        assert!(parse_filter("temp >").is_err());
+        assert!(parse_filter("").is_err());
+    }
+
+    #[test]
+    fn matching() {
+        let info = json!({ "temp": 66.0, "hashRate": 500 });
+        assert!(parse_filter("temp>65").unwrap().matches(Some(&info)));
+        assert!(!parse_filter("hashrate<400").unwrap().matches(Some(&info)));
+        assert!(parse_filter("online").unwrap().matches(Some(&info)));
+        assert!(parse_filter("offline").unwrap().matches(None));
+        // Unreachable devices and missing fields never satisfy a comparison
+        assert!(!parse_filter("temp>0").unwrap().matches(None));
+        assert!(!parse_filter("power>0").unwrap().matches(Some(&info)));
+    }
+}
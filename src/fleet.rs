@@ -0,0 +1,1101 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{Result, bail};
+use bitaxe_api::{Difficulty, SystemInfo};
+use clap::{Args, Subcommand};
+use serde_json::{Value, json};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use crate::client::{ClientOptions, Target};
+use crate::color::{RED, YELLOW, paint};
+use crate::config_file::{AppConfig, lookup_device};
+use crate::error::{self, BitaxeError};
+use crate::filter::{Filter, SortKey, parse_filter, parse_sort_key};
+use crate::interrupt::Interrupt;
+use crate::logging;
+use crate::notify::Alerts;
+use crate::output::{self, tsv_field};
+use crate::ratelimit;
+use crate::settings::{self, Setting};
+use crate::temperature;
+use crate::{
+    confirm, fetch_info, get_number, get_str, parse_duration,
+    patch_system, send_restart, wait_until_online, runtime, RESTART_GRACE, RESTART_WAIT,
+};
+
+/// `fleet` subcommands: operate on every device in the [devices] table
+#[derive(Subcommand, Debug)]
+pub enum FleetCommand {
+    /// One row per configured device plus a totals footer
+    Status {
+        /// Emit JSON ({"devices": [...], "totals": {...}}) instead of a table
+        #[arg(long)]
+        json: bool,
+
+        /// Tab-separated grid with a header row and readings as reported
+        /// (no units, `-` when missing), for `column -t` or `awk -F'\t'`
+        #[arg(long, conflicts_with = "json")]
+        tsv: bool,
+
+        /// Sort rows by name or a numeric column (hashrate, temp, power,
+        /// uptime, ...). Unreachable devices always come last.
+        #[arg(long, value_parser = parse_sort_key)]
+        sort: Option<SortKey>,
+
+        /// Sort descending (unreachable devices still last)
+        #[arg(long, requires = "sort")]
+        desc: bool,
+
+        /// Only show matching devices: `temp>65`, `hashrate<400`, `online`, `offline`.
+        /// Repeat to require several conditions.
+        #[arg(long, value_parser = parse_filter)]
+        filter: Vec<Filter>,
+
+        #[command(flatten)]
+        opts: FleetOpts,
+    },
+
+    /// Redraw the status table every interval, marking what changed
+    Watch {
+        /// Time between polls (ex: 5, 10s, 1m; at least 1s)
+        #[arg(long, default_value = "5s", value_parser = parse_duration)]
+        interval: Duration,
+
+        /// Emit one JSON object per device per poll instead of a table
+        #[arg(long)]
+        jsonl: bool,
+
+        #[command(flatten)]
+        opts: FleetOpts,
+    },
+
+    /// One-glance rollup: totals, efficiency, best diff, hottest device
+    Summary {
+        /// Emit JSON instead of text
+        #[arg(long)]
+        json: bool,
+
+        #[command(flatten)]
+        opts: FleetOpts,
+    },
+
+    /// Restart every targeted device (all, or those named with --device)
+    Restart {
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
+
+        /// Pause between restarts (ex: 30s, 2m) so the pool doesn't lose every worker at once
+        #[arg(long, value_parser = parse_duration)]
+        stagger: Option<Duration>,
+
+        /// With --stagger, wait for each device to come back before restarting the next
+        #[arg(long, requires = "stagger")]
+        wait: bool,
+
+        #[command(flatten)]
+        opts: FleetOpts,
+    },
+
+    /// Push one setting to every targeted device
+    Set {
+        #[arg(value_enum)]
+        setting: Setting,
+
+        value: f64,
+
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
+
+        /// Keep going when a device fails instead of stopping the rollout
+        #[arg(long)]
+        continue_on_error: bool,
+
+        /// Push a frequency or core voltage outside the ASIC's safe range
+        /// (with a warning per device). This can damage the boards.
+        #[arg(long)]
+        force: bool,
+
+        #[command(flatten)]
+        opts: FleetOpts,
+    },
+
+    /// Apply a [profiles] entry from the config to every targeted device
+    Apply {
+        profile: String,
+
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
+
+        /// Keep going when a device fails instead of stopping the rollout
+        #[arg(long)]
+        continue_on_error: bool,
+
+        /// Apply a frequency or core voltage outside the ASIC's safe range
+        /// (with a warning per device). This can damage the boards.
+        #[arg(long)]
+        force: bool,
+
+        #[command(flatten)]
+        opts: FleetOpts,
+    },
+}
+
+/// Options shared by every fleet operation
+#[derive(Args, Debug, Clone)]
+pub struct FleetOpts {
+    /// Maximum number of devices queried at the same time
+    #[arg(long, visible_alias = "jobs", default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+    pub concurrency: u16,
+
+    /// Only target devices carrying this tag; repeat to require several tags
+    #[arg(long)]
+    pub tag: Vec<String>,
+
+    /// With several --tag flags, match devices carrying any of them instead of all
+    #[arg(long, requires = "tag")]
+    pub any_tag: bool,
+}
+
+impl FleetCommand {
+    fn opts(&self) -> &FleetOpts {
+        match self {
+            FleetCommand::Status { opts, .. }
+            | FleetCommand::Watch { opts, .. }
+            | FleetCommand::Summary { opts, .. }
+            | FleetCommand::Restart { opts, .. }
+            | FleetCommand::Set { opts, .. }
+            | FleetCommand::Apply { opts, .. } => opts,
+        }
+    }
+}
+
+/// Outcome of querying one configured device
+pub struct DeviceResult {
+    pub name: String,
+    pub host: String,
+    /// System info on success, the error message when unreachable
+    pub info: Result<Value, String>,
+    /// When the response arrived (after any rate-limit delay)
+    pub fetched_at: SystemTime,
+}
+
+/// A poll of `name` at `http://{name}` that just finished, for tests
+#[cfg(test)]
+pub(crate) fn result(name: &str, info: Result<Value, String>) -> DeviceResult {
+    DeviceResult { name: name.to_string(), host: format!("http://{name}"), info, fetched_at: SystemTime::now() }
+}
+
+pub fn run(
+    cmd: &FleetCommand,
+    cfg: &AppConfig,
+    only: &[String],
+    client_opts: &ClientOptions,
+    colorize: bool,
+) -> Result<()> {
+    let opts = cmd.opts();
+    let targets = select_targets(cfg, only, &opts.tag, opts.any_tag, client_opts)?;
+    match cmd {
+        FleetCommand::Status { json, tsv, sort, desc, filter, opts } => {
+            let view = TableView { sort: *sort, desc: *desc, filters: filter.clone() };
+            fleet_status(&targets, opts, &view, *json, *tsv)
+        }
+        FleetCommand::Watch { interval, jsonl, opts } => {
+            let interval = ratelimit::poll_interval(*interval);
+            fleet_watch(&targets, opts, interval, *jsonl, colorize, Alerts::from_config(cfg)?)
+        }
+        FleetCommand::Summary { json, opts } => fleet_summary(&targets, opts, *json),
+        FleetCommand::Restart { yes, stagger, wait, opts } => {
+            fleet_restart(&targets, opts, *yes, *stagger, *wait)
+        }
+        FleetCommand::Set { setting, value, yes, continue_on_error, force, opts } => {
+            fleet_apply(&targets, opts, &[(*setting, *value)], *yes, *continue_on_error, *force)
+        }
+        FleetCommand::Apply { profile, yes, continue_on_error, force, opts } => {
+            let changes = settings::lookup_profile(cfg, profile)?;
+            fleet_apply(&targets, opts, &changes, *yes, *continue_on_error, *force)
+        }
+    }
+}
+
+/// Devices the command operates on: every configured device, or only the
+/// ones named with --device, further narrowed by --tag.
+/// Unknown names and an empty selection are errors.
+pub fn select_targets(
+    cfg: &AppConfig,
+    only: &[String],
+    tags: &[String],
+    any_tag: bool,
+    client_opts: &ClientOptions,
+) -> Result<Vec<Target>> {
+    if cfg.devices.is_empty() {
+        bail!("No devices configured. Add one with `bitaxe-cli device add <name> <host>`.");
+    }
+    for name in only {
+        lookup_device(cfg, name)?;
+    }
+
+    // BTreeMap iteration keeps the targets sorted by name
+    let targets: Vec<Target> = cfg
+        .devices
+        .iter()
+        .filter(|(name, _)| only.is_empty() || only.contains(name))
+        .filter(|(_, dev)| matches_tags(&dev.tags, tags, any_tag))
+        .map(|(name, dev)| client_opts.device_target(name, dev))
+        .collect::<Result<_>>()?;
+
+    if targets.is_empty() {
+        let joiner = if any_tag { " or " } else { " and " };
+        bail!("No configured device matches tag {}", tags.join(joiner));
+    }
+    Ok(targets)
+}
+
+/// Tag filter: no wanted tags matches everything; otherwise the device must
+/// carry all of them, or at least one with `any`
+fn matches_tags(device_tags: &[String], wanted: &[String], any: bool) -> bool {
+    if wanted.is_empty() {
+        return true;
+    }
+    if any {
+        wanted.iter().any(|t| device_tags.contains(t))
+    } else {
+        wanted.iter().all(|t| device_tags.contains(t))
+    }
+}
+
+/// Query every target; failures are recorded, never propagated.
+/// Results come back in target (name) order regardless of completion order.
+pub fn fetch_fleet(targets: &[Target], opts: &FleetOpts) -> Vec<DeviceResult> {
+    runtime().block_on(poll_fleet(targets.to_vec(), opts.concurrency as usize))
+}
+
+/// `fetch_fleet` from inside the runtime
+pub async fn poll_fleet(targets: Vec<Target>, concurrency: usize) -> Vec<DeviceResult> {
+    bounded_map(targets, concurrency, |t| async move {
+        let info = fetch_info(&t.client, &t.host).await.map_err(|e| e.to_string());
+        DeviceResult { name: t.name().to_string(), host: t.host, info, fetched_at: SystemTime::now() }
+    })
+    .await
+}
+
+/// Run `f` over `items`, each as a task of its own with at most `concurrency`
+/// running at once, keeping input order. The requests spend their time
+/// waiting on the network, so the wall time comes down to roughly the
+/// slowest single one.
+pub async fn bounded_map<T, R, F, Fut>(items: Vec<T>, concurrency: usize, f: F) -> Vec<R>
+where
+    F: Fn(T) -> Fut,
+    Fut: Future<Output = R> + Send + 'static,
+    R: Send + 'static,
+{
+    let permits = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+    for (i, item) in items.into_iter().enumerate() {
+        let (permits, work) = (permits.clone(), f(item));
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            (i, work.await)
+        });
+    }
+
+    let mut results = Vec::with_capacity(tasks.len());
+    while let Some(done) = tasks.join_next().await {
+        results.push(done.expect("fleet task panicked"));
+    }
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Summed hashrate/power and average temperature over reachable devices
+struct Totals {
+    hashrate: f64,
+    power: f64,
+    avg_temp: Option<f64>,
+    online: usize,
+    offline: usize,
+}
+
+fn totals(results: &[DeviceResult]) -> Totals {
+    let mut t = Totals { hashrate: 0.0, power: 0.0, avg_temp: None, online: 0, offline: 0 };
+    let mut temps = Vec::new();
+
+    for r in results {
+        match &r.info {
+            Ok(info) => {
+                let info = SystemInfo::from(info);
+                t.online += 1;
+                t.hashrate += info.hash_rate.unwrap_or(0.0);
+                t.power += info.power.unwrap_or(0.0);
+                if let Some(temp) = info.temp {
+                    temps.push(temp);
+                }
+            }
+            Err(_) => t.offline += 1,
+        }
+    }
+
+    if !temps.is_empty() {
+        t.avg_temp = Some(temps.iter().sum::<f64>() / temps.len() as f64);
+    }
+    t
+}
+
+/// Row selection and ordering for `fleet status`
+struct TableView {
+    sort: Option<SortKey>,
+    desc: bool,
+    filters: Vec<Filter>,
+}
+
+impl TableView {
+    /// Drop rows failing any filter, then sort. Unreachable devices go
+    /// last in either direction, after rows missing the sort field; ties
+    /// are broken by name.
+    fn apply(&self, mut results: Vec<DeviceResult>) -> Vec<DeviceResult> {
+        results.retain(|r| self.filters.iter().all(|f| f.matches(r.info.as_ref().ok())));
+
+        if let Some(key) = self.sort {
+            results.sort_by(|a, b| {
+                let offline = a.info.is_err().cmp(&b.info.is_err());
+                let by_key = match key {
+                    SortKey::Name if self.desc => b.name.cmp(&a.name),
+                    SortKey::Name => a.name.cmp(&b.name),
+                    SortKey::Field(field) => {
+                        let va = a.info.as_ref().ok().and_then(|i| field.value(i));
+                        let vb = b.info.as_ref().ok().and_then(|i| field.value(i));
+                        match (va, vb) {
+                            (Some(x), Some(y)) if self.desc => y.total_cmp(&x),
+                            (Some(x), Some(y)) => x.total_cmp(&y),
+                            (Some(_), None) => std::cmp::Ordering::Less,
+                            (None, Some(_)) => std::cmp::Ordering::Greater,
+                            (None, None) => std::cmp::Ordering::Equal,
+                        }
+                    }
+                };
+                offline.then(by_key).then_with(|| a.name.cmp(&b.name))
+            });
+        }
+        results
+    }
+}
+
+fn fleet_status(
+    targets: &[Target],
+    opts: &FleetOpts,
+    view: &TableView,
+    json: bool,
+    tsv: bool,
+) -> Result<()> {
+    let results = view.apply(fetch_fleet(targets, opts));
+    if tsv {
+        print!("{}", tsv_grid(&results));
+        return Ok(());
+    }
+    print_results(&results, json)
+}
+
+/// Columns of `fleet status --tsv` after the name, by AxeOS key and stable field name
+const TSV_COLUMNS: &[(&str, &str)] = &[
+    ("hostname", "hostname"),
+    ("hashRate", "hashrate"),
+    ("temp", "temp"),
+    ("vrTemp", "vr_temp"),
+    ("power", "power"),
+    ("sharesAccepted", "shares_accepted"),
+    ("sharesRejected", "shares_rejected"),
+    ("uptimeSeconds", "uptime_seconds"),
+];
+
+/// One row per device, no totals row, so every line parses the same way
+fn tsv_grid(results: &[DeviceResult]) -> String {
+    let mut header = vec!["name"];
+    header.extend(TSV_COLUMNS.iter().map(|(_, name)| *name));
+    header.push("state");
+    let mut out = header.join("\t") + "\n";
+    for r in results {
+        let mut row = vec![tsv_field(&r.name)];
+        for (key, _) in TSV_COLUMNS {
+            let cell = match r.info.as_ref().ok().and_then(|info| info.get(key)) {
+                Some(Value::String(s)) => tsv_field(s),
+                Some(v @ Value::Number(_)) => v.to_string(),
+                _ => "-".to_string(),
+            };
+            row.push(cell);
+        }
+        row.push(if r.info.is_ok() { "OK" } else { "UNREACHABLE" }.to_string());
+        out += &(row.join("\t") + "\n");
+    }
+    out
+}
+
+/// The `fleet status` output: a table with a totals row, or the JSON document
+pub fn print_results(results: &[DeviceResult], json: bool) -> Result<()> {
+    let totals = totals(results);
+    if json {
+        print_json(results, &totals)?;
+    } else {
+        print_table(results, &totals);
+    }
+    Ok(())
+}
+
+fn print_table(results: &[DeviceResult], totals: &Totals) {
+    for line in table_lines(results, totals, &[], false) {
+        println!("{line}");
+    }
+}
+
+/// How a `fleet watch` row differs from the previous poll
+#[derive(Clone, Copy, PartialEq)]
+enum RowChange {
+    Same,
+    /// Reachable both times; flags mark which cells changed
+    Changed([bool; CELLS]),
+    /// Was reachable last poll, unreachable now
+    Lost,
+}
+
+/// Cells after the name column: hostname, hashrate, temp, power, shares, uptime
+const CELLS: usize = 6;
+
+fn row_cells(info: &Value) -> [String; CELLS] {
+    let info = SystemInfo::from(info);
+    let shares = match (info.shares_accepted, info.shares_rejected) {
+        (Some(a), Some(rj)) => format!("{a}/{rj}"),
+        (Some(a), None) => a.to_string(),
+        _ => "-".to_string(),
+    };
+    [
+        info.hostname.unwrap_or_else(|| "-".to_string()),
+        fmt_opt(info.hash_rate, 2, " GH/s"),
+        fmt_temp(info.temp),
+        fmt_opt(info.power, 2, " W"),
+        shares,
+        info.uptime_seconds.map(|secs| format_uptime(secs as f64)).unwrap_or_else(|| "-".to_string()),
+    ]
+}
+
+/// Render the table. With `changes` (one per row), a marker column is added
+/// and changed cells are highlighted when coloring.
+fn table_lines(results: &[DeviceResult], totals: &Totals, changes: &[RowChange], colorize: bool) -> Vec<String> {
+    // Names are short config keys, but swarm peers are listed by address
+    let w = results.iter().map(|r| r.name.chars().count()).max().unwrap_or(0).max(12);
+    let widths = [16, 12, 8, 9, 14, 12];
+    let marks = !changes.is_empty();
+
+    // Pad before painting: escape sequences would otherwise count as width
+    let join = |mark: &str, name: &str, cells: [String; CELLS], state: String, changed: [bool; CELLS]| {
+        let mut line = if marks { format!("{mark} ") } else { String::new() };
+        line.push_str(&format!("{name:<w$}"));
+        for (i, cell) in cells.iter().enumerate() {
+            let padded = if i == 0 {
+                format!("{cell:<width$}", width = widths[i])
+            } else {
+                format!("{cell:>width$}", width = widths[i])
+            };
+            line.push(' ');
+            line.push_str(&paint(colorize && changed[i], YELLOW, &padded));
+        }
+        line.push_str("  ");
+        line.push_str(&state);
+        line
+    };
+
+    let header = ["HOSTNAME", "HASHRATE", "TEMP", "POWER", "SHARES", "UPTIME"].map(String::from);
+    let mut lines = vec![join(" ", "NAME", header, "STATE".to_string(), [false; CELLS])];
+
+    for (i, r) in results.iter().enumerate() {
+        let change = changes.get(i).copied().unwrap_or(RowChange::Same);
+        let line = match &r.info {
+            Ok(info) => {
+                let (mark, changed) = match change {
+                    RowChange::Changed(c) => ("*", c),
+                    _ => (" ", [false; CELLS]),
+                };
+                join(mark, &r.name, row_cells(info), "OK".to_string(), changed)
+            }
+            Err(_) => {
+                let lost = change == RowChange::Lost;
+                let state = paint(colorize && lost, RED, "UNREACHABLE");
+                join(if lost { "!" } else { " " }, &r.name, ["-"; CELLS].map(String::from), state, [false; CELLS])
+            }
+        };
+        lines.push(line);
+    }
+
+    let footer = [
+        String::new(),
+        format!("{:.2} GH/s", totals.hashrate),
+        fmt_temp(totals.avg_temp),
+        format!("{:.2} W", totals.power),
+        String::new(),
+        String::new(),
+    ];
+    let online = format!("{}/{} online", totals.online, totals.online + totals.offline);
+    lines.push(join(" ", "TOTAL", footer, online, [false; CELLS]));
+    lines
+}
+
+/// Compare this poll against the last one, row by row (targets keep their order)
+fn diff_rows(previous: &[Option<[String; CELLS]>], results: &[DeviceResult]) -> Vec<RowChange> {
+    results
+        .iter()
+        .enumerate()
+        .map(|(i, r)| match (previous.get(i).and_then(Option::as_ref), &r.info) {
+            (Some(old), Ok(info)) => {
+                let new = row_cells(info);
+                // Uptime ticks on every poll; it isn't a change worth flagging
+                let mut changed: [bool; CELLS] = std::array::from_fn(|c| old[c] != new[c]);
+                changed[CELLS - 1] = false;
+                if changed.iter().any(|&c| c) { RowChange::Changed(changed) } else { RowChange::Same }
+            }
+            (Some(_), Err(_)) => RowChange::Lost,
+            (None, _) => RowChange::Same,
+        })
+        .collect()
+}
+
+/// Poll the fleet every `interval` until Ctrl-C, redrawing the table
+/// in place on a terminal, or emitting one JSON object per device per poll
+fn fleet_watch(
+    targets: &[Target],
+    opts: &FleetOpts,
+    interval: Duration,
+    jsonl: bool,
+    colorize: bool,
+    mut alerts: Option<Alerts>,
+) -> Result<()> {
+    let interrupt = Interrupt::install()?;
+    let redraw = !jsonl && io::stdout().is_terminal();
+    let mut previous: Vec<Option<[String; CELLS]>> = Vec::new();
+
+    if redraw {
+        // Hidden cursor; Interrupt::finish shows it again
+        print!("\x1b[?25l\x1b[2J");
+    }
+
+    loop {
+        let results = fetch_fleet(targets, opts);
+        for r in &results {
+            logging::poll(&r.name, r.info.as_ref().map_err(String::clone));
+        }
+        if let Some(alerts) = &mut alerts {
+            for r in &results {
+                alerts.observe(&r.name, r.info.as_ref().ok());
+            }
+        }
+
+        if jsonl {
+            for r in &results {
+                let time = r.fetched_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+                let mut line = device_json(r);
+                line["time"] = json!((time * 1000.0).round() / 1000.0);
+                println!("{line}");
+            }
+        } else {
+            let changes = diff_rows(&previous, &results);
+            let lines = table_lines(&results, &totals(&results), &changes, colorize);
+
+            // Overwrite the previous frame line by line instead of clearing
+            // the screen first, so the table doesn't flicker
+            let mut frame = String::new();
+            if redraw {
+                frame.push_str("\x1b[H");
+            }
+            let every = interval.as_secs_f64();
+            frame.push_str(&format!("Every {every}s: {} devices (Ctrl-C to stop)", targets.len()));
+            for line in std::iter::once(String::new()).chain(lines) {
+                frame.push_str(if redraw { "\x1b[K\n" } else { "\n" });
+                frame.push_str(&line);
+            }
+            frame.push_str(if redraw { "\x1b[K\n\x1b[J" } else { "\n\n" });
+            print!("{frame}");
+            io::stdout().flush()?;
+        }
+
+        previous = results.iter().map(|r| r.info.as_ref().ok().map(row_cells)).collect();
+        if !interrupt.sleep(interval) {
+            return interrupt.finish();
+        }
+    }
+}
+
+/// One device as it appears in `--json` / `--jsonl` output
+pub fn device_json(r: &DeviceResult) -> Value {
+    match &r.info {
+        Ok(info) => json!({
+            "name": r.name,
+            "device": r.name,
+            "host": r.host,
+            "online": true,
+            "hostname": get_str(info, "hostname"),
+            "hashRate": get_number(info, "hashRate"),
+            "temp": get_number(info, "temp"),
+            "vrTemp": get_number(info, "vrTemp"),
+            "power": get_number(info, "power"),
+            "sharesAccepted": get_number(info, "sharesAccepted"),
+            "sharesRejected": get_number(info, "sharesRejected"),
+            "uptimeSeconds": get_number(info, "uptimeSeconds"),
+            "wifiRSSI": get_number(info, "wifiRSSI"),
+        }),
+        Err(e) => json!({
+            "name": r.name,
+            "device": r.name,
+            "host": r.host,
+            "online": false,
+            "error": e,
+        }),
+    }
+}
+
+fn print_json(results: &[DeviceResult], totals: &Totals) -> Result<()> {
+    let devices: Vec<Value> = results.iter().map(device_json).collect();
+
+    let out = json!({
+        "devices": devices,
+        "totals": {
+            "hashRate": totals.hashrate,
+            "power": totals.power,
+            "avgTemp": totals.avg_temp,
+            "online": totals.online,
+            "offline": totals.offline,
+        },
+    });
+    println!("{}", output::json(&out)?);
+    Ok(())
+}
+
+fn fleet_summary(targets: &[Target], opts: &FleetOpts, json: bool) -> Result<()> {
+    let results = fetch_fleet(targets, opts);
+    let t = totals(&results);
+
+    let mut accepted: u64 = 0;
+    let mut rejected: u64 = 0;
+    // (device, numeric value, value as reported)
+    let mut best: Option<(&str, f64, String)> = None;
+    let mut hottest: Option<(&str, f64)> = None;
+
+    for r in &results {
+        let Ok(info) = &r.info else { continue };
+        let info = SystemInfo::from(info);
+        accepted += info.shares_accepted.unwrap_or(0);
+        rejected += info.shares_rejected.unwrap_or(0);
+
+        if let Some(Difficulty { shown, value: Some(diff) }) = info.best_diff
+            && best.as_ref().is_none_or(|(_, b, _)| diff > *b)
+        {
+            best = Some((&r.name, diff, shown));
+        }
+        if let Some(temp) = info.temp
+            && hottest.is_none_or(|(_, h)| temp > h)
+        {
+            hottest = Some((&r.name, temp));
+        }
+    }
+
+    // J/TH = W / (GH/s / 1000)
+    let efficiency = (t.hashrate > 0.0).then(|| t.power / (t.hashrate / 1000.0));
+
+    if t.online == 0 {
+        eprintln!("Warning: all {} devices are unreachable; totals are zero", t.offline);
+    }
+
+    if json {
+        let out = json!({
+            "online": t.online,
+            "offline": t.offline,
+            "hashRate": t.hashrate,
+            "power": t.power,
+            "efficiencyJTH": efficiency,
+            "sharesAccepted": accepted,
+            "sharesRejected": rejected,
+            "bestDiff": best.as_ref().map(|(name, _, shown)| json!({ "device": name, "value": shown })),
+            "hottest": hottest.map(|(name, temp)| json!({ "device": name, "temp": temp })),
+        });
+        println!("{}", output::json(&out)?);
+        return Ok(());
+    }
+
+    println!("=== Fleet Summary ===");
+    println!("Devices         : {} online, {} offline", t.online, t.offline);
+    println!("Total Hashrate  : {:.2} GH/s", t.hashrate);
+    println!("Total Power     : {:.2} W", t.power);
+    if let Some(eff) = efficiency {
+        println!("Efficiency      : {:.2} J/TH", eff);
+    }
+    println!("Shares Accepted : {accepted}");
+    println!("Shares Rejected : {rejected}");
+    if let Some((name, _, shown)) = &best {
+        println!("Best Diff       : {shown} ({name})");
+    }
+    if let Some((name, temp)) = hottest {
+        println!("Hottest         : {name} at {}", fmt_temp(Some(temp)));
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+enum RestartOutcome {
+    Restarted,
+    Failed(String),
+    Unreachable(String),
+}
+
+async fn restart_one(t: Target) -> RestartOutcome {
+    match send_restart(&t).await {
+        Ok(()) => RestartOutcome::Restarted,
+        // Transport errors mean we never got an HTTP answer
+        Err(e) => match error::find(&e) {
+            Some(
+                BitaxeError::Refused { .. }
+                | BitaxeError::Timeout { .. }
+                | BitaxeError::Dns { .. }
+                | BitaxeError::Transport { .. },
+            ) => RestartOutcome::Unreachable(e.to_string()),
+            _ => RestartOutcome::Failed(e.to_string()),
+        },
+    }
+}
+
+fn fleet_restart(
+    targets: &[Target],
+    opts: &FleetOpts,
+    yes: bool,
+    stagger: Option<Duration>,
+    wait: bool,
+) -> Result<()> {
+    let names: Vec<&str> = targets.iter().map(Target::name).collect();
+    println!("Targets: {}", names.join(", "));
+    if !yes && !confirm(&format!("Restart {} device(s)?", targets.len()))? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    let outcomes: Vec<RestartOutcome> = match stagger {
+        None => runtime().block_on(bounded_map(targets.to_vec(), opts.concurrency as usize, restart_one)),
+        Some(pause) => {
+            let mut out = Vec::new();
+            for (i, t) in targets.iter().enumerate() {
+                println!("Restarting {}...", t.name());
+                let mut outcome = runtime().block_on(restart_one(t.clone()));
+
+                if wait && matches!(outcome, RestartOutcome::Restarted) {
+                    // Give it a moment to actually go down before polling
+                    thread::sleep(RESTART_GRACE);
+                    if !runtime().block_on(wait_until_online(&t.client, &t.host, RESTART_WAIT)) {
+                        outcome = RestartOutcome::Failed(format!(
+                            "did not come back within {}s",
+                            RESTART_WAIT.as_secs()
+                        ));
+                    }
+                }
+                out.push(outcome);
+
+                if i + 1 < targets.len() {
+                    thread::sleep(pause);
+                }
+            }
+            out
+        }
+    };
+
+    println!("{:<12} RESULT", "NAME");
+    let mut failed = 0;
+    for (t, outcome) in targets.iter().zip(&outcomes) {
+        let name = t.name();
+        match outcome {
+            RestartOutcome::Restarted => println!("{name:<12} restarted"),
+            RestartOutcome::Failed(e) => {
+                failed += 1;
+                println!("{name:<12} failed ({e})");
+            }
+            RestartOutcome::Unreachable(e) => {
+                failed += 1;
+                println!("{name:<12} unreachable ({e})");
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("{failed} of {} device(s) were not restarted", targets.len());
+    }
+    Ok(())
+}
+
+enum ApplyOutcome {
+    Applied,
+    Skipped(String),
+    Failed(String),
+}
+
+/// Preview `changes` on every target, confirm once, then PATCH in parallel.
+/// Devices that can't take the values (or can't be reached) are skipped.
+fn fleet_apply(
+    targets: &[Target],
+    opts: &FleetOpts,
+    changes: &[(Setting, f64)],
+    yes: bool,
+    continue_on_error: bool,
+    force: bool,
+) -> Result<()> {
+    let results = fetch_fleet(targets, opts);
+
+    // Preview current -> new and decide up front which devices are skipped
+    let mut plan: Vec<Result<(), String>> = Vec::new();
+    for r in &results {
+        let verdict = match &r.info {
+            Ok(info) => settings::validate_all(changes, info, force),
+            Err(e) => Err(format!("unreachable: {e}")),
+        };
+        let preview = settings::preview(changes, r.info.as_ref().ok());
+        match &verdict {
+            Ok(()) => println!("{:<12} {preview}", r.name),
+            Err(reason) => println!("{:<12} {preview}  [skip: {reason}]", r.name),
+        }
+        plan.push(verdict);
+    }
+
+    let todo = plan.iter().filter(|p| p.is_ok()).count();
+    let what = settings::describe(changes);
+    if todo == 0 {
+        bail!("No device can take {what}");
+    }
+    if !yes && !confirm(&format!("Apply {what} to {todo} device(s)?"))? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    let body = Arc::new(settings::body(changes));
+    let stop = Arc::new(AtomicBool::new(false));
+    let work: Vec<(Target, Result<(), String>)> = targets.iter().cloned().zip(plan).collect();
+    let apply = |(t, verdict): (Target, Result<(), String>)| {
+        let (body, stop) = (body.clone(), stop.clone());
+        async move {
+            if let Err(reason) = verdict {
+                return ApplyOutcome::Skipped(reason);
+            }
+            if stop.load(Ordering::Relaxed) {
+                return ApplyOutcome::Skipped("rollout stopped after an earlier failure".to_string());
+            }
+            match patch_system(&t.client, &t.host, &body).await {
+                Ok(()) => ApplyOutcome::Applied,
+                Err(e) => {
+                    if !continue_on_error {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                    ApplyOutcome::Failed(e.to_string())
+                }
+            }
+        }
+    };
+    let outcomes = runtime().block_on(bounded_map(work, opts.concurrency as usize, apply));
+
+    print_apply_summary(&results, &outcomes)
+}
+
+fn print_apply_summary(results: &[DeviceResult], outcomes: &[ApplyOutcome]) -> Result<()> {
+    let (mut applied, mut skipped, mut failed) = (0, 0, 0);
+    println!("{:<12} RESULT", "NAME");
+    for (r, outcome) in results.iter().zip(outcomes) {
+        match outcome {
+            ApplyOutcome::Applied => {
+                applied += 1;
+                println!("{:<12} applied", r.name);
+            }
+            ApplyOutcome::Skipped(why) => {
+                skipped += 1;
+                println!("{:<12} skipped ({why})", r.name);
+            }
+            ApplyOutcome::Failed(e) => {
+                failed += 1;
+                println!("{:<12} failed ({e})", r.name);
+            }
+        }
+    }
+    println!("{applied} applied, {skipped} skipped, {failed} failed");
+
+    if failed > 0 {
+        bail!("{failed} device(s) failed to apply the change");
+    }
+    Ok(())
+}
+
+fn fmt_opt(v: Option<f64>, decimals: usize, unit: &str) -> String {
+    match v {
+        Some(v) => format!("{:.*}{unit}", decimals, v),
+        None => "-".to_string(),
+    }
+}
+
+/// A °C reading in --temp-unit
+fn fmt_temp(celsius: Option<f64>) -> String {
+    let unit = temperature::unit();
+    fmt_opt(celsius.map(|c| unit.of_celsius(c)), 1, &format!(" {}", unit.symbol()))
+}
+
+/// Format seconds as `3d 04h 12m`
+pub fn format_uptime(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    let days = secs / 86_400;
+    let hours = (secs % 86_400) / 3_600;
+    let mins = (secs % 3_600) / 60;
+    if days > 0 {
+        format!("{days}d {hours:02}h {mins:02}m")
+    } else {
+        format!("{hours}h {mins:02}m")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_file::DeviceConfig;
+
+    fn tags(list: &[&str]) -> Vec<String> {
+        list.iter().map(|t| t.to_string()).collect()
+    }
+
+    fn fleet() -> AppConfig {
+        let mut cfg = AppConfig::default();
+        for (name, t) in [("gamma", &["window", "bm1370"][..]), ("ultra", &["bm1366"][..]), ("max", &[][..])] {
+            cfg.devices.insert(
+                name.to_string(),
+                DeviceConfig {
+                    host: format!("http://{name}"),
+                    hostname: None,
+                    asic_model: None,
+                    tags: tags(t),
+                    timeout_secs: None,
+                    username: None,
+                    password: None,
+                    path_prefix: None,
+                },
+            );
+        }
+        cfg
+    }
+
+    fn names(targets: &[Target]) -> Vec<&str> {
+        targets.iter().map(Target::name).collect()
+    }
+
+    #[test]
+    fn no_tags_matches_everything() {
+        assert!(matches_tags(&[], &[], false));
+        assert!(matches_tags(&tags(&["a"]), &[], true));
+    }
+
+    #[test]
+    fn repeated_tags_mean_intersection() {
+        let dev = tags(&["window", "bm1370"]);
+        assert!(matches_tags(&dev, &tags(&["window"]), false));
+        assert!(matches_tags(&dev, &tags(&["window", "bm1370"]), false));
+        assert!(!matches_tags(&dev, &tags(&["window", "bm1366"]), false));
+    }
+
+    #[test]
+    fn any_tag_means_union() {
+        let dev = tags(&["window"]);
+        assert!(matches_tags(&dev, &tags(&["window", "bm1366"]), true));
+        assert!(!matches_tags(&dev, &tags(&["shelf", "bm1366"]), true));
+    }
+
+    #[test]
+    fn selection_is_sorted_and_filtered() {
+        let cfg = fleet();
+        let all = select_targets(&cfg, &[], &[], false, &ClientOptions::default()).unwrap();
+        assert_eq!(names(&all), ["gamma", "max", "ultra"]);
+
+        let union = select_targets(&cfg, &[], &tags(&["bm1370", "bm1366"]), true, &ClientOptions::default()).unwrap();
+        assert_eq!(names(&union), ["gamma", "ultra"]);
+
+        let named = select_targets(&cfg, &tags(&["ultra", "gamma"]), &tags(&["window"]), false, &ClientOptions::default()).unwrap();
+        assert_eq!(names(&named), ["gamma"]);
+    }
+
+    #[test]
+    fn empty_selection_is_an_error() {
+        let cfg = fleet();
+        assert!(select_targets(&cfg, &[], &tags(&["window", "bm1366"]), false, &ClientOptions::default()).is_err());
+        assert!(select_targets(&cfg, &tags(&["nope"]), &[], false, &ClientOptions::default()).is_err());
+    }
+
+    #[test]
+    fn unreachable_devices_sort_last_either_way() {
+        let fleet = || {
+            vec![
+                result("alpha", Err("refused".to_string())),
+                result("bravo", Ok(json!({"temp": 58.0}))),
+                result("charlie", Ok(json!({"temp": 66.0}))),
+                result("delta", Ok(json!({}))),
+            ]
+        };
+        let order = |sort: &str, desc: bool| {
+            let view = TableView { sort: Some(parse_sort_key(sort).unwrap()), desc, filters: Vec::new() };
+            view.apply(fleet()).into_iter().map(|r| r.name).collect::<Vec<_>>()
+        };
+        assert_eq!(order("temp", false), ["bravo", "charlie", "delta", "alpha"]);
+        assert_eq!(order("temp", true), ["charlie", "bravo", "delta", "alpha"]);
+        assert_eq!(order("name", false), ["bravo", "charlie", "delta", "alpha"]);
+        assert_eq!(order("NAME", true), ["delta", "charlie", "bravo", "alpha"]);
+        assert!(parse_sort_key("speed").unwrap_err().contains("known: name, hashrate"));
+    }
+
+    #[test]
+    fn tsv_grid_has_one_line_per_device() {
+        let results = [
+            result("gamma", Ok(json!({"hostname": "bitaxe\tgamma", "hashRate": 512.3, "temp": 61, "power": 14.2}))),
+            result("ultra", Err("refused".to_string())),
+        ];
+        let grid = tsv_grid(&results);
+        let lines: Vec<&str> = grid.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "name\thostname\thashrate\ttemp\tvr_temp\tpower\tshares_accepted\tshares_rejected\tuptime_seconds\t\
+                 state",
+                "gamma\tbitaxe gamma\t512.3\t61\t-\t14.2\t-\t-\t-\tOK",
+                "ultra\t-\t-\t-\t-\t-\t-\t-\t-\tUNREACHABLE",
+            ]
+        );
+        assert!(lines.iter().all(|l| l.split('\t').count() == TSV_COLUMNS.len() + 2));
+    }
+
+    #[test]
+    fn watch_diff_flags_changes_and_lost_devices() {
+        let before = json!({"hostname": "a", "temp": 60.0, "uptimeSeconds": 100});
+        let hotter = json!({"hostname": "a", "temp": 62.0, "uptimeSeconds": 105});
+        let previous = vec![Some(row_cells(&before)), Some(row_cells(&before)), None];
+        let results = vec![
+            result("a", Ok(hotter)),
+            result("b", Err("refused".to_string())),
+            result("c", Ok(before.clone())),
+        ];
+
+        let changes = diff_rows(&previous, &results);
+        let mut temp_only = [false; CELLS];
+        temp_only[2] = true;
+        assert!(changes[0] == RowChange::Changed(temp_only));
+        assert!(changes[1] == RowChange::Lost);
+        assert!(changes[2] == RowChange::Same);
+
+        // Only uptime moved: not a change
+        let later = json!({"hostname": "a", "temp": 60.0, "uptimeSeconds": 200});
+        let changes = diff_rows(&[Some(row_cells(&before))], &[result("a", Ok(later))]);
+        assert!(changes[0] == RowChange::Same);
+    }
+
+    #[test]
+    fn refused_restarts_are_unreachable() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let target = ClientOptions::default().host_target(&format!("http://127.0.0.1:{port}")).unwrap();
+        let outcome = runtime().block_on(restart_one(target));
+        assert!(matches!(&outcome, RestartOutcome::Unreachable(e) if e.contains("Couldn't connect")), "{outcome:?}");
+    }
+}
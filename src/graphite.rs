@@ -0,0 +1,242 @@
+use std::io::{ErrorKind, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use crate::client::{DEFAULT_TIMEOUT, Target};
+use crate::config_file::{AppConfig, GraphiteConfig};
+use crate::fleet::{DeviceResult, FleetOpts, fetch_fleet};
+use crate::get_number;
+use crate::influx::FIELDS;
+use crate::rules::short_duration;
+use crate::statsd::sanitize;
+
+const DEFAULT_ADDR: &str = "127.0.0.1:2003";
+const DEFAULT_PREFIX: &str = "bitaxe";
+/// First pause after carbon goes away; doubles up to `MAX_BACKOFF`
+const BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// `push graphite`: where to send. Flags override the [graphite] config table.
+#[derive(Args, Debug)]
+pub struct GraphiteArgs {
+    /// Carbon plaintext listener, host:port (default: `addr` in [graphite], then 127.0.0.1:2003)
+    #[arg(long)]
+    addr: Option<String>,
+
+    /// Path in front of every metric (default: `prefix` in [graphite], then "bitaxe")
+    #[arg(long)]
+    prefix: Option<String>,
+
+    /// Print the lines instead of sending them
+    #[arg(long)]
+    dry_run: bool,
+
+    #[command(flatten)]
+    pub opts: FleetOpts,
+}
+
+/// `bitaxe.gamma.hashrate 512.3 1714060800` for every reading of every
+/// device that answered, stamped with when it answered
+pub fn lines(prefix: &str, results: &[DeviceResult]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for r in results {
+        let Ok(info) = &r.info else { continue };
+        let ts = r.fetched_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let device = sanitize(&r.name);
+        for (key, field) in FIELDS {
+            if let Some(v) = get_number(info, key) {
+                let path: Vec<&str> = [prefix, &device, field].into_iter().filter(|s| !s.is_empty()).collect();
+                lines.push(format!("{} {v} {ts}", path.join(".")));
+            }
+        }
+    }
+    lines
+}
+
+/// A connection to carbon that is reopened, with backoff, once it drops
+pub struct Carbon {
+    addr: String,
+    pub prefix: String,
+    stream: Option<TcpStream>,
+    backoff: Duration,
+    /// No reconnect attempt before this
+    retry_at: Option<Instant>,
+}
+
+impl Carbon {
+    /// Flags first, then [graphite], then the defaults. Doesn't connect yet.
+    pub fn new(conf: Option<&GraphiteConfig>, addr: Option<&str>, prefix: Option<&str>) -> Self {
+        Carbon {
+            addr: addr.or(conf.and_then(|c| c.addr.as_deref())).unwrap_or(DEFAULT_ADDR).to_string(),
+            prefix: prefix.or(conf.and_then(|c| c.prefix.as_deref())).unwrap_or(DEFAULT_PREFIX).to_string(),
+            stream: None,
+            backoff: BACKOFF,
+            retry_at: None,
+        }
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Write `lines` in a single batch, connecting first if needed. While
+    /// carbon is down this fails fast until the backoff has passed.
+    pub fn send(&mut self, lines: &[String]) -> Result<()> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let payload = lines.join("\n") + "\n";
+        // A carbon restart closes our end quietly; the first write after
+        // that would vanish into the dead socket
+        if self.stream.as_ref().is_some_and(closed) {
+            tracing::debug!(addr = self.addr, "carbon closed the connection");
+            self.stream = None;
+        }
+        let mut stream = match self.stream.take() {
+            Some(stream) => stream,
+            None => self.connect()?,
+        };
+        // On failure the connection is dropped and the next batch dials again
+        if let Err(e) = stream.write_all(payload.as_bytes()).and_then(|_| stream.flush()) {
+            bail!("Writing to carbon at {} failed: {e}", self.addr);
+        }
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn connect(&mut self) -> Result<TcpStream> {
+        if let Some(at) = self.retry_at
+            && let Some(wait) = at.checked_duration_since(Instant::now())
+        {
+            let wait = Duration::from_secs(wait.as_secs() + 1);
+            bail!("carbon at {} is unreachable; next attempt in {}", self.addr, short_duration(wait));
+        }
+        match open(&self.addr) {
+            Ok(stream) => {
+                self.backoff = BACKOFF;
+                self.retry_at = None;
+                Ok(stream)
+            }
+            Err(e) => {
+                self.retry_at = Some(Instant::now() + self.backoff);
+                self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+                Err(e)
+            }
+        }
+    }
+}
+
+fn open(addr: &str) -> Result<TcpStream> {
+    let sock = addr
+        .to_socket_addrs()
+        .with_context(|| format!("Couldn't resolve carbon address {addr}"))?
+        .next()
+        .with_context(|| format!("No address for {addr}"))?;
+    let stream = TcpStream::connect_timeout(&sock, DEFAULT_TIMEOUT)
+        .with_context(|| format!("Couldn't connect to carbon at {addr}"))?;
+    stream.set_write_timeout(Some(DEFAULT_TIMEOUT))?;
+    Ok(stream)
+}
+
+/// Carbon never writes back, so anything but "nothing to read yet" means
+/// the peer has gone
+fn closed(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return true;
+    }
+    let gone = !matches!(stream.peek(&mut [0u8]), Err(e) if e.kind() == ErrorKind::WouldBlock);
+    gone || stream.set_nonblocking(false).is_err()
+}
+
+/// Poll every target once and write the readings to carbon in one batch
+/// (or print them with --dry-run)
+pub fn push(args: &GraphiteArgs, targets: &[Target], cfg: &AppConfig) -> Result<()> {
+    let mut carbon = Carbon::new(cfg.graphite.as_ref(), args.addr.as_deref(), args.prefix.as_deref());
+    let results = fetch_fleet(targets, &args.opts);
+    for r in &results {
+        if let Err(e) = &r.info {
+            eprintln!("Warning: {}: {e}", r.name);
+        }
+    }
+    let lines = lines(&carbon.prefix, &results);
+    if lines.is_empty() {
+        bail!("No data to push: none of the {} device(s) answered", targets.len());
+    }
+    if args.dry_run {
+        for line in &lines {
+            println!("{line}");
+        }
+        return Ok(());
+    }
+    carbon.send(&lines)?;
+    println!("Wrote {} line(s) to {}", lines.len(), carbon.addr());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+    use serde_json::json;
+    use crate::fleet::result;
+
+    /// A poll that finished at 2024-04-25 16:00:00 UTC
+    fn stamped(name: &str, info: Result<serde_json::Value, String>) -> DeviceResult {
+        DeviceResult { fetched_at: UNIX_EPOCH + Duration::from_secs(1_714_060_800), ..result(name, info) }
+    }
+
+    #[test]
+    fn plaintext_lines_with_sanitized_paths() {
+        let results = [
+            stamped("gamma", Ok(json!({"hashRate": 512.3, "temp": 61, "hostname": "gamma"}))),
+            stamped("garage rig.2", Ok(json!({"power": 14.2}))),
+            stamped("ultra", Err("timed out".into())),
+        ];
+        assert_eq!(
+            lines("bitaxe", &results),
+            [
+                "bitaxe.gamma.hashrate 512.3 1714060800",
+                "bitaxe.gamma.temp 61 1714060800",
+                "bitaxe.garage_rig_2.power 14.2 1714060800"
+            ]
+        );
+        assert_eq!(lines("", &results[1..2]), ["garage_rig_2.power 14.2 1714060800"]);
+    }
+
+    /// Accept one connection, read `count` lines, then close it as a
+    /// restarting carbon would
+    fn receive(listener: &TcpListener, count: usize) -> String {
+        let mut reader = BufReader::new(listener.accept().unwrap().0);
+        let mut got = String::new();
+        for _ in 0..count {
+            reader.read_line(&mut got).unwrap();
+        }
+        got
+    }
+
+    #[test]
+    fn batches_and_reconnects_after_carbon_restarts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let mut carbon = Carbon::new(None, Some(&addr), None);
+
+        carbon.send(&["a 1 0".into(), "b 2 0".into()]).unwrap();
+        assert_eq!(receive(&listener, 2), "a 1 0\nb 2 0\n");
+
+        // Carbon restarted and closed our connection: the next batch goes
+        // over a new one instead of into the dead socket
+        thread::sleep(Duration::from_millis(50));
+        carbon.send(&["c 3 0".into()]).unwrap();
+        assert_eq!(receive(&listener, 1), "c 3 0\n");
+
+        // Down for good: one attempt, then nothing until the backoff passes
+        drop(listener);
+        thread::sleep(Duration::from_millis(50));
+        assert!(carbon.send(&["d 4 0".into()]).is_err());
+        let err = carbon.send(&["d 4 0".into()]).unwrap_err().to_string();
+        assert!(err.contains("next attempt in"), "{err}");
+    }
+}
@@ -0,0 +1,950 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result, bail};
+use clap::{Args, Subcommand, ValueEnum};
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{Connection, ToSql, params_from_iter};
+use serde_json::{Map, Value, json};
+use crate::alert::AlertEvent;
+use crate::config_file::{AppConfig, config_path};
+use crate::fleet::DeviceResult;
+use crate::logger::{csv_field, rfc3339};
+use crate::reboot::{self, Reboot};
+use crate::shares::Seed;
+use crate::{get_number, influx, output, parse_duration};
+
+#[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct HistoryArgs {
+    #[command(subcommand)]
+    command: Option<HistoryCommand>,
+
+    /// Database to use (default: ~/.config/bitaxe-cli/history.db)
+    #[arg(long, value_name = "PATH", global = true)]
+    db: Option<PathBuf>,
+
+    #[command(flatten)]
+    range: RangeArgs,
+
+    /// Print a JSON array instead of a table
+    #[arg(long, conflicts_with = "csv")]
+    pub json: bool,
+
+    /// Print CSV instead of a table
+    #[arg(long)]
+    csv: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryCommand {
+    /// Dump samples to a file (or stdout) for other tools
+    Export {
+        #[command(flatten)]
+        range: RangeArgs,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+
+        /// File to write (default: stdout)
+        #[arg(long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+
+    /// Delete samples older than a retention window
+    Prune {
+        /// How much history to keep, ex: 90d (default: history_retention in the config)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        keep: Option<Duration>,
+
+        /// Only report how many samples would be deleted
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Compact the database afterwards to give the space back
+        #[arg(long)]
+        vacuum: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// Which samples a query or export covers
+#[derive(Args, Debug)]
+struct RangeArgs {
+    /// Only samples from this long ago until now, ex: 24h, 7d
+    #[arg(long, value_name = "DURATION", conflicts_with = "from")]
+    last: Option<String>,
+
+    /// Start of the range: a UTC date/time (2024-05-01, 2024-05-01T12:00) or an age (2d)
+    #[arg(long, value_name = "TIME")]
+    from: Option<String>,
+
+    /// End of the range, exclusive; same formats as --from
+    #[arg(long, value_name = "TIME")]
+    to: Option<String>,
+
+    /// Metrics to include, by AxeOS key or column name (default: hashrate,temp,power; all for export)
+    #[arg(long, value_delimiter = ',', value_name = "METRICS")]
+    metric: Vec<String>,
+
+    /// Average samples into buckets this wide, ex: 5m, 1h
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    resolution: Option<Duration>,
+}
+
+impl RangeArgs {
+    fn query(&self, devices: &[String], default_metrics: Vec<&'static str>) -> Result<Query> {
+        let now = unix_now();
+        let from = match (&self.last, &self.from) {
+            (Some(last), _) => Some(now - parse_duration(last).map_err(anyhow::Error::msg)?.as_secs() as i64),
+            (None, Some(from)) => Some(parse_time(from, now).with_context(|| format!("Invalid --from '{from}'"))?),
+            (None, None) => None,
+        };
+        let to = self
+            .to
+            .as_deref()
+            .map(|to| parse_time(to, now).with_context(|| format!("Invalid --to '{to}'")))
+            .transpose()?;
+        if let (Some(from), Some(to)) = (from, to)
+            && from >= to
+        {
+            bail!("The range is empty: --from must be before --to");
+        }
+
+        let metrics = if self.metric.is_empty() {
+            default_metrics
+        } else {
+            self.metric.iter().map(|m| metric_column(m)).collect::<Result<_>>()?
+        };
+        let resolution = self.resolution.map(|r| r.as_secs() as i64);
+        if resolution == Some(0) {
+            bail!("--resolution must be at least 1s");
+        }
+        Ok(Query { from, to, devices: devices.to_vec(), metrics, resolution })
+    }
+}
+
+/// Metrics shown when --metric isn't given
+const DEFAULT_METRICS: &[&str] = &["hashrate", "temp", "power"];
+
+/// Schema changes, applied in order. `PRAGMA user_version` records how many
+/// have run, so new columns go in a new entry rather than edits to old ones.
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE samples (
+        id INTEGER PRIMARY KEY,
+        device TEXT NOT NULL,
+        ts INTEGER NOT NULL,
+        hashrate REAL,
+        temp REAL,
+        vr_temp REAL,
+        power REAL,
+        voltage REAL,
+        frequency REAL,
+        core_voltage REAL,
+        core_voltage_actual REAL,
+        shares_accepted REAL,
+        shares_rejected REAL,
+        wifi_rssi REAL,
+        uptime_seconds REAL
+    );
+    CREATE INDEX samples_device_ts ON samples (device, ts);
+", "
+    CREATE TABLE failures (
+        id INTEGER PRIMARY KEY,
+        device TEXT NOT NULL,
+        ts INTEGER NOT NULL,
+        error TEXT NOT NULL
+    );
+    CREATE INDEX failures_device_ts ON failures (device, ts);
+    CREATE TABLE alert_events (
+        id INTEGER PRIMARY KEY,
+        device TEXT NOT NULL,
+        ts INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        severity TEXT NOT NULL,
+        firing INTEGER NOT NULL,
+        value REAL,
+        threshold REAL
+    );
+    CREATE INDEX alert_events_device_ts ON alert_events (device, ts);
+", "
+    CREATE TABLE reboots (
+        id INTEGER PRIMARY KEY,
+        device TEXT NOT NULL,
+        ts INTEGER NOT NULL,
+        uptime_before REAL NOT NULL,
+        requested INTEGER NOT NULL
+    );
+    CREATE INDEX reboots_device_ts ON reboots (device, ts);
+"];
+
+/// One poll of one miner; `values` follows the order of `influx::FIELDS`
+pub struct Sample {
+    pub device: String,
+    /// Unix time in seconds
+    pub ts: i64,
+    pub values: Vec<Option<f64>>,
+}
+
+impl Sample {
+    pub fn new(device: &str, at: SystemTime, info: &Value) -> Self {
+        Sample {
+            device: device.to_string(),
+            ts: unix_secs(at),
+            values: influx::FIELDS.iter().map(|(key, _)| get_number(info, key)).collect(),
+        }
+    }
+}
+
+/// A poll that got no answer, kept so reports can tell a miner that was
+/// down from one nobody was logging
+pub struct Failure {
+    pub device: String,
+    pub ts: i64,
+    pub error: String,
+}
+
+impl Failure {
+    /// `None` when the device answered
+    pub fn new(result: &DeviceResult) -> Option<Self> {
+        let error = result.info.as_ref().err()?;
+        Some(Failure { device: result.name.clone(), ts: unix_secs(result.fetched_at), error: error.clone() })
+    }
+}
+
+/// An alert event read back for `report`
+#[derive(Debug, PartialEq)]
+pub struct StoredEvent {
+    pub ts: i64,
+    pub name: String,
+    pub severity: String,
+    pub firing: bool,
+    pub value: Option<f64>,
+    pub threshold: Option<f64>,
+}
+
+/// ~/.config/bitaxe-cli/history.db, next to the config file
+pub fn default_path() -> Option<PathBuf> {
+    Some(config_path()?.parent()?.join("history.db"))
+}
+
+/// Sample store backed by SQLite
+pub struct HistoryDb {
+    conn: Connection,
+}
+
+impl HistoryDb {
+    /// Open (creating if needed) the database at `path` and bring its schema
+    /// up to date
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let conn = Connection::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        // WAL keeps the last committed sample intact if we are killed mid-write
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::init(conn).with_context(|| format!("Failed to prepare {}", path.display()))
+    }
+
+    fn init(mut conn: Connection) -> Result<Self> {
+        let applied: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        for (i, sql) in MIGRATIONS.iter().enumerate().skip(applied as usize) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(sql)?;
+            tx.pragma_update(None, "user_version", i as i64 + 1)?;
+            tx.commit()?;
+        }
+        Ok(HistoryDb { conn })
+    }
+
+    /// Write the samples and failed polls in a single transaction: all of them
+    /// land or none do
+    pub fn insert(&mut self, samples: &[Sample], failures: &[Failure]) -> Result<()> {
+        let columns: Vec<&str> = influx::FIELDS.iter().map(|(_, name)| *name).collect();
+        let placeholders = vec!["?"; columns.len() + 2].join(", ");
+        let sql = format!("INSERT INTO samples (device, ts, {}) VALUES ({placeholders})", columns.join(", "));
+
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(&sql)?;
+            for sample in samples {
+                let mut params: Vec<&dyn ToSql> = vec![&sample.device, &sample.ts];
+                params.extend(sample.values.iter().map(|v| v as &dyn ToSql));
+                stmt.execute(params.as_slice())?;
+            }
+            let mut stmt = tx.prepare_cached("INSERT INTO failures (device, ts, error) VALUES (?, ?, ?)")?;
+            for f in failures {
+                stmt.execute((&f.device, f.ts, &f.error))?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Alert events as the notifiers got them, for `report`
+    pub fn insert_events(&mut self, events: &[AlertEvent]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO alert_events (device, ts, name, severity, firing, value, threshold)
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )?;
+            for e in events {
+                let row = (&e.device, unix_secs(e.at), e.name(), e.severity.name(), e.firing, e.value, e.threshold);
+                stmt.execute(row)?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Reboots spotted by the uptime going backwards, stamped with when the
+    /// miner came back up
+    pub fn insert_reboots(&mut self, reboots: &[Reboot]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt =
+                tx.prepare_cached("INSERT INTO reboots (device, ts, uptime_before, requested) VALUES (?, ?, ?, ?)")?;
+            for r in reboots {
+                stmt.execute((&r.device, unix_secs(r.booted), r.uptime_before, r.requested))?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Each device's uptime in its latest sample that reported one
+    pub fn last_uptimes(&self) -> Result<Vec<(String, f64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT device, uptime_seconds, MAX(ts) FROM samples
+             WHERE uptime_seconds IS NOT NULL GROUP BY device ORDER BY device",
+        )?;
+        let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Each device's latest share count and when it last changed: the first
+    /// sample of the run of that count. A count that never changed dates from
+    /// the device's first sample.
+    pub fn last_shares(&self) -> Result<Vec<Seed>> {
+        let mut stmt = self.conn.prepare(
+            "WITH latest AS (
+                SELECT device, shares_accepted AS accepted, MAX(ts) FROM samples
+                WHERE shares_accepted IS NOT NULL GROUP BY device
+            )
+            SELECT l.device, l.accepted, (
+                SELECT MIN(s.ts) FROM samples s
+                WHERE s.device = l.device AND s.shares_accepted IS NOT NULL AND s.ts > COALESCE((
+                    SELECT MAX(p.ts) FROM samples p WHERE p.device = l.device AND p.shares_accepted != l.accepted
+                ), -1)
+            ) FROM latest l ORDER BY l.device",
+        )?;
+        let rows = stmt.query_map([], |r| {
+            Ok(Seed { device: r.get(0)?, accepted: r.get(1)?, last_share: at(r.get(2)?) })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+/// The given database, or history.db next to the config file
+pub fn db_path(path: Option<&Path>) -> Result<PathBuf> {
+    match path {
+        Some(p) => Ok(p.to_path_buf()),
+        None => default_path().context("Can't locate the config directory; pass --db PATH"),
+    }
+}
+
+/// How often a `Recorder` prunes when `history_retention` is set
+const PRUNE_EVERY: Duration = Duration::from_secs(24 * 3600);
+
+/// The database as written by a polling loop (`log --db`, `daemon`). With
+/// `history_retention` set it is pruned on the first write and then once a day.
+pub struct Recorder {
+    db: HistoryDb,
+    retention: Option<Duration>,
+    last_prune: Option<Instant>,
+    /// Picks up where the database left off, so reboots while nothing was
+    /// recording are caught on the first sample
+    reboots: reboot::Tracker,
+}
+
+impl Recorder {
+    /// Open `path`, or history.db next to the config file
+    pub fn open(path: Option<&Path>, cfg: &AppConfig) -> Result<Self> {
+        let db = HistoryDb::open(&db_path(path)?)?;
+        let mut reboots = reboot::Tracker::new(reboot::log_path());
+        reboots.seed(&db.last_uptimes()?);
+        Ok(Recorder { db, retention: retention(cfg)?, last_prune: None, reboots })
+    }
+
+    pub fn record(&mut self, samples: &[Sample], failures: &[Failure]) -> Result<()> {
+        if let Some(keep) = self.retention
+            && self.last_prune.is_none_or(|t| t.elapsed() >= PRUNE_EVERY)
+        {
+            let cutoff = SystemTime::now().checked_sub(keep).unwrap_or(UNIX_EPOCH);
+            let removed: i64 = self.db.prune(unix_secs(cutoff), &[], false)?.iter().map(|(_, n)| n).sum();
+            if removed > 0 {
+                eprintln!("Pruned {removed} samples older than {}", rfc3339(cutoff));
+            }
+            self.last_prune = Some(Instant::now());
+        }
+        self.db.insert(samples, failures)?;
+        let uptime = influx::FIELDS.iter().position(|(key, _)| *key == "uptimeSeconds");
+        let reboots: Vec<Reboot> = samples
+            .iter()
+            .filter_map(|s| {
+                let value = s.values.get(uptime?).copied().flatten()?;
+                self.reboots.observe_uptime(&s.device, value, at(s.ts))
+            })
+            .collect();
+        if reboots.is_empty() { Ok(()) } else { self.db.insert_reboots(&reboots) }
+    }
+}
+
+/// Rows matching a `history` query. `metrics` are column names from
+/// `influx::FIELDS`; with `resolution` each row is the average of one bucket.
+pub struct Query {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub devices: Vec<String>,
+    pub metrics: Vec<&'static str>,
+    /// Bucket width in seconds
+    pub resolution: Option<i64>,
+}
+
+/// One sample (or bucket average); `values` follows `Query::metrics`
+pub struct Row {
+    pub device: String,
+    pub ts: i64,
+    pub values: Vec<Option<f64>>,
+}
+
+impl HistoryDb {
+    pub fn query(&self, q: &Query) -> Result<Vec<Row>> {
+        let (time, values) = match q.resolution {
+            Some(r) => {
+                let avgs: Vec<String> = q.metrics.iter().map(|m| format!("AVG({m})")).collect();
+                (format!("ts / {r} * {r}"), avgs.join(", "))
+            }
+            None => ("ts".to_string(), q.metrics.join(", ")),
+        };
+
+        let mut filters = Vec::new();
+        let mut params = Vec::new();
+        if let Some(from) = q.from {
+            filters.push("ts >= ?".to_string());
+            params.push(SqlValue::Integer(from));
+        }
+        if let Some(to) = q.to {
+            filters.push("ts < ?".to_string());
+            params.push(SqlValue::Integer(to));
+        }
+        if !q.devices.is_empty() {
+            filters.push(format!("device IN ({})", vec!["?"; q.devices.len()].join(", ")));
+            params.extend(q.devices.iter().map(|d| SqlValue::Text(d.clone())));
+        }
+
+        let mut sql = format!("SELECT device, {time} AS t, {values} FROM samples");
+        if !filters.is_empty() {
+            sql += &format!(" WHERE {}", filters.join(" AND "));
+        }
+        if q.resolution.is_some() {
+            sql += " GROUP BY device, t";
+        }
+        sql += " ORDER BY t, device";
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(params), |r| {
+            let values = (0..q.metrics.len()).map(|i| r.get(i + 2)).collect::<rusqlite::Result<_>>()?;
+            Ok(Row { device: r.get(0)?, ts: r.get(1)?, values })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Devices with samples, failed polls or alert events in [from, to)
+    pub fn devices_between(&self, from: i64, to: i64) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT device FROM samples WHERE ts >= ?1 AND ts < ?2
+             UNION SELECT device FROM failures WHERE ts >= ?1 AND ts < ?2
+             UNION SELECT device FROM alert_events WHERE ts >= ?1 AND ts < ?2
+             UNION SELECT device FROM reboots WHERE ts >= ?1 AND ts < ?2
+             ORDER BY device",
+        )?;
+        let rows = stmt.query_map((from, to), |r| r.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// When `device` didn't answer in [from, to), oldest first
+    pub fn failures(&self, device: &str, from: i64, to: i64) -> Result<Vec<i64>> {
+        let mut stmt =
+            self.conn.prepare("SELECT ts FROM failures WHERE device = ? AND ts >= ? AND ts < ? ORDER BY ts")?;
+        let rows = stmt.query_map((device, from, to), |r| r.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Alert events of `device` in [from, to), oldest first
+    pub fn events(&self, device: &str, from: i64, to: i64) -> Result<Vec<StoredEvent>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT ts, name, severity, firing, value, threshold FROM alert_events
+             WHERE device = ? AND ts >= ? AND ts < ? ORDER BY ts, id",
+        )?;
+        let rows = stmt.query_map((device, from, to), |r| {
+            Ok(StoredEvent {
+                ts: r.get(0)?,
+                name: r.get(1)?,
+                severity: r.get(2)?,
+                firing: r.get(3)?,
+                value: r.get(4)?,
+                threshold: r.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Whether each reboot of `device` in [from, to) was requested, oldest first
+    pub fn reboots(&self, device: &str, from: i64, to: i64) -> Result<Vec<bool>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT requested FROM reboots WHERE device = ? AND ts >= ? AND ts < ? ORDER BY ts")?;
+        let rows = stmt.query_map((device, from, to), |r| r.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Delete samples older than `cutoff` (Unix seconds), optionally only for
+    /// some devices, along with failed polls, alert events and reboots from then.
+    /// Returns the sample count per device; `dry_run` only counts.
+    pub fn prune(&mut self, cutoff: i64, devices: &[String], dry_run: bool) -> Result<Vec<(String, i64)>> {
+        let mut filter = "ts < ?".to_string();
+        let mut params = vec![SqlValue::Integer(cutoff)];
+        if !devices.is_empty() {
+            filter += &format!(" AND device IN ({})", vec!["?"; devices.len()].join(", "));
+            params.extend(devices.iter().map(|d| SqlValue::Text(d.clone())));
+        }
+
+        let tx = self.conn.transaction()?;
+        let counts = {
+            let mut stmt = tx.prepare(&format!("SELECT device, count(*) FROM samples WHERE {filter} GROUP BY device ORDER BY device"))?;
+            let rows = stmt.query_map(params_from_iter(&params), |r| Ok((r.get(0)?, r.get(1)?)))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        if !dry_run {
+            for table in ["samples", "failures", "alert_events", "reboots"] {
+                tx.execute(&format!("DELETE FROM {table} WHERE {filter}"), params_from_iter(&params))?;
+            }
+            tx.commit()?;
+        }
+        Ok(counts)
+    }
+
+    /// Rewrite the file so space freed by pruning goes back to the disk
+    pub fn vacuum(&self) -> Result<()> {
+        self.conn.execute_batch("VACUUM")?;
+        Ok(())
+    }
+}
+
+/// `history`: read samples back out of the database, or maintain it
+pub fn run(args: &HistoryArgs, devices: &[String], cfg: &AppConfig) -> Result<()> {
+    let path = db_path(args.db.as_deref())?;
+    if !path.exists() {
+        bail!("No history database at {} (record samples with `log --db`)", path.display());
+    }
+    let mut db = HistoryDb::open(&path)?;
+
+    match &args.command {
+        None => {
+            let query = args.range.query(devices, DEFAULT_METRICS.to_vec())?;
+            let rows = db.query(&query)?;
+            if rows.is_empty() {
+                eprintln!("No samples in {} match the given range and devices.", path.display());
+            }
+            let mut out = io::stdout().lock();
+            if args.json {
+                write_json(&mut out, &query.metrics, &rows)?;
+            } else if args.csv {
+                write_csv(&mut out, &query.metrics, &rows)?;
+            } else if !rows.is_empty() {
+                print_table(&query.metrics, &rows);
+            }
+            Ok(())
+        }
+        Some(HistoryCommand::Export { range, format, output }) => {
+            let query = range.query(devices, all_metrics())?;
+            let rows = db.query(&query)?;
+            if rows.is_empty() {
+                eprintln!("No samples in {} match the given range and devices.", path.display());
+            }
+            let mut out: Box<dyn Write> = match output {
+                Some(file) => Box::new(BufWriter::new(
+                    File::create(file).with_context(|| format!("Failed to create {}", file.display()))?,
+                )),
+                None => Box::new(io::stdout().lock()),
+            };
+            match format {
+                ExportFormat::Csv => write_csv(&mut out, &query.metrics, &rows)?,
+                ExportFormat::Json => write_json(&mut out, &query.metrics, &rows)?,
+            }
+            out.flush()?;
+            if let Some(file) = output {
+                eprintln!("Exported {} rows to {}", rows.len(), file.display());
+            }
+            Ok(())
+        }
+        Some(HistoryCommand::Prune { keep, dry_run, vacuum }) => {
+            let keep = match keep {
+                Some(keep) => *keep,
+                None => retention(cfg)?.context("Pass --keep (ex: 90d) or set history_retention in the config")?,
+            };
+            let cutoff = unix_now() - keep.as_secs() as i64;
+            let removed = db.prune(cutoff, devices, *dry_run)?;
+            let total: i64 = removed.iter().map(|(_, n)| n).sum();
+            let verb = if *dry_run { "Would delete" } else { "Deleted" };
+            for (device, n) in &removed {
+                println!("{device}: {n}");
+            }
+            println!("{verb} {total} samples older than {}.", rfc3339(at(cutoff)));
+            if *vacuum && !*dry_run {
+                db.vacuum()?;
+                println!("Compacted {}.", path.display());
+            }
+            Ok(())
+        }
+    }
+}
+
+/// `history_retention` from the config, if set
+pub fn retention(cfg: &AppConfig) -> Result<Option<Duration>> {
+    cfg.history_retention
+        .as_deref()
+        .map(|s| parse_duration(s).map_err(|e| anyhow::anyhow!("history_retention in config: {e}")))
+        .transpose()
+}
+
+pub fn unix_secs(t: SystemTime) -> i64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+pub fn unix_now() -> i64 {
+    unix_secs(SystemTime::now())
+}
+
+fn all_metrics() -> Vec<&'static str> {
+    influx::FIELDS.iter().map(|(_, column)| *column).collect()
+}
+
+/// Accept an AxeOS key (`hashRate`) or a column name (`hashrate`)
+fn metric_column(name: &str) -> Result<&'static str> {
+    influx::FIELDS
+        .iter()
+        .find(|(key, column)| key.eq_ignore_ascii_case(name) || column.eq_ignore_ascii_case(name))
+        .map(|(_, column)| *column)
+        .ok_or_else(|| {
+            let known: Vec<&str> = influx::FIELDS.iter().map(|(key, _)| *key).collect();
+            anyhow::anyhow!("Unknown metric '{name}' (known: {})", known.join(", "))
+        })
+}
+
+/// A UTC timestamp (`2024-05-01`, `2024-05-01T12:00`, `2024-05-01 12:00:30Z`)
+/// or an age counted back from `now` (`24h`, `7d`), as Unix seconds
+pub fn parse_time(s: &str, now: i64) -> Result<i64> {
+    let s = s.trim();
+    if s.chars().next().is_some_and(|c| c.is_ascii_digit()) && !s.contains('-') {
+        let age = parse_duration(s).map_err(anyhow::Error::msg)?;
+        return Ok(now - age.as_secs() as i64);
+    }
+
+    let s = s.strip_suffix(['Z', 'z']).unwrap_or(s);
+    let (date, time) = match s.split_once(['T', 't', ' ']) {
+        Some((d, t)) => (d, Some(t)),
+        None => (s, None),
+    };
+    let format_hint = "expected YYYY-MM-DD, YYYY-MM-DDTHH:MM[:SS] (UTC) or an age like 24h";
+
+    let parts: Vec<&str> = date.split('-').collect();
+    let [y, m, d] = parts.as_slice() else { bail!(format_hint) };
+    let (Ok(year), Ok(month), Ok(day)) = (y.parse::<i64>(), m.parse::<i64>(), d.parse::<i64>()) else {
+        bail!(format_hint)
+    };
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        bail!("{date} is not a valid date");
+    }
+
+    let mut secs = 0;
+    if let Some(time) = time {
+        let parts: Vec<&str> = time.split(':').collect();
+        if !(2..=3).contains(&parts.len()) {
+            bail!(format_hint);
+        }
+        let limits = [24, 60, 60];
+        for (i, part) in parts.iter().enumerate() {
+            let Ok(n) = part.parse::<i64>() else { bail!(format_hint) };
+            if !(0..limits[i]).contains(&n) {
+                bail!("{time} is not a valid time of day");
+            }
+            secs += n * [3600, 60, 1][i];
+        }
+    }
+    Ok(days_from_civil(year, month, day) * 86_400 + secs)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 (the inverse of the calendar math in `rfc3339`)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+pub fn at(ts: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(ts.max(0) as u64)
+}
+
+fn write_csv(out: &mut impl Write, metrics: &[&str], rows: &[Row]) -> io::Result<()> {
+    writeln!(out, "time,device,{}", metrics.join(","))?;
+    for row in rows {
+        let values: Vec<String> = row.values.iter().map(|v| v.map(|v| v.to_string()).unwrap_or_default()).collect();
+        writeln!(out, "{},{},{}", rfc3339(at(row.ts)), csv_field(&row.device), values.join(","))?;
+    }
+    Ok(())
+}
+
+fn write_json(out: &mut impl Write, metrics: &[&str], rows: &[Row]) -> Result<()> {
+    writeln!(out, "{}", output::json(&rows_json(metrics, rows))?)?;
+    Ok(())
+}
+
+fn rows_json(metrics: &[&str], rows: &[Row]) -> Value {
+    let rows: Vec<Value> = rows
+        .iter()
+        .map(|row| {
+            let mut obj = Map::new();
+            obj.insert("time".into(), json!(rfc3339(at(row.ts))));
+            obj.insert("device".into(), json!(row.device));
+            for (metric, value) in metrics.iter().zip(&row.values) {
+                obj.insert(metric.to_string(), json!(value));
+            }
+            Value::Object(obj)
+        })
+        .collect();
+    Value::Array(rows)
+}
+
+fn print_table(metrics: &[&str], rows: &[Row]) {
+    let name_width = rows.iter().map(|r| r.device.len()).max().unwrap_or(0).max("DEVICE".len());
+    let widths: Vec<usize> = metrics.iter().map(|m| m.len().max(10)).collect();
+
+    let mut header = format!("{:<20}  {:<name_width$}", "TIME", "DEVICE");
+    for (metric, width) in metrics.iter().zip(&widths) {
+        header += &format!("  {:>width$}", metric.to_uppercase());
+    }
+    println!("{header}");
+
+    for row in rows {
+        let mut line = format!("{:<20}  {:<name_width$}", rfc3339(at(row.ts)), row.device);
+        for (value, width) in row.values.iter().zip(&widths) {
+            let text = value.map(|v| format!("{v:.2}")).unwrap_or_else(|| "-".to_string());
+            line += &format!("  {text:>width$}");
+        }
+        println!("{line}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::alert::{Condition, Severity};
+
+    fn memory_db() -> HistoryDb {
+        HistoryDb::init(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn sample(device: &str, ts: i64, hashrate: f64) -> Sample {
+        Sample::new(device, at(ts), &json!({"hashRate": hashrate, "temp": 60.0}))
+    }
+
+    #[test]
+    fn dates_and_ages_parse() {
+        let now = 1_714_060_800;
+        assert_eq!(parse_time("2024-04-25", now).unwrap(), 1_714_003_200);
+        assert_eq!(parse_time("2024-04-25T16:00", now).unwrap(), now);
+        assert_eq!(parse_time("2024-04-25 16:00:30Z", now).unwrap(), now + 30);
+        assert_eq!(parse_time("2024-02-29", now).unwrap(), 1_709_164_800);
+        assert_eq!(parse_time("24h", now).unwrap(), now - 86_400);
+        assert_eq!(parse_time("7d", now).unwrap(), now - 7 * 86_400);
+    }
+
+    #[test]
+    fn bad_dates_are_rejected() {
+        for bad in ["2023-02-29", "2024-13-01", "2024-04-25T24:00", "yesterday", "2024-04", "5 parsecs"] {
+            assert!(parse_time(bad, 0).is_err(), "{bad} should not parse");
+        }
+    }
+
+    #[test]
+    fn metrics_match_keys_or_columns() {
+        assert_eq!(metric_column("hashRate").unwrap(), "hashrate");
+        assert_eq!(metric_column("vr_temp").unwrap(), "vr_temp");
+        assert_eq!(metric_column("VRTEMP").unwrap(), "vr_temp");
+        assert!(metric_column("nonce").is_err());
+    }
+
+    #[test]
+    fn query_filters_and_buckets() {
+        let mut db = memory_db();
+        db.insert(&[
+            sample("gamma", 1000, 500.0),
+            sample("gamma", 1100, 510.0),
+            sample("gamma", 1300, 530.0),
+            sample("ultra", 1100, 400.0),
+        ], &[])
+        .unwrap();
+
+        let mut q = Query { from: Some(1050), to: None, devices: vec!["gamma".into()], metrics: vec!["hashrate"], resolution: None };
+        let rows = db.query(&q).unwrap();
+        assert_eq!(rows.iter().map(|r| r.ts).collect::<Vec<_>>(), vec![1100, 1300]);
+
+        q.from = None;
+        q.devices.clear();
+        q.resolution = Some(300);
+        let rows = db.query(&q).unwrap();
+        let got: Vec<(&str, i64, Option<f64>)> = rows.iter().map(|r| (r.device.as_str(), r.ts, r.values[0])).collect();
+        assert_eq!(got, vec![("gamma", 900, Some(505.0)), ("ultra", 900, Some(400.0)), ("gamma", 1200, Some(530.0))]);
+    }
+
+    #[test]
+    fn prune_only_removes_older_samples() {
+        let mut db = memory_db();
+        db.insert(
+            &[sample("gamma", 100, 1.0), sample("gamma", 200, 2.0), sample("ultra", 150, 3.0), sample("ultra", 300, 4.0)],
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(db.prune(200, &[], true).unwrap(), vec![("gamma".into(), 1), ("ultra".into(), 1)]);
+        let q = Query { from: None, to: None, devices: vec![], metrics: vec!["hashrate"], resolution: None };
+        assert_eq!(db.query(&q).unwrap().len(), 4, "dry run deletes nothing");
+
+        db.prune(200, &[], false).unwrap();
+        let left: Vec<i64> = db.query(&q).unwrap().iter().map(|r| r.ts).collect();
+        // The sample exactly at the cutoff is kept
+        assert_eq!(left, vec![200, 300]);
+    }
+
+    #[test]
+    fn last_share_is_where_the_count_last_changed() {
+        let mut db = memory_db();
+        let shares = |device: &str, ts: i64, accepted: f64| {
+            Sample::new(device, at(ts), &json!({"sharesAccepted": accepted}))
+        };
+        db.insert(&[
+            shares("gamma", 100, 10.0),
+            shares("gamma", 200, 12.0),
+            shares("gamma", 300, 12.0),
+            shares("gamma", 400, 12.0),
+            shares("ultra", 150, 7.0),
+            shares("ultra", 250, 7.0),
+            sample("nano", 100, 1.0),
+        ], &[])
+        .unwrap();
+        let seeds = db.last_shares().unwrap();
+        assert_eq!(
+            seeds,
+            vec![
+                Seed { device: "gamma".into(), accepted: 12.0, last_share: at(200) },
+                Seed { device: "ultra".into(), accepted: 7.0, last_share: at(150) },
+            ]
+        );
+    }
+
+    #[test]
+    fn failures_and_events_are_kept_for_reports() {
+        let mut db = memory_db();
+        let failed = Failure { device: "ultra".into(), ts: 150, error: "timed out".into() };
+        db.insert(&[sample("gamma", 100, 1.0)], &[failed]).unwrap();
+        let event = AlertEvent {
+            device: "gamma".into(),
+            condition: Condition::TempAbove,
+            rule: None,
+            severity: Severity::Critical,
+            firing: true,
+            value: Some(71.5),
+            threshold: Some(70.0),
+            since: at(200),
+            at: at(200),
+        };
+        db.insert_events(&[event]).unwrap();
+
+        assert_eq!(db.devices_between(0, 1000).unwrap(), ["gamma", "ultra"]);
+        assert_eq!(db.devices_between(120, 1000).unwrap(), ["gamma", "ultra"]);
+        assert_eq!(db.failures("ultra", 0, 1000).unwrap(), [150]);
+        let stored = StoredEvent {
+            ts: 200,
+            name: "temp_above".into(),
+            severity: "critical".into(),
+            firing: true,
+            value: Some(71.5),
+            threshold: Some(70.0),
+        };
+        assert_eq!(db.events("gamma", 0, 1000).unwrap(), [stored]);
+
+        let reboot = |ts, requested| Reboot { device: "ultra".into(), uptime_before: 900.0, booted: at(ts), requested };
+        db.insert_reboots(&[reboot(160, false), reboot(170, true)]).unwrap();
+        assert_eq!(db.reboots("ultra", 0, 1000).unwrap(), [false, true]);
+        assert_eq!(db.reboots("ultra", 165, 1000).unwrap(), [true]);
+
+        db.prune(300, &[], false).unwrap();
+        assert!(db.devices_between(0, 1000).unwrap().is_empty());
+    }
+
+    #[test]
+    fn migrations_run_once() {
+        let db = memory_db();
+        let version: i64 = db.conn.pragma_query_value(None, "user_version", |r| r.get(0)).unwrap();
+        assert_eq!(version, MIGRATIONS.len() as i64);
+        // Re-running on an up-to-date database is a no-op
+        let db = HistoryDb::init(db.conn).unwrap();
+        let tables: i64 = db
+            .conn
+            .query_row("SELECT count(*) FROM sqlite_master WHERE name = 'samples'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(tables, 1);
+    }
+
+    #[test]
+    fn samples_round_trip() {
+        let mut db = memory_db();
+        let info = json!({"hashRate": 512.5, "temp": 61.0, "uptimeSeconds": 3600});
+        let at = UNIX_EPOCH + std::time::Duration::from_secs(1_714_060_800);
+        db.insert(&[Sample::new("gamma", at, &info), Sample::new("ultra", at, &json!({}))], &[]).unwrap();
+
+        let (device, ts, hashrate, power): (String, i64, Option<f64>, Option<f64>) = db
+            .conn
+            .query_row("SELECT device, ts, hashrate, power FROM samples ORDER BY id LIMIT 1", [], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?))
+            })
+            .unwrap();
+        assert_eq!((device.as_str(), ts, hashrate, power), ("gamma", 1_714_060_800, Some(512.5), None));
+        let rows: i64 = db.conn.query_row("SELECT count(*) FROM samples", [], |r| r.get(0)).unwrap();
+        assert_eq!(rows, 2);
+    }
+}
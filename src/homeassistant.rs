@@ -0,0 +1,208 @@
+use serde_json::{Value, json};
+use crate::{get_number, get_str};
+
+/// Topic prefix Home Assistant listens on for discovery unless reconfigured
+pub const DEFAULT_PREFIX: &str = "homeassistant";
+
+/// One Home Assistant sensor, read from the `<prefix>/<device>/status` JSON
+struct Sensor {
+    /// Last part of the entity id, ex: bitaxe_gamma_hashrate
+    id: &'static str,
+    name: &'static str,
+    /// Key in the status payload (and in AxeOS' info)
+    key: &'static str,
+    unit: Option<&'static str>,
+    device_class: Option<&'static str>,
+    state_class: &'static str,
+    diagnostic: bool,
+}
+
+const SENSORS: &[Sensor] = &[
+    Sensor {
+        id: "hashrate",
+        name: "Hashrate",
+        key: "hashRate",
+        unit: Some("GH/s"),
+        device_class: None,
+        state_class: "measurement",
+        diagnostic: false,
+    },
+    Sensor {
+        id: "temp",
+        name: "ASIC temperature",
+        key: "temp",
+        unit: Some("°C"),
+        device_class: Some("temperature"),
+        state_class: "measurement",
+        diagnostic: false,
+    },
+    Sensor {
+        id: "vr_temp",
+        name: "VR temperature",
+        key: "vrTemp",
+        unit: Some("°C"),
+        device_class: Some("temperature"),
+        state_class: "measurement",
+        diagnostic: false,
+    },
+    Sensor {
+        id: "power",
+        name: "Power",
+        key: "power",
+        unit: Some("W"),
+        device_class: Some("power"),
+        state_class: "measurement",
+        diagnostic: false,
+    },
+    // Share counters restart from zero on reboot, which total_increasing expects
+    Sensor {
+        id: "shares_accepted",
+        name: "Shares accepted",
+        key: "sharesAccepted",
+        unit: None,
+        device_class: None,
+        state_class: "total_increasing",
+        diagnostic: false,
+    },
+    Sensor {
+        id: "shares_rejected",
+        name: "Shares rejected",
+        key: "sharesRejected",
+        unit: None,
+        device_class: None,
+        state_class: "total_increasing",
+        diagnostic: false,
+    },
+    Sensor {
+        id: "wifi_rssi",
+        name: "WiFi signal",
+        key: "wifiRSSI",
+        unit: Some("dBm"),
+        device_class: Some("signal_strength"),
+        state_class: "measurement",
+        diagnostic: true,
+    },
+];
+
+/// Entity ids only allow letters, digits, `_` and `-`
+fn object_id(device: &str) -> String {
+    let id: String = device
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    format!("bitaxe_{id}")
+}
+
+fn config_topic(ha_prefix: &str, device: &str, sensor: &Sensor) -> String {
+    format!("{ha_prefix}/sensor/{}_{}/config", object_id(device), sensor.id)
+}
+
+/// Retained discovery configs, as (topic, payload), for the sensors this
+/// miner actually reports. All of them share one `device` block so Home
+/// Assistant groups them; they go unavailable when either the bridge
+/// (`availability`) or the miner itself is offline.
+pub fn discovery(
+    ha_prefix: &str,
+    state_topic: &str,
+    availability: &str,
+    device: &str,
+    info: &Value,
+) -> Vec<(String, String)> {
+    let mut block = json!({
+        "identifiers": [object_id(device)],
+        "name": device,
+        "manufacturer": "Bitaxe",
+    });
+    if let Some(model) = get_str(info, "ASICModel") {
+        block["model"] = json!(model);
+    }
+    if let Some(version) = get_str(info, "version") {
+        block["sw_version"] = json!(version);
+    }
+
+    SENSORS
+        .iter()
+        .filter(|s| get_number(info, s.key).is_some())
+        .map(|s| {
+            let mut config = json!({
+                "name": s.name,
+                "unique_id": format!("{}_{}", object_id(device), s.id),
+                "state_topic": state_topic,
+                "value_template": format!("{{{{ value_json.{} }}}}", s.key),
+                "state_class": s.state_class,
+                "availability": [
+                    {"topic": availability},
+                    {
+                        "topic": state_topic,
+                        "value_template": "{{ 'online' if value_json.online else 'offline' }}",
+                    },
+                ],
+                "availability_mode": "all",
+                "device": block,
+            });
+            if let Some(unit) = s.unit {
+                config["unit_of_measurement"] = json!(unit);
+            }
+            if let Some(class) = s.device_class {
+                config["device_class"] = json!(class);
+            }
+            if s.diagnostic {
+                config["entity_category"] = json!("diagnostic");
+            }
+            (config_topic(ha_prefix, device, s), config.to_string())
+        })
+        .collect()
+}
+
+/// Every config topic a device could have, for `--ha-cleanup` to empty
+pub fn all_topics(ha_prefix: &str, device: &str) -> Vec<String> {
+    SENSORS.iter().map(|s| config_topic(ha_prefix, device, s)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configs(info: Value) -> Vec<(String, Value)> {
+        discovery("homeassistant", "bitaxe/Gamma 1/status", "bitaxe/availability", "Gamma 1", &info)
+            .into_iter()
+            .map(|(topic, payload)| (topic, serde_json::from_str(&payload).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn only_reported_metrics_get_entities() {
+        let out = configs(json!({"hashRate": 512.5, "temp": 61, "sharesAccepted": 10, "ASICModel": "BM1370"}));
+        let topics: Vec<&str> = out.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(
+            topics,
+            [
+                "homeassistant/sensor/bitaxe_gamma_1_hashrate/config",
+                "homeassistant/sensor/bitaxe_gamma_1_temp/config",
+                "homeassistant/sensor/bitaxe_gamma_1_shares_accepted/config",
+            ]
+        );
+        assert_eq!(all_topics("homeassistant", "Gamma 1").len(), SENSORS.len());
+    }
+
+    #[test]
+    fn sensors_carry_units_classes_and_one_device() {
+        let out = configs(json!({"temp": 61, "sharesRejected": 2, "wifiRSSI": -55, "ASICModel": "BM1370"}));
+        let (_, temp) = &out[0];
+        assert_eq!(temp["unit_of_measurement"], "°C");
+        assert_eq!(temp["device_class"], "temperature");
+        assert_eq!(temp["state_class"], "measurement");
+        assert_eq!(temp["value_template"], "{{ value_json.temp }}");
+        assert_eq!(temp["device"]["identifiers"][0], "bitaxe_gamma_1");
+        assert_eq!(temp["device"]["model"], "BM1370");
+
+        let (_, rejected) = &out[1];
+        assert_eq!(rejected["state_class"], "total_increasing");
+        assert!(rejected.get("unit_of_measurement").is_none());
+        assert_eq!(rejected["device"], temp["device"]);
+
+        let (_, rssi) = &out[2];
+        assert_eq!(rssi["device_class"], "signal_strength");
+        assert_eq!(rssi["entity_category"], "diagnostic");
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::Result;
+use reqwest::Client;
+use serde_json::json;
+use crate::{confirm, fetch_info, get_str, patch_system};
+
+/// Longest hostname the ESP32 network stack keeps (DHCP and mDNS use it)
+const MAX_LEN: usize = 32;
+
+/// A single DNS label: letters, digits and hyphens, not starting or ending
+/// with a hyphen. Dots would make mDNS announce a different name.
+pub fn parse(s: &str) -> Result<String, String> {
+    if s.is_empty() {
+        return Err("the hostname can't be empty".into());
+    }
+    if s.len() > MAX_LEN {
+        return Err(format!("'{s}' is {} characters long; the miner keeps at most {MAX_LEN}", s.len()));
+    }
+    if let Some(c) = s.chars().find(|c| !c.is_ascii_alphanumeric() && *c != '-') {
+        return Err(format!("'{s}' contains '{c}'; use letters, digits and '-'"));
+    }
+    if s.starts_with('-') || s.ends_with('-') {
+        return Err(format!("'{s}' can't start or end with '-'"));
+    }
+    Ok(s.to_string())
+}
+
+/// Show the current and new name, confirm, PATCH the new one
+pub async fn run(client: &Client, host: &str, name: &str, yes: bool) -> Result<()> {
+    let info = fetch_info(client, host).await?;
+    let old = get_str(&info, "hostname").unwrap_or("(not reported)");
+    if old == name {
+        println!("Hostname is already '{name}'.");
+        return Ok(());
+    }
+
+    println!("Hostname: {old} -> {name}");
+    if !yes && !confirm("Rename the miner?")? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    patch_system(client, host, &json!({"hostname": name})).await?;
+    println!("Hostname changed from '{old}' to '{name}'.");
+    println!("Restart the miner (`bitaxe-cli restart`) for the network and mDNS to use the new name.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_plain_dns_labels_are_accepted() {
+        assert_eq!(parse("bitaxe-07"), Ok("bitaxe-07".into()));
+        assert_eq!(parse(&"a".repeat(MAX_LEN)).map(|s| s.len()), Ok(MAX_LEN));
+        assert!(parse(&"a".repeat(MAX_LEN + 1)).unwrap_err().contains("at most 32"));
+        assert!(parse("").is_err());
+        assert!(parse("rack_1").unwrap_err().contains("contains '_'"));
+        assert!(parse("gamma.local").is_err());
+        assert!(parse("my miner").is_err());
+        assert!(parse("-gamma").unwrap_err().contains("start or end"));
+        assert!(parse("gamma-").is_err());
+        assert!(parse("bitaxé").is_err());
+    }
+}
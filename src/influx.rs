@@ -0,0 +1,154 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use serde_json::Value;
+use crate::client::{DEFAULT_TIMEOUT, Target, bare_host};
+use crate::config_file::AppConfig;
+use crate::fleet::{FleetOpts, fetch_fleet};
+use crate::{check_response, get_number, get_str, runtime};
+
+/// AxeOS key -> line protocol field name (also the CSV log columns)
+pub const FIELDS: &[(&str, &str)] = &[
+    ("hashRate", "hashrate"),
+    ("temp", "temp"),
+    ("vrTemp", "vr_temp"),
+    ("power", "power"),
+    ("voltage", "voltage"),
+    ("frequency", "frequency"),
+    ("coreVoltage", "core_voltage"),
+    ("coreVoltageActual", "core_voltage_actual"),
+    ("sharesAccepted", "shares_accepted"),
+    ("sharesRejected", "shares_rejected"),
+    ("wifiRSSI", "wifi_rssi"),
+    ("uptimeSeconds", "uptime_seconds"),
+];
+
+/// Build one InfluxDB line protocol record stamped now, ex:
+/// `bitaxe,asic_model=BM1370,device=gamma,host=192.168.1.50,hostname=bitaxe hashrate=512.3,temp=61 1714060800000000000`.
+///
+/// Tags are the values that identify a miner and rarely change (`device`
+/// for configured devices, `host`, and the reported `hostname` and
+/// `asic_model`); everything measured is a field, so series cardinality
+/// stays at one per miner. Absent values are skipped; `None` when the
+/// payload has no numeric fields at all.
+pub fn line(host: &str, device: Option<&str>, info: &Value) -> Option<String> {
+    line_at(host, device, info, SystemTime::now())
+}
+
+/// `line` with an explicit timestamp (written in nanoseconds)
+pub fn line_at(host: &str, device: Option<&str>, info: &Value, at: SystemTime) -> Option<String> {
+    let fields: Vec<String> = FIELDS
+        .iter()
+        .filter_map(|(key, field)| get_number(info, key).map(|v| format!("{field}={v}")))
+        .collect();
+    if fields.is_empty() {
+        return None;
+    }
+
+    let ts = at.duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    // Tags in key order, as Influx recommends
+    let tags = [
+        ("asic_model", get_str(info, "ASICModel")),
+        ("device", device),
+        ("host", Some(bare_host(host))),
+        ("hostname", get_str(info, "hostname")),
+    ];
+    let tags: String = tags
+        .iter()
+        .filter_map(|(key, value)| value.filter(|v| !v.is_empty()).map(|v| format!(",{key}={}", escape_tag(v))))
+        .collect();
+    Some(format!("bitaxe{tags} {} {ts}", fields.join(",")))
+}
+
+/// Tag values must escape commas, equals signs and spaces
+fn escape_tag(v: &str) -> String {
+    v.replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+/// `push influx`: where to write. Flags override the [influx] config table.
+#[derive(Args, Debug)]
+pub struct PushArgs {
+    /// InfluxDB base URL, ex: http://localhost:8086
+    #[arg(long)]
+    url: Option<String>,
+
+    #[arg(long)]
+    org: Option<String>,
+
+    #[arg(long)]
+    bucket: Option<String>,
+
+    /// API token with write access to the bucket
+    #[arg(long)]
+    token: Option<String>,
+
+    #[command(flatten)]
+    pub opts: FleetOpts,
+}
+
+/// Poll every target once and POST the lines to InfluxDB v2's /api/v2/write.
+/// Unreachable devices are reported and skipped; it fails when none answered.
+pub fn push(args: &PushArgs, targets: &[Target], cfg: &AppConfig) -> Result<()> {
+    let conf = cfg.influx.as_ref();
+    let setting = |flag: &Option<String>, key: &str, from_cfg: Option<&String>| {
+        flag.clone()
+            .or_else(|| from_cfg.cloned())
+            .with_context(|| format!("Pass --{key} or set {key} in the [influx] table of the config"))
+    };
+    let url = setting(&args.url, "url", conf.and_then(|c| c.url.as_ref()))?;
+    let org = setting(&args.org, "org", conf.and_then(|c| c.org.as_ref()))?;
+    let bucket = setting(&args.bucket, "bucket", conf.and_then(|c| c.bucket.as_ref()))?;
+    let token = setting(&args.token, "token", conf.and_then(|c| c.token.as_ref()))?;
+
+    let mut lines = Vec::new();
+    // Results come back in target order
+    for (target, result) in targets.iter().zip(fetch_fleet(targets, &args.opts)) {
+        match &result.info {
+            Ok(info) => lines.extend(line_at(&target.host, target.alias.as_deref(), info, result.fetched_at)),
+            Err(e) => eprintln!("Warning: {}: {e}", result.name),
+        }
+    }
+    if lines.is_empty() {
+        bail!("No data to push: none of the {} device(s) answered", targets.len());
+    }
+
+    let client = reqwest::Client::builder().timeout(DEFAULT_TIMEOUT).build()?;
+    let write = client
+        .post(format!("{}/api/v2/write", url.trim_end_matches('/')))
+        .query(&[("org", org.as_str()), ("bucket", bucket.as_str()), ("precision", "ns")])
+        .header("Authorization", format!("Token {token}"))
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(lines.join("\n"))
+        .send();
+    runtime().block_on(async {
+        let resp = write.await.with_context(|| format!("Failed to reach InfluxDB at {url}"))?;
+        // Influx explains rejected lines in the body's `message`
+        check_response(resp, "InfluxDB write").await
+    })?;
+    println!("Wrote {} line(s) to {bucket}", lines.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use serde_json::json;
+
+    #[test]
+    fn identity_is_tags_measurements_are_fields() {
+        let info = json!({"hashRate": 512.5, "temp": 61, "hostname": "bitaxe gamma", "ASICModel": "BM1370"});
+        let at = UNIX_EPOCH + Duration::from_secs(1_714_060_800);
+        assert_eq!(
+            line_at("http://192.168.1.50", Some("gamma"), &info, at).unwrap(),
+            "bitaxe,asic_model=BM1370,device=gamma,host=192.168.1.50,hostname=bitaxe\\ gamma hashrate=512.5,temp=61 1714060800000000000"
+        );
+    }
+
+    #[test]
+    fn bare_hosts_have_no_device_tag() {
+        let line = line_at("http://10.0.0.2", None, &json!({"power": 14.2}), UNIX_EPOCH).unwrap();
+        assert_eq!(line, "bitaxe,host=10.0.0.2 power=14.2 0");
+        assert!(line_at("http://10.0.0.2", None, &json!({"hostname": "x"}), UNIX_EPOCH).is_none());
+    }
+}
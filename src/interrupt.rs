@@ -0,0 +1,73 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+
+/// Exit code after Ctrl-C, as shells report for SIGINT (128 + 2)
+pub const EXIT_INTERRUPTED: u8 = 130;
+
+/// Returned by long-running commands when they stop because of Ctrl-C
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl std::fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Ctrl-C flag for loops that take over the terminal. Installing it replaces
+/// the default "kill immediately" behavior, so the loop gets a chance to
+/// leave the screen in a sane state before exiting.
+#[derive(Clone)]
+pub struct Interrupt(Arc<AtomicBool>);
+
+/// The process-wide handler; ctrlc only allows setting one
+static INSTALLED: OnceLock<Interrupt> = OnceLock::new();
+
+impl Interrupt {
+    /// Install the handler, or share the one already installed (for
+    /// commands that run a loop per host)
+    pub fn install() -> Result<Self> {
+        if let Some(existing) = INSTALLED.get() {
+            return Ok(existing.clone());
+        }
+        let flag = Arc::new(AtomicBool::new(false));
+        let handler_flag = flag.clone();
+        ctrlc::set_handler(move || handler_flag.store(true, Ordering::Relaxed))?;
+        Ok(INSTALLED.get_or_init(|| Interrupt(flag)).clone())
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Sleep for `dur`, waking early on Ctrl-C. Returns false if interrupted.
+    pub fn sleep(&self, dur: Duration) -> bool {
+        let end = Instant::now() + dur;
+        while !self.is_set() {
+            let left = end.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return true;
+            }
+            thread::sleep(left.min(Duration::from_millis(100)));
+        }
+        false
+    }
+
+    /// On a terminal, reset colors, show the cursor and end the current
+    /// line; then report the interruption so `main` exits with 130.
+    /// Piped output is left alone so JSON lines stay parseable.
+    pub fn finish(&self) -> Result<()> {
+        let mut out = io::stdout();
+        if out.is_terminal() {
+            writeln!(out, "\x1b[0m\x1b[?25h")?;
+            out.flush()?;
+        }
+        Err(Interrupted.into())
+    }
+}
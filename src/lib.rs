@@ -0,0 +1,286 @@
+//! Async access to the AxeOS HTTP API of Bitaxe miners on tokio, as used by
+//! the bitaxe-cli binary. No terminal output and no argument parsing: every
+//! call returns a value or an [`Error`] saying what went wrong.
+//!
+//! ```no_run
+//! use bitaxe_api::BitaxeClient;
+//!
+//! # async fn run() -> Result<(), bitaxe_api::Error> {
+//! let miner = BitaxeClient::new("http://192.168.1.50")?;
+//! let info = miner.system_info().await?;
+//! println!("{:?} GH/s", info.hash_rate);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Programs without a runtime of their own use [`blocking::BitaxeClient`],
+//! which has the same methods.
+
+use std::fmt;
+use std::time::Duration;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde_json::Value;
+
+pub mod blocking;
+mod system_info;
+pub use system_info::{DIFFICULTY_SUFFIXES, Difficulty, SystemInfo, parse_difficulty};
+
+/// Whole-request timeout of `BitaxeClient::new`
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub const INFO_PATH: &str = "/api/system/info";
+pub const SYSTEM_PATH: &str = "/api/system";
+pub const RESTART_PATH: &str = "/api/system/restart";
+/// Not in every AxeOS build; those answer 404
+pub const SESSION_RESET_PATH: &str = "/api/system/session/reset";
+
+/// Characters of a body that isn't AxeOS JSON quoted back in the error
+const SNIPPET_LEN: usize = 120;
+/// Bytes of an error body we are willing to read
+const ERROR_BODY_LIMIT: u64 = 4096;
+/// Characters of an error body kept in `Error::Status`
+const ERROR_BODY_SHOWN: usize = 200;
+
+/// Why a call failed
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The miner didn't answer within the client's timeout. For a restart
+    /// the request may still have arrived.
+    #[error("{url} didn't answer in time")]
+    Timeout { url: String, source: reqwest::Error },
+    /// Nothing answered at the address, or it didn't resolve
+    #[error("Couldn't connect to {url}")]
+    Connect { url: String, source: reqwest::Error },
+    /// The miner answered with a non-2xx status, and what it said about it
+    #[error("Miner answered with status {status}{}", detail.as_ref().map(|d| format!(": {d}")).unwrap_or_default())]
+    Status { status: StatusCode, detail: Option<String> },
+    /// The body wasn't a JSON object; usually the address belongs to
+    /// another device (a router's admin page, a NAS...)
+    #[error("Host responded but didn't return Bitaxe JSON — is this the right device?")]
+    NotBitaxe(#[source] Unexpected),
+    /// The firmware doesn't have this endpoint
+    #[error("{path} is not supported by this firmware")]
+    Unsupported { path: &'static str },
+    /// Any other transport failure. This and the two above keep reqwest's
+    /// error as their `source()`.
+    #[error("Request to the miner failed")]
+    Http(#[source] reqwest::Error),
+    /// `blocking::BitaxeClient` couldn't set up its runtime
+    #[error("Couldn't start the async runtime")]
+    Runtime(#[source] std::io::Error),
+}
+
+/// What came back instead of AxeOS JSON, whitespace collapsed and cut short
+#[derive(Debug)]
+pub struct Unexpected {
+    pub content_type: Option<String>,
+    pub snippet: String,
+}
+
+impl fmt::Display for Unexpected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = self.content_type.as_deref().map(|t| format!(" ({t})")).unwrap_or_default();
+        if self.snippet.is_empty() {
+            write!(f, "got an empty body{kind}")
+        } else {
+            write!(f, "got{kind}: {}", self.snippet)
+        }
+    }
+}
+
+impl std::error::Error for Unexpected {}
+
+impl Error {
+    fn transport(url: &str, e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Error::Timeout { url: url.to_string(), source: e }
+        } else if e.is_connect() {
+            Error::Connect { url: url.to_string(), source: e }
+        } else {
+            Error::Http(e)
+        }
+    }
+}
+
+/// One miner, by base URL (`http://192.168.1.50`, or with a proxy path).
+/// Nothing is retried: a restart that is sent twice restarts twice, so
+/// callers decide what is safe to repeat.
+#[derive(Clone, Debug)]
+pub struct BitaxeClient {
+    http: Client,
+    base: String,
+}
+
+impl BitaxeClient {
+    /// With `DEFAULT_TIMEOUT` and no credentials
+    pub fn new(base_url: &str) -> Result<Self, Error> {
+        let http = Client::builder().timeout(DEFAULT_TIMEOUT).build().map_err(Error::Http)?;
+        Ok(BitaxeClient::with_client(http, base_url))
+    }
+
+    /// With a client set up elsewhere (timeouts, basic auth headers, ...)
+    pub fn with_client(http: Client, base_url: &str) -> Self {
+        BitaxeClient { http, base: base_url.trim_end_matches('/').to_string() }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base
+    }
+
+    /// GET /api/system/info: everything the miner reports, with the keys
+    /// it doesn't know in `SystemInfo::extra`
+    pub async fn system_info(&self) -> Result<SystemInfo, Error> {
+        // parse_info only lets objects through, which always deserialize
+        Ok(SystemInfo::from(&self.system_info_json().await?))
+    }
+
+    /// GET /api/system/info, as AxeOS sent it
+    pub async fn system_info_json(&self) -> Result<Value, Error> {
+        let url = self.url(INFO_PATH);
+        let resp = self.send(&url, self.http.get(&url)).await?;
+        let content_type = resp.headers().get(CONTENT_TYPE).and_then(|t| t.to_str().ok()).map(str::to_string);
+        let body = resp.text().await.map_err(|e| Error::transport(&url, e))?;
+        parse_info(&body, content_type.as_deref())
+    }
+
+    /// POST /api/system/restart. AxeOS answers before it goes down.
+    pub async fn restart(&self) -> Result<(), Error> {
+        let url = self.url(RESTART_PATH);
+        self.send(&url, self.http.post(&url)).await.map(drop)
+    }
+
+    /// PATCH /api/system with a JSON object of settings, ex:
+    /// `{"frequency": 525, "coreVoltage": 1150}`
+    pub async fn patch_settings(&self, settings: &Value) -> Result<(), Error> {
+        let url = self.url(SYSTEM_PATH);
+        self.send(&url, self.http.patch(&url).json(settings)).await.map(drop)
+    }
+
+    /// POST /api/system/session/reset; `Error::Unsupported` on firmware
+    /// without it
+    pub async fn reset_session(&self) -> Result<(), Error> {
+        let url = self.url(SESSION_RESET_PATH);
+        match self.send(&url, self.http.post(&url)).await {
+            Err(Error::Status { status: StatusCode::NOT_FOUND, .. }) => {
+                Err(Error::Unsupported { path: SESSION_RESET_PATH })
+            }
+            other => other.map(drop),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base)
+    }
+
+    async fn send(&self, url: &str, request: RequestBuilder) -> Result<Response, Error> {
+        check_status(request.send().await.map_err(|e| Error::transport(url, e))?).await
+    }
+}
+
+/// Pass successful responses through; otherwise `Error::Status` with what
+/// the body says about it
+pub async fn check_status(mut resp: Response) -> Result<Response, Error> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let mut raw = Vec::new();
+    // A broken body shouldn't hide the status, so read errors are ignored
+    while let Ok(Some(chunk)) = resp.chunk().await {
+        let room = ERROR_BODY_LIMIT as usize - raw.len();
+        raw.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        if raw.len() == ERROR_BODY_LIMIT as usize {
+            break;
+        }
+    }
+    Err(Error::Status { status, detail: error_detail(&raw) })
+}
+
+/// Human-readable reason from an error body, if there is one worth showing:
+/// the `message`/`error`/`detail` field of a JSON body, or short plain text
+pub fn error_detail(raw: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(raw).ok()?.trim();
+    if text.is_empty() {
+        return None;
+    }
+
+    if let Ok(json) = serde_json::from_str::<Value>(text) {
+        for key in ["message", "error", "detail"] {
+            if let Some(msg) = get_str(&json, key) {
+                return Some(truncate(msg, ERROR_BODY_SHOWN));
+            }
+        }
+    }
+
+    // Binary or markup-heavy bodies aren't useful in a one-line error
+    if text.chars().any(|c| c.is_control() && !c.is_whitespace()) || text.starts_with('<') {
+        return None;
+    }
+    Some(truncate(&text.replace(['\r', '\n'], " "), ERROR_BODY_SHOWN))
+}
+
+/// A system info body as JSON. Anything else is `Error::NotBitaxe`, which
+/// says so plainly instead of passing on serde's complaint.
+pub fn parse_info(body: &str, content_type: Option<&str>) -> Result<Value, Error> {
+    if let Ok(info) = serde_json::from_str::<Value>(body)
+        && info.is_object()
+    {
+        return Ok(info);
+    }
+    let snippet = truncate(&body.split_whitespace().collect::<Vec<_>>().join(" "), SNIPPET_LEN);
+    Err(Error::NotBitaxe(Unexpected { content_type: content_type.map(str::to_string), snippet }))
+}
+
+/// A numeric field, however the firmware encoded it
+pub fn get_number(root: &Value, key: &str) -> Option<f64> {
+    root.get(key).and_then(|v| {
+        v.as_f64()
+            .or_else(|| v.as_i64().map(|i| i as f64))
+            .or_else(|| v.as_u64().map(|u| u as f64))
+    })
+}
+
+pub fn get_str<'a>(root: &'a Value, key: &str) -> Option<&'a str> {
+    root.get(key).and_then(|v| v.as_str())
+}
+
+/// At most `max` characters, with `…` when something was cut
+pub fn truncate(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        s.to_string()
+    } else {
+        let cut: String = s.chars().take(max).collect();
+        format!("{cut}…")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn pages_that_are_not_axeos_are_called_out() {
+        assert_eq!(parse_info(r#"{"hashRate": 512.5}"#, Some("application/json")).unwrap()["hashRate"], 512.5);
+        let html = "<!DOCTYPE html>\n<html>\n  <head><title>TP-Link Router</title></head></html>";
+        let e = parse_info(html, Some("text/html")).unwrap_err();
+        assert!(e.to_string().contains("is this the right device?"));
+        assert_eq!(
+            e.source().unwrap().to_string(),
+            "got (text/html): <!DOCTYPE html> <html> <head><title>TP-Link Router</title></head></html>"
+        );
+        assert_eq!(parse_info("", None).unwrap_err().source().unwrap().to_string(), "got an empty body");
+        assert!(parse_info("[1, 2]", None).is_err());
+    }
+
+    #[test]
+    fn error_bodies_worth_quoting() {
+        assert_eq!(error_detail(br#"{"message": "Invalid frequency"}"#).as_deref(), Some("Invalid frequency"));
+        assert_eq!(error_detail(b"busy\r\nretry later").as_deref(), Some("busy  retry later"));
+        assert_eq!(error_detail(b"<html><body>404</body></html>"), None);
+        assert_eq!(error_detail(b"  "), None);
+        assert_eq!(truncate("hashrate", 4), "hash…");
+    }
+}
@@ -0,0 +1,238 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result};
+use clap::{ArgGroup, Args, ValueEnum};
+use serde_json::Value;
+use crate::client::Target;
+use crate::config_file::AppConfig;
+#[cfg(feature = "sqlite")]
+use crate::history::{Failure, Recorder, Sample, unix_secs};
+use crate::interrupt::Interrupt;
+use crate::{fetch_info, get_number, influx, logging, runtime};
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("sink").required(true).multiple(true)))]
+pub struct LogArgs {
+    /// File to append to (CSV files get a header when created)
+    #[arg(long, group = "sink")]
+    file: Option<PathBuf>,
+
+    /// Format of --file; influx writes line protocol and skips failed polls
+    #[arg(long, value_enum, default_value_t = LogFormat::Csv, requires = "file")]
+    format: LogFormat,
+
+    /// Also store samples in a SQLite database (default: ~/.config/bitaxe-cli/history.db)
+    #[cfg(feature = "sqlite")]
+    #[arg(long, group = "sink", value_name = "PATH")]
+    db: Option<Option<PathBuf>>,
+
+    /// Seconds between samples
+    #[arg(long, default_value_t = 60)]
+    interval: u64,
+
+    /// Stop after this many samples (default: run until Ctrl-C)
+    #[arg(long)]
+    count: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    Csv,
+    Influx,
+}
+
+/// Record one sample per interval until --count samples or Ctrl-C. Failed
+/// fetches still get a CSV row, with empty metrics and the error message,
+/// and are stored in the database without readings. With `history_retention` set
+/// the database is pruned at start-up and then once a day.
+pub fn run(args: &LogArgs, target: &Target, cfg: &AppConfig) -> Result<()> {
+    let interrupt = Interrupt::install()?;
+    let header = (args.format == LogFormat::Csv).then(header);
+    let mut log = args.file.as_deref().map(|path| LogFile::open(path, header)).transpose()?;
+    #[cfg(feature = "sqlite")]
+    let mut db = args.db.as_ref().map(|path| Recorder::open(path.as_deref(), cfg)).transpose()?;
+    #[cfg(not(feature = "sqlite"))]
+    let _ = cfg;
+    let mut taken = 0;
+
+    loop {
+        let info = runtime().block_on(fetch_info(&target.client, &target.host));
+        logging::poll(target.name(), info.as_ref().map_err(|e| format!("{e:#}")));
+        let now = SystemTime::now();
+        if let Err(e) = &info {
+            eprintln!("Fetch failed: {e:#}");
+        }
+        if let Some(log) = &mut log {
+            match (args.format, &info) {
+                (LogFormat::Csv, _) => {
+                    log.append(&csv_row(now, target.name(), info.as_ref().map_err(|e| format!("{e:#}"))))?
+                }
+                (LogFormat::Influx, Ok(info)) => {
+                    if let Some(line) = influx::line_at(&target.host, target.alias.as_deref(), info, now) {
+                        log.append(&line)?;
+                    }
+                }
+                (LogFormat::Influx, Err(_)) => {}
+            }
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = &mut db {
+            let (samples, failures) = match &info {
+                Ok(info) => (vec![Sample::new(target.name(), now, info)], vec![]),
+                Err(e) => {
+                    let error = format!("{e:#}");
+                    (vec![], vec![Failure { device: target.name().to_string(), ts: unix_secs(now), error }])
+                }
+            };
+            db.record(&samples, &failures)?;
+        }
+
+        taken += 1;
+        if args.count.is_some_and(|n| taken >= n) {
+            return Ok(());
+        }
+        if !interrupt.sleep(Duration::from_secs(args.interval)) {
+            return interrupt.finish();
+        }
+    }
+}
+
+/// Append-only log file that survives log rotation: before every line it
+/// checks the path still points at the open file and reopens it if not
+pub struct LogFile {
+    path: PathBuf,
+    out: BufWriter<File>,
+    /// Written first whenever the file is new or empty
+    header: Option<String>,
+    #[cfg(unix)]
+    inode: u64,
+}
+
+impl LogFile {
+    pub fn open(path: &Path, header: Option<String>) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let meta = file.metadata()?;
+        let mut log = LogFile {
+            path: path.to_path_buf(),
+            out: BufWriter::new(file),
+            header,
+            #[cfg(unix)]
+            inode: std::os::unix::fs::MetadataExt::ino(&meta),
+        };
+        // A fresh (or truncated) file gets the header
+        if meta.len() == 0
+            && let Some(header) = log.header.clone()
+        {
+            log.write_line(&header)?;
+        }
+        Ok(log)
+    }
+
+    fn rotated(&self) -> bool {
+        match std::fs::metadata(&self.path) {
+            Err(_) => true,
+            #[cfg(unix)]
+            Ok(meta) => std::os::unix::fs::MetadataExt::ino(&meta) != self.inode,
+            #[cfg(not(unix))]
+            Ok(_) => false,
+        }
+    }
+
+    pub fn append(&mut self, line: &str) -> Result<()> {
+        if self.rotated() {
+            *self = LogFile::open(&self.path, self.header.take())?;
+        }
+        self.write_line(line)
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        writeln!(self.out, "{line}")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// One CSV row: the FIELDS values, or empty ones and the error
+pub fn csv_row(at: SystemTime, device: &str, info: Result<&Value, String>) -> String {
+    let mut row = vec![rfc3339(at), csv_field(device)];
+    match info {
+        Ok(info) => {
+            let values = influx::FIELDS.iter().map(|(key, _)| get_number(info, key).map(|v| v.to_string()));
+            row.extend(values.map(Option::unwrap_or_default));
+            row.push(String::new());
+        }
+        Err(e) => {
+            row.extend(influx::FIELDS.iter().map(|_| String::new()));
+            row.push(csv_field(&e));
+        }
+    }
+    row.join(",")
+}
+
+/// CSV column names, matching `csv_row`
+pub fn header() -> String {
+    let mut cols = vec!["timestamp", "device"];
+    cols.extend(influx::FIELDS.iter().map(|(_, name)| *name));
+    cols.push("error");
+    cols.join(",")
+}
+
+/// Quote a field when it contains a separator, quote or line break
+pub fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// UTC timestamp like `2024-04-25T16:00:00Z`
+pub fn rfc3339(t: SystemTime) -> String {
+    let secs = t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_are_utc_rfc3339() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(UNIX_EPOCH + Duration::from_secs(1_714_060_800)), "2024-04-25T16:00:00Z");
+        // Leap day
+        assert_eq!(rfc3339(UNIX_EPOCH + Duration::from_secs(1_709_164_799)), "2024-02-28T23:59:59Z");
+        assert_eq!(rfc3339(UNIX_EPOCH + Duration::from_secs(1_709_164_800)), "2024-02-29T00:00:00Z");
+    }
+
+    #[test]
+    fn fields_with_separators_are_quoted() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::Path;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::EnvFilter;
+use crate::get_number;
+
+/// Start writing structured events (one JSON object per line) to `path`,
+/// rotated daily into `path.YYYY-MM-DD`. The level comes from RUST_LOG,
+/// else -v (debug for this tool) / -vv (trace, libraries included), else
+/// info. Nothing is recorded without a path, and terminal output is never
+/// affected. Keep the guard alive until exit so buffered events are flushed.
+pub fn init(path: Option<&Path>, verbose: u8) -> Result<Option<WorkerGuard>> {
+    let Some(path) = path else { return Ok(None) };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().with_context(|| format!("--log-file {} has no file name", path.display()))?;
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let (writer, guard) = tracing_appender::non_blocking(tracing_appender::rolling::daily(dir, name));
+    let level = match verbose {
+        0 => "info",
+        1 => "info,bitaxe_cli=debug",
+        _ => "trace",
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+    tracing_subscriber::fmt().json().with_writer(writer).with_env_filter(filter).init();
+    Ok(Some(guard))
+}
+
+/// Record one poll of `device` with its key metrics, or why it failed
+pub fn poll(device: &str, info: Result<&Value, String>) {
+    match info {
+        Ok(info) => tracing::info!(
+            device,
+            hashrate = get_number(info, "hashRate"),
+            temp = get_number(info, "temp"),
+            vr_temp = get_number(info, "vrTemp"),
+            power = get_number(info, "power"),
+            "poll"
+        ),
+        Err(e) => tracing::warn!(device, error = %e, "poll failed"),
+    }
+}
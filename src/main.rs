@@ -1,16 +1,107 @@
+mod alert;
+mod average;
+mod check;
+mod client;
+mod color;
+mod config_file;
+mod console;
+mod daemon;
+mod device;
+mod difficulty;
+mod discover;
+mod display;
+mod doctor;
+mod error;
+mod exporter;
+mod field;
+mod filter;
+mod fleet;
+mod graphite;
+#[cfg(feature = "sqlite")]
+mod history;
+mod homeassistant;
+mod hostname;
+mod influx;
+mod interrupt;
+mod logger;
+mod logging;
+mod mqtt;
+mod notify;
+mod output;
+mod push;
+mod ratelimit;
+mod raw;
+mod reboot;
+#[cfg(feature = "sqlite")]
+mod report;
+mod retry;
+mod rules;
+mod schema;
+mod settings;
+mod shares;
+mod snapshot;
+mod sparkline;
+mod statistics;
+mod statsd;
+mod status;
+mod swarm;
+mod syslogger;
+mod systemd;
+mod temperature;
+mod threshold;
+mod timings;
+#[cfg(feature = "tui")]
+mod tui;
+mod watchdog;
+
 use std::env;
+use std::io::{self, IsTerminal, Read, Write};
 use std::path::PathBuf;
-use clap::{Parser, Subcommand};
-use reqwest::blocking::Client;
-use serde::Deserialize;
-use serde_json;
+use std::process::ExitCode;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use reqwest::{Client, Response};
 use anyhow::{Result, bail};
-
-/// Config structure (matches config.toml)
-#[derive(Debug, Deserialize)]
-struct AppConfig {
-    host: Option<String>,
-}
+use bitaxe_api::{BitaxeClient, INFO_PATH, SystemInfo, get_number, get_str, truncate};
+use client::{ClientOptions, Target};
+use alert::AlertsCommand;
+use check::{CheckArgs, PluginExit};
+use color::{ColorChoice, DIM, paint, should_colorize};
+use config_file::{AppConfig, RetryConfig, UnsetVar, load_config, lookup_device};
+use console::ConsoleArgs;
+use daemon::DaemonArgs;
+use device::DeviceCommand;
+use discover::DiscoverArgs;
+use display::DisplayArgs;
+use error::BitaxeError;
+use exporter::{ExporterArgs, MetricsArgs};
+use field::FieldSpec;
+use fleet::FleetCommand;
+#[cfg(feature = "sqlite")]
+use history::HistoryArgs;
+#[cfg(feature = "sqlite")]
+use report::ReportArgs;
+use logger::LogArgs;
+use mqtt::MqttArgs;
+use push::PushCommand;
+use raw::RawArgs;
+use interrupt::{EXIT_INTERRUPTED, Interrupt, Interrupted};
+use retry::{Attempts, Backoff, Policy};
+use rules::Metric;
+use snapshot::SettingsCommand;
+use statistics::StatsArgs;
+use sparkline::Sparklines;
+use status::{Decimals, SectionArgs, StatusView, Trends, print_info};
+use swarm::SwarmArgs;
+use syslogger::Syslog;
+use temperature::TempUnit;
+use threshold::ThresholdArgs;
+use tokio::runtime::Runtime;
+#[cfg(feature = "tui")]
+use tui::TuiArgs;
+use watchdog::WatchdogArgs;
 
 /// Simple CLI for Bitaxe AxeOS API (read-only + restart)
 #[derive(Parser, Debug)]
@@ -21,9 +112,76 @@ struct AppConfig {
 )]
 struct Cli {
     /// Override Bitaxe host (ex: http://192.168.1.123)
-    /// Priority: CLI flag > BITAXE_URL env var > config file
-    #[arg(long)]
-    host: Option<String>,
+    /// Priority: CLI flag > BITAXE_URL env var > config file.
+    /// `status` and `restart` accept several (comma-separated or repeated).
+    /// `-` reads them from stdin, one per line.
+    #[arg(long, value_delimiter = ',')]
+    host: Vec<String>,
+
+    /// Config file to use instead of ~/.config/bitaxe-cli/config.{toml,yaml,json}
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Use a named device from the [devices] table in the config file
+    /// (`--name` works too). Below --host and BITAXE_URL, above `host` in
+    /// the config. `status`, `restart` and fleet commands accept it several times.
+    #[arg(long, global = true, visible_alias = "name", value_name = "NAME")]
+    device: Vec<String>,
+
+    /// Path inserted between the host and /api/... for miners behind a
+    /// reverse proxy (ex: /bitaxe). Overrides `path_prefix` / `api_base` in the config.
+    #[arg(long, value_name = "PATH", global = true)]
+    api_base: Option<String>,
+
+    /// Minimum gap between requests to the same miner (ex: 500ms, 0 to
+    /// disable). Defaults to `request_spacing` in the config, then 500ms.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, global = true)]
+    request_spacing: Option<Duration>,
+
+    /// Times a read is repeated after a timeout, connection error or 5xx
+    /// answer, with growing pauses. Defaults to `retries` in [retry], then 1.
+    #[arg(long, value_name = "N", global = true)]
+    retries: Option<u32>,
+
+    /// Time allowed for a whole request (connect + response), ex: 500ms, 5s,
+    /// 2m (a bare number is seconds). Defaults to BITAXE_TIMEOUT, the
+    /// device's `timeout_secs`, `timeout` in the config, then 5s.
+    #[arg(long, value_name = "DURATION", value_parser = parse_timeout, global = true)]
+    timeout: Option<Duration>,
+
+    /// Time allowed for establishing the TCP connection. The request
+    /// timeout still applies; set this lower to fail fast on miners that are
+    /// switched off.
+    #[arg(long, value_name = "DURATION", value_parser = parse_timeout, global = true)]
+    connect_timeout: Option<Duration>,
+
+    /// When to use colored output
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto, global = true)]
+    color: ColorChoice,
+
+    /// Disable colored output (also honored: NO_COLOR env var)
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Record polls, alerts and actions as JSON lines in this file, rotated
+    /// daily (for unattended `watch`, `fleet watch`, `log` and `mqtt`)
+    #[arg(long, value_name = "PATH", global = true)]
+    log_file: Option<PathBuf>,
+
+    /// Print --json output on a single line instead of indented
+    #[arg(long, global = true)]
+    compact: bool,
+
+    /// Show temperatures in °C or °F (default: `temp_unit` in [display], then c).
+    /// Thresholds on the command line are read in the same unit; --json, --field,
+    /// exporters, the history database and the config stay in °C.
+    #[arg(long, value_enum, global = true)]
+    temp_unit: Option<TempUnit>,
+
+    /// More detail in --log-file (-vv for everything; RUST_LOG overrides), and a
+    /// warning on stderr when a miner's answer lacks fields `status` expects
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
 
     #[command(subcommand)]
     command: Commands,
@@ -31,184 +189,1164 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    /// Show system info (hashrate, temps, power, wifi, etc.)
-    Status,
+    /// Show system info once (hashrate, temps, power, wifi, etc.); `watch` keeps polling
+    Status {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+
+        /// One terse line like `512GH 61°C 14.2W 0.3%rej` (for status bars)
+        #[arg(long, conflicts_with_all = ["format", "precision", "include", "exclude", "warn", "crit"])]
+        oneline: bool,
+
+        /// Print just this info key's value for scripts, ex: `hashRate` or
+        /// `hashRate=%.0f` (%s %d %f %.Nf). Repeat for one value per line.
+        #[arg(long, value_name = "KEY[=FORMAT]", value_parser = field::parse_field,
+              conflicts_with_all = ["format", "oneline", "precision", "target", "include", "exclude", "warn", "crit"])]
+        field: Vec<FieldSpec>,
+
+        /// Decimal places for hashrate, temperatures, power and voltage
+        /// (text output; influx always carries full precision)
+        #[arg(long, value_name = "N")]
+        precision: Option<usize>,
+
+        /// Show best-difficulty progress toward this difficulty and the
+        /// expected time to reach it at the current hashrate (ex: 10G)
+        #[arg(long, value_name = "DIFF", value_parser = difficulty::parse_target)]
+        target: Option<f64>,
+
+        /// Run this shell command when a temperature reaches the critical threshold,
+        /// ex: "notify %device% %temp%" (%host% %device% %sensor% %temp% %vrtemp% %hashrate%)
+        #[arg(long, value_name = "COMMAND")]
+        on_crit: Option<String>,
+
+        /// Also print how long fetching and parsing the system info took,
+        /// on stderr: `fetched and parsed in 83ms`
+        #[arg(long)]
+        timings: bool,
+
+        #[command(flatten)]
+        thresholds: ThresholdArgs,
+
+        #[command(flatten)]
+        sections: SectionArgs,
+    },
 
     /// Restart the miner
-    Restart,
-}
+    Restart {
+        /// Block until the miner is back online (see `wait-online`)
+        #[arg(long)]
+        wait_online: bool,
+    },
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let cfg = load_config().unwrap_or(AppConfig { host: None });
+    /// Poll the miner until it answers, so scripts can chain
+    /// `restart && wait-online && status`. Fails if it isn't back in time.
+    WaitOnline {
+        /// Give up after this long (ex: 90s, 5m). `--timeout` is the per-request limit.
+        #[arg(long, value_name = "DURATION", default_value = "120s", value_parser = parse_duration)]
+        max_wait: Duration,
+    },
 
-    let host = resolve_host(&cli, &cfg)?;
-    let client = Client::builder()
-        .timeout(std::time::Duration::from_secs(5))
-        .build()?;
+    /// Refresh the system info every interval until Ctrl-C, with trend
+    /// lines; `--once` prints a single `status`
+    Watch {
+        /// Time between refreshes (ex: 5, 2s, 1m; at least 1s)
+        #[arg(long, default_value = "5s", value_parser = parse_duration)]
+        interval: Duration,
 
-    match cli.command {
-        Commands::Status => show_status(&client, &host)?,
-        Commands::Restart => restart_miner(&client, &host)?,
-    }
+        /// Stop after this many polls; the exit status is the last poll's.
+        /// `--count 1` is the same as `--once`.
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        count: Option<u32>,
 
-    Ok(())
+        /// Print one reading exactly as `status` would, then exit
+        #[arg(long, conflicts_with_all = ["count", "syslog"])]
+        once: bool,
+
+        /// Output format; influx, env and tsv print each poll instead of redrawing
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+
+        /// Decimal places for hashrate, temperatures, power and voltage
+        #[arg(long, value_name = "N")]
+        precision: Option<usize>,
+
+        /// Show best-difficulty progress toward this difficulty (ex: 10G)
+        #[arg(long, value_name = "DIFF", value_parser = difficulty::parse_target)]
+        target: Option<f64>,
+
+        /// Rolling hashrate averages shown next to the reading, weighted by
+        /// time over the polls so far (ex: 1m,10m,1h; 0 hides them)
+        #[arg(long, value_name = "WINDOWS", value_delimiter = ',', default_value = "10m,1h",
+              value_parser = parse_duration)]
+        avg: Vec<Duration>,
+
+        /// Leave out the sparklines of the last polls next to hashrate, core
+        /// temp and power (also off without colors)
+        #[arg(long)]
+        no_sparklines: bool,
+
+        /// Run this shell command when a temperature reaches the critical threshold
+        /// (once per excursion),
+        /// ex: "notify %device% %temp%" (%host% %device% %sensor% %temp% %vrtemp% %hashrate%)
+        #[arg(long, value_name = "COMMAND")]
+        on_crit: Option<String>,
+
+        /// Also send a summary of every poll to the system log (warning from
+        /// 65 °C, error from 70 °C or when the miner doesn't answer)
+        #[arg(long)]
+        syslog: bool,
+
+        /// Identifier for --syslog messages
+        #[arg(long, value_name = "TAG", default_value = "bitaxe-cli", requires = "syslog")]
+        syslog_tag: String,
+
+        /// Facility for --syslog messages (ex: daemon, user, local0)
+        #[arg(long, value_name = "NAME", default_value = "daemon", value_parser = syslogger::parse_facility,
+              requires = "syslog")]
+        syslog_facility: syslog::Facility,
+
+        #[command(flatten)]
+        thresholds: ThresholdArgs,
+
+        #[command(flatten)]
+        sections: SectionArgs,
+    },
+
+    /// Check that the miner is reachable and measure round-trip latency
+    Ping {
+        /// Number of requests to send (reports min/avg/max when > 1)
+        #[arg(long, default_value_t = 1)]
+        count: u32,
+    },
+
+    /// Apply a [profiles] entry from the config (frequency, core voltage, fan)
+    Apply {
+        profile: String,
+
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
+
+        /// Apply a frequency or core voltage outside the ASIC's safe range
+        /// (with a warning). This can damage the board.
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Start a fresh session (best session diff, share counts) without restarting
+    ResetSession {
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Rename the miner (letters, digits and '-', up to 32 characters), ex:
+    /// to tell identical boards apart in `fleet` output
+    SetHostname {
+        #[arg(value_parser = hostname::parse)]
+        name: String,
+
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Show the onboard screen's settings, or turn it on/off and rotate it
+    /// where the firmware supports that
+    Display(DisplayArgs),
+
+    /// Manage named devices in the config file
+    Device {
+        #[command(subcommand)]
+        command: DeviceCommand,
+    },
+
+    /// Find Bitaxe devices on the local network via mDNS
+    Discover(DiscoverArgs),
+
+    /// Query every configured device at once
+    Fleet {
+        #[command(subcommand)]
+        command: FleetCommand,
+    },
+
+    /// Show the miner and the peers in its AxeOS swarm list, fleet-style
+    Swarm(SwarmArgs),
+
+    /// Check config, host, connectivity and the API response, with hints
+    Doctor,
+
+    /// Record a sample every interval to a CSV/Influx file and/or the history database
+    Log(LogArgs),
+
+    /// Serve Prometheus metrics for the configured devices (or --host)
+    Exporter(ExporterArgs),
+
+    /// Poll once and write Prometheus metrics to stdout or a textfile-collector file
+    Metrics(MetricsArgs),
+
+    /// Nagios/Icinga plugin: one status line with perfdata, exit 0 OK,
+    /// 1 WARNING, 2 CRITICAL, 3 UNKNOWN (ex: check --metric temp -w 65 -c 70)
+    Check(CheckArgs),
+
+    /// Check the [alerts] notifiers
+    Alerts {
+        #[command(subcommand)]
+        command: AlertsCommand,
+    },
+
+    /// Publish the devices' status to an MQTT broker every interval
+    Mqtt(MqttArgs),
+
+    /// Restart miners that stay stuck (hashrate near zero or no new shares),
+    /// at most once per cooldown
+    Watchdog(WatchdogArgs),
+
+    /// Poll the devices every interval into the outputs listed in [daemon]
+    /// (CSV, history, MQTT, exporter, alerts) until SIGINT/SIGTERM
+    Daemon(DaemonArgs),
+
+    /// Send one poll of the devices to a time-series database
+    Push {
+        #[command(subcommand)]
+        command: PushCommand,
+    },
+
+    /// Query samples stored by `log --db` (filter with --device)
+    #[cfg(feature = "sqlite")]
+    History(HistoryArgs),
+
+    /// Summarize a period of stored history per device: hashrate, shares,
+    /// efficiency, temperatures, energy, uptime and alerts (ex: report --period 7d)
+    #[cfg(feature = "sqlite")]
+    Report(ReportArgs),
+
+    /// Back up the miner's tuning and pool settings to a file, or restore them
+    Settings {
+        #[command(subcommand)]
+        command: SettingsCommand,
+    },
+
+    /// Full-screen dashboard of the configured devices with live charts
+    /// (r restart, p pause, q quit)
+    #[cfg(feature = "tui")]
+    Tui(TuiArgs),
+
+    /// Summarize the hashrate, temperature and power samples the miner keeps
+    /// itself (newer AxeOS), or dump them with --csv / --json
+    Stats(StatsArgs),
+
+    /// Follow the miner's console log live (WebSocket /api/ws), reconnecting
+    /// if the connection drops
+    Logs(ConsoleArgs),
+
+    /// Send a request to any API endpoint and print the status and body.
+    /// Unstable: for endpoints without a command yet, and for debugging.
+    Raw(RawArgs),
 }
 
-/// Try to load ~/.config/bitaxe-cli/config.toml if it exists
-fn load_config() -> Result<AppConfig> {
-    let mut builder = config::Config::builder();
+/// How `status` prints its data
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human-readable block
+    Text,
+    /// InfluxDB line protocol (for Telegraf's exec input)
+    Influx,
+    /// `BITAXE_HASHRATE=512.34` lines for `eval` in shell scripts
+    Env,
+    /// `hashrate<TAB>512.34` lines for `column -t`, `cut` or `awk -F'\t'`
+    Tsv,
+}
 
-    if let Some(path) = config_path() {
-        if path.exists() {
-            builder = builder.add_source(config::File::from(path));
-        }
+fn main() -> ExitCode {
+    let Err(e) = run() else {
+        return ExitCode::SUCCESS;
+    };
+
+    // Ctrl-C is a normal way to leave watch mode, not an error worth printing
+    if e.downcast_ref::<Interrupted>().is_some() {
+        return ExitCode::from(EXIT_INTERRUPTED);
+    }
+    // `check` has printed its status line already
+    if let Some(PluginExit(code)) = e.downcast_ref::<PluginExit>() {
+        return ExitCode::from(*code);
     }
 
-    // If there are no sources, this still builds an empty config,
-    // and deserialization into AppConfig (all fields Option) is fine.
-    let cfg = builder.build().ok();
-    if let Some(cfg) = cfg {
-        let app_cfg: AppConfig = cfg.try_deserialize()?;
-        Ok(app_cfg)
+    if error::json_mode() {
+        match output::json(&error::json_object(&e)) {
+            Ok(out) => eprintln!("{out}"),
+            Err(_) => eprintln!("Error: {e:?}"),
+        }
     } else {
-        Ok(AppConfig { host: None })
+        eprintln!("Error: {e:?}");
+        if let Some(hint) = error::find(&e).and_then(BitaxeError::hint) {
+            eprintln!("Hint: {hint}");
+        }
+    }
+    ExitCode::from(error::exit_code(&e))
+}
+
+/// Whether the command was asked for JSON output, so its failures come out
+/// as JSON too
+fn prints_json(command: &Commands) -> bool {
+    match command {
+        Commands::Fleet { command: FleetCommand::Status { json, .. } | FleetCommand::Summary { json, .. } }
+        | Commands::Alerts { command: AlertsCommand::Status { json } } => *json,
+        #[cfg(feature = "sqlite")]
+        Commands::History(args) => args.json,
+        #[cfg(feature = "sqlite")]
+        Commands::Report(args) => args.json,
+        Commands::Stats(args) => args.json,
+        Commands::Swarm(args) => args.json,
+        _ => false,
     }
 }
 
-/// Build the config file path: ~/.config/bitaxe-cli/config.toml
-fn config_path() -> Option<PathBuf> {
-    // Cross-platform home dir (HOME on Linux/Mac, USERPROFILE on Windows)
-    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
-    let path = PathBuf::from(home).join(".config").join("bitaxe-cli").join("config.toml");
-    Some(path)
+/// Whether the command line that failed to parse is a `check`, whose usage
+/// errors are plugin states rather than clap's exit code 2
+fn is_check(args: impl IntoIterator<Item = std::ffi::OsString>) -> bool {
+    let matches = Cli::command().ignore_errors(true).try_get_matches_from(args);
+    matches.is_ok_and(|m| m.subcommand_name() == Some("check"))
 }
 
-/// Decide which host to use: CLI > BITAXE_URL env > config file
-fn resolve_host(cli: &Cli, cfg: &AppConfig) -> Result<String> {
-    if let Some(h) = &cli.host {
-        return Ok(h.to_string());
+fn run() -> Result<()> {
+    let mut cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) if e.use_stderr() && is_check(std::env::args_os()) => return Err(check::usage_error(&e).into()),
+        Err(e) => e.exit(),
+    };
+    error::set_json(prints_json(&cli.command));
+    if cli.host.iter().any(|h| h == "-") {
+        cli.host = stdin_hosts(&cli.host)?;
     }
+    let _log_guard = logging::init(cli.log_file.as_deref(), cli.verbose)?;
+    schema::set_notes(cli.verbose > 0);
+    if let Some(path) = &cli.config {
+        config_file::set_config_path(path.clone());
+    }
+    let cfg = match load_config() {
+        Ok(cfg) => cfg,
+        // A file named with --config has to load, and so does one that refers
+        // to an unset variable (without it the hosts would just go missing);
+        // doctor reports the problem itself
+        Err(e)
+            if (cli.config.is_some() || e.downcast_ref::<UnsetVar>().is_some())
+                && !matches!(cli.command, Commands::Doctor) =>
+        {
+            return Err(e.context(BitaxeError::Config));
+        }
+        Err(_) => AppConfig::default(),
+    };
+
+    let env_timeout = match env::var("BITAXE_TIMEOUT") {
+        Ok(s) if !s.is_empty() => Some(parse_timeout(&s).map_err(|e| anyhow::anyhow!("BITAXE_TIMEOUT: {e}"))?),
+        _ => None,
+    };
+    let config_timeout = match &cfg.timeout {
+        Some(s) => Some(parse_timeout(s).map_err(|e| anyhow::anyhow!("timeout in config: {e}"))?),
+        None => None,
+    };
+    let opts = ClientOptions {
+        timeout: cli.timeout.or(env_timeout),
+        connect_timeout: cli.connect_timeout,
+        config_timeout,
+        api_base: cli.api_base.clone(),
+        config_api_base: cfg.api_base.clone(),
+    };
+    let colorize = should_colorize(cli.color, cli.no_color);
+
+    let spacing = match (cli.request_spacing, &cfg.request_spacing) {
+        (Some(d), _) => d,
+        (None, Some(s)) => parse_duration(s).map_err(|e| anyhow::anyhow!("request_spacing in config: {e}"))?,
+        (None, None) => ratelimit::DEFAULT_SPACING,
+    };
+    ratelimit::configure(spacing);
+    retry::configure(backoff(cli.retries, cfg.retry.as_ref())?, cli.verbose > 0);
+    output::set_compact(cli.compact);
+    timings::set_timings(matches!(cli.command, Commands::Status { timings: true, .. }));
+    let display_unit = cfg.display.as_ref().and_then(|d| d.temp_unit);
+    temperature::set_unit(cli.temp_unit.or(display_unit).unwrap_or_default());
 
-    if let Ok(h) = env::var("BITAXE_URL") {
-        if !h.is_empty() {
-            return Ok(h);
+    // Device management and fleet commands work on the [devices] table
+    // rather than a single resolved host
+    match &cli.command {
+        Commands::Device { command } => return device::run(command, &cfg, &opts),
+        Commands::Fleet { command } => return fleet::run(command, &cfg, &cli.device, &opts, colorize),
+        Commands::Discover(args) => return discover::run(args, &cfg, &opts),
+        Commands::Doctor => return doctor::run(&cli, &opts, colorize),
+        Commands::Exporter(args) => {
+            return exporter::run(args, &metrics_targets(&cli, &cfg, &opts, &args.opts)?, &cfg);
         }
+        Commands::Metrics(args) => return exporter::write_once(args, &metrics_targets(&cli, &cfg, &opts, &args.opts)?),
+        Commands::Check(args) => return check::run(args, metrics_targets(&cli, &cfg, &opts, &args.opts)),
+        Commands::Alerts { command } => return alert::run(command, &cfg),
+        Commands::Mqtt(args) => return mqtt::run(args, &metrics_targets(&cli, &cfg, &opts, &args.opts)?, &cfg),
+        Commands::Watchdog(args) => return watchdog::run(args, &metrics_targets(&cli, &cfg, &opts, &args.opts)?, &cfg),
+        Commands::Daemon(args) if args.print_systemd_unit => return daemon::print_unit(args, &cfg),
+        Commands::Daemon(args) => return daemon::run(args, &metrics_targets(&cli, &cfg, &opts, &args.opts)?, &cfg),
+        Commands::Push { command } => {
+            return push::run(command, &metrics_targets(&cli, &cfg, &opts, command.opts())?, &cfg);
+        }
+        #[cfg(feature = "sqlite")]
+        Commands::History(args) => return history::run(args, &cli.device, &cfg),
+        #[cfg(feature = "sqlite")]
+        Commands::Report(args) => return report::run(args, &cli.device),
+        #[cfg(feature = "tui")]
+        Commands::Tui(args) => return tui::run(args, metrics_targets(&cli, &cfg, &opts, &args.opts)?),
+        _ => {}
     }
 
-    if let Some(h) = &cfg.host {
-        return Ok(h.to_string());
+    let targets = resolve_targets(&cli, &cfg, &opts)?;
+
+    // status and restart loop over every host; the rest need exactly one
+    match cli.command {
+        Commands::Status { oneline: true, ref on_crit, .. } => {
+            let hook = on_crit.as_deref().or(cfg.on_crit.as_deref());
+            // Several hosts get their name in front, one line each
+            let named = targets.len() > 1;
+            return for_each_host(&targets, false, |t| show_oneline(t, named, hook))
+                .map_err(|e| name_failure(&targets, e));
+        }
+        Commands::Status { ref field, ref on_crit, .. } if !field.is_empty() => {
+            let hook = on_crit.as_deref().or(cfg.on_crit.as_deref());
+            let named = targets.len() > 1;
+            return for_each_host(&targets, false, |t| show_fields(t, field, named, hook))
+                .map_err(|e| name_failure(&targets, e));
+        }
+        Commands::Status { format, precision, target: diff_target, ref on_crit, ref thresholds, ref sections, .. } => {
+            let hook = on_crit.as_deref().or(cfg.on_crit.as_deref());
+            let view = StatusView {
+                colorize,
+                sections: sections.sections(),
+                precision,
+                decimals: Decimals::from_config(&cfg),
+                target: diff_target,
+                averages: Vec::new(),
+                thresholds: thresholds.clone(),
+                sparklines: false,
+            };
+            // Named devices get their own title line; bare hosts need a header
+            let headers = format == OutputFormat::Text && targets.iter().any(|t| t.alias.is_none());
+            let named = targets.len() > 1;
+            return for_each_host(&targets, headers, |t| show_status(t, format, &view, hook, named))
+                .map_err(|e| name_failure(&targets, e));
+        }
+        Commands::Restart { wait_online } => {
+            return for_each_host(&targets, true, |t| restart_miner(t, wait_online))
+                .map_err(|e| name_failure(&targets, e));
+        }
+        _ => {}
+    }
+
+    let target = single_target(targets, &cli.command)?;
+    let (client, host) = (&target.client, target.host.as_str());
+    let result = match cli.command {
+        Commands::Watch {
+            interval,
+            count,
+            once,
+            format,
+            precision,
+            target: diff_target,
+            avg,
+            no_sparklines,
+            on_crit,
+            syslog,
+            syslog_tag,
+            syslog_facility,
+            thresholds,
+            sections,
+        } => {
+            let averages = avg.into_iter().filter(|w| !w.is_zero()).collect();
+            let sections = sections.sections();
+            let sparklines = colorize && !no_sparklines;
+            let view = StatusView {
+                colorize,
+                sections,
+                precision,
+                decimals: Decimals::from_config(&cfg),
+                target: diff_target,
+                averages,
+                thresholds,
+                sparklines,
+            };
+            let hook = on_crit.as_deref().or(cfg.on_crit.as_deref());
+            // A single poll has no trend to show, so it's `status`, same output and exit code
+            if once || count == Some(1) {
+                if syslog {
+                    bail!("--syslog reports a watch loop; it has nothing to add to a single poll");
+                }
+                let view = StatusView { averages: Vec::new(), sparklines: false, ..view };
+                return show_status(&target, format, &view, hook, false);
+            }
+            if count.is_none() && format == OutputFormat::Text && !io::stdout().is_terminal() {
+                eprintln!("watch polls until Ctrl-C; use --once or --count N for a fixed number of readings");
+            }
+            let polling = Polling { interval: ratelimit::poll_interval(interval), count };
+            let syslog = syslog.then(|| Syslog::connect(&syslog_tag, syslog_facility)).transpose()?;
+            watch_miner(&target, polling, format, &view, hook, notify::Alerts::from_config(&cfg)?, syslog)
+        }
+        Commands::Ping { count } => runtime().block_on(ping_miner(client, host, count)),
+        Commands::WaitOnline { max_wait } => wait_online(&target, max_wait),
+        Commands::ResetSession { yes } => runtime().block_on(reset_session(client, host, yes)),
+        Commands::SetHostname { name, yes } => runtime().block_on(hostname::run(client, host, &name, yes)),
+        Commands::Apply { profile, yes, force } => {
+            runtime().block_on(apply_profile(client, host, &cfg, &profile, yes, force))
+        }
+        Commands::Display(args) => runtime().block_on(display::run(&args, client, host)),
+        Commands::Swarm(args) => runtime().block_on(swarm::run(&args, &target)),
+        Commands::Settings { command } => runtime().block_on(snapshot::run(&command, client, host)),
+        Commands::Log(args) => logger::run(&args, &target, &cfg),
+        Commands::Stats(args) => runtime().block_on(statistics::run(&args, &target)),
+        Commands::Logs(args) => console::run(&args, &target, colorize),
+        Commands::Raw(args) => runtime().block_on(raw::run(&args, &target)),
+        Commands::Status { .. }
+        | Commands::Restart { .. }
+        | Commands::Device { .. }
+        | Commands::Fleet { .. }
+        | Commands::Discover(_)
+        | Commands::Doctor
+        | Commands::Exporter(_)
+        | Commands::Metrics(_)
+        | Commands::Check(_)
+        | Commands::Alerts { .. }
+        | Commands::Mqtt(_)
+        | Commands::Watchdog(_)
+        | Commands::Daemon(_)
+        | Commands::Push { .. } => {
+            unreachable!("handled before host resolution")
+        }
+        #[cfg(feature = "sqlite")]
+        Commands::History(_) => unreachable!("handled before host resolution"),
+        #[cfg(feature = "sqlite")]
+        Commands::Report(_) => unreachable!("handled before host resolution"),
+        #[cfg(feature = "tui")]
+        Commands::Tui(_) => unreachable!("handled before host resolution"),
+    };
+
+    result.map_err(|e| name_failure(std::slice::from_ref(&target), e))
+}
+
+/// Replace `--host -` with the hosts piped in, ex: `discover | awk ... |
+/// status --host -`. Blank lines are skipped; other --host values stay.
+fn stdin_hosts(hosts: &[String]) -> Result<Vec<String>> {
+    let mut piped = String::new();
+    io::stdin().read_to_string(&mut piped)?;
+    let piped: Vec<String> = piped.split_whitespace().map(str::to_string).collect();
+    if piped.is_empty() {
+        bail!("`--host -` was given but nothing came in on stdin");
     }
 
-    bail!("No host configured. Use --host, set BITAXE_URL, or create ~/.config/bitaxe-cli/config.toml");
+    let mut out: Vec<String> = hosts.iter().filter(|h| *h != "-").cloned().collect();
+    out.extend(piped);
+    Ok(out)
 }
 
-fn get_number(root: &serde_json::Value, key: &str) -> Option<f64> {
-    root.get(key).and_then(|v| {
-        v.as_f64()
-            .or_else(|| v.as_i64().map(|i| i as f64))
-            .or_else(|| v.as_u64().map(|u| u as f64))
-    })
+/// Prometheus output covers every configured device (narrowed by --device
+/// and --tag) unless a host was given explicitly
+fn metrics_targets(cli: &Cli, cfg: &AppConfig, opts: &ClientOptions, fleet: &fleet::FleetOpts) -> Result<Vec<Target>> {
+    if cfg.devices.is_empty() || !cli.host.is_empty() {
+        resolve_targets(cli, cfg, opts)
+    } else {
+        fleet::select_targets(cfg, &cli.device, &fleet.tag, fleet.any_tag, opts)
+    }
+}
+
+/// Put the device alias in front of a single device's failure, ex:
+/// "request to 'ultra' timed out". Bare hosts already appear in the URL of
+/// the underlying error, and multi-host runs name each host as they go.
+fn name_failure(targets: &[Target], e: anyhow::Error) -> anyhow::Error {
+    let [Target { alias: Some(alias), .. }] = targets else {
+        return e;
+    };
+    // Only transport failures: other errors (bad input, HTTP status) say what went wrong already
+    let Some(re) = e.chain().find_map(|cause| cause.downcast_ref::<reqwest::Error>()) else {
+        return e;
+    };
+    let summary = if re.is_timeout() {
+        format!("request to '{alias}' timed out")
+    } else if re.is_connect() {
+        format!("could not connect to '{alias}'")
+    } else {
+        format!("request to '{alias}' failed")
+    };
+    e.context(summary)
 }
 
-fn get_str<'a>(root: &'a serde_json::Value, key: &str) -> Option<&'a str> {
-    root.get(key).and_then(|v| v.as_str())
+/// The retry schedule: --retries, then [retry] in the config, then the defaults
+fn backoff(retries: Option<u32>, cfg: Option<&RetryConfig>) -> Result<Backoff> {
+    let mut backoff = Backoff::default();
+    if let Some(cfg) = cfg {
+        backoff.retries = cfg.retries.unwrap_or(backoff.retries);
+        if let Some(s) = &cfg.backoff {
+            backoff.base = parse_duration(s).map_err(|e| anyhow::anyhow!("backoff in [retry]: {e}"))?;
+        }
+        if let Some(s) = &cfg.max_backoff {
+            backoff.max = parse_duration(s).map_err(|e| anyhow::anyhow!("max_backoff in [retry]: {e}"))?;
+        }
+    }
+    backoff.retries = retries.unwrap_or(backoff.retries);
+    Ok(backoff)
 }
 
-fn get_any_as_string(root: &serde_json::Value, key: &str) -> Option<String> {
-    let v = root.get(key)?;
-    if let Some(s) = v.as_str() {
-        Some(s.to_string())
-    } else if let Some(n) = v.as_f64() {
-        Some(format!("{}", n))
-    } else if let Some(i) = v.as_i64() {
-        Some(format!("{}", i))
-    } else if let Some(u) = v.as_u64() {
-        Some(format!("{}", u))
+/// Decide which hosts to use: CLI > BITAXE_URL env > named devices > config file host.
+/// Per-device settings (timeout, auth, path prefix) only apply to hosts that
+/// come from the [devices] table.
+fn resolve_targets(cli: &Cli, cfg: &AppConfig, opts: &ClientOptions) -> Result<Vec<Target>> {
+    // Look the devices up first so a typo is reported even when --host or
+    // the env var would otherwise win.
+    let names: Vec<&String> = if cli.device.is_empty() {
+        cfg.default_device.iter().collect()
     } else {
-        None
+        cli.device.iter().collect()
+    };
+    let devices = names
+        .into_iter()
+        .map(|name| lookup_device(cfg, name).map(|dev| (name, dev)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let cli_hosts: Vec<&str> = cli.host.iter().map(|h| h.trim()).filter(|h| !h.is_empty()).collect();
+    if !cli_hosts.is_empty() {
+        return cli_hosts.into_iter().map(|h| opts.host_target(h)).collect();
     }
+
+    if let Ok(h) = env::var("BITAXE_URL")
+        && !h.is_empty()
+    {
+        return Ok(vec![opts.host_target(&h)?]);
+    }
+
+    if !devices.is_empty() {
+        return devices.into_iter().map(|(name, dev)| opts.device_target(name, dev)).collect();
+    }
+
+    if let Some(h) = &cfg.host {
+        return Ok(vec![opts.host_target(h)?]);
+    }
+
+    Err(BitaxeError::NoHost.into())
 }
 
-fn show_status(client: &Client, host: &str) -> Result<()> {
-    let url = format!("{host}/api/system/info");
-    let resp = client.get(&url).send()?;
-    if !resp.status().is_success() {
-        bail!("Request failed with status {}", resp.status());
+/// Commands other than `status` and `restart` talk to one miner at a time
+fn single_target(mut targets: Vec<Target>, command: &Commands) -> Result<Target> {
+    if targets.len() > 1 {
+        let name = match command {
+            Commands::Watch { .. } => "watch",
+            Commands::Ping { .. } => "ping",
+            Commands::WaitOnline { .. } => "wait-online",
+            Commands::Apply { .. } => "apply",
+            Commands::ResetSession { .. } => "reset-session",
+            Commands::SetHostname { .. } => "set-hostname",
+            Commands::Swarm(_) => "swarm",
+            Commands::Settings { .. } => "settings",
+            Commands::Log(_) => "log",
+            Commands::Stats(_) => "stats",
+            Commands::Logs(_) => "logs",
+            Commands::Raw(_) => "raw",
+            _ => "this command",
+        };
+        bail!("`{name}` works on a single host; use `fleet` commands to target several devices");
     }
+    Ok(targets.remove(0))
+}
 
-    let info: serde_json::Value = resp.json()?;
+/// Run `f` for every host, printing a header per host when there are several.
+/// Failures are reported and skipped; the command fails if any host did.
+fn for_each_host(targets: &[Target], headers: bool, mut f: impl FnMut(&Target) -> Result<()>) -> Result<()> {
+    if let [target] = targets {
+        return f(target);
+    }
 
-    println!("=== Bitaxe System Info ===");
+    let mut failed = 0;
+    for (i, target) in targets.iter().enumerate() {
+        if headers {
+            if i > 0 {
+                println!();
+            }
+            println!("==> {} <==", target.name());
+        }
+        if let Err(e) = f(target) {
+            eprintln!("Error: {}: {e:#}", target.name());
+            failed += 1;
+        }
+    }
 
-    // Hostname
-    if let Some(hostname) = get_str(&info, "hostname") {
-        println!("Hostname        : {hostname}");
+    if failed > 0 {
+        bail!("{failed} of {} hosts failed", targets.len());
     }
+    Ok(())
+}
+
+/// Where every request to a miner runs. Started on first use, so commands
+/// that never reach one (`device remove`, `history`, ...) don't pay for its threads.
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| Runtime::new().expect("Couldn't start the async runtime"))
+}
+
+/// The library client for `host`, sharing the connections of `client`
+fn miner(client: &Client, host: &str) -> BitaxeClient {
+    BitaxeClient::with_client(client.clone(), host)
+}
+
+async fn fetch_info(client: &Client, host: &str) -> Result<serde_json::Value> {
+    let api = miner(client, host);
+    let started = Instant::now();
+    let info = retry::call(host, Policy::Idempotent, || api.system_info_json())
+        .await
+        .map_err(|(e, attempts)| BitaxeError::api_retried(host, "Request", e, attempts))?;
+    timings::report(host, started.elapsed());
+    schema::note(host, &info);
+    Ok(info)
+}
+
+/// Pass successful responses through. Otherwise fail with the status and
+/// whatever AxeOS said about it (see `bitaxe_api::error_detail`). `what`
+/// names the operation ("Restart", "Update", ...).
+async fn check_response(resp: Response, what: &str) -> Result<Response> {
+    let origin = resp.url().origin().ascii_serialization();
+    let attempts = resp.extensions().get::<Attempts>().map_or(1, |a| a.0);
+    let checked = bitaxe_api::check_status(resp).await;
+    checked.map_err(|e| BitaxeError::api_retried(&origin, what, e, attempts).into())
+}
+
+/// A library error in CLI wording; `what` as for `check_response`
+fn api_error(host: &str, e: bitaxe_api::Error, what: &str) -> anyhow::Error {
+    BitaxeError::from_api(host, what, e).into()
+}
 
-    // Hashing
-    if let Some(hash) = get_number(&info, "hashRate") {
-        println!("Hashrate        : {:.2} GH/s", hash);
+/// `named`: several hosts are being shown, so tsv rows say which one they are about
+fn show_status(
+    target: &Target,
+    format: OutputFormat,
+    view: &StatusView,
+    hook: Option<&str>,
+    named: bool,
+) -> Result<()> {
+    let info = runtime().block_on(fetch_info(&target.client, &target.host))?;
+    if let Some(hook) = hook {
+        alert::on_crit(hook, target, &info);
     }
-    if let Some(best) = get_any_as_string(&info, "bestDiff") {
-    println!("Best Diff       : {best}");
+    match format {
+        OutputFormat::Text => print_info(&info, target, view, &Trends::default()),
+        OutputFormat::Influx => match influx::line(&target.host, target.alias.as_deref(), &info) {
+            Some(line) => println!("{line}"),
+            None => bail!("No numeric fields in the response from {}", target.name()),
+        },
+        OutputFormat::Env => print!("{}", status::env_block(&info)),
+        OutputFormat::Tsv => print!("{}", status::tsv_block(&info, named.then(|| target.name()))),
     }
-    if let Some(best_session) = get_any_as_string(&info, "bestSessionDiff") {
-        println!("Best Session    : {best_session}");
+    Ok(())
+}
+
+/// `status --field`: one bare value per line, prefixed with the host when
+/// there are several. A missing key fails after printing the others.
+fn show_fields(target: &Target, fields: &[FieldSpec], named: bool, hook: Option<&str>) -> Result<()> {
+    let info = runtime().block_on(fetch_info(&target.client, &target.host))?;
+    if let Some(hook) = hook {
+        alert::on_crit(hook, target, &info);
     }
-    if let Some(accepted) = get_number(&info, "sharesAccepted") {
-        println!("Shares Accepted : {:.0}", accepted);
+    let mut missing = Vec::new();
+    for spec in fields {
+        match spec.render(&info) {
+            Some(value) if named => println!("{}: {value}", target.name()),
+            Some(value) => println!("{value}"),
+            None => missing.push(spec.key.as_str()),
+        }
     }
-    if let Some(rejected) = get_number(&info, "sharesRejected") {
-        println!("Shares Rejected : {:.0}", rejected);
+    if !missing.is_empty() {
+        bail!("No field {} in the response from {}", missing.join(", "), target.name());
     }
+    Ok(())
+}
 
-    // Temps
-    if let Some(temp) = get_number(&info, "temp") {
-        println!("Core Temp       : {:.1} °C", temp);
+fn show_oneline(target: &Target, named: bool, hook: Option<&str>) -> Result<()> {
+    let info = runtime().block_on(fetch_info(&target.client, &target.host))?;
+    if let Some(hook) = hook {
+        alert::on_crit(hook, target, &info);
     }
-    if let Some(vr) = get_number(&info, "vrTemp") {
-        println!("VR Temp         : {:.1} °C", vr);
+    let line = status::oneline(&SystemInfo::from(&info));
+    if named {
+        println!("{}: {line}", target.name());
+    } else {
+        println!("{line}");
     }
+    Ok(())
+}
+
+/// Polls with an identical hashrate before it is flagged `(unchanged)`.
+/// A hashing miner's reading moves a little on every poll, so a value that
+/// sits still usually means a hung miner or a stale web page.
+const STALE_POLLS: u32 = 3;
 
-    // Power
-    if let Some(power) = get_number(&info, "power") {
-        println!("Power           : {:.2} W", power);
+/// How often `watch` polls, and how many times (`None`: until Ctrl-C)
+struct Polling {
+    interval: Duration,
+    count: Option<u32>,
+}
+
+/// Poll every `interval` until Ctrl-C (or `count` polls). Each tick makes
+/// exactly one request; the display, the sparklines and averages, the
+/// --on-crit hook and [alerts] all read that same response instead of
+/// fetching their own copy.
+fn watch_miner(
+    target: &Target,
+    polling: Polling,
+    format: OutputFormat,
+    view: &StatusView,
+    hook: Option<&str>,
+    mut alerts: Option<notify::Alerts>,
+    mut syslog: Option<Syslog>,
+) -> Result<()> {
+    let (client, host) = (&target.client, target.host.as_str());
+    let tty = io::stdout().is_terminal();
+    let mut sparks = Sparklines::new();
+    let mut rolling = average::Rolling::new(&view.averages);
+    let mut shares = shares::Tracker::new();
+    let mut last_hash: Option<f64> = None;
+    let mut same_streak = 0;
+    // The hook fires when a reading crosses into critical, not on every hot poll
+    let mut was_crit = false;
+    let interrupt = Interrupt::install()?;
+
+    for poll in 1.. {
+        let info = runtime().block_on(fetch_info(client, host));
+        logging::poll(target.name(), info.as_ref().map_err(|e| format!("{e:#}")));
+        rolling.push(Instant::now(), info.as_ref().ok().and_then(|i| get_number(i, "hashRate")));
+        sparks.push(info.as_ref().ok());
+        if let Some(syslog) = &mut syslog {
+            syslog.poll(target.name(), info.as_ref().map_err(|e| format!("{e:#}")));
+        }
+        if let Some(alerts) = &mut alerts {
+            alerts.observe(target.name(), info.as_ref().ok());
+        }
+        match info {
+            Ok(info) if format == OutputFormat::Influx => {
+                match influx::line(host, target.alias.as_deref(), &info) {
+                    Some(line) => println!("{line}"),
+                    None => eprintln!("No numeric fields in the response from {}", target.name()),
+                }
+                io::stdout().flush()?;
+                if let Some(hook) = hook {
+                    alert::on_crit_once(hook, target, &info, &mut was_crit);
+                }
+            }
+            // A blank line between polls, so each block stands alone
+            Ok(info) if matches!(format, OutputFormat::Env | OutputFormat::Tsv) => {
+                let block =
+                    if format == OutputFormat::Env { status::env_block(&info) } else { status::tsv_block(&info, None) };
+                println!("{block}");
+                io::stdout().flush()?;
+                if let Some(hook) = hook {
+                    alert::on_crit_once(hook, target, &info, &mut was_crit);
+                }
+            }
+            Ok(info) => {
+                if let Some(hash) = get_number(&info, "hashRate") {
+                    same_streak = if last_hash == Some(hash) { same_streak + 1 } else { 1 };
+                    last_hash = Some(hash);
+                }
+
+                // Screen clearing only makes sense on a terminal; the
+                // sparklines follow the color decision (off for --no-color)
+                // and fit the terminal's current width.
+                let width = if view.sparklines { sparkline::width(sparkline::terminal_columns()) } else { 0 };
+                let spark = |metric| (width > 0).then(|| sparks.line(metric, width)).flatten();
+                let averages = rolling.averages();
+                let mut trend: Vec<String> = Vec::new();
+                if !averages.is_empty() {
+                    trend.push(format!("({})", average::describe(&averages, view.precision.unwrap_or(1))));
+                }
+                trend.extend(spark(Metric::Hashrate));
+                if same_streak >= STALE_POLLS {
+                    trend.push(paint(view.colorize, DIM, "(unchanged)"));
+                }
+                if tty {
+                    print!("\x1b[2J\x1b[H");
+                }
+                let trends = Trends {
+                    hashrate: (!trend.is_empty()).then(|| trend.join("  ")),
+                    temp: spark(Metric::Temp),
+                    power: spark(Metric::Power),
+                    shares: shares.observe(target.name(), &info, SystemTime::now()),
+                };
+                print_info(&info, target, view, &trends);
+
+                if let Some(hook) = hook {
+                    alert::on_crit_once(hook, target, &info, &mut was_crit);
+                }
+            }
+            Err(e) if polling.count == Some(poll) => return Err(e),
+            Err(e) => eprintln!("Fetch failed: {e}"),
+        }
+
+        if polling.count == Some(poll) {
+            break;
+        }
+        if !interrupt.sleep(polling.interval) {
+            return interrupt.finish();
+        }
     }
+    Ok(())
+}
 
-    if let Some(v_raw) = get_number(&info, "voltage") {
-        let v = v_raw / 1000.0;
-        println!("PSU Voltage     : {:.2} V", v);
+async fn apply_profile(client: &Client, host: &str, cfg: &AppConfig, name: &str, yes: bool, force: bool) -> Result<()> {
+    let changes = settings::lookup_profile(cfg, name)?;
+    let info = fetch_info(client, host).await?;
+    if let Err(reason) = settings::validate_all(&changes, &info, force) {
+        bail!("Profile '{name}' can't be applied: {reason}");
     }
 
-    // Frequency + voltage
-    if let Some(freq) = get_number(&info, "frequency") {
-        println!("Frequency       : {:.0} MHz", freq);
+    println!("Profile '{name}': {}", settings::preview(&changes, Some(&info)));
+    if !yes && !confirm("Apply these settings?")? {
+        println!("Aborted.");
+        return Ok(());
     }
-    if let Some(cv) = get_number(&info, "coreVoltage") {
-        println!("Core V (set)    : {:.0} mV", cv);
+
+    patch_system(client, host, &settings::body(&changes)).await?;
+    println!("Applied {}", settings::describe(&changes));
+    Ok(())
+}
+
+fn restart_miner(target: &Target, wait: bool) -> Result<()> {
+    runtime().block_on(send_restart(target))?;
+    println!("Restart command sent successfully.");
+    if wait {
+        // AxeOS answers before it goes down; don't mistake that for the reboot
+        thread::sleep(RESTART_GRACE);
+        wait_online(target, RESTART_WAIT)?;
     }
-    if let Some(cva) = get_number(&info, "coreVoltageActual") {
-        println!("Core V (actual) : {:.0} mV", cva);
+    Ok(())
+}
+
+/// Restart is not idempotent: a second POST that lands while the miner is
+/// going down (or already booting) restarts it again, so it is only retried
+/// when the connection failed before anything was sent. AxeOS answers before
+/// it reboots, so any 2xx counts as success however long it took; a timeout
+/// after sending is reported as "maybe" instead of being retried. Either way
+/// the restart is logged so the reboot that follows isn't taken for a crash.
+async fn send_restart(target: &Target) -> Result<()> {
+    let host = target.host.as_str();
+    let api = miner(&target.client, host);
+    match retry::call(host, Policy::NotSent, || api.restart()).await {
+        Ok(()) => {}
+        // A connect timeout is a timeout too, but then nothing was sent
+        Err((e, attempts)) if matches!(&e, bitaxe_api::Error::Timeout { source, .. } if !source.is_connect()) => {
+            reboot::note_restart(target.name());
+            return Err(anyhow::Error::new(BitaxeError::api_retried(host, "Restart", e, attempts)).context(
+                "Restart was sent but the miner didn't answer in time; it may be restarting already (check with `ping`)",
+            ));
+        }
+        Err((e, attempts)) => return Err(BitaxeError::api_retried(host, "Restart", e, attempts).into()),
     }
+    reboot::note_restart(target.name());
+    tracing::info!(host, "restart sent");
+    Ok(())
+}
+
+/// PATCH /api/system with a JSON object of settings
+async fn patch_system(client: &Client, host: &str, body: &serde_json::Value) -> Result<()> {
+    ratelimit::throttle(host).await;
+    miner(client, host).patch_settings(body).await.map_err(|e| api_error(host, e, "Update"))?;
+    tracing::info!(host, settings = %body, "settings changed");
+    Ok(())
+}
 
-    // Network
-    if let Some(rssi) = get_number(&info, "wifiRSSI") {
-        println!("WiFi RSSI       : {:.0} dBm", rssi);
+async fn reset_session(client: &Client, host: &str, yes: bool) -> Result<()> {
+    if !yes && !confirm("Reset session statistics (best session diff, share counts)?")? {
+        println!("Aborted.");
+        return Ok(());
     }
-    if let Some(status) = get_str(&info, "wifiStatus") {
-        println!("WiFi Status     : {status}");
+
+    // Stock AxeOS builds don't all ship it; those are reported as
+    // unsupported rather than an error
+    ratelimit::throttle(host).await;
+    let reset = miner(client, host).reset_session().await;
+    if let Err(bitaxe_api::Error::Unsupported { .. }) = reset {
+        let version = fetch_info(client, host)
+            .await
+            .ok()
+            .and_then(|info| get_str(&info, "version").map(str::to_string))
+            .unwrap_or_else(|| "unknown".to_string());
+        return Err(BitaxeError::Unsupported { what: "Session reset", version }.into());
     }
+    reset.map_err(|e| api_error(host, e, "Session reset"))?;
 
+    println!("Session statistics reset.");
     Ok(())
 }
 
-fn restart_miner(client: &Client, host: &str) -> Result<()> {
-    let url = format!("{host}/api/system/restart");
-    let resp = client.post(&url).send()?;
-    if !resp.status().is_success() {
-        bail!("Restart failed with status {}", resp.status());
+/// Poll the info endpoint until the miner answers or `timeout` elapses
+async fn wait_until_online(client: &Client, host: &str, timeout: Duration) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if fetch_info(client, host).await.is_ok() {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+    }
+    false
+}
+
+/// Time a restarted miner gets to actually go down before it is polled
+const RESTART_GRACE: Duration = Duration::from_secs(5);
+/// How long `restart --wait-online` and `fleet restart --wait` wait for it to come back
+const RESTART_WAIT: Duration = Duration::from_secs(120);
+/// Spinner frames for `wait-online` on a terminal
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// `wait-online`: poll every couple of seconds until the miner answers,
+/// with a spinner and the elapsed time on stderr when it's a terminal
+fn wait_online(target: &Target, max_wait: Duration) -> Result<()> {
+    let interrupt = Interrupt::install()?;
+    let progress = io::stderr().is_terminal();
+    let start = Instant::now();
+    let mut frame = 0;
+
+    loop {
+        if runtime().block_on(fetch_info(&target.client, &target.host)).is_ok() {
+            if progress {
+                eprint!("\r\x1b[K");
+            }
+            println!("{} is online (after {}s)", target.name(), start.elapsed().as_secs());
+            return Ok(());
+        }
+
+        // Two seconds between polls, ticking the spinner meanwhile
+        let next = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < next {
+            if start.elapsed() >= max_wait {
+                if progress {
+                    eprintln!();
+                }
+                bail!("{} did not come online within {}s", target.name(), max_wait.as_secs());
+            }
+            if progress {
+                let elapsed = start.elapsed().as_secs();
+                eprint!("\r{} Waiting for {}... {elapsed}s", SPINNER[frame % SPINNER.len()], target.name());
+                frame += 1;
+            }
+            if !interrupt.sleep(Duration::from_millis(250)) {
+                if progress {
+                    eprintln!();
+                }
+                return interrupt.finish();
+            }
+        }
+    }
+}
+
+/// Ask a yes/no question on stdin; anything but y/yes is a no
+fn confirm(prompt: &str) -> Result<bool> {
+    print!("{prompt} [y/N] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Parse durations like `500ms`, `30s`, `2m`, `1h`, `7d`; a bare number is seconds
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let invalid = || format!("invalid duration '{s}' (expected e.g. 500ms, 30s, 2m, 1h, 7d)");
+    let n: f64 = num.parse().map_err(|_| invalid())?;
+
+    let secs = match unit.trim() {
+        "ms" => n / 1000.0,
+        "" | "s" => n,
+        "m" => n * 60.0,
+        "h" => n * 3600.0,
+        "d" => n * 86_400.0,
+        other => return Err(format!("unknown duration unit '{other}' in '{s}' (use ms, s, m, h or d)")),
+    };
+    // Out of range for a Duration: from_secs_f64 would panic
+    Duration::try_from_secs_f64(secs).map_err(|_| invalid())
+}
+
+/// A duration that can bound a request: zero would fail every request
+/// before it is sent
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    let d = parse_duration(s)?;
+    if d.is_zero() {
+        return Err(format!("timeout '{s}' has to be above zero"));
+    }
+    Ok(d)
+}
+
+async fn ping_miner(client: &Client, host: &str, count: u32) -> Result<()> {
+    let url = format!("{host}{INFO_PATH}");
+    let mut times = Vec::new();
+
+    for i in 0..count {
+        if i > 0 {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+
+        ratelimit::throttle(host).await;
+        let start = Instant::now();
+        match client.get(&url).send().await {
+            Ok(resp) if resp.status().is_success() => {
+                let ms = start.elapsed().as_secs_f64() * 1000.0;
+                println!("{host} reachable in {:.0}ms", ms);
+                times.push(ms);
+            }
+            Ok(resp) => println!("{host} responded with status {}", resp.status()),
+            Err(e) => println!("{host} unreachable: {e}"),
+        }
+    }
+
+    if count > 1 && !times.is_empty() {
+        let min = times.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = times.iter().cloned().fold(0.0, f64::max);
+        let avg = times.iter().sum::<f64>() / times.len() as f64;
+        println!(
+            "--- {} sent, {} ok, min/avg/max = {:.0}/{:.0}/{:.0} ms ---",
+            count,
+            times.len(),
+            min,
+            avg,
+            max
+        );
+    }
+
+    if times.is_empty() {
+        bail!("{host} did not respond successfully");
     }
-    println!("Restart command sent successfully.");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_out_of_range_are_invalid() {
+        assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        let e = parse_duration("99999999999999999999999d").unwrap_err();
+        assert_eq!(e, "invalid duration '99999999999999999999999d' (expected e.g. 500ms, 30s, 2m, 1h, 7d)");
+        assert!(parse_timeout("0s").unwrap_err().contains("above zero"));
+    }
+
+    #[test]
+    fn json_errors_follow_the_parsed_flag() {
+        let parsed = |args: &[&str]| prints_json(&Cli::try_parse_from(args).unwrap().command);
+        assert!(parsed(&["bitaxe-cli", "fleet", "status", "--json"]));
+        assert!(parsed(&["bitaxe-cli", "swarm", "--json"]));
+        assert!(!parsed(&["bitaxe-cli", "fleet", "status"]));
+        // A --json that is another option's value doesn't count
+        assert!(!parsed(&["bitaxe-cli", "status", "--on-crit=--json"]));
+        assert!(!parsed(&["bitaxe-cli", "raw", "--path", "/api/system", "--method", "patch", "--body=--json"]));
+    }
+
+    #[test]
+    fn check_usage_errors_are_recognized() {
+        let check = |args: &[&str]| is_check(args.iter().map(std::ffi::OsString::from));
+        assert!(check(&["bitaxe-cli", "check"]));
+        assert!(check(&["bitaxe-cli", "--host", "x", "check", "--metric", "temp", "-w", "abc"]));
+        assert!(!check(&["bitaxe-cli", "status", "--nope"]));
+    }
+}
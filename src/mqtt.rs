@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use rumqttc::{Client, Connection, Event, Incoming, LastWill, MqttOptions, Outgoing, QoS, Transport};
+use serde_json::json;
+use crate::client::Target;
+use crate::config_file::{AppConfig, MqttConfig};
+use crate::fleet::{DeviceResult, FleetOpts, device_json, fetch_fleet};
+use crate::homeassistant;
+use crate::influx::FIELDS;
+use crate::interrupt::Interrupt;
+use crate::{get_number, logging};
+
+#[derive(Args, Debug)]
+pub struct MqttArgs {
+    /// Broker URL, ex: mqtt://homelab:1883 or mqtts://homelab:8883
+    /// (default: `broker` in the [mqtt] table)
+    #[arg(long)]
+    broker: Option<String>,
+
+    /// Seconds between polls
+    #[arg(long, default_value_t = 30)]
+    interval: u64,
+
+    /// Also publish every metric to its own topic, ex: bitaxe/gamma/temp
+    #[arg(long)]
+    per_metric: bool,
+
+    /// QoS for all messages: 0, 1 or 2 (default: `qos` in [mqtt], then 0)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=2))]
+    qos: Option<u8>,
+
+    /// First topic level (default: `topic_prefix` in [mqtt], then "bitaxe")
+    #[arg(long)]
+    topic_prefix: Option<String>,
+
+    /// Also publish retained Home Assistant discovery configs, so every
+    /// device shows up in HA with its sensors
+    #[arg(long)]
+    ha_discovery: bool,
+
+    /// Remove the discovery configs (and availability topic) of the targeted
+    /// devices from the broker, then exit
+    #[arg(long, conflicts_with_all = ["ha_discovery", "per_metric", "interval"])]
+    ha_cleanup: bool,
+
+    /// Home Assistant's discovery prefix
+    #[arg(long, default_value = homeassistant::DEFAULT_PREFIX)]
+    ha_prefix: String,
+
+    #[command(flatten)]
+    pub opts: FleetOpts,
+}
+
+/// Queued publishes kept while the broker is unreachable; polls beyond
+/// that are dropped rather than blocking the poll loop
+const QUEUE: usize = 1024;
+/// First pause after the broker drops; doubles up to `MAX_BACKOFF`
+const BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Where to connect, from a broker URL
+#[derive(Debug, PartialEq)]
+struct Broker {
+    host: String,
+    port: u16,
+    tls: bool,
+}
+
+impl Broker {
+    /// `mqtt://host[:port]`, `mqtts://host[:port]` or a bare `host[:port]`
+    fn parse(url: &str) -> Result<Self> {
+        let (tls, rest) = match url.split_once("://") {
+            Some(("mqtt" | "tcp", rest)) => (false, rest),
+            Some(("mqtts" | "ssl", rest)) => (true, rest),
+            Some((scheme, _)) => bail!("Unsupported broker scheme '{scheme}' (use mqtt:// or mqtts://)"),
+            None => (false, url),
+        };
+        let rest = rest.trim_end_matches('/');
+        let (host, port) = match rest.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port.parse().with_context(|| format!("Invalid port in broker URL '{url}'"))?;
+                (host, port)
+            }
+            None => (rest, if tls { 8883 } else { 1883 }),
+        };
+        if host.is_empty() {
+            bail!("No host in broker URL '{url}'");
+        }
+        Ok(Broker { host: host.to_string(), port, tls })
+    }
+}
+
+/// Device names go into topics, where `/`, `+` and `#` have a meaning
+fn topic_segment(name: &str) -> String {
+    name.replace(['/', '+', '#'], "_")
+}
+
+fn qos(level: u8) -> QoS {
+    match level {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
+/// Everything published for one poll of one device, as (topic, payload).
+/// The status payload is the device's `fleet status --json` entry plus a
+/// `time`; per-metric topics get the bare number.
+fn messages(prefix: &str, r: &DeviceResult, per_metric: bool) -> Vec<(String, String)> {
+    let base = format!("{prefix}/{}", topic_segment(&r.name));
+    let mut payload = device_json(r);
+    let time = r.fetched_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+    payload["time"] = json!((time * 1000.0).round() / 1000.0);
+
+    let mut out = vec![(format!("{base}/status"), payload.to_string())];
+    if per_metric && let Ok(info) = &r.info {
+        for (key, field) in FIELDS {
+            if let Some(v) = get_number(info, key) {
+                out.push((format!("{base}/{field}"), v.to_string()));
+            }
+        }
+    }
+    out
+}
+
+fn options(broker: &Broker, conf: Option<&MqttConfig>, availability: &str, qos: QoS) -> Result<MqttOptions> {
+    let client_id = conf
+        .and_then(|c| c.client_id.clone())
+        .unwrap_or_else(|| format!("bitaxe-cli-{}", std::process::id()));
+    let mut opts = MqttOptions::new(client_id, broker.host.clone(), broker.port);
+    opts.set_keep_alive(Duration::from_secs(30));
+    opts.set_last_will(LastWill::new(availability, "offline", qos, true));
+
+    if let Some(user) = conf.and_then(|c| c.username.as_deref()) {
+        opts.set_credentials(user, conf.and_then(|c| c.password.as_deref()).unwrap_or(""));
+    }
+    if broker.tls {
+        let read = |path: &Path| fs::read(path).with_context(|| format!("Failed to read {}", path.display()));
+        let cert = conf.and_then(|c| c.client_cert.as_deref());
+        let client_auth = match (cert, conf.and_then(|c| c.client_key.as_deref())) {
+            (Some(cert), Some(key)) => Some((read(cert)?, read(key)?)),
+            (None, None) => None,
+            _ => bail!("Set both client_cert and client_key in [mqtt], or neither"),
+        };
+        let transport = match conf.and_then(|c| c.ca_file.as_deref()) {
+            Some(ca) => Transport::tls(read(ca)?, client_auth, None),
+            None if client_auth.is_some() => bail!("client_cert in [mqtt] needs ca_file as well"),
+            None => Transport::tls_with_default_config(),
+        };
+        opts.set_transport(transport);
+    }
+    Ok(opts)
+}
+
+/// The publishing side of a connection, shared with the event loop
+#[derive(Clone)]
+struct Link {
+    client: Client,
+    availability: String,
+    qos: QoS,
+    /// Keep reconnecting; a one-shot run (`--ha-cleanup`) gives up instead
+    persistent: bool,
+    /// Set on every connect after the first, so retained discovery configs
+    /// are sent again to a broker that may have lost them
+    reconnected: Arc<AtomicBool>,
+}
+
+/// Drive the connection: mark the bridge online after every (re)connect
+/// and retry with backoff when the broker drops. Reports on `done` once the
+/// final disconnect went out, or with the error that ended a one-shot run.
+fn event_loop(mut conn: Connection, link: Link, interrupt: Interrupt, done: mpsc::Sender<Result<(), String>>) {
+    let mut backoff = BACKOFF;
+    let mut connected_before = false;
+    for event in conn.iter() {
+        match event {
+            Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                backoff = BACKOFF;
+                if link.persistent {
+                    eprintln!("Connected to MQTT broker");
+                    let _ = link.client.try_publish(link.availability.as_str(), link.qos, true, "online");
+                }
+                if connected_before {
+                    link.reconnected.store(true, Ordering::Relaxed);
+                }
+                connected_before = true;
+            }
+            Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+            Ok(_) => {}
+            Err(e) if !link.persistent => {
+                let _ = done.send(Err(e.to_string()));
+                return;
+            }
+            Err(e) => {
+                if interrupt.is_set() {
+                    break;
+                }
+                eprintln!("MQTT connection lost: {e}; reconnecting in {}s", backoff.as_secs());
+                if !interrupt.sleep(backoff) {
+                    break;
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+    let _ = done.send(Ok(()));
+}
+
+/// Broker and topics, from flags with the [mqtt] table as the fallback
+pub struct Settings {
+    pub url: String,
+    pub prefix: String,
+    qos: QoS,
+    pub per_metric: bool,
+    /// Discovery prefix when Home Assistant discovery is on
+    pub ha_prefix: Option<String>,
+}
+
+impl Settings {
+    /// `broker`, `qos` and `prefix` override the [mqtt] table; without any
+    /// flags (as in `daemon`) everything comes from the config
+    pub fn resolve(
+        conf: Option<&MqttConfig>,
+        broker: Option<String>,
+        qos: Option<u8>,
+        prefix: Option<String>,
+    ) -> Result<Self> {
+        let url = broker
+            .or_else(|| conf.and_then(|c| c.broker.clone()))
+            .context("Pass --broker or set broker in the [mqtt] table of the config")?;
+        let level = match qos.or(conf.and_then(|c| c.qos)) {
+            Some(q @ 0..=2) => q,
+            Some(q) => bail!("qos in [mqtt] must be 0, 1 or 2, not {q}"),
+            None => 0,
+        };
+        let prefix = prefix
+            .or_else(|| conf.and_then(|c| c.topic_prefix.clone()))
+            .unwrap_or_else(|| "bitaxe".to_string());
+        Ok(Settings {
+            url,
+            prefix,
+            qos: self::qos(level),
+            per_metric: conf.is_some_and(|c| c.per_metric),
+            ha_prefix: conf.filter(|c| c.ha_discovery).map(|_| homeassistant::DEFAULT_PREFIX.to_string()),
+        })
+    }
+}
+
+/// A broker connection that publishes polls, with the retained
+/// `<prefix>/availability` topic reading `online` until `close`
+pub struct Publisher {
+    link: Link,
+    settings: Settings,
+    done: mpsc::Receiver<Result<(), String>>,
+    /// Discovery configs last sent per device, to notice when they change
+    announced: HashMap<String, Vec<(String, String)>>,
+}
+
+impl Publisher {
+    /// Start connecting in the background; publishes queue up until the
+    /// broker answers
+    pub fn connect(conf: Option<&MqttConfig>, settings: Settings, interrupt: &Interrupt) -> Result<Self> {
+        Self::start(conf, settings, true, interrupt)
+    }
+
+    fn start(conf: Option<&MqttConfig>, settings: Settings, persistent: bool, interrupt: &Interrupt) -> Result<Self> {
+        let broker = Broker::parse(&settings.url)?;
+        let availability = format!("{}/availability", settings.prefix);
+        let (client, conn) = Client::new(options(&broker, conf, &availability, settings.qos)?, QUEUE);
+        let link = Link {
+            client,
+            availability,
+            qos: settings.qos,
+            persistent,
+            reconnected: Arc::new(AtomicBool::new(false)),
+        };
+        let (done_tx, done) = mpsc::channel();
+        {
+            let (link, interrupt) = (link.clone(), interrupt.clone());
+            thread::spawn(move || event_loop(conn, link, interrupt, done_tx));
+        }
+        Ok(Publisher { link, settings, done, announced: HashMap::new() })
+    }
+
+    /// Queue the messages for one poll of every device; returns how many
+    /// were dropped because the queue is full (the broker is unreachable)
+    pub fn publish(&mut self, results: &[DeviceResult]) -> usize {
+        if self.link.reconnected.swap(false, Ordering::Relaxed) {
+            self.announced.clear();
+        }
+        let (link, settings) = (&self.link, &self.settings);
+        let mut dropped = 0;
+        let mut publish = |topic: String, retain: bool, payload: String| {
+            if link.client.try_publish(topic, link.qos, retain, payload).is_err() {
+                dropped += 1;
+            }
+        };
+        for r in results {
+            let state_topic = format!("{}/{}/status", settings.prefix, topic_segment(&r.name));
+            if let (Some(ha_prefix), Ok(info)) = (&settings.ha_prefix, &r.info) {
+                let configs = homeassistant::discovery(ha_prefix, &state_topic, &link.availability, &r.name, info);
+                let previous = self.announced.get(&r.name);
+                if previous != Some(&configs) {
+                    // Entities the miner stopped reporting are removed
+                    for (topic, _) in previous.into_iter().flatten() {
+                        if !configs.iter().any(|(t, _)| t == topic) {
+                            publish(topic.clone(), true, String::new());
+                        }
+                    }
+                    for (topic, payload) in &configs {
+                        publish(topic.clone(), true, payload.clone());
+                    }
+                    self.announced.insert(r.name.clone(), configs);
+                }
+            }
+            for (topic, payload) in messages(&settings.prefix, r, settings.per_metric) {
+                publish(topic, false, payload);
+            }
+        }
+        dropped
+    }
+
+    /// Best effort: say goodbye, but don't hang on a dead broker
+    pub fn close(&mut self) {
+        let _ = self.link.client.try_publish(self.link.availability.as_str(), self.link.qos, true, "offline");
+        let _ = self.link.client.try_disconnect();
+        let _ = self.done.recv_timeout(Duration::from_secs(2));
+    }
+}
+
+/// Poll the targets every --interval and publish until Ctrl-C. The
+/// retained `<prefix>/availability` topic reads `online` while this runs
+/// and `offline` after it stops (the broker publishes it as the last will
+/// if the connection dies instead).
+pub fn run(args: &MqttArgs, targets: &[Target], cfg: &AppConfig) -> Result<()> {
+    let conf = cfg.mqtt.as_ref();
+    let mut settings = Settings::resolve(conf, args.broker.clone(), args.qos, args.topic_prefix.clone())?;
+    settings.per_metric |= args.per_metric;
+    if args.ha_discovery || settings.ha_prefix.is_some() {
+        settings.ha_prefix = Some(args.ha_prefix.clone());
+    }
+
+    let interrupt = Interrupt::install()?;
+    if args.ha_cleanup {
+        let publisher = Publisher::start(conf, settings, false, &interrupt)?;
+        return ha_cleanup(&publisher, &args.ha_prefix, targets);
+    }
+    let url = settings.url.clone();
+    eprintln!("Publishing {} device(s) to {url} under {}/ every {}s", targets.len(), settings.prefix, args.interval);
+    let mut publisher = Publisher::connect(conf, settings, &interrupt)?;
+    loop {
+        let results = fetch_fleet(targets, &args.opts);
+        for r in &results {
+            logging::poll(&r.name, r.info.as_ref().map_err(String::clone));
+        }
+        let dropped = publisher.publish(&results);
+        if dropped > 0 {
+            eprintln!("Warning: broker unreachable, dropped {dropped} message(s)");
+        }
+
+        if !interrupt.sleep(Duration::from_secs(args.interval)) {
+            break;
+        }
+    }
+
+    publisher.close();
+    interrupt.finish()
+}
+
+/// How long `--ha-cleanup` waits for the broker to take its messages
+const CLEANUP_WAIT: Duration = Duration::from_secs(10);
+
+/// `--ha-cleanup`: empty every discovery config the targets could have
+/// (and the availability topic), which makes Home Assistant drop the entities
+fn ha_cleanup(publisher: &Publisher, ha_prefix: &str, targets: &[Target]) -> Result<()> {
+    let (link, done) = (&publisher.link, &publisher.done);
+    let mut topics: Vec<String> =
+        targets.iter().flat_map(|t| homeassistant::all_topics(ha_prefix, t.name())).collect();
+    topics.push(link.availability.clone());
+    for topic in &topics {
+        link.client
+            .try_publish(topic.as_str(), link.qos, true, "")
+            .context("Too many targets to clean up in one go")?;
+    }
+    link.client.try_disconnect()?;
+    match done.recv_timeout(CLEANUP_WAIT) {
+        Ok(Ok(())) => {
+            println!("Cleared {} retained topic(s) for {} device(s)", topics.len(), targets.len());
+            Ok(())
+        }
+        Ok(Err(e)) => bail!("MQTT broker: {e}"),
+        Err(_) => bail!("MQTT broker didn't take the messages within {}s", CLEANUP_WAIT.as_secs()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    #[test]
+    fn broker_urls() {
+        assert_eq!(
+            Broker::parse("mqtt://homelab:1883").unwrap(),
+            Broker { host: "homelab".into(), port: 1883, tls: false }
+        );
+        assert_eq!(Broker::parse("mqtts://homelab").unwrap(), Broker { host: "homelab".into(), port: 8883, tls: true });
+        assert_eq!(Broker::parse("10.0.0.2").unwrap(), Broker { host: "10.0.0.2".into(), port: 1883, tls: false });
+        assert!(Broker::parse("http://homelab").is_err());
+        assert!(Broker::parse("mqtt://homelab:x").is_err());
+    }
+
+    #[test]
+    fn topics_per_device_and_metric() {
+        let r = DeviceResult {
+            name: "rack/a".into(),
+            host: "http://10.0.0.5".into(),
+            info: Ok(json!({"hashRate": 512.5, "temp": 61})),
+            fetched_at: SystemTime::now(),
+        };
+        let msgs = messages("bitaxe", &r, true);
+        let topics: Vec<&str> = msgs.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(topics, ["bitaxe/rack_a/status", "bitaxe/rack_a/hashrate", "bitaxe/rack_a/temp"]);
+        assert_eq!(msgs[2].1, "61");
+
+        let status: serde_json::Value = serde_json::from_str(&msgs[0].1).unwrap();
+        assert_eq!(status["online"], true);
+        assert_eq!(status["hashRate"], 512.5);
+        assert_eq!(messages("bitaxe", &r, false).len(), 1);
+    }
+}
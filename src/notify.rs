@@ -0,0 +1,384 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde_json::{Value, json};
+use tokio::task::JoinHandle;
+use crate::alert::{AlertEvent, Monitor, Severity};
+use crate::config_file::{AlertsConfig, AppConfig, NotifierConfig};
+#[cfg(feature = "sqlite")]
+use crate::history::HistoryDb;
+use crate::logger::rfc3339;
+use crate::rules;
+use crate::runtime;
+use crate::shares::Seed;
+
+/// Deliveries per event, including the first
+const ATTEMPTS: u32 = 4;
+/// Pause before the first retry; doubles for each one after that
+const BACKOFF: Duration = Duration::from_secs(1);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `[alerts]` for a polling loop: the condition monitor and rules engine
+/// plus the notifiers their events go to
+pub struct Alerts {
+    monitor: Monitor,
+    rules: rules::Engine,
+    out: Dispatcher,
+    /// Where rule states are saved for `alerts status`, until saving fails
+    state_path: Option<PathBuf>,
+    /// Where events are stored for `report`, until storing fails
+    #[cfg(feature = "sqlite")]
+    history: Option<HistoryDb>,
+}
+
+impl Alerts {
+    /// `None` when the config has no [alerts] table
+    pub fn from_config(cfg: &AppConfig) -> Result<Option<Self>> {
+        let Some(alerts) = &cfg.alerts else { return Ok(None) };
+        let rules = rules::Engine::from_config(cfg)?;
+        let state_path = if rules.is_empty() { None } else { rules::state_path() };
+        Ok(Some(Alerts {
+            monitor: Monitor::new(alerts),
+            rules,
+            out: Dispatcher::new(alerts)?,
+            state_path,
+            #[cfg(feature = "sqlite")]
+            history: None,
+        }))
+    }
+
+    /// Also store every event in the history database
+    #[cfg(feature = "sqlite")]
+    pub fn record_to(&mut self, db: HistoryDb) {
+        self.history = Some(db);
+    }
+
+    /// See `rules::Engine::seed_shares`
+    pub fn seed_shares(&mut self, seeds: &[Seed]) {
+        self.rules.seed_shares(seeds);
+    }
+
+    /// Check one poll of `device` (`None` when it couldn't be reached);
+    /// returns how many events it raised
+    pub fn observe(&mut self, device: &str, info: Option<&Value>) -> usize {
+        let now = SystemTime::now();
+        let mut events = self.monitor.observe(device, info, now);
+        events.extend(self.rules.observe(device, info, now));
+        let raised = events.len();
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = &mut self.history
+            && !events.is_empty()
+            && let Err(e) = db.insert_events(&events)
+        {
+            eprintln!("Warning: alert events won't be stored for `report`: {e:#}");
+            self.history = None;
+        }
+        self.out.send(events);
+
+        if let Some(path) = &self.state_path
+            && let Err(e) = rules::save_states(path, &self.rules.states())
+        {
+            eprintln!("Warning: alert rule states won't be saved for `alerts status`: {e:#}");
+            self.state_path = None;
+        }
+        raised
+    }
+
+    /// Wait for notifications still being delivered
+    pub fn flush(&mut self) {
+        self.out.flush();
+    }
+}
+
+/// Sends alert events to every configured notifier
+pub struct Dispatcher {
+    client: Client,
+    notifiers: Vec<NotifierConfig>,
+    /// Background deliveries, until `flush` or the next `send` reaps them
+    pending: Vec<JoinHandle<()>>,
+}
+
+impl Dispatcher {
+    pub fn new(cfg: &AlertsConfig) -> Result<Self> {
+        let mut notifiers: Vec<NotifierConfig> =
+            cfg.webhook.iter().map(|url| NotifierConfig::Webhook { url: url.clone() }).collect();
+        notifiers.extend(cfg.notify.iter().cloned());
+        Ok(Dispatcher { client: Client::builder().timeout(TIMEOUT).build()?, notifiers, pending: Vec::new() })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notifiers.is_empty()
+    }
+
+    /// Report each event on stderr and queue it for the notifiers. Delivery
+    /// runs in the background so a slow endpoint never delays a poll.
+    pub fn send(&mut self, events: Vec<AlertEvent>) {
+        for event in &events {
+            eprintln!("Alert: {} - {}", event.title(), event.detail());
+            tracing::warn!(
+                device = event.device,
+                condition = event.name(),
+                severity = event.severity.name(),
+                firing = event.firing,
+                value = event.value,
+                threshold = event.threshold,
+                "alert"
+            );
+        }
+        if events.is_empty() || self.notifiers.is_empty() {
+            return;
+        }
+        self.pending.retain(|h| !h.is_finished());
+        let (client, notifiers) = (self.client.clone(), self.notifiers.clone());
+        let handle = runtime().spawn(async move {
+            for event in &events {
+                for notifier in &notifiers {
+                    if let Err(e) = deliver(&client, notifier, event).await {
+                        eprintln!("Warning: {} notifier: {e:#}", kind(notifier));
+                        tracing::error!(notifier = kind(notifier), error = format!("{e:#}"), "notification failed");
+                    }
+                }
+            }
+        });
+        self.pending.push(handle);
+    }
+
+    /// Block until every queued delivery is done, retries included
+    pub fn flush(&mut self) {
+        for handle in self.pending.drain(..) {
+            let _ = runtime().block_on(handle);
+        }
+    }
+
+    /// Deliver one event to every notifier right away, with each outcome
+    /// labelled by notifier type (for `alerts test`)
+    pub fn send_now(&self, event: &AlertEvent) -> Vec<(&'static str, Result<()>)> {
+        self.notifiers.iter().map(|n| (kind(n), runtime().block_on(deliver(&self.client, n, event)))).collect()
+    }
+}
+
+fn kind(notifier: &NotifierConfig) -> &'static str {
+    match notifier {
+        NotifierConfig::Webhook { .. } => "webhook",
+        NotifierConfig::Discord { .. } => "discord",
+        NotifierConfig::Slack { .. } => "slack",
+        NotifierConfig::Ntfy { .. } => "ntfy",
+        NotifierConfig::Desktop => "desktop",
+    }
+}
+
+async fn deliver(client: &Client, notifier: &NotifierConfig, event: &AlertEvent) -> Result<()> {
+    match notifier {
+        NotifierConfig::Webhook { url } => post(client, url, &event.payload()).await,
+        NotifierConfig::Discord { url } => post(client, url, &discord_body(event)).await,
+        NotifierConfig::Slack { url } => post(client, url, &slack_body(event)).await,
+        NotifierConfig::Ntfy { server, topic, token } => {
+            let url = format!("{}/{topic}", server.trim_end_matches('/'));
+            let (priority, tags) = ntfy_priority(event);
+            // ntfy asks clients to back off on 429, so that one isn't retried
+            send(&url, false, || {
+                let req = client
+                    .post(&url)
+                    .header("X-Title", header_text(&event.title()))
+                    .header("X-Priority", priority)
+                    .header("X-Tags", tags)
+                    .body(event.detail());
+                match token {
+                    Some(token) => req.bearer_auth(token),
+                    None => req,
+                }
+            })
+            .await
+        }
+        NotifierConfig::Desktop => desktop(event),
+    }
+}
+
+/// Raise an OS notification titled with the device. Without a
+/// notification daemon (a headless box, no D-Bus session) this fails
+/// like any other notifier: logged, and the poll loop goes on.
+#[cfg(feature = "desktop")]
+fn desktop(event: &AlertEvent) -> Result<()> {
+    let mut notification = notify_rust::Notification::new();
+    notification.appname("bitaxe-cli").summary(&event.title()).body(&event.detail());
+    #[cfg(not(target_os = "macos"))]
+    notification.urgency(urgency(event));
+    notification.show().map_err(|e| anyhow::anyhow!("no desktop notification shown: {e}"))?;
+    Ok(())
+}
+
+#[cfg(not(feature = "desktop"))]
+fn desktop(_event: &AlertEvent) -> Result<()> {
+    bail!("this build doesn't include desktop notifications; rebuild with `--features desktop`")
+}
+
+/// Same mapping as ntfy: critical alerts are urgent, info ones quiet, and
+/// every recovery is a normal notification
+#[cfg(all(feature = "desktop", not(target_os = "macos")))]
+fn urgency(event: &AlertEvent) -> notify_rust::Urgency {
+    use notify_rust::Urgency;
+    match (event.firing, event.severity) {
+        (true, Severity::Critical) => Urgency::Critical,
+        (true, Severity::Warning) | (false, _) => Urgency::Normal,
+        (true, Severity::Info) => Urgency::Low,
+    }
+}
+
+/// ntfy priority and emoji tags by severity: critical alerts (overheating
+/// and unreachable miners, unless a rule says otherwise) are urgent, info
+/// ones quiet, and every recovery is a normal notification
+fn ntfy_priority(event: &AlertEvent) -> (&'static str, &'static str) {
+    match (event.firing, event.severity) {
+        (true, Severity::Critical) => ("high", "warning"),
+        (true, Severity::Warning) => ("default", "warning"),
+        (true, Severity::Info) => ("low", "information_source"),
+        (false, _) => ("default", "white_check_mark"),
+    }
+}
+
+/// Header values have to be ASCII; anything else goes in RFC 2047 form,
+/// which ntfy decodes
+fn header_text(s: &str) -> String {
+    if s.is_ascii() {
+        s.to_string()
+    } else {
+        format!("=?UTF-8?B?{}?=", STANDARD.encode(s))
+    }
+}
+
+const RED: u32 = 0xD0_3030;
+const GREEN: u32 = 0x2E_B67D;
+
+fn color(event: &AlertEvent) -> u32 {
+    if event.firing { RED } else { GREEN }
+}
+
+/// A Discord embed: colored bar, device/value/threshold fields, timestamp
+fn discord_body(event: &AlertEvent) -> Value {
+    json!({
+        "embeds": [{
+            "title": event.title(),
+            "description": event.detail(),
+            "color": color(event),
+            "timestamp": rfc3339(event.at),
+            "fields": [
+                {"name": "Device", "value": event.device, "inline": true},
+                {"name": "Condition", "value": event.name(), "inline": true},
+                {"name": "Since", "value": rfc3339(event.since), "inline": true},
+            ],
+        }],
+    })
+}
+
+/// A Slack attachment with the same content as the Discord embed
+fn slack_body(event: &AlertEvent) -> Value {
+    json!({
+        "text": event.title(),
+        "attachments": [{
+            "color": format!("#{:06x}", color(event)),
+            "title": event.title(),
+            "text": event.detail(),
+            "fields": [
+                {"title": "Device", "value": event.device, "short": true},
+                {"title": "Condition", "value": event.name(), "short": true},
+            ],
+            "ts": unix_secs(event.at),
+        }],
+    })
+}
+
+fn unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// POST `body` as JSON, see `send`
+async fn post(client: &Client, url: &str, body: &Value) -> Result<()> {
+    send(url, true, || client.post(url).json(body)).await
+}
+
+/// Send the request built by `build`, retrying with backoff while the
+/// endpoint is unreachable or answers 5xx (or 429 with `retry_429`); other
+/// statuses mean retrying won't help
+async fn send(url: &str, retry_429: bool, build: impl Fn() -> RequestBuilder) -> Result<()> {
+    let mut attempt = 1;
+    loop {
+        let outcome = build().send().await;
+        let retryable = match &outcome {
+            Ok(resp) if resp.status().is_success() => return Ok(()),
+            Ok(resp) if resp.status() == StatusCode::TOO_MANY_REQUESTS && !retry_429 => {
+                bail!("{url} is rate limiting (429); notification dropped")
+            }
+            Ok(resp) => resp.status().is_server_error() || resp.status() == StatusCode::TOO_MANY_REQUESTS,
+            Err(_) => true,
+        };
+        if !retryable || attempt >= ATTEMPTS {
+            match outcome {
+                Ok(resp) => bail!("{url} answered {}", resp.status()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        tokio::time::sleep(BACKOFF * 2u32.pow(attempt - 1)).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert::Condition;
+
+    fn event(firing: bool) -> AlertEvent {
+        AlertEvent {
+            device: "gamma".into(),
+            condition: Condition::TempAbove,
+            rule: None,
+            severity: Severity::Critical,
+            firing,
+            value: Some(70.456),
+            threshold: Some(68.0),
+            since: UNIX_EPOCH + Duration::from_secs(1_714_060_800),
+            at: UNIX_EPOCH + Duration::from_secs(1_714_060_860),
+        }
+    }
+
+    #[test]
+    fn discord_embed_is_red_then_green() {
+        let body = discord_body(&event(true));
+        let embed = &body["embeds"][0];
+        assert_eq!(embed["title"], "gamma: Temperature alert");
+        assert_eq!(embed["description"], "70.46 °C (threshold 68 °C)");
+        assert_eq!(embed["color"], RED);
+        assert_eq!(embed["timestamp"], "2024-04-25T16:01:00Z");
+        assert_eq!(discord_body(&event(false))["embeds"][0]["color"], GREEN);
+    }
+
+    #[test]
+    fn slack_attachment_carries_color_and_ts() {
+        let body = slack_body(&event(false));
+        let attachment = &body["attachments"][0];
+        assert_eq!(attachment["color"], "#2eb67d");
+        assert_eq!(attachment["title"], "gamma: Temperature recovered");
+        assert_eq!(attachment["ts"], 1_714_060_860);
+    }
+
+    #[test]
+    fn ntfy_overheat_is_high_priority() {
+        assert_eq!(ntfy_priority(&event(true)), ("high", "warning"));
+        assert_eq!(ntfy_priority(&event(false)), ("default", "white_check_mark"));
+        assert_eq!(header_text("gamma: Temperature alert"), "gamma: Temperature alert");
+        assert_eq!(header_text("gämma"), "=?UTF-8?B?Z8OkbW1h?=");
+    }
+
+    #[cfg(all(feature = "desktop", not(target_os = "macos")))]
+    #[test]
+    fn desktop_urgency_follows_severity() {
+        use notify_rust::Urgency;
+        assert_eq!(urgency(&event(true)), Urgency::Critical);
+        assert_eq!(urgency(&event(false)), Urgency::Normal);
+        let quiet = AlertEvent { severity: Severity::Info, ..event(true) };
+        assert_eq!(urgency(&quiet), Urgency::Low);
+    }
+}
@@ -0,0 +1,21 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde::Serialize;
+
+/// Set from --compact before the command runs
+static COMPACT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_compact(compact: bool) {
+    COMPACT.store(compact, Ordering::Relaxed);
+}
+
+/// One cell of tab-separated output: tabs and line breaks inside a value
+/// would shift the columns, so they become spaces
+pub fn tsv_field(s: &str) -> String {
+    s.replace(['\t', '\r', '\n'], " ")
+}
+
+/// `--json` output: indented for reading, or a single line with --compact
+/// (for appending to a JSONL file)
+pub fn json<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<String> {
+    if COMPACT.load(Ordering::Relaxed) { serde_json::to_string(value) } else { serde_json::to_string_pretty(value) }
+}
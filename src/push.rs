@@ -0,0 +1,37 @@
+use anyhow::Result;
+use clap::Subcommand;
+use crate::client::Target;
+use crate::config_file::AppConfig;
+use crate::fleet::FleetOpts;
+use crate::graphite::{self, GraphiteArgs};
+use crate::influx::{self, PushArgs};
+use crate::statsd::{self, StatsdArgs};
+
+/// `push` destinations
+#[derive(Subcommand, Debug)]
+pub enum PushCommand {
+    /// Write line protocol to an InfluxDB v2 bucket
+    Influx(PushArgs),
+    /// Send gauges to a StatsD server over UDP
+    Statsd(StatsdArgs),
+    /// Write to Graphite/carbon over TCP (plaintext protocol)
+    Graphite(GraphiteArgs),
+}
+
+impl PushCommand {
+    pub fn opts(&self) -> &FleetOpts {
+        match self {
+            PushCommand::Influx(args) => &args.opts,
+            PushCommand::Statsd(args) => &args.opts,
+            PushCommand::Graphite(args) => &args.opts,
+        }
+    }
+}
+
+pub fn run(cmd: &PushCommand, targets: &[Target], cfg: &AppConfig) -> Result<()> {
+    match cmd {
+        PushCommand::Influx(args) => influx::push(args, targets, cfg),
+        PushCommand::Statsd(args) => statsd::push(args, targets, cfg),
+        PushCommand::Graphite(args) => graphite::push(args, targets, cfg),
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Spacing used when neither --request-spacing nor the config sets one
+pub const DEFAULT_SPACING: Duration = Duration::from_millis(500);
+
+/// Shortest polling interval `watch` and `fleet watch` accept. Faster
+/// loops mostly keep the miner's single-threaded web server busy (and
+/// AxeOS only refreshes most readings every second or so anyway).
+pub const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `requested`, raised to `MIN_INTERVAL` with a warning when below it
+pub fn poll_interval(requested: Duration) -> Duration {
+    if requested < MIN_INTERVAL {
+        eprintln!(
+            "Warning: polling every {:?} would overload the miner; using {}s",
+            requested,
+            MIN_INTERVAL.as_secs()
+        );
+        return MIN_INTERVAL;
+    }
+    requested
+}
+
+/// Minimum spacing between successive requests to the same miner. The
+/// ESP32 web server behind AxeOS copes badly with bursts, so watch, fleet
+/// and the exporters all go through one shared limiter.
+pub struct RateLimiter {
+    spacing: Duration,
+    /// When the most recently reserved request for each host may start
+    next: Mutex<HashMap<String, Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(spacing: Duration) -> Self {
+        RateLimiter { spacing, next: Mutex::new(HashMap::new()) }
+    }
+
+    /// Book the next slot for `host` and return how long the caller has to
+    /// wait for it. Concurrent callers queue up one spacing apart.
+    fn reserve(&self, host: &str, now: Instant) -> Duration {
+        let mut next = self.next.lock().unwrap();
+        let slot = match next.get(host) {
+            Some(&last) => (last + self.spacing).max(now),
+            None => now,
+        };
+        next.insert(host.to_string(), slot);
+        slot - now
+    }
+
+    /// Wait until a request to `host` is allowed
+    pub async fn wait(&self, host: &str) {
+        if self.spacing.is_zero() {
+            return;
+        }
+        let delay = self.reserve(host, Instant::now());
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+static LIMITER: OnceLock<RateLimiter> = OnceLock::new();
+
+/// Set the process-wide spacing; only the first call has an effect
+pub fn configure(spacing: Duration) {
+    let _ = LIMITER.set(RateLimiter::new(spacing));
+}
+
+/// Wait for the shared limiter before sending a request to `host`
+pub async fn throttle(host: &str) {
+    LIMITER.get_or_init(|| RateLimiter::new(DEFAULT_SPACING)).wait(host).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn first_request_goes_immediately() {
+        let limiter = RateLimiter::new(500 * MS);
+        assert_eq!(limiter.reserve("a", Instant::now()), Duration::ZERO);
+    }
+
+    #[test]
+    fn second_request_waits_for_the_remainder() {
+        let limiter = RateLimiter::new(500 * MS);
+        let t0 = Instant::now();
+        limiter.reserve("a", t0);
+        assert_eq!(limiter.reserve("a", t0 + 200 * MS), 300 * MS);
+    }
+
+    #[test]
+    fn no_wait_once_spacing_has_passed() {
+        let limiter = RateLimiter::new(500 * MS);
+        let t0 = Instant::now();
+        limiter.reserve("a", t0);
+        assert_eq!(limiter.reserve("a", t0 + 800 * MS), Duration::ZERO);
+        // The slot moved to the later request
+        assert_eq!(limiter.reserve("a", t0 + 900 * MS), 400 * MS);
+    }
+
+    #[test]
+    fn simultaneous_requests_queue_up() {
+        let limiter = RateLimiter::new(500 * MS);
+        let t0 = Instant::now();
+        let waits: Vec<Duration> = (0..3).map(|_| limiter.reserve("a", t0)).collect();
+        assert_eq!(waits, vec![Duration::ZERO, 500 * MS, 1000 * MS]);
+    }
+
+    #[test]
+    fn hosts_are_independent() {
+        let limiter = RateLimiter::new(500 * MS);
+        let t0 = Instant::now();
+        limiter.reserve("a", t0);
+        assert_eq!(limiter.reserve("b", t0), Duration::ZERO);
+    }
+}
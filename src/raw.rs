@@ -0,0 +1,119 @@
+use std::io::Read;
+use anyhow::{Context, Result, bail};
+use clap::{Args, ValueEnum};
+use reqwest::RequestBuilder;
+use serde_json::Value;
+use crate::client::Target;
+use crate::error::BitaxeError;
+use crate::retry::{self, Policy};
+use crate::{output, truncate};
+
+/// `raw`: an escape hatch for endpoints this CLI doesn't cover yet.
+/// Unstable: its output may change between releases.
+#[derive(Args, Debug)]
+pub struct RawArgs {
+    /// HTTP method
+    #[arg(long, value_enum, default_value_t = Method::Get)]
+    method: Method,
+
+    /// Endpoint below the miner's base URL, ex: /api/system/asic
+    #[arg(long, value_name = "PATH")]
+    path: String,
+
+    /// JSON request body (POST/PATCH), or `-` to read it from stdin
+    #[arg(long, value_name = "JSON")]
+    body: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Method {
+    Get,
+    Post,
+    Patch,
+}
+
+impl Method {
+    fn name(self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Patch => "PATCH",
+        }
+    }
+}
+
+/// Send the request and print the status (stderr) and body (stdout).
+/// JSON bodies are re-indented (or compacted with --compact); anything else
+/// is printed as it came. A non-2xx status fails after printing the body.
+pub async fn run(args: &RawArgs, target: &Target) -> Result<()> {
+    let url = url(&target.host, &args.path)?;
+    let body = match args.body.as_deref() {
+        Some(_) if args.method == Method::Get => bail!("--body needs --method post or patch"),
+        Some("-") => Some(parse_body(&stdin()?)?),
+        Some(text) => Some(parse_body(text)?),
+        None => None,
+    };
+
+    let client = &target.client;
+    let build = || -> RequestBuilder {
+        let request = match args.method {
+            Method::Get => client.get(&url),
+            Method::Post => client.post(&url),
+            Method::Patch => client.patch(&url),
+        };
+        match &body {
+            Some(body) => request.json(body),
+            None => request,
+        }
+    };
+    // Only reads are repeated after a timeout; writes may have landed
+    let policy = if args.method == Method::Get { Policy::Idempotent } else { Policy::NotSent };
+    let resp = retry::send(&target.host, policy, build).await.map_err(|e| BitaxeError::retried(&target.host, e))?;
+    let status = resp.status();
+    tracing::info!(host = target.host, method = args.method.name(), path = args.path, status = status.as_u16(), "raw");
+    let text = resp.text().await.context("Couldn't read the response body")?;
+
+    eprintln!("HTTP {status}");
+    match serde_json::from_str::<Value>(&text) {
+        Ok(json) => println!("{}", output::json(&json)?),
+        Err(_) if text.is_empty() => {}
+        Err(_) => println!("{}", text.trim_end()),
+    }
+    if !status.is_success() {
+        bail!("{} {} failed with status {status}", args.method.name(), args.path);
+    }
+    Ok(())
+}
+
+/// The path appended to the host (which carries any base path already).
+/// Full URLs are refused so the request can't leave the configured miner.
+fn url(host: &str, path: &str) -> Result<String> {
+    if path.contains("://") {
+        bail!("--path takes a path like /api/system/info, not a URL (set the miner with --host or --device)");
+    }
+    Ok(format!("{host}/{}", path.trim_start_matches('/')))
+}
+
+fn parse_body(text: &str) -> Result<Value> {
+    serde_json::from_str(text).with_context(|| format!("--body isn't valid JSON: {}", truncate(text.trim(), 60)))
+}
+
+fn stdin() -> Result<String> {
+    let mut text = String::new();
+    std::io::stdin().read_to_string(&mut text).context("Couldn't read the body from stdin")?;
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_join_the_host_and_urls_are_refused() {
+        assert_eq!(url("http://10.0.0.2", "/api/system/asic").unwrap(), "http://10.0.0.2/api/system/asic");
+        assert_eq!(url("http://proxy/bitaxe", "api/swarm/info").unwrap(), "http://proxy/bitaxe/api/swarm/info");
+        assert!(url("http://10.0.0.2", "http://elsewhere/api").is_err());
+        assert_eq!(parse_body(r#"{"frequency": 525}"#).unwrap()["frequency"], 525);
+        assert!(parse_body("{frequency: 525}").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result};
+use serde_json::Value;
+use crate::config_file::config_path;
+use crate::exporter::write_atomic;
+use crate::get_number;
+
+/// How far uptime may go backwards without counting as a reboot; polls
+/// land a little late or early and the firmware rounds
+const SLACK: f64 = 10.0;
+/// A miner that comes back up within this long after `restart` (or the
+/// watchdog, or the tui) asked it to wasn't an unexpected reboot
+const RESTART_WINDOW: Duration = Duration::from_secs(300);
+/// Restarts older than this are dropped from the log when it is written
+const KEEP: Duration = Duration::from_secs(24 * 3600);
+
+/// A miner reporting less uptime than it did on the previous poll
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reboot {
+    pub device: String,
+    /// Seconds it had been up at the previous poll
+    pub uptime_before: f64,
+    /// When it came back up, from the uptime it reports now
+    pub booted: SystemTime,
+    /// Whether this CLI sent it a restart just before
+    pub requested: bool,
+}
+
+/// Uptime per device across polls, and how many reboots nobody asked for
+#[derive(Default)]
+pub struct Tracker {
+    uptime: HashMap<String, f64>,
+    unexpected: HashMap<String, u64>,
+    /// Restarts this CLI sent, see `note_restart`
+    log: Option<PathBuf>,
+}
+
+impl Tracker {
+    /// Telling requested reboots apart from the rest by the restarts in `log`
+    pub fn new(log: Option<PathBuf>) -> Self {
+        Tracker { log, ..Tracker::default() }
+    }
+
+    /// Carry on from the uptimes a previous run last saw, so a reboot while
+    /// the CLI was stopped still shows up
+    #[cfg(feature = "sqlite")]
+    pub fn seed(&mut self, uptimes: &[(String, f64)]) {
+        self.uptime.extend(uptimes.iter().cloned());
+    }
+
+    /// Feed one poll; `None` unless the miner rebooted since the previous one
+    pub fn observe(&mut self, device: &str, info: &Value, at: SystemTime) -> Option<Reboot> {
+        self.observe_uptime(device, get_number(info, "uptimeSeconds")?, at)
+    }
+
+    pub fn observe_uptime(&mut self, device: &str, uptime: f64, at: SystemTime) -> Option<Reboot> {
+        let before = self.uptime.insert(device.to_string(), uptime);
+        let count = self.unexpected.entry(device.to_string()).or_insert(0);
+        let before = before.filter(|before| uptime < before - SLACK)?;
+        let booted = at.checked_sub(Duration::from_secs_f64(uptime.max(0.0))).unwrap_or(UNIX_EPOCH);
+        let restart = self.log.as_deref().and_then(|log| restarted_at(log, device));
+        let requested = restart.is_some_and(|sent| asked_for(sent, booted));
+        if !requested {
+            *count += 1;
+        }
+        Some(Reboot { device: device.to_string(), uptime_before: before, booted, requested })
+    }
+
+    /// Unexpected reboots seen since tracking began; `None` until the
+    /// device has reported its uptime
+    pub fn unexpected(&self, device: &str) -> Option<u64> {
+        self.unexpected.get(device).copied()
+    }
+}
+
+/// Whether a miner that booted at `booted` was answering a restart sent at `sent`
+fn asked_for(sent: SystemTime, booted: SystemTime) -> bool {
+    let slack = Duration::from_secs_f64(SLACK);
+    match booted.duration_since(sent) {
+        Ok(after) => after <= RESTART_WINDOW,
+        Err(e) => e.duration() <= slack,
+    }
+}
+
+/// ~/.config/bitaxe-cli/restarts.json, next to the config file
+pub fn log_path() -> Option<PathBuf> {
+    Some(config_path()?.parent()?.join("restarts.json"))
+}
+
+/// Remember that `device` was just told to restart, so the reboot that
+/// follows isn't alerted on by this or another bitaxe-cli process. Never
+/// fails the restart: a log that can't be written is only traced.
+pub fn note_restart(device: &str) {
+    let Some(path) = log_path() else { return };
+    if let Err(e) = write_restart(&path, device, SystemTime::now()) {
+        tracing::warn!(device, error = format!("{e:#}"), "couldn't log the restart");
+    }
+}
+
+fn write_restart(path: &Path, device: &str, at: SystemTime) -> Result<()> {
+    let mut log = read_log(path);
+    let cutoff = unix(at.checked_sub(KEEP).unwrap_or(UNIX_EPOCH));
+    log.retain(|_, sent| *sent >= cutoff);
+    log.insert(device.to_string(), unix(at));
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    write_atomic(path, &serde_json::to_string_pretty(&log)?)
+}
+
+/// Device name to when its last restart was sent, in Unix seconds. A
+/// missing or unreadable log is empty.
+fn read_log(path: &Path) -> BTreeMap<String, u64> {
+    fs::read_to_string(path).ok().and_then(|text| serde_json::from_str(&text).ok()).unwrap_or_default()
+}
+
+fn restarted_at(path: &Path, device: &str) -> Option<SystemTime> {
+    read_log(path).get(device).map(|secs| UNIX_EPOCH + Duration::from_secs(*secs))
+}
+
+fn unix(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn t(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_714_060_800 + secs)
+    }
+
+    #[test]
+    fn uptime_going_backwards_is_a_reboot() {
+        let mut tracker = Tracker::new(None);
+        assert_eq!(tracker.unexpected("gamma"), None);
+        assert_eq!(tracker.observe("gamma", &json!({"uptimeSeconds": 3600}), t(0)), None);
+        assert_eq!(tracker.observe("gamma", &json!({"uptimeSeconds": 3630}), t(30)), None);
+        // A late poll reporting a touch less is jitter, not a reboot
+        assert_eq!(tracker.observe("gamma", &json!({"uptimeSeconds": 3625}), t(60)), None);
+        let reboot = tracker.observe("gamma", &json!({"uptimeSeconds": 20}), t(90)).unwrap();
+        assert_eq!(reboot, Reboot { device: "gamma".into(), uptime_before: 3625.0, booted: t(70), requested: false });
+        assert_eq!(tracker.unexpected("gamma"), Some(1));
+        assert_eq!(tracker.observe("gamma", &json!({}), t(120)), None);
+
+        #[cfg(feature = "sqlite")]
+        {
+            let mut seeded = Tracker::new(None);
+            seeded.seed(&[("gamma".into(), 7200.0)]);
+            assert!(seeded.observe_uptime("gamma", 100.0, t(0)).is_some());
+        }
+    }
+
+    #[test]
+    fn reboots_right_after_our_restart_were_requested() {
+        let dir = std::env::temp_dir().join(format!("bitaxe-cli-reboot-{}", std::process::id()));
+        let log = dir.join("restarts.json");
+        write_restart(&log, "gamma", t(0)).unwrap();
+        write_restart(&log, "ultra", t(0)).unwrap();
+        assert_eq!(restarted_at(&log, "gamma"), Some(t(0)));
+
+        let mut tracker = Tracker::new(Some(log.clone()));
+        tracker.observe_uptime("gamma", 5000.0, t(0));
+        assert!(tracker.observe_uptime("gamma", 25.0, t(30)).unwrap().requested);
+        assert_eq!(tracker.unexpected("gamma"), Some(0));
+        // Long after the restart, the next reboot is unexpected again
+        tracker.observe_uptime("gamma", 3600.0, t(3605));
+        assert!(!tracker.observe_uptime("gamma", 10.0, t(3615)).unwrap().requested);
+        assert!(!asked_for(t(100), t(50)));
+        assert!(asked_for(t(100), t(95)));
+
+        // Old entries go when the log is next written
+        write_restart(&log, "gamma", t(100_000)).unwrap();
+        assert_eq!(restarted_at(&log, "ultra"), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
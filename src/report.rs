@@ -0,0 +1,535 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use anyhow::{Context, Result, bail};
+use clap::{Args, ValueEnum};
+use serde_json::{Value, json};
+use crate::history::{self, HistoryDb, Query, StoredEvent, at, parse_time, unix_now};
+use crate::logger::rfc3339;
+use crate::rules::short_duration;
+use crate::temperature;
+use crate::{output, parse_duration};
+
+/// Columns read for a report, in this order
+const METRICS: [&str; 5] = ["hashrate", "temp", "power", "shares_accepted", "shares_rejected"];
+/// Polls further apart than this many intervals leave a gap
+const GAP_FACTOR: i64 = 3;
+const DAY: i64 = 86_400;
+
+/// `report`: a summary of what the history database holds for a period
+#[derive(Args, Debug)]
+pub struct ReportArgs {
+    /// Database to read (default: ~/.config/bitaxe-cli/history.db)
+    #[arg(long, value_name = "PATH")]
+    db: Option<PathBuf>,
+
+    /// What to cover: today, yesterday (UTC days), a span back from now (7d, 24h),
+    /// a UTC date (2024-05-01) or FROM..TO with TO exclusive (2024-05-01..2024-05-08)
+    #[arg(long, default_value = "yesterday")]
+    period: String,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text, conflicts_with = "json")]
+    format: ReportFormat,
+
+    /// Print JSON instead
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ReportFormat {
+    Text,
+    Markdown,
+}
+
+/// The window a report covers: [from, to) in Unix seconds
+#[derive(Debug, PartialEq)]
+struct Period {
+    from: i64,
+    to: i64,
+}
+
+impl Period {
+    fn parse(s: &str, now: i64) -> Result<Self> {
+        let today = now - now.rem_euclid(DAY);
+        let (from, to) = match s.trim() {
+            "today" => (today, now),
+            "yesterday" => (today - DAY, today),
+            s if s.contains("..") => {
+                let (from, to) = s.split_once("..").unwrap_or_default();
+                (parse_time(from, now)?, parse_time(to, now)?)
+            }
+            s if parse_duration(s).is_ok() => (parse_time(s, now)?, now),
+            s => {
+                let from = parse_time(s, now)?;
+                if from.rem_euclid(DAY) != 0 {
+                    bail!("A single time isn't a period; use FROM..TO");
+                }
+                (from, from + DAY)
+            }
+        };
+        if from >= to {
+            bail!("The period is empty: it must start before it ends");
+        }
+        Ok(Period { from, to })
+    }
+
+    fn span(&self) -> i64 {
+        self.to - self.from
+    }
+
+    /// `2024-05-01` for a whole UTC day, the two ends otherwise
+    fn label(&self) -> String {
+        let (from, to) = (rfc3339(at(self.from)), rfc3339(at(self.to)));
+        if self.span() == DAY && self.from.rem_euclid(DAY) == 0 {
+            format!("{} (UTC)", &from[..10])
+        } else {
+            format!("{from} to {to}")
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Spread {
+    avg: f64,
+    min: f64,
+    max: f64,
+}
+
+/// Nearest-rank percentiles
+#[derive(Debug, PartialEq)]
+struct Percentiles {
+    p50: f64,
+    p90: f64,
+    p99: f64,
+    max: f64,
+}
+
+/// How much of the period the polls cover
+#[derive(Debug, Default, PartialEq)]
+struct Coverage {
+    /// The usual time between polls (the median), once there are two
+    interval: Option<i64>,
+    /// Seconds not covered, summed over the gaps
+    missing: i64,
+    gaps: usize,
+    longest_gap: i64,
+}
+
+/// Everything a report says about one device
+#[derive(Debug)]
+struct DeviceReport {
+    device: String,
+    answered: usize,
+    failed: usize,
+    coverage: Coverage,
+    hashrate: Option<Spread>,
+    /// Found in the period, from the changes of the miner's counters
+    accepted: Option<f64>,
+    rejected: Option<f64>,
+    /// J/TH over every sample with both readings
+    efficiency: Option<f64>,
+    temp: Option<Percentiles>,
+    /// kWh over the covered stretches, and scaled up to the whole period
+    energy: Option<(f64, f64)>,
+    events: Vec<StoredEvent>,
+    /// Whether each reboot in the period was requested through this CLI
+    reboots: Vec<bool>,
+}
+
+/// One poll: when, and the METRICS if it answered
+type Poll = (i64, Option<Vec<Option<f64>>>);
+
+impl DeviceReport {
+    fn build(device: &str, polls: &[Poll], events: Vec<StoredEvent>, period: &Period) -> Self {
+        let answered: Vec<(i64, &[Option<f64>])> =
+            polls.iter().filter_map(|(ts, values)| Some((*ts, values.as_deref()?))).collect();
+        let column = |i: usize| -> Vec<(i64, f64)> {
+            answered.iter().filter_map(|(ts, values)| Some((*ts, values[i]?))).collect()
+        };
+        let times: Vec<i64> = polls.iter().map(|(ts, _)| *ts).collect();
+        let coverage = coverage(&times, period);
+        let limit = coverage.interval.map(|i| i * GAP_FACTOR);
+
+        let hashes: Vec<f64> = column(0).into_iter().map(|(_, v)| v).collect();
+        let mut temps: Vec<f64> = column(1).into_iter().map(|(_, v)| v).collect();
+        let power = column(2);
+        let efficiency = {
+            let both = answered.iter().filter_map(|(_, v)| Some((v[0].filter(|h| *h > 0.0)?, v[2]?)));
+            let (watts, hashrate) = both.fold((0.0, 0.0), |(w, h), (hash, watts)| (w + watts, h + hash));
+            (hashrate > 0.0).then(|| watts / (hashrate / 1000.0))
+        };
+        let energy = limit
+            .map(|limit| energy(&power, limit))
+            .filter(|(_, secs)| *secs > 0)
+            .map(|(kwh, secs)| (kwh, kwh * period.span() as f64 / secs as f64));
+
+        DeviceReport {
+            device: device.to_string(),
+            answered: answered.len(),
+            failed: polls.len() - answered.len(),
+            coverage,
+            hashrate: spread(&hashes),
+            accepted: found(&column(3)),
+            rejected: found(&column(4)),
+            efficiency,
+            temp: percentiles(&mut temps),
+            energy,
+            events,
+            reboots: Vec::new(),
+        }
+    }
+
+    /// Share of polls that got an answer
+    fn uptime(&self) -> Option<f64> {
+        let total = self.answered + self.failed;
+        (total > 0).then(|| self.answered as f64 * 100.0 / total as f64)
+    }
+
+    /// (unexpected, requested)
+    fn reboot_counts(&self) -> (usize, usize) {
+        let requested = self.reboots.iter().filter(|r| **r).count();
+        (self.reboots.len() - requested, requested)
+    }
+
+    /// Label and value of every line, shared by the text and markdown forms
+    fn rows(&self, period: &Period) -> Vec<(&'static str, String)> {
+        let none = || "no data".to_string();
+        let c = &self.coverage;
+        let covered = period.span() - c.missing;
+        let mut coverage = format!(
+            "{} of {} ({:.1}%)",
+            duration(covered),
+            duration(period.span()),
+            covered as f64 * 100.0 / period.span() as f64
+        );
+        if c.gaps > 0 {
+            coverage += &format!("; {} gap(s), longest {}", c.gaps, duration(c.longest_gap));
+        }
+        if let Some(interval) = c.interval {
+            coverage += &format!("; polled every {}", duration(interval));
+        }
+        let uptime = match self.uptime() {
+            Some(pct) => format!("{pct:.1}% ({} of {} polls answered)", self.answered, self.answered + self.failed),
+            None => "no polls".to_string(),
+        };
+        let shares = match (self.accepted, self.rejected) {
+            (None, None) => none(),
+            (a, r) => format!("{:.0} accepted, {:.0} rejected", a.unwrap_or(0.0), r.unwrap_or(0.0)),
+        };
+        let reboots = match self.reboot_counts() {
+            (0, 0) => "none".to_string(),
+            (unexpected, requested) => format!("{unexpected} unexpected, {requested} requested"),
+        };
+        vec![
+            ("Coverage", coverage),
+            ("Uptime", uptime),
+            ("Reboots", reboots),
+            (
+                "Hashrate",
+                self.hashrate.as_ref().map_or_else(none, |h| {
+                    format!("avg {:.2}  min {:.2}  max {:.2} GH/s", h.avg, h.min, h.max)
+                }),
+            ),
+            ("Shares", shares),
+            ("Efficiency", self.efficiency.map_or_else(none, |e| format!("{e:.2} J/TH"))),
+            (
+                "Temp",
+                self.temp.as_ref().map_or_else(none, |t| {
+                    let unit = temperature::unit();
+                    let [p50, p90, p99, max] = [t.p50, t.p90, t.p99, t.max].map(|c| unit.of_celsius(c));
+                    format!("p50 {p50:.1}  p90 {p90:.1}  p99 {p99:.1}  max {max:.1} {}", unit.symbol())
+                }),
+            ),
+            (
+                "Energy",
+                self.energy.map_or_else(none, |(measured, estimated)| {
+                    format!("{measured:.3} kWh measured (~{estimated:.3} kWh over the whole period)")
+                }),
+            ),
+            ("Alerts", format!("{} event(s)", self.events.len())),
+        ]
+    }
+
+    fn to_json(&self) -> Value {
+        let c = &self.coverage;
+        json!({
+            "device": self.device,
+            "polls": {"answered": self.answered, "failed": self.failed},
+            "uptime_percent": self.uptime(),
+            "reboots": {"unexpected": self.reboot_counts().0, "requested": self.reboot_counts().1},
+            "coverage": {
+                "interval_seconds": c.interval,
+                "missing_seconds": c.missing,
+                "gaps": c.gaps,
+                "longest_gap_seconds": c.longest_gap,
+            },
+            "hashrate": self.hashrate.as_ref().map(|h| json!({"avg": h.avg, "min": h.min, "max": h.max})),
+            "shares": {"accepted": self.accepted, "rejected": self.rejected},
+            "efficiency_j_per_th": self.efficiency,
+            "temp": self.temp.as_ref().map(|t| json!({"p50": t.p50, "p90": t.p90, "p99": t.p99, "max": t.max})),
+            "energy_kwh": self.energy.map(|(kwh, estimated)| json!({"measured": kwh, "estimated": estimated})),
+            "alerts": self.events.iter().map(event_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Gaps are stretches longer than GAP_FACTOR intervals without a poll,
+/// counting from the start of the period and up to its end
+fn coverage(times: &[i64], period: &Period) -> Coverage {
+    let mut steps: Vec<i64> = times.windows(2).map(|w| w[1] - w[0]).filter(|d| *d > 0).collect();
+    steps.sort_unstable();
+    let Some(interval) = steps.get(steps.len() / 2).copied() else {
+        // Nothing to tell the polling rate by: none of the period counts
+        return Coverage { interval: None, missing: period.span(), gaps: 1, longest_gap: period.span() };
+    };
+    let edges = [period.from].into_iter().chain(times.iter().copied()).chain([period.to]);
+    let edges: Vec<i64> = edges.collect();
+    let mut c = Coverage { interval: Some(interval), ..Coverage::default() };
+    for gap in edges.windows(2).map(|w| w[1] - w[0]).filter(|d| *d > interval * GAP_FACTOR) {
+        c.missing += gap;
+        c.gaps += 1;
+        c.longest_gap = c.longest_gap.max(gap);
+    }
+    c
+}
+
+fn spread(values: &[f64]) -> Option<Spread> {
+    if values.is_empty() {
+        return None;
+    }
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    Some(Spread { avg: values.iter().sum::<f64>() / values.len() as f64, min, max })
+}
+
+fn percentiles(values: &mut [f64]) -> Option<Percentiles> {
+    values.sort_by(f64::total_cmp);
+    let rank = |p: f64| values[((p / 100.0 * values.len() as f64).ceil() as usize).clamp(1, values.len()) - 1];
+    let max = *values.last()?;
+    Some(Percentiles { p50: rank(50.0), p90: rank(90.0), p99: rank(99.0), max })
+}
+
+/// What a share counter went up by; a drop means the miner restarted and
+/// counted from zero again
+fn found(counts: &[(i64, f64)]) -> Option<f64> {
+    let steps = counts.windows(2).map(|w| if w[1].1 < w[0].1 { w[1].1 } else { w[1].1 - w[0].1 });
+    (counts.len() > 1).then(|| steps.sum())
+}
+
+/// kWh between consecutive power readings no more than `limit` seconds
+/// apart, and the seconds that covers
+fn energy(power: &[(i64, f64)], limit: i64) -> (f64, i64) {
+    let mut joules = 0.0;
+    let mut secs = 0;
+    for w in power.windows(2) {
+        let dt = w[1].0 - w[0].0;
+        if dt <= limit {
+            joules += (w[0].1 + w[1].1) / 2.0 * dt as f64;
+            secs += dt;
+        }
+    }
+    (joules / 3.6e6, secs)
+}
+
+fn duration(secs: i64) -> String {
+    short_duration(Duration::from_secs(secs.max(0) as u64))
+}
+
+fn event_line(e: &StoredEvent) -> String {
+    let state = if e.firing { "firing" } else { "resolved" };
+    let mut line = format!("{} {} ({}) {state}", rfc3339(at(e.ts)), e.name, e.severity);
+    if let Some(value) = e.value {
+        line += &format!(": {value}");
+        if let Some(threshold) = e.threshold {
+            line += &format!(", threshold {threshold}");
+        }
+    }
+    line
+}
+
+fn event_json(e: &StoredEvent) -> Value {
+    json!({
+        "time": rfc3339(at(e.ts)),
+        "name": e.name,
+        "severity": e.severity,
+        "firing": e.firing,
+        "value": e.value,
+        "threshold": e.threshold,
+    })
+}
+
+fn text(period: &Period, reports: &[DeviceReport]) -> String {
+    let mut out = format!("Report for {}\n", period.label());
+    for report in reports {
+        out += &format!("\n{}\n", report.device);
+        for (label, value) in report.rows(period) {
+            out += &format!("  {label:<11} {value}\n");
+        }
+        for event in &report.events {
+            out += &format!("    {}\n", event_line(event));
+        }
+    }
+    out
+}
+
+fn markdown(period: &Period, reports: &[DeviceReport]) -> String {
+    let mut out = format!("# Report for {}\n", period.label());
+    for report in reports {
+        out += &format!("\n## {}\n\n| | |\n|---|---|\n", report.device);
+        for (label, value) in report.rows(period) {
+            out += &format!("| {label} | {value} |\n");
+        }
+        if !report.events.is_empty() {
+            out += "\n";
+        }
+        for event in &report.events {
+            out += &format!("- {}\n", event_line(event));
+        }
+    }
+    out
+}
+
+/// A report per device (`--device`, or every device with data in the period)
+pub fn run(args: &ReportArgs, devices: &[String]) -> Result<()> {
+    let period =
+        Period::parse(&args.period, unix_now()).with_context(|| format!("Invalid --period '{}'", args.period))?;
+    let path = history::db_path(args.db.as_deref())?;
+    if !path.exists() {
+        bail!("No history database at {} (record samples with `log --db` or `daemon`)", path.display());
+    }
+    let db = HistoryDb::open(&path)?;
+    let devices = match devices {
+        [] => db.devices_between(period.from, period.to)?,
+        named => named.to_vec(),
+    };
+    if devices.is_empty() {
+        bail!("Nothing stored in {} for {}", path.display(), period.label());
+    }
+
+    let mut reports = Vec::new();
+    for device in &devices {
+        let query = Query {
+            from: Some(period.from),
+            to: Some(period.to),
+            devices: vec![device.clone()],
+            metrics: METRICS.to_vec(),
+            resolution: None,
+        };
+        let mut polls: Vec<Poll> = db.query(&query)?.into_iter().map(|row| (row.ts, Some(row.values))).collect();
+        polls.extend(db.failures(device, period.from, period.to)?.into_iter().map(|ts| (ts, None)));
+        polls.sort_by_key(|(ts, _)| *ts);
+        let events = db.events(device, period.from, period.to)?;
+        let mut report = DeviceReport::build(device, &polls, events, &period);
+        report.reboots = db.reboots(device, period.from, period.to)?;
+        reports.push(report);
+    }
+
+    if args.json {
+        let body = json!({
+            "from": rfc3339(at(period.from)),
+            "to": rfc3339(at(period.to)),
+            "devices": reports.iter().map(DeviceReport::to_json).collect::<Vec<_>>(),
+        });
+        println!("{}", output::json(&body)?);
+    } else if args.format == ReportFormat::Markdown {
+        print!("{}", markdown(&period, &reports));
+    } else {
+        print!("{}", text(&period, &reports));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_714_060_800; // 2024-04-25T16:00:00Z
+    const MIDNIGHT: i64 = 1_714_003_200;
+
+    #[test]
+    fn periods() {
+        assert_eq!(Period::parse("yesterday", NOW).unwrap(), Period { from: MIDNIGHT - DAY, to: MIDNIGHT });
+        assert_eq!(Period::parse("today", NOW).unwrap(), Period { from: MIDNIGHT, to: NOW });
+        assert_eq!(Period::parse("7d", NOW).unwrap(), Period { from: NOW - 7 * DAY, to: NOW });
+        assert_eq!(Period::parse("2024-04-25", NOW).unwrap(), Period { from: MIDNIGHT, to: MIDNIGHT + DAY });
+        let range = Period::parse("2024-04-18..2024-04-25", NOW).unwrap();
+        assert_eq!(range, Period { from: MIDNIGHT - 7 * DAY, to: MIDNIGHT });
+        assert_eq!(Period::parse("yesterday", NOW).unwrap().label(), "2024-04-24 (UTC)");
+        assert_eq!(range.label(), "2024-04-18T00:00:00Z to 2024-04-25T00:00:00Z");
+        for bad in ["2024-04-25..2024-04-18", "2024-04-25T12:00", "last week"] {
+            assert!(Period::parse(bad, NOW).is_err(), "{bad} should not parse");
+        }
+    }
+
+    #[test]
+    fn gaps_are_missing_time() {
+        let period = Period { from: 0, to: 1000 };
+        // Every 10s, then nothing from 300 to 600, and it stopped at 900
+        let times: Vec<i64> = (0..=300).step_by(10).chain((600..=900).step_by(10)).collect();
+        let c = coverage(&times, &period);
+        assert_eq!(c, Coverage { interval: Some(10), missing: 400, gaps: 2, longest_gap: 300 });
+        // Late by less than the gap threshold is still covered
+        assert_eq!(coverage(&[20, 40, 60, 980], &Period { from: 0, to: 1000 }).missing, 920);
+        assert_eq!(coverage(&[], &period), Coverage { interval: None, missing: 1000, gaps: 1, longest_gap: 1000 });
+    }
+
+    #[test]
+    fn device_summary() {
+        let period = Period { from: 0, to: 400 };
+        let poll = |ts, hash, temp, watts, accepted| {
+            (ts, Some(vec![Some(hash), Some(temp), Some(watts), Some(accepted), None]))
+        };
+        let polls = [
+            poll(0, 500.0, 60.0, 15.0, 100.0),
+            poll(100, 520.0, 62.0, 15.0, 110.0),
+            (200, None),
+            // Restarted in between: the 5 shares since count too
+            poll(300, 480.0, 64.0, 13.0, 5.0),
+            poll(399, 500.0, 70.0, 13.0, 15.0),
+        ];
+        let report = DeviceReport::build("gamma", &polls, Vec::new(), &period);
+        assert_eq!(report.uptime(), Some(80.0));
+        assert_eq!(report.hashrate, Some(Spread { avg: 500.0, min: 480.0, max: 520.0 }));
+        assert_eq!(report.accepted, Some(25.0));
+        assert_eq!(report.rejected, None);
+        assert_eq!(report.temp, Some(Percentiles { p50: 62.0, p90: 70.0, p99: 70.0, max: 70.0 }));
+        assert!((report.efficiency.unwrap() - 28.0).abs() < 1e-9);
+        // The failed poll is bridged: 200s isn't a gap at a 100s interval
+        let (measured, estimated) = report.energy.unwrap();
+        assert!((measured - (100.0 * 15.0 + 200.0 * 14.0 + 99.0 * 13.0) / 3.6e6).abs() < 1e-12);
+        assert!((estimated - measured * 400.0 / 399.0).abs() < 1e-12);
+
+        let rows = report.rows(&period);
+        assert_eq!(rows[1], ("Uptime", "80.0% (4 of 5 polls answered)".to_string()));
+        assert_eq!(rows[2], ("Reboots", "none".to_string()));
+        assert_eq!(rows[4], ("Shares", "25 accepted, 0 rejected".to_string()));
+        let report = DeviceReport { reboots: vec![false, true, false], ..report };
+        assert_eq!(report.rows(&period)[2], ("Reboots", "2 unexpected, 1 requested".to_string()));
+    }
+
+    #[test]
+    fn events_in_both_formats() {
+        let event = StoredEvent {
+            ts: MIDNIGHT,
+            name: "temp_above".into(),
+            severity: "warning".into(),
+            firing: true,
+            value: Some(71.5),
+            threshold: Some(70.0),
+        };
+        let period = Period { from: MIDNIGHT, to: MIDNIGHT + DAY };
+        let report = DeviceReport::build("gamma", &[], vec![event], &period);
+        let text = text(&period, &[report]);
+        let head = "Report for 2024-04-25 (UTC)\n\ngamma\n  Coverage    0s of 24h (0.0%); 1 gap(s), longest 24h\n";
+        assert!(text.starts_with(head), "{text}");
+        assert!(text.contains("  Hashrate    no data\n"), "{text}");
+        assert!(text.ends_with("    2024-04-25T00:00:00Z temp_above (warning) firing: 71.5, threshold 70\n"), "{text}");
+
+        let report = DeviceReport::build("gamma", &[], Vec::new(), &period);
+        let md = markdown(&period, &[report]);
+        let head = "# Report for 2024-04-25 (UTC)\n\n## gamma\n\n| | |\n|---|---|\n| Coverage | 0s of 24h";
+        assert!(md.starts_with(head), "{md}");
+        assert!(md.ends_with("| Alerts | 0 event(s) |\n"), "{md}");
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use crate::client::bare_host;
+use crate::ratelimit;
+
+/// How often and how patiently a failed request is repeated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backoff {
+    /// Repeats after the first try
+    pub retries: u32,
+    /// Pause before the first retry; doubles for each one after that
+    pub base: Duration,
+    /// Longest pause between two tries
+    pub max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff { retries: 1, base: Duration::from_millis(500), max: Duration::from_secs(10) }
+    }
+}
+
+impl Backoff {
+    /// Pause before retry number `retry` (from 1): `base` doubled per retry
+    /// and capped at `max`, then moved into the upper half of that by
+    /// `jitter` (0 to 1), so miners that dropped off together aren't all
+    /// retried at the same instant
+    pub fn delay(&self, retry: u32, jitter: f64) -> Duration {
+        let full = self.base.saturating_mul(2u32.saturating_pow(retry.saturating_sub(1))).min(self.max);
+        full.div_f64(2.0).mul_f64(1.0 + jitter.clamp(0.0, 1.0))
+    }
+}
+
+/// Which failures a request may be repeated after
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Reads: sending them twice is harmless, so timeouts and 5xx answers
+    /// are retried too
+    Idempotent,
+    /// Actions like restart: only retried when the connection was never
+    /// established, i.e. the miner can't have seen the first request
+    NotSent,
+}
+
+impl Policy {
+    fn retries(self, e: &reqwest::Error) -> bool {
+        match self {
+            Policy::Idempotent => e.is_connect() || e.is_timeout(),
+            // A connect timeout reports both is_connect and is_timeout; a
+            // plain timeout means the request went out and may have landed
+            Policy::NotSent => e.is_connect(),
+        }
+    }
+
+    /// What to announce before repeating a request that failed with `e`
+    fn reason(self, e: &reqwest::Error) -> Option<String> {
+        if !self.retries(e) {
+            None
+        } else if e.is_timeout() {
+            Some("timed out".to_string())
+        } else {
+            Some("connection failed".to_string())
+        }
+    }
+
+    /// The same for one that was answered with `status`
+    fn reason_for_status(self, status: StatusCode) -> Option<String> {
+        (self == Policy::Idempotent && status.is_server_error()).then(|| format!("status {status}"))
+    }
+}
+
+/// The request failed on every try
+#[derive(Debug)]
+pub struct Failed {
+    pub source: reqwest::Error,
+    pub attempts: u32,
+}
+
+/// Tries a response took, kept in its extensions when there was more than
+/// one so the status error can mention them
+#[derive(Clone, Copy, Debug)]
+pub struct Attempts(pub u32);
+
+static BACKOFF: OnceLock<Backoff> = OnceLock::new();
+/// With -v each retry is announced on stderr
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Set the process-wide schedule; only the first call has an effect
+pub fn configure(backoff: Backoff, verbose: bool) {
+    let _ = BACKOFF.set(backoff);
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+/// Send the request built by `build`, repeating it on the failures `policy`
+/// allows. Every attempt waits for the shared rate limiter first. When the
+/// last try still got a 5xx, that response is returned for the caller to
+/// report.
+pub async fn send(host: &str, policy: Policy, build: impl Fn() -> RequestBuilder) -> Result<Response, Failed> {
+    match repeat(host, policy, || build().send()).await {
+        (Ok(mut resp), attempts) => {
+            if attempts > 1 {
+                resp.extensions_mut().insert(Attempts(attempts));
+            }
+            Ok(resp)
+        }
+        (Err(source), attempts) => Err(Failed { source, attempts }),
+    }
+}
+
+/// `send` for a `bitaxe_api::BitaxeClient` call: the last error comes back
+/// with the number of tries it took
+pub async fn call<T, Fut>(host: &str, policy: Policy, f: impl Fn() -> Fut) -> Result<T, (bitaxe_api::Error, u32)>
+where
+    Fut: Future<Output = Result<T, bitaxe_api::Error>>,
+{
+    let (result, attempts) = repeat(host, policy, f).await;
+    result.map_err(|e| (e, attempts))
+}
+
+/// What one try came to, and whether it is worth another
+trait Outcome {
+    /// Why to try again under `policy`; `None` keeps this result
+    fn retry_reason(&self, policy: Policy) -> Option<String>;
+}
+
+impl Outcome for reqwest::Result<Response> {
+    fn retry_reason(&self, policy: Policy) -> Option<String> {
+        match self {
+            Err(e) => policy.reason(e),
+            Ok(resp) => policy.reason_for_status(resp.status()),
+        }
+    }
+}
+
+impl<T> Outcome for Result<T, bitaxe_api::Error> {
+    fn retry_reason(&self, policy: Policy) -> Option<String> {
+        match self {
+            Ok(_) => None,
+            Err(
+                bitaxe_api::Error::Timeout { source, .. }
+                | bitaxe_api::Error::Connect { source, .. }
+                | bitaxe_api::Error::Http(source),
+            ) => policy.reason(source),
+            Err(bitaxe_api::Error::Status { status, .. }) => policy.reason_for_status(*status),
+            Err(_) => None,
+        }
+    }
+}
+
+/// Run `attempt` until its outcome is kept or the retries run out; the last
+/// outcome and how many tries it took
+async fn repeat<R, Fut>(host: &str, policy: Policy, attempt: impl Fn() -> Fut) -> (R, u32)
+where
+    R: Outcome,
+    Fut: Future<Output = R>,
+{
+    let backoff = *BACKOFF.get_or_init(Backoff::default);
+    let mut tries = 1;
+    loop {
+        ratelimit::throttle(host).await;
+        let result = attempt().await;
+        let reason = match result.retry_reason(policy) {
+            Some(reason) if tries <= backoff.retries => reason,
+            _ => return (result, tries),
+        };
+
+        let delay = backoff.delay(tries, jitter());
+        tracing::debug!(host, attempt = tries, reason, delay_ms = delay.as_millis() as u64, "retrying");
+        if VERBOSE.load(Ordering::Relaxed) {
+            let total = backoff.retries + 1;
+            eprintln!("{}: {reason}, retrying in {delay:.1?} (attempt {} of {total})", bare_host(host), tries + 1);
+        }
+        tokio::time::sleep(delay).await;
+        tries += 1;
+    }
+}
+
+/// A number between 0 and 1 that differs per call, without pulling in a
+/// random number crate: std seeds every `RandomState` afresh
+fn jitter() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn delays_double_up_to_the_cap() {
+        let backoff = Backoff { retries: 6, base: 500 * MS, max: 4000 * MS };
+        let top: Vec<Duration> = (1..=6).map(|retry| backoff.delay(retry, 1.0)).collect();
+        assert_eq!(top, [500 * MS, 1000 * MS, 2000 * MS, 4000 * MS, 4000 * MS, 4000 * MS]);
+        // Jitter only ever shortens the pause, by at most half
+        assert_eq!(backoff.delay(1, 0.0), 250 * MS);
+        assert_eq!(backoff.delay(3, 0.5), 1500 * MS);
+        assert_eq!(backoff.delay(40, 0.0), 2000 * MS);
+    }
+
+    #[test]
+    fn library_errors_retried_like_responses() {
+        let busy: Result<(), bitaxe_api::Error> =
+            Err(bitaxe_api::Error::Status { status: StatusCode::BAD_GATEWAY, detail: None });
+        assert_eq!(busy.retry_reason(Policy::Idempotent).as_deref(), Some("status 502 Bad Gateway"));
+        assert_eq!(busy.retry_reason(Policy::NotSent), None);
+        let refused: Result<(), bitaxe_api::Error> =
+            Err(bitaxe_api::Error::Status { status: StatusCode::BAD_REQUEST, detail: None });
+        assert_eq!(refused.retry_reason(Policy::Idempotent), None);
+        let missing: Result<(), bitaxe_api::Error> = Err(bitaxe_api::Error::Unsupported { path: "/api/x" });
+        assert_eq!(missing.retry_reason(Policy::Idempotent), None);
+    }
+
+    #[test]
+    fn jitter_stays_in_range() {
+        assert!((0..100).map(|_| jitter()).all(|j| (0.0..1.0).contains(&j)));
+    }
+}
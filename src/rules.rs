@@ -0,0 +1,738 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::alert::{AlertEvent, Condition, Severity};
+use crate::config_file::{AppConfig, RuleConfig, config_path};
+use crate::exporter::write_atomic;
+use crate::reboot;
+use crate::temperature;
+use crate::{get_number, parse_duration, shares};
+
+/// What a rule can compare, by the name used in `when`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Metric {
+    Hashrate,
+    Temp,
+    VrTemp,
+    Power,
+    Frequency,
+    CoreVoltage,
+    FanRpm,
+    WifiRssi,
+    Uptime,
+    SharesRejected,
+    RejectRate,
+    FallbackPool,
+    /// Failed polls in a row; the only metric an unreachable miner has
+    FailedPolls,
+    /// Accepted shares per minute, over successive polls
+    ShareRate,
+    /// Seconds since the accepted-share count last went up
+    SinceLastShare,
+}
+
+const METRICS: &[Metric] = &[
+    Metric::Hashrate,
+    Metric::Temp,
+    Metric::VrTemp,
+    Metric::Power,
+    Metric::Frequency,
+    Metric::CoreVoltage,
+    Metric::FanRpm,
+    Metric::WifiRssi,
+    Metric::Uptime,
+    Metric::SharesRejected,
+    Metric::RejectRate,
+    Metric::FallbackPool,
+    Metric::FailedPolls,
+    Metric::ShareRate,
+    Metric::SinceLastShare,
+];
+
+impl Metric {
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::Hashrate => "hashrate",
+            Metric::Temp => "temp",
+            Metric::VrTemp => "vr_temp",
+            Metric::Power => "power",
+            Metric::Frequency => "frequency",
+            Metric::CoreVoltage => "core_voltage",
+            Metric::FanRpm => "fan_rpm",
+            Metric::WifiRssi => "wifi_rssi",
+            Metric::Uptime => "uptime",
+            Metric::SharesRejected => "shares_rejected",
+            Metric::RejectRate => "reject_rate",
+            Metric::FallbackPool => "fallback_pool",
+            Metric::FailedPolls => "failed_polls",
+            Metric::ShareRate => "share_rate",
+            Metric::SinceLastShare => "since_last_share",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Metric::Hashrate => "Hashrate",
+            Metric::Temp => "Temperature",
+            Metric::VrTemp => "VR temperature",
+            Metric::Power => "Power",
+            Metric::Frequency => "Frequency",
+            Metric::CoreVoltage => "Core voltage",
+            Metric::FanRpm => "Fan speed",
+            Metric::WifiRssi => "WiFi signal",
+            Metric::Uptime => "Uptime",
+            Metric::SharesRejected => "Rejected shares",
+            Metric::RejectRate => "Reject rate",
+            Metric::FallbackPool => "Fallback pool",
+            Metric::FailedPolls => "Failed polls",
+            Metric::ShareRate => "Share rate",
+            Metric::SinceLastShare => "Time since last share",
+        }
+    }
+
+    pub fn unit(self) -> &'static str {
+        match self {
+            Metric::Hashrate => " GH/s",
+            Metric::Temp | Metric::VrTemp => " °C",
+            Metric::Power => " W",
+            Metric::Frequency => " MHz",
+            Metric::CoreVoltage => " mV",
+            Metric::FanRpm => " RPM",
+            Metric::WifiRssi => " dBm",
+            Metric::Uptime | Metric::SinceLastShare => " s",
+            Metric::RejectRate => "%",
+            Metric::ShareRate => " /min",
+            Metric::SharesRejected | Metric::FallbackPool | Metric::FailedPolls => "",
+        }
+    }
+
+    /// The AxeOS key the metric is read from, for the ones that are a plain field
+    pub fn key(self) -> Option<&'static str> {
+        match self {
+            Metric::Hashrate => Some("hashRate"),
+            Metric::Temp => Some("temp"),
+            Metric::VrTemp => Some("vrTemp"),
+            Metric::Power => Some("power"),
+            Metric::Frequency => Some("frequency"),
+            Metric::CoreVoltage => Some("coreVoltageActual"),
+            Metric::FanRpm => Some("fanrpm"),
+            Metric::WifiRssi => Some("wifiRSSI"),
+            Metric::Uptime => Some("uptimeSeconds"),
+            Metric::SharesRejected => Some("sharesRejected"),
+            Metric::RejectRate
+            | Metric::FallbackPool
+            | Metric::FailedPolls
+            | Metric::ShareRate
+            | Metric::SinceLastShare => None,
+        }
+    }
+
+    /// The value in one poll, when the miner reported it
+    pub fn reading(self, info: &Value) -> Option<f64> {
+        match self {
+            Metric::RejectRate => Condition::RejectRateAbove.reading(info),
+            Metric::FallbackPool => Condition::FallbackPool.reading(info),
+            // The rest without a key are derived from several polls, by the engine
+            metric => get_number(info, metric.key()?),
+        }
+    }
+
+    /// Metrics that take several polls to work out, so a one-off `check` can't use them
+    pub fn needs_history(self) -> bool {
+        matches!(self, Metric::FailedPolls | Metric::ShareRate | Metric::SinceLastShare)
+    }
+
+    /// By name, or by the AxeOS key it is read from (`hashRate`, `vrTemp`)
+    pub fn parse(s: &str) -> Result<Self> {
+        match METRICS.iter().find(|m| m.name() == s || m.key() == Some(s)) {
+            Some(m) => Ok(*m),
+            None => {
+                let names: Vec<&str> = METRICS.iter().map(|m| m.name()).collect();
+                bail!("unknown metric '{s}' (expected one of: {})", names.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+impl Op {
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Eq => "==",
+            Op::Ne => "!=",
+        }
+    }
+
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Op::Gt => value > threshold,
+            Op::Ge => value >= threshold,
+            Op::Lt => value < threshold,
+            Op::Le => value <= threshold,
+            Op::Eq => value == threshold,
+            Op::Ne => value != threshold,
+        }
+    }
+}
+
+/// One `[alerts.rules.NAME]` entry, parsed
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub name: String,
+    pub metric: Metric,
+    op: Op,
+    pub threshold: f64,
+    /// How long the condition must hold before the rule fires
+    sustain: Duration,
+    pub severity: Severity,
+    /// Once firing, how far back past the threshold the value has to go
+    /// before the rule resolves
+    hysteresis: f64,
+    devices: Vec<String>,
+    tags: Vec<String>,
+}
+
+impl Rule {
+    pub fn from_config(name: &str, cfg: &RuleConfig) -> Result<Self> {
+        let (metric, op, threshold, sustain) =
+            parse_when(&cfg.when).with_context(|| format!("[alerts.rules.{name}] when = \"{}\"", cfg.when))?;
+        if !cfg.hysteresis.is_finite() || cfg.hysteresis < 0.0 {
+            bail!("[alerts.rules.{name}] hysteresis must be zero or more");
+        }
+        Ok(Rule {
+            name: name.to_string(),
+            metric,
+            op,
+            threshold,
+            sustain,
+            severity: cfg.severity,
+            hysteresis: cfg.hysteresis,
+            devices: cfg.devices.clone(),
+            tags: cfg.tags.clone(),
+        })
+    }
+
+    /// The condition as written, ex: "temp > 68 for 2m"
+    pub fn when(&self) -> String {
+        let mut s = format!("{} {} {}", self.metric.name(), self.op.symbol(), self.threshold);
+        if !self.sustain.is_zero() {
+            s += &format!(" for {}", short_duration(self.sustain));
+        }
+        s
+    }
+
+    /// Unscoped rules cover every device; otherwise the device has to be
+    /// listed or carry one of the tags
+    fn applies(&self, device: &str, tags: &[String]) -> bool {
+        (self.devices.is_empty() && self.tags.is_empty())
+            || self.devices.iter().any(|d| d == device)
+            || self.tags.iter().any(|t| tags.contains(t))
+    }
+
+    /// Whether `value` breaches the rule. A firing rule keeps firing until
+    /// the value is back past the threshold by `hysteresis`, so a reading
+    /// hovering around the threshold doesn't flap.
+    fn breached(&self, value: f64, firing: bool) -> bool {
+        let margin = if firing { self.hysteresis } else { 0.0 };
+        match self.op {
+            Op::Gt | Op::Ge => self.op.holds(value, self.threshold - margin),
+            Op::Lt | Op::Le => self.op.holds(value, self.threshold + margin),
+            Op::Eq | Op::Ne => self.op.holds(value, self.threshold),
+        }
+    }
+}
+
+/// `METRIC OP THRESHOLD [for DURATION]`, ex: "temp > 68 for 2m" or
+/// "fallback_pool == true"
+fn parse_when(s: &str) -> Result<(Metric, Op, f64, Duration)> {
+    let (cond, sustain) = match s.split_once(" for ") {
+        Some((cond, d)) => (cond, parse_duration(d).map_err(anyhow::Error::msg)?),
+        None => (s, Duration::ZERO),
+    };
+    let Expr { metric, op, threshold } = Expr::parse(cond)?;
+    Ok((metric, op, threshold, sustain))
+}
+
+/// One reading compared with a threshold, ex: `temp > 68`, `hashRate<400`
+/// or `fallback_pool == true`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr {
+    pub metric: Metric,
+    op: Op,
+    pub threshold: f64,
+}
+
+impl Expr {
+    pub fn parse(s: &str) -> Result<Self> {
+        let Some(at) = s.find(['<', '>', '=', '!']) else {
+            bail!("missing comparison (use >, >=, <, <=, == or !=)");
+        };
+        let (metric, rest) = s.split_at(at);
+        let (op, threshold) = [Op::Ge, Op::Le, Op::Eq, Op::Ne, Op::Gt, Op::Lt]
+            .into_iter()
+            .find_map(|op| Some((op, rest.strip_prefix(op.symbol())?)))
+            .context("unknown comparison (use >, >=, <, <=, == or !=)")?;
+        let metric = Metric::parse(metric.trim())?;
+        let threshold = match threshold.trim() {
+            "true" => 1.0,
+            "false" => 0.0,
+            t => t.parse().ok().filter(|n: &f64| n.is_finite()).with_context(|| format!("invalid threshold '{t}'"))?,
+        };
+        Ok(Expr { metric, op, threshold })
+    }
+
+    /// The reading, when the poll has it and it satisfies the comparison
+    pub fn matches(&self, info: &Value) -> Option<f64> {
+        self.metric.reading(info).filter(|&value| self.op.holds(value, self.threshold))
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.metric.name(), self.op.symbol(), self.threshold)
+    }
+}
+
+/// ex: 90s, 2m, 1h30m
+pub fn short_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, 0) => format!("{m}m"),
+        (0, m, s) => format!("{m}m{s}s"),
+        (h, 0, 0) => format!("{h}h"),
+        (h, m, _) => format!("{h}h{m}m"),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Inactive,
+    /// Breached, but not yet for the rule's sustain duration
+    Pending,
+    Firing,
+    /// Was firing and recovered; otherwise treated like inactive
+    Resolved,
+}
+
+impl State {
+    pub fn name(self) -> &'static str {
+        match self {
+            State::Inactive => "inactive",
+            State::Pending => "pending",
+            State::Firing => "firing",
+            State::Resolved => "resolved",
+        }
+    }
+}
+
+/// Where one rule stands on one device
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RuleStatus {
+    pub rule: String,
+    pub device: String,
+    pub severity: Severity,
+    pub when: String,
+    pub state: State,
+    /// When the current state began (the first breaching poll while pending
+    /// or firing), Unix seconds
+    pub since: u64,
+    /// The latest reading with its unit, ex: "70.5 °C" (in the --temp-unit
+    /// of the process that saved it)
+    pub value: Option<String>,
+    /// Last evaluation, Unix seconds
+    pub updated: u64,
+}
+
+struct Track {
+    state: State,
+    since: SystemTime,
+    value: Option<f64>,
+    at: SystemTime,
+}
+
+/// Evaluates `[alerts.rules]` against each poll and keeps every
+/// (rule, device) pair's state between polls
+pub struct Engine {
+    rules: Vec<Rule>,
+    /// Configured tags per device name
+    tags: HashMap<String, Vec<String>>,
+    tracks: BTreeMap<(usize, String), Track>,
+    failures: HashMap<String, u32>,
+    shares: shares::Tracker,
+    reboots: reboot::Tracker,
+}
+
+impl Engine {
+    pub fn new(rules: Vec<Rule>, tags: HashMap<String, Vec<String>>) -> Self {
+        Engine {
+            rules,
+            tags,
+            tracks: BTreeMap::new(),
+            failures: HashMap::new(),
+            shares: shares::Tracker::new(),
+            reboots: reboot::Tracker::default(),
+        }
+    }
+
+    /// The rules in [alerts.rules], scoped with the devices' configured tags
+    pub fn from_config(cfg: &AppConfig) -> Result<Self> {
+        let rules = match &cfg.alerts {
+            Some(alerts) => alerts.rules.iter().map(|(name, r)| Rule::from_config(name, r)).collect::<Result<_>>()?,
+            None => Vec::new(),
+        };
+        let tags = cfg.devices.iter().map(|(name, d)| (name.clone(), d.tags.clone())).collect();
+        let mut engine = Engine::new(rules, tags);
+        engine.reboots = reboot::Tracker::new(reboot::log_path());
+        Ok(engine)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Pick the share counters up where the history database left them
+    pub fn seed_shares(&mut self, seeds: &[shares::Seed]) {
+        self.shares.seed(seeds);
+    }
+
+    /// Share rate and time since the last share as of the device's latest poll
+    pub fn shares(&self, device: &str) -> Option<shares::Stats> {
+        self.shares.stats(device)
+    }
+
+    /// See `reboot::Tracker::unexpected`
+    pub fn unexpected_reboots(&self, device: &str) -> Option<u64> {
+        self.reboots.unexpected(device)
+    }
+
+    /// Feed one poll of `device` (`None` when it couldn't be reached) and
+    /// return the rules that started or stopped firing
+    pub fn observe(&mut self, device: &str, info: Option<&Value>, at: SystemTime) -> Vec<AlertEvent> {
+        let failures = match info {
+            Some(_) => {
+                self.failures.remove(device);
+                0
+            }
+            None => {
+                let n = self.failures.entry(device.to_string()).or_insert(0);
+                *n += 1;
+                *n
+            }
+        };
+        let shares = info.and_then(|info| self.shares.observe(device, info, at));
+        if let Some(info) = info {
+            self.reboots.observe(device, info, at);
+        }
+        let no_tags = Vec::new();
+        let tags = self.tags.get(device).unwrap_or(&no_tags);
+
+        let mut events = Vec::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            if !rule.applies(device, tags) {
+                continue;
+            }
+            let value = match rule.metric {
+                Metric::FailedPolls => Some(f64::from(failures)),
+                Metric::ShareRate => shares.and_then(|s| s.per_minute),
+                Metric::SinceLastShare => shares.map(|s| s.since_last.as_secs() as f64),
+                metric => info.and_then(|info| metric.reading(info)),
+            };
+            let track = self.tracks.entry((i, device.to_string())).or_insert(Track {
+                state: State::Inactive,
+                since: at,
+                value: None,
+                at,
+            });
+            track.at = at;
+            // A reading the miner didn't report leaves the state as it was
+            let Some(value) = value else { continue };
+            track.value = Some(value);
+            if let Some(firing) = step(rule, track, value, at) {
+                events.push(AlertEvent {
+                    device: device.to_string(),
+                    condition: Condition::Rule(rule.metric),
+                    rule: Some(rule.name.clone()),
+                    severity: rule.severity,
+                    firing,
+                    value: Some(value),
+                    threshold: Some(rule.threshold),
+                    since: track.since,
+                    at,
+                });
+                if !firing {
+                    track.since = at;
+                }
+            }
+        }
+        events
+    }
+
+    /// Every (rule, device) pair evaluated so far, by rule and then device
+    pub fn states(&self) -> Vec<RuleStatus> {
+        self.tracks
+            .iter()
+            .map(|((i, device), track)| {
+                let rule = &self.rules[*i];
+                RuleStatus {
+                    rule: rule.name.clone(),
+                    device: device.clone(),
+                    severity: rule.severity,
+                    when: rule.when(),
+                    state: track.state,
+                    since: unix_secs(track.since),
+                    value: track.value.map(|v| {
+                        let unit = temperature::unit();
+                        let shown = (unit.metric_value(rule.metric, v) * 100.0).round() / 100.0;
+                        format!("{shown}{}", unit.metric_unit(rule.metric))
+                    }),
+                    updated: unix_secs(track.at),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Advance one (rule, device) state with a new reading. Returns
+/// `Some(true)` when the rule starts firing, `Some(false)` when it resolves.
+/// While firing, `since` is when the breach began; on resolving the caller
+/// reports that before moving it to the resolution time.
+fn step(rule: &Rule, track: &mut Track, value: f64, at: SystemTime) -> Option<bool> {
+    let firing = track.state == State::Firing;
+    let bad = rule.breached(value, firing);
+    match (track.state, bad) {
+        (State::Inactive | State::Resolved, true) => {
+            track.since = at;
+            if rule.sustain.is_zero() {
+                track.state = State::Firing;
+                return Some(true);
+            }
+            track.state = State::Pending;
+        }
+        (State::Pending, true) => {
+            if at.duration_since(track.since).unwrap_or_default() >= rule.sustain {
+                track.state = State::Firing;
+                return Some(true);
+            }
+        }
+        (State::Pending, false) => {
+            track.state = State::Inactive;
+            track.since = at;
+        }
+        (State::Firing, false) => {
+            track.state = State::Resolved;
+            return Some(false);
+        }
+        (State::Firing, true) | (State::Inactive | State::Resolved, false) => {}
+    }
+    None
+}
+
+fn unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// ~/.config/bitaxe-cli/alerts-state.json, next to the config file. The
+/// polling commands rewrite it after every evaluation for `alerts status`.
+pub fn state_path() -> Option<PathBuf> {
+    Some(config_path()?.parent()?.join("alerts-state.json"))
+}
+
+pub fn save_states(path: &Path, states: &[RuleStatus]) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    write_atomic(path, &serde_json::to_string_pretty(states)?)
+}
+
+/// The last saved states, `None` when nothing has been recorded yet
+pub fn load_states(path: &Path) -> Result<Option<Vec<RuleStatus>>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let states = serde_json::from_str(&text).with_context(|| format!("{} is not valid alert state", path.display()))?;
+    Ok(Some(states))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rule(when: &str, hysteresis: f64) -> Rule {
+        let cfg = RuleConfig {
+            when: when.into(),
+            severity: Severity::Critical,
+            hysteresis,
+            devices: Vec::new(),
+            tags: Vec::new(),
+        };
+        Rule::from_config("overheat", &cfg).unwrap()
+    }
+
+    fn t(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn temp(v: f64) -> Value {
+        json!({"temp": v})
+    }
+
+    #[test]
+    fn parses_conditions() {
+        let r = rule("temp > 68 for 2m", 0.0);
+        assert_eq!((r.metric, r.op, r.threshold, r.sustain), (Metric::Temp, Op::Gt, 68.0, Duration::from_secs(120)));
+        assert_eq!(r.when(), "temp > 68 for 2m");
+
+        assert_eq!(parse_when("hashrate<=400.5").unwrap(), (Metric::Hashrate, Op::Le, 400.5, Duration::ZERO));
+        assert_eq!(parse_when("failed_polls >= 3").unwrap().1, Op::Ge);
+        assert_eq!(parse_when("fallback_pool == true").unwrap().2, 1.0);
+        assert_eq!(parse_when("wifi_rssi < -75 for 90s").unwrap().2, -75.0);
+        assert_eq!(rule("wifi_rssi < -75 for 90s", 0.0).when(), "wifi_rssi < -75 for 1m30s");
+
+        // AxeOS keys name the same metrics
+        let expr = Expr::parse("hashRate<400").unwrap();
+        assert_eq!((expr.metric, expr.to_string()), (Metric::Hashrate, "hashrate < 400".to_string()));
+        assert_eq!(expr.matches(&json!({"hashRate": 380.5})), Some(380.5));
+        assert_eq!(expr.matches(&json!({"hashRate": 400})), None);
+        assert_eq!(Expr::parse("vrTemp >= 70").unwrap().metric, Metric::VrTemp);
+    }
+
+    #[test]
+    fn rejects_bad_conditions() {
+        let err = |s| format!("{:#}", parse_when(s).unwrap_err());
+        assert!(err("tmp > 68").contains("unknown metric 'tmp'"));
+        assert!(err("temp 68").contains("missing comparison"));
+        assert!(err("temp => 68").contains("unknown comparison"));
+        assert!(err("temp > hot").contains("invalid threshold 'hot'"));
+        assert!(err("temp > 68 for soon").contains("invalid duration"));
+        assert!(err("temp > NaN").contains("invalid threshold"));
+
+        let cfg = RuleConfig { when: "temp > 68".into(), hysteresis: -1.0, ..Default::default() };
+        assert!(Rule::from_config("x", &cfg).is_err());
+    }
+
+    #[test]
+    fn pending_then_firing_then_resolved() {
+        let mut engine = Engine::new(vec![rule("temp > 68 for 2m", 0.0)], HashMap::new());
+        let state = |e: &Engine| e.states()[0].state;
+
+        assert!(engine.observe("gamma", Some(&temp(60.0)), t(0)).is_empty());
+        assert_eq!(state(&engine), State::Inactive);
+        // Breached, but not for long enough yet
+        assert!(engine.observe("gamma", Some(&temp(70.0)), t(10)).is_empty());
+        assert_eq!(state(&engine), State::Pending);
+        assert!(engine.observe("gamma", Some(&temp(71.0)), t(60)).is_empty());
+        let fired = engine.observe("gamma", Some(&temp(72.0)), t(130));
+        assert_eq!(fired.len(), 1);
+        assert!(fired[0].firing);
+        assert_eq!((fired[0].since, fired[0].rule.as_deref()), (t(10), Some("overheat")));
+        assert_eq!(fired[0].severity, Severity::Critical);
+        assert_eq!(state(&engine), State::Firing);
+        // No repeats while it stays hot
+        assert!(engine.observe("gamma", Some(&temp(75.0)), t(140)).is_empty());
+
+        let resolved = engine.observe("gamma", Some(&temp(60.0)), t(150));
+        assert_eq!((resolved.len(), resolved[0].firing, resolved[0].since), (1, false, t(10)));
+        let status = &engine.states()[0];
+        assert_eq!((status.state, status.since, status.value.as_deref()), (State::Resolved, 150, Some("60 °C")));
+    }
+
+    #[test]
+    fn short_breaches_never_fire() {
+        let mut engine = Engine::new(vec![rule("temp > 68 for 2m", 0.0)], HashMap::new());
+        for (secs, v) in [(0, 70.0), (60, 70.0), (90, 60.0), (100, 70.0), (200, 70.0)] {
+            assert!(engine.observe("gamma", Some(&temp(v)), t(secs)).is_empty(), "at {secs}s");
+        }
+        // The dip at 90s restarted the clock
+        assert_eq!(engine.states()[0].since, 100);
+        assert_eq!(engine.observe("gamma", Some(&temp(70.0)), t(220)).len(), 1);
+    }
+
+    #[test]
+    fn hysteresis_stops_flapping() {
+        let mut engine = Engine::new(vec![rule("temp > 68", 2.0)], HashMap::new());
+        let mut events = Vec::new();
+        for v in [69.0, 67.5, 68.5, 66.5, 68.1, 65.9, 67.0, 68.5] {
+            events.extend(engine.observe("gamma", Some(&temp(v)), t(0)).into_iter().map(|e| e.firing));
+        }
+        // Fires at 69, holds through 67.5..66.5 (above 66), resolves at 65.9, fires again at 68.5
+        assert_eq!(events, [true, false, true]);
+
+        let mut below = Engine::new(vec![rule("hashrate < 400", 50.0)], HashMap::new());
+        let hash = |v: f64| json!({"hashRate": v});
+        assert_eq!(below.observe("gamma", Some(&hash(390.0)), t(0)).len(), 1);
+        assert!(below.observe("gamma", Some(&hash(420.0)), t(5)).is_empty());
+        assert_eq!(below.observe("gamma", Some(&hash(451.0)), t(10)).len(), 1);
+    }
+
+    #[test]
+    fn missing_readings_and_failed_polls() {
+        let rules = vec![rule("temp > 68", 0.0), rule("failed_polls >= 2", 0.0)];
+        let mut engine = Engine::new(rules, HashMap::new());
+        assert_eq!(engine.observe("gamma", Some(&temp(70.0)), t(0)).len(), 1);
+        // Unreachable: temp keeps firing, failed_polls counts up
+        assert!(engine.observe("gamma", None, t(5)).is_empty());
+        let down = engine.observe("gamma", None, t(10));
+        assert_eq!((down.len(), down[0].condition), (1, Condition::Rule(Metric::FailedPolls)));
+        let states: Vec<State> = engine.states().iter().map(|s| s.state).collect();
+        assert_eq!(states, [State::Firing, State::Firing]);
+
+        let back = engine.observe("gamma", Some(&temp(60.0)), t(15));
+        assert_eq!(back.iter().filter(|e| !e.firing).count(), 2);
+    }
+
+    #[test]
+    fn rules_scoped_by_device_and_tag() {
+        let scoped = |devices: &[&str], tags: &[&str]| {
+            let cfg = RuleConfig {
+                when: "temp > 68".into(),
+                devices: devices.iter().map(|d| d.to_string()).collect(),
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+                ..Default::default()
+            };
+            Rule::from_config("hot", &cfg).unwrap()
+        };
+        let tags = HashMap::from([("shed".to_string(), vec!["outdoor".to_string()])]);
+        let mut engine = Engine::new(vec![scoped(&["gamma"], &[]), scoped(&[], &["outdoor"])], tags);
+
+        assert_eq!(engine.observe("gamma", Some(&temp(70.0)), t(0)).len(), 1);
+        assert_eq!(engine.observe("shed", Some(&temp(70.0)), t(0)).len(), 1);
+        assert!(engine.observe("ultra", Some(&temp(70.0)), t(0)).is_empty());
+        let pairs: Vec<(String, String)> = engine.states().into_iter().map(|s| (s.rule, s.device)).collect();
+        assert_eq!(pairs, [("hot".into(), "gamma".into()), ("hot".into(), "shed".into())]);
+    }
+
+    #[test]
+    fn states_round_trip_through_the_file() {
+        let mut engine = Engine::new(vec![rule("temp > 68 for 2m", 0.0)], HashMap::new());
+        engine.observe("gamma", Some(&temp(70.0)), t(10));
+        let path = std::env::temp_dir().join(format!("bitaxe-cli-rules-{}.json", std::process::id()));
+        save_states(&path, &engine.states()).unwrap();
+        let loaded = load_states(&path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, engine.states());
+        assert_eq!(loaded[0].state, State::Pending);
+        assert!(load_states(&path).unwrap().is_none());
+    }
+}
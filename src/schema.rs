@@ -0,0 +1,101 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde_json::Value;
+use crate::get_str;
+
+/// Fields `status` relies on; a miss usually means unusual firmware or a
+/// renamed field
+pub const EXPECTED_KEYS: &[&str] = &[
+    "hostname", "hashRate", "bestDiff", "sharesAccepted", "sharesRejected", "temp", "power", "voltage", "frequency",
+    "ASICModel", "version",
+];
+
+/// The rest of what AxeOS sends in /api/system/info, as far as we know
+const KNOWN_KEYS: &[&str] = &[
+    "apEnabled", "asicCount", "autofanspeed", "axeOSVersion", "bestSessionDiff", "boardVersion", "coreVoltage",
+    "coreVoltageActual", "current", "display", "displayTimeout", "expectedHashrate", "fallbackStratumPort",
+    "fallbackStratumURL", "fallbackStratumUser", "fanrpm", "fanspeed", "flipscreen", "freeHeap", "idfVersion",
+    "invertfanpolarity", "invertscreen", "isPSRAMAvailable", "isUsingFallbackStratum", "macAddr", "maxPower",
+    "minFanSpeed", "nominalVoltage", "overclockEnabled", "overheat_mode", "poolDifficulty", "responseTime",
+    "rotation", "runningPartition", "sharesRejectedReasons", "smallCoreCount", "ssid", "statsFrequency",
+    "stratumDiff", "stratumPort", "stratumURL", "stratumUser", "temptarget", "uptimeSeconds", "vrTemp", "wifiRSSI",
+    "wifiStatus",
+];
+
+/// Set from -v before the command runs
+static NOTES: AtomicBool = AtomicBool::new(false);
+/// Hosts already noted, so polling loops say it once
+static NOTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+pub fn set_notes(on: bool) {
+    NOTES.store(on, Ordering::Relaxed);
+}
+
+/// How a response differs from what this CLI knows about
+#[derive(Debug, Default, PartialEq)]
+pub struct Drift {
+    /// EXPECTED_KEYS that aren't there
+    pub missing: Vec<&'static str>,
+    /// Keys that are there but that we don't know; a rename shows up here
+    pub unknown: Vec<String>,
+}
+
+impl Drift {
+    pub fn of(info: &Value) -> Self {
+        let Some(map) = info.as_object() else { return Drift::default() };
+        let missing = EXPECTED_KEYS.iter().copied().filter(|k| !map.contains_key(*k)).collect();
+        let known = |k: &str| EXPECTED_KEYS.contains(&k) || KNOWN_KEYS.contains(&k);
+        let mut unknown: Vec<String> = map.keys().filter(|k| !known(k)).cloned().collect();
+        unknown.sort();
+        Drift { missing, unknown }
+    }
+
+    /// `missing temp, power (unknown keys present: asicTemp)`
+    pub fn describe(&self) -> String {
+        let mut text = format!("missing {}", self.missing.join(", "));
+        if !self.unknown.is_empty() {
+            text += &format!(" (unknown keys present: {})", self.unknown.join(", "));
+        }
+        text
+    }
+}
+
+/// With -v, warn on stderr the first time a host's answer lacks expected
+/// fields, which `status` would otherwise just leave out. Never fails.
+pub fn note(host: &str, info: &Value) {
+    if !NOTES.load(Ordering::Relaxed) {
+        return;
+    }
+    let drift = Drift::of(info);
+    if drift.missing.is_empty() {
+        return;
+    }
+    let Ok(mut noted) = NOTED.lock() else { return };
+    if noted.iter().any(|h| h == host) {
+        return;
+    }
+    noted.push(host.to_string());
+    let version = get_str(info, "version").unwrap_or("unknown");
+    eprintln!("Warning: {host} (firmware {version}): {}", drift.describe());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn renamed_fields_show_as_missing_and_unknown() {
+        let mut info = json!({"fanrpm": 4000, "hashrate10m": 1.0, "asicTemp": 61.0});
+        for key in EXPECTED_KEYS.iter().filter(|k| **k != "temp") {
+            info[key] = json!(1);
+        }
+        let drift = Drift::of(&info);
+        assert_eq!(drift, Drift { missing: vec!["temp"], unknown: vec!["asicTemp".into(), "hashrate10m".into()] });
+        assert_eq!(drift.describe(), "missing temp (unknown keys present: asicTemp, hashrate10m)");
+
+        info["temp"] = json!(60.0);
+        assert!(Drift::of(&info).missing.is_empty());
+        assert_eq!(Drift::of(&json!([1, 2])), Drift::default());
+    }
+}
@@ -0,0 +1,198 @@
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+use crate::config_file::{AppConfig, Profile};
+use crate::{get_number, get_str};
+
+/// Tunable settings that can be PATCHed to /api/system
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Setting {
+    /// ASIC frequency in MHz
+    Frequency,
+    /// ASIC core voltage in mV
+    CoreVoltage,
+    /// Fan speed in percent (used when autofanspeed is off)
+    Fanspeed,
+    /// Automatic fan control (1 = on, 0 = off)
+    Autofanspeed,
+}
+
+impl Setting {
+    /// Key used by AxeOS in both /api/system/info and the PATCH body
+    pub fn key(self) -> &'static str {
+        match self {
+            Setting::Frequency => "frequency",
+            Setting::CoreVoltage => "coreVoltage",
+            Setting::Fanspeed => "fanspeed",
+            Setting::Autofanspeed => "autofanspeed",
+        }
+    }
+
+    pub fn unit(self) -> &'static str {
+        match self {
+            Setting::Frequency => " MHz",
+            Setting::CoreVoltage => " mV",
+            Setting::Fanspeed => " %",
+            Setting::Autofanspeed => "",
+        }
+    }
+}
+
+/// Safe frequency (MHz) and core voltage (mV) ranges for one ASIC model
+struct AsicLimits {
+    model: &'static str,
+    frequency: (f64, f64),
+    core_voltage: (f64, f64),
+}
+
+const ASIC_LIMITS: &[AsicLimits] = &[
+    AsicLimits { model: "BM1397", frequency: (350.0, 650.0), core_voltage: (1200.0, 1500.0) },
+    AsicLimits { model: "BM1366", frequency: (400.0, 575.0), core_voltage: (1100.0, 1300.0) },
+    AsicLimits { model: "BM1368", frequency: (400.0, 650.0), core_voltage: (1100.0, 1300.0) },
+    AsicLimits { model: "BM1370", frequency: (400.0, 800.0), core_voltage: (1000.0, 1300.0) },
+];
+
+/// Used when the miner doesn't report a model we know
+const FALLBACK_LIMITS: AsicLimits =
+    AsicLimits { model: "unknown ASIC", frequency: (400.0, 575.0), core_voltage: (1100.0, 1300.0) };
+
+/// Check `value` against the limits for the miner's ASIC model.
+/// The error names the model and the allowed range. With `force`, a
+/// frequency or core voltage outside them only gets a loud warning (fan
+/// values outside 0-100 aren't something the firmware can do at all).
+pub fn validate(setting: Setting, value: f64, info: &Value, force: bool) -> Result<(), String> {
+    let model = get_str(info, "ASICModel").unwrap_or_default();
+    let limits = ASIC_LIMITS.iter().find(|l| l.model == model).unwrap_or(&FALLBACK_LIMITS);
+
+    let (min, max) = match setting {
+        Setting::Frequency => limits.frequency,
+        Setting::CoreVoltage => limits.core_voltage,
+        Setting::Fanspeed => (0.0, 100.0),
+        Setting::Autofanspeed => (0.0, 1.0),
+    };
+
+    if value < min || value > max {
+        if force && matches!(setting, Setting::Frequency | Setting::CoreVoltage) {
+            eprintln!(
+                "WARNING: {} {value}{} is outside the safe range for {} ({min}-{max}{}). Applying it anyway \
+                 (--force); this can overheat or permanently damage the board.",
+                setting.key(),
+                setting.unit(),
+                limits.model,
+                setting.unit(),
+            );
+            return Ok(());
+        }
+        return Err(match setting {
+            Setting::Frequency | Setting::CoreVoltage => format!(
+                "{} allows {min}-{max}{} for {} (--force to override)",
+                limits.model,
+                setting.unit(),
+                setting.key()
+            ),
+            _ => format!("{} must be {min}-{max}", setting.key()),
+        });
+    }
+    Ok(())
+}
+
+/// Current value of a setting as reported by /api/system/info
+pub fn current(setting: Setting, info: &Value) -> Option<f64> {
+    get_number(info, setting.key())
+}
+
+/// A set of setting changes applied together in one PATCH
+pub type Changes = Vec<(Setting, f64)>;
+
+/// The changes a config profile describes, in a fixed order
+pub fn profile_changes(profile: &Profile) -> Changes {
+    let mut changes = Vec::new();
+    if let Some(v) = profile.frequency {
+        changes.push((Setting::Frequency, v));
+    }
+    if let Some(v) = profile.core_voltage {
+        changes.push((Setting::CoreVoltage, v));
+    }
+    if let Some(v) = profile.fanspeed {
+        changes.push((Setting::Fanspeed, v));
+    }
+    if let Some(on) = profile.autofanspeed {
+        changes.push((Setting::Autofanspeed, if on { 1.0 } else { 0.0 }));
+    }
+    changes
+}
+
+/// Find a named profile and turn it into changes, listing the configured
+/// names when it is missing
+pub fn lookup_profile(cfg: &AppConfig, name: &str) -> Result<Changes> {
+    let Some(profile) = cfg.profiles.get(name) else {
+        if cfg.profiles.is_empty() {
+            bail!("Unknown profile '{name}': no [profiles] are configured");
+        }
+        let names: Vec<&str> = cfg.profiles.keys().map(String::as_str).collect();
+        bail!("Unknown profile '{name}'. Configured profiles: {}", names.join(", "));
+    };
+
+    let changes = profile_changes(profile);
+    if changes.is_empty() {
+        bail!("Profile '{name}' doesn't set anything (expected frequency, core_voltage, fanspeed or autofanspeed)");
+    }
+    Ok(changes)
+}
+
+/// Every change must be acceptable for the miner; the first problem is reported
+pub fn validate_all(changes: &[(Setting, f64)], info: &Value, force: bool) -> Result<(), String> {
+    changes.iter().try_for_each(|(setting, value)| validate(*setting, *value, info, force))
+}
+
+/// `frequency = 575, coreVoltage = 1200`
+pub fn describe(changes: &[(Setting, f64)]) -> String {
+    changes
+        .iter()
+        .map(|(s, v)| format!("{} = {v}", s.key()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `frequency 525 -> 575 MHz, coreVoltage 1150 -> 1200 mV`
+pub fn preview(changes: &[(Setting, f64)], info: Option<&Value>) -> String {
+    changes
+        .iter()
+        .map(|(s, v)| {
+            let cur = info
+                .and_then(|i| current(*s, i))
+                .map(|c| c.to_string())
+                .unwrap_or_else(|| "?".to_string());
+            format!("{} {cur} -> {v}{}", s.key(), s.unit())
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// JSON body for PATCH /api/system
+pub fn body(changes: &[(Setting, f64)]) -> Value {
+    let mut map = Map::new();
+    for (setting, value) in changes {
+        map.insert(setting.key().to_string(), Value::from(*value));
+    }
+    Value::Object(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn limits_follow_the_asic_model() {
+        let bm1370 = json!({"ASICModel": "BM1370"});
+        assert!(validate(Setting::Frequency, 750.0, &bm1370, false).is_ok());
+        // Unknown boards get the conservative bounds
+        let err = validate(Setting::Frequency, 750.0, &json!({}), false).unwrap_err();
+        assert_eq!(err, "unknown ASIC allows 400-575 MHz for frequency (--force to override)");
+        assert!(validate(Setting::CoreVoltage, 1400.0, &bm1370, false).is_err());
+
+        assert!(validate(Setting::CoreVoltage, 1400.0, &bm1370, true).is_ok());
+        assert!(validate(Setting::Fanspeed, 120.0, &bm1370, true).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+use serde_json::Value;
+use crate::get_number;
+use crate::rules::short_duration;
+
+/// Span the share rate is measured over
+pub const RATE_WINDOW: Duration = Duration::from_secs(600);
+
+/// What the accepted-share counter says over successive polls
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    /// Accepted shares per minute over the last RATE_WINDOW; `None` until
+    /// two polls are some time apart
+    pub per_minute: Option<f64>,
+    /// Since the counter last went up. Until that has been seen it counts
+    /// from the first poll (or the history database's first sample).
+    pub since_last: Duration,
+}
+
+impl Stats {
+    /// `42s ago (1.9/min)`
+    pub fn describe(&self) -> String {
+        let ago = format!("{} ago", short_duration(self.since_last));
+        match self.per_minute {
+            Some(rate) => format!("{ago} ({rate:.1}/min)"),
+            None => ago,
+        }
+    }
+}
+
+/// Where a device's counter stood when a previous run stopped, from the
+/// history database
+#[derive(Clone, Debug, PartialEq)]
+pub struct Seed {
+    pub device: String,
+    pub accepted: f64,
+    /// When the counter last changed
+    pub last_share: SystemTime,
+}
+
+#[derive(Default)]
+struct Counter {
+    /// The counter as last reported
+    last: Option<f64>,
+    /// Shares accepted since tracking began, with restarts folded in
+    total: f64,
+    /// (poll, total) over the rate window, plus one just before it
+    samples: VecDeque<(SystemTime, f64)>,
+    last_share: Option<SystemTime>,
+    stats: Option<Stats>,
+}
+
+/// Share rate and time since the last accepted share, per device, from
+/// `sharesAccepted` in each poll. A counter that goes down means the miner
+/// restarted: whatever it reports then was accepted after the restart,
+/// rather than a negative rate.
+#[derive(Default)]
+pub struct Tracker {
+    devices: HashMap<String, Counter>,
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Tracker::default()
+    }
+
+    /// Carry on from a previous run, so the time since the last share
+    /// doesn't start over with every restart of the CLI
+    pub fn seed(&mut self, seeds: &[Seed]) {
+        for seed in seeds {
+            let counter = self.devices.entry(seed.device.clone()).or_default();
+            counter.last = Some(seed.accepted);
+            counter.last_share = Some(seed.last_share);
+        }
+    }
+
+    /// Feed one poll; `None` when the miner doesn't report its share count
+    pub fn observe(&mut self, device: &str, info: &Value, at: SystemTime) -> Option<Stats> {
+        let accepted = get_number(info, "sharesAccepted")?;
+        let counter = self.devices.entry(device.to_string()).or_default();
+        let new = match counter.last {
+            Some(prev) if accepted >= prev => accepted - prev,
+            Some(prev) => {
+                tracing::debug!(device, from = prev, to = accepted, "share counter reset");
+                accepted
+            }
+            None => 0.0,
+        };
+        counter.last = Some(accepted);
+        if new > 0.0 {
+            counter.total += new;
+            counter.last_share = Some(at);
+        }
+        let last_share = *counter.last_share.get_or_insert(at);
+
+        counter.samples.push_back((at, counter.total));
+        while counter.samples.len() > 2
+            && at.duration_since(counter.samples[1].0).unwrap_or_default() >= RATE_WINDOW
+        {
+            counter.samples.pop_front();
+        }
+        let (first, total) = counter.samples[0];
+        let minutes = at.duration_since(first).unwrap_or_default().as_secs_f64() / 60.0;
+        let stats = Stats {
+            per_minute: (minutes > 0.0).then(|| (counter.total - total) / minutes),
+            since_last: at.duration_since(last_share).unwrap_or_default(),
+        };
+        counter.stats = Some(stats);
+        Some(stats)
+    }
+
+    /// As of the device's latest poll
+    pub fn stats(&self, device: &str) -> Option<Stats> {
+        self.devices.get(device)?.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn poll(tracker: &mut Tracker, t0: SystemTime, secs: u64, accepted: u64) -> Stats {
+        let at = t0 + Duration::from_secs(secs);
+        tracker.observe("gamma", &json!({"sharesAccepted": accepted}), at).unwrap()
+    }
+
+    #[test]
+    fn rate_and_time_since_the_last_share() {
+        let t0 = SystemTime::now();
+        let mut t = Tracker::new();
+        let first = poll(&mut t, t0, 0, 100);
+        assert_eq!(first, Stats { per_minute: None, since_last: Duration::ZERO });
+        assert_eq!(poll(&mut t, t0, 60, 103).per_minute, Some(3.0));
+        let stalled = poll(&mut t, t0, 180, 103);
+        assert_eq!(stalled.per_minute, Some(1.0));
+        assert_eq!(stalled.since_last, Duration::from_secs(120));
+        assert_eq!(stalled.describe(), "2m ago (1.0/min)");
+        assert_eq!(t.stats("gamma"), Some(stalled));
+        assert_eq!(t.observe("gamma", &json!({}), t0), None);
+    }
+
+    #[test]
+    fn a_restart_is_a_reset_not_a_negative_rate() {
+        let t0 = SystemTime::now();
+        let mut t = Tracker::new();
+        poll(&mut t, t0, 0, 5000);
+        poll(&mut t, t0, 60, 5006);
+        // Rebooted and accepted 4 since
+        let after = poll(&mut t, t0, 120, 4);
+        assert_eq!(after.per_minute, Some(5.0));
+        assert_eq!(after.since_last, Duration::ZERO);
+        // Rebooted again, nothing since: the last share is still the one before
+        let zero = poll(&mut t, t0, 180, 0);
+        assert_eq!(zero.since_last, Duration::from_secs(60));
+        assert!(zero.per_minute.unwrap() > 0.0);
+    }
+
+    #[test]
+    fn seeds_carry_the_last_share_over() {
+        let t0 = SystemTime::now();
+        let mut t = Tracker::new();
+        t.seed(&[Seed { device: "gamma".into(), accepted: 42.0, last_share: t0 }]);
+        assert_eq!(poll(&mut t, t0, 300, 42).since_last, Duration::from_secs(300));
+        assert_eq!(poll(&mut t, t0, 330, 43).since_last, Duration::ZERO);
+    }
+}
@@ -0,0 +1,165 @@
+use std::fs;
+use std::path::Path;
+use anyhow::{Context, Result, bail};
+use bitaxe_api::SystemInfo;
+use clap::Subcommand;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::settings::{self, Setting};
+use crate::{confirm, fetch_info, patch_system};
+
+/// `settings` subcommands: back up and restore a miner's tuning
+#[derive(Subcommand, Debug)]
+pub enum SettingsCommand {
+    /// Write the tunable settings and pool config to a file (.json, else TOML)
+    Export { file: String },
+
+    /// PATCH the settings from an exported file back to the miner
+    Import {
+        file: String,
+
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
+
+        /// Apply a frequency or core voltage outside the ASIC's safe range
+        /// (with a warning). This can damage the board.
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// AxeOS keys saved in a snapshot. Pool passwords aren't readable through
+/// the API, so they are neither exported nor touched on import.
+const SNAPSHOT_KEYS: &[&str] = &[
+    "frequency",
+    "coreVoltage",
+    "fanspeed",
+    "autofanspeed",
+    "stratumURL",
+    "stratumPort",
+    "stratumUser",
+    "fallbackStratumURL",
+    "fallbackStratumPort",
+    "fallbackStratumUser",
+];
+
+/// Tunables checked against the ASIC limits before import
+const VALIDATED: &[Setting] = &[Setting::Frequency, Setting::CoreVoltage, Setting::Fanspeed, Setting::Autofanspeed];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    /// Firmware version the snapshot was taken on
+    version: Option<String>,
+    hostname: Option<String>,
+    asic_model: Option<String>,
+    /// AxeOS keys and values, as sent in PATCH /api/system
+    settings: Map<String, Value>,
+}
+
+pub async fn run(cmd: &SettingsCommand, client: &Client, host: &str) -> Result<()> {
+    match cmd {
+        SettingsCommand::Export { file } => export(client, host, Path::new(file)).await,
+        SettingsCommand::Import { file, yes, force } => import(client, host, Path::new(file), *yes, *force).await,
+    }
+}
+
+async fn export(client: &Client, host: &str, path: &Path) -> Result<()> {
+    let info = fetch_info(client, host).await?;
+    let settings: Map<String, Value> = SNAPSHOT_KEYS
+        .iter()
+        .filter_map(|key| info.get(*key).filter(|v| !v.is_null()).map(|v| (key.to_string(), v.clone())))
+        .collect();
+    if settings.is_empty() {
+        bail!("The miner reported none of the exportable settings");
+    }
+
+    let typed = SystemInfo::from(&info);
+    let snapshot =
+        Snapshot { version: typed.version, hostname: typed.hostname, asic_model: typed.asic_model, settings };
+    let text = if is_json(path) {
+        serde_json::to_string_pretty(&snapshot)? + "\n"
+    } else {
+        toml::to_string(&snapshot)?
+    };
+    fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Saved {} settings to {}", snapshot.settings.len(), path.display());
+    Ok(())
+}
+
+async fn import(client: &Client, host: &str, path: &Path, yes: bool, force: bool) -> Result<()> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let snapshot: Snapshot = if is_json(path) {
+        serde_json::from_str(&text).with_context(|| format!("{} is not a valid snapshot", path.display()))?
+    } else {
+        toml::from_str(&text).with_context(|| format!("{} is not a valid snapshot", path.display()))?
+    };
+    let info = fetch_info(client, host).await?;
+
+    let typed = SystemInfo::from(&info);
+    if let (Some(saved), Some(now)) = (snapshot.version.as_deref(), typed.version.as_deref())
+        && saved != now
+    {
+        eprintln!("Warning: snapshot was taken on firmware {saved}, the miner runs {now}");
+    }
+    if let (Some(saved), Some(now)) = (snapshot.asic_model.as_deref(), typed.asic_model.as_deref())
+        && saved != now
+    {
+        eprintln!("Warning: snapshot is from a {saved} board, this miner has a {now}");
+    }
+
+    let mut body = Map::new();
+    for (key, value) in &snapshot.settings {
+        if !SNAPSHOT_KEYS.contains(&key.as_str()) {
+            eprintln!("Warning: ignoring unknown setting '{key}'");
+            continue;
+        }
+        if let Some(setting) = VALIDATED.iter().find(|s| s.key() == key) {
+            let Some(n) = value.as_f64() else {
+                bail!("{key} must be a number in the snapshot");
+            };
+            if let Err(reason) = settings::validate(*setting, n, &info, force) {
+                bail!("Snapshot can't be applied: {reason}");
+            }
+        }
+        if !info.get(key).is_some_and(|cur| same(cur, value)) {
+            let current = info.get(key).map_or("?".to_string(), show);
+            println!("{key}: {current} -> {}", show(value));
+            body.insert(key.clone(), value.clone());
+        }
+    }
+
+    if body.is_empty() {
+        println!("The miner already matches {}.", path.display());
+        return Ok(());
+    }
+    if !yes && !confirm(&format!("Apply {} setting(s)?", body.len()))? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    patch_system(client, host, &Value::Object(body)).await?;
+    println!("Settings restored. Frequency and voltage changes take effect after a restart on some firmware.");
+    Ok(())
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json"))
+}
+
+/// Numbers compare by value, so 525 and 525.0 count as unchanged
+fn same(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(x), Some(y)) => x == y,
+        _ => a == b,
+    }
+}
+
+/// Strings without their JSON quotes
+fn show(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
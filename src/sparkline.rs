@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+use serde_json::Value;
+use crate::get_number;
+use crate::rules::Metric;
+
+/// Polls kept per metric, and the widest a sparkline gets
+pub const SAMPLES: usize = 60;
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// Drawn for a poll that didn't carry the reading
+const GAP: char = ' ';
+/// Columns taken by the label, the reading and the averages before it
+const TEXT_WIDTH: usize = 60;
+/// Narrower than this says nothing, so none is drawn
+const MIN_WIDTH: usize = 8;
+
+/// The metrics `watch` draws sparklines for
+const METRICS: [Metric; 3] = [Metric::Hashrate, Metric::Temp, Metric::Power];
+
+/// Render samples as block characters scaled between their min and max.
+/// A flat series sits mid-height; missing samples leave a gap.
+pub fn render(samples: &[Option<f64>]) -> String {
+    let values = samples.iter().flatten();
+    let min = values.clone().copied().fold(f64::INFINITY, f64::min);
+    let max = values.copied().fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+
+    samples
+        .iter()
+        .map(|v| match v {
+            None => GAP,
+            Some(_) if range <= f64::EPSILON => BARS[BARS.len() / 2],
+            Some(v) => BARS[((v - min) / range * (BARS.len() - 1) as f64).round() as usize],
+        })
+        .collect()
+}
+
+/// Sparkline width for a terminal `columns` wide: what is left after the
+/// reading, up to SAMPLES. Zero when that's too narrow to be useful.
+pub fn width(columns: Option<usize>) -> usize {
+    let width = columns.map_or(SAMPLES, |c| c.saturating_sub(TEXT_WIDTH).min(SAMPLES));
+    if width < MIN_WIDTH { 0 } else { width }
+}
+
+/// The terminal's width, if stdout is one
+pub fn terminal_columns() -> Option<usize> {
+    terminal_size::terminal_size().map(|(w, _)| usize::from(w.0))
+}
+
+/// Recent readings of METRICS, one sample per poll
+pub struct Sparklines {
+    series: Vec<(Metric, VecDeque<Option<f64>>)>,
+}
+
+impl Sparklines {
+    pub fn new() -> Self {
+        Sparklines { series: METRICS.iter().map(|m| (*m, VecDeque::with_capacity(SAMPLES))).collect() }
+    }
+
+    /// Record a poll; `None` (it failed) leaves a gap in every series
+    pub fn push(&mut self, info: Option<&Value>) {
+        for (metric, samples) in &mut self.series {
+            if samples.len() == SAMPLES {
+                samples.pop_front();
+            }
+            samples.push_back(info.and_then(|info| get_number(info, metric.key()?)));
+        }
+    }
+
+    /// The latest `width` samples of `metric`, if any of them has a reading
+    pub fn line(&self, metric: Metric, width: usize) -> Option<String> {
+        let (_, samples) = self.series.iter().find(|(m, _)| *m == metric)?;
+        let recent: Vec<Option<f64>> = samples.iter().skip(samples.len().saturating_sub(width)).copied().collect();
+        recent.iter().any(Option::is_some).then(|| render(&recent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn scaled_between_min_and_max() {
+        let ramp: Vec<Option<f64>> = (0..8).map(|i| Some(500.0 + f64::from(i))).collect();
+        assert_eq!(render(&ramp), "▁▂▃▄▅▆▇█");
+        assert_eq!(render(&[Some(60.0), Some(62.0), Some(61.0), Some(60.0)]), "▁█▅▁");
+        assert_eq!(render(&[Some(15.2), Some(15.2), Some(15.2)]), "▅▅▅");
+        assert_eq!(render(&[Some(1.0), None, Some(3.0), None]), "▁ █ ");
+        assert_eq!(render(&[None, Some(7.0)]), " ▅");
+        assert_eq!(render(&[]), "");
+    }
+
+    #[test]
+    fn width_follows_the_terminal() {
+        assert_eq!(width(None), SAMPLES);
+        assert_eq!(width(Some(200)), SAMPLES);
+        assert_eq!(width(Some(100)), 40);
+        assert_eq!(width(Some(65)), 0);
+    }
+
+    #[test]
+    fn keeps_the_latest_samples_per_metric() {
+        let mut sparks = Sparklines::new();
+        for hash in [500.0, 510.0, 520.0] {
+            sparks.push(Some(&json!({"hashRate": hash, "temp": 60.0})));
+        }
+        sparks.push(None);
+        assert_eq!(sparks.line(Metric::Hashrate, 60).unwrap(), "▁▅█ ");
+        // Scaled within what is shown: one reading is a flat line
+        assert_eq!(sparks.line(Metric::Hashrate, 2).unwrap(), "▅ ");
+        assert_eq!(sparks.line(Metric::Temp, 60).unwrap(), "▅▅▅ ");
+        assert_eq!(sparks.line(Metric::Power, 60), None);
+        assert_eq!(sparks.line(Metric::VrTemp, 60), None);
+
+        for _ in 0..SAMPLES {
+            sparks.push(Some(&json!({"hashRate": 500.0})));
+        }
+        assert_eq!(sparks.line(Metric::Hashrate, SAMPLES).unwrap(), "▅".repeat(SAMPLES));
+    }
+}
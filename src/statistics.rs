@@ -0,0 +1,314 @@
+use std::time::Duration;
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use reqwest::StatusCode;
+use serde_json::{Map, Value};
+use crate::client::Target;
+use crate::error::BitaxeError;
+use crate::logger::csv_field;
+use crate::retry::{self, Policy};
+use crate::rules::short_duration;
+use crate::temperature;
+use crate::{check_response, fetch_info, get_str, output};
+
+const STATISTICS_PATH: &str = "/api/system/statistics";
+/// The reduced form the AxeOS dashboard charts use
+const DASHBOARD_PATH: &str = "/api/system/statistics/dashboard";
+/// Column order of the dashboard form, which comes without labels
+const DASHBOARD_COLUMNS: [&str; 4] = ["hashrate", "temperature", "power", "timestamp"];
+
+/// `stats`: the sample history kept on the miner itself
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    /// Print every sample as CSV instead of a summary
+    #[arg(long, conflicts_with = "json")]
+    csv: bool,
+
+    /// Print every sample as a JSON array instead of a summary
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// One column of samples; `None` where a sample has no number
+#[derive(Debug, PartialEq)]
+struct Series {
+    name: String,
+    values: Vec<Option<f64>>,
+}
+
+/// Statistics in one shape, whichever layout the firmware sent
+#[derive(Debug, PartialEq)]
+struct Samples {
+    /// Milliseconds since the miner booted, per sample (empty if not sent)
+    timestamps: Vec<Option<f64>>,
+    series: Vec<Series>,
+    /// The miner's clock when it answered, on the same scale
+    now: Option<f64>,
+}
+
+impl Samples {
+    fn len(&self) -> usize {
+        self.series.first().map_or(self.timestamps.len(), |s| s.values.len())
+    }
+
+    /// Oldest to newest sample, and newest to the miner's clock
+    fn span(&self) -> Option<(Duration, Option<Duration>)> {
+        let first = self.timestamps.iter().flatten().next()?;
+        let last = self.timestamps.iter().flatten().next_back()?;
+        let age = self.now.map(|now| millis(now - last));
+        Some((millis(last - first), age))
+    }
+}
+
+fn millis(ms: f64) -> Duration {
+    Duration::from_secs_f64(ms.max(0.0) / 1000.0)
+}
+
+pub async fn run(args: &StatsArgs, target: &Target) -> Result<()> {
+    let samples = parse(&fetch(target).await?)?;
+    if args.csv {
+        print!("{}", csv(&samples));
+    } else if args.json {
+        println!("{}", output::json(&rows_json(&samples))?);
+    } else {
+        print_summary(&samples);
+    }
+    Ok(())
+}
+
+/// The full endpoint, falling back to the dashboard one. Firmware with
+/// neither is named in the error.
+async fn fetch(target: &Target) -> Result<Value> {
+    let (client, host) = (&target.client, &target.host);
+    for path in [STATISTICS_PATH, DASHBOARD_PATH] {
+        let url = format!("{host}{path}");
+        let resp = retry::send(host, Policy::Idempotent, || client.get(&url))
+            .await
+            .map_err(|e| BitaxeError::retried(host, e))?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            tracing::debug!(host, path, "statistics endpoint not found");
+            continue;
+        }
+        let resp = check_response(resp, "Statistics").await?;
+        return resp.json().await.context("Statistics are not valid JSON");
+    }
+    let version = fetch_info(client, host)
+        .await
+        .ok()
+        .and_then(|info| get_str(&info, "version").map(str::to_string))
+        .unwrap_or_else(|| "unknown".to_string());
+    bail!("On-device statistics are not supported by firmware {version}");
+}
+
+/// Firmware has sent `statistics` as rows under `labels`, as unlabeled
+/// dashboard rows, as an array of objects, and as an object of columns
+fn parse(body: &Value) -> Result<Samples> {
+    let stats = body.get("statistics").unwrap_or(body);
+    let mut columns: Vec<(String, Vec<Option<f64>>)> = match stats {
+        Value::Array(rows) if rows.iter().all(Value::is_object) => {
+            let mut names: Vec<&String> = Vec::new();
+            for row in rows.iter().filter_map(Value::as_object) {
+                names.extend(row.keys().filter(|k| !names.contains(k)).collect::<Vec<_>>());
+            }
+            names.into_iter().map(|name| (name.clone(), rows.iter().map(|r| number(r.get(name))).collect())).collect()
+        }
+        Value::Array(rows) => {
+            let labels: Vec<String> = match body.get("labels").and_then(Value::as_array) {
+                Some(labels) => labels
+                    .iter()
+                    .map(|l| l.as_str().map(str::to_string).context("Statistics labels aren't strings"))
+                    .collect::<Result<_>>()?,
+                None => DASHBOARD_COLUMNS.map(str::to_string).to_vec(),
+            };
+            for row in rows {
+                let width = row.as_array().context("Statistics mix rows and other values")?.len();
+                if width != labels.len() {
+                    let names = labels.join(", ");
+                    bail!("A statistics sample has {width} values for {} columns ({names})", labels.len());
+                }
+            }
+            let column = |i: usize| rows.iter().map(|r| number(r.get(i))).collect();
+            labels.into_iter().enumerate().map(|(i, name)| (name, column(i))).collect()
+        }
+        Value::Object(map) => columns_of(map)?,
+        _ => bail!("Unrecognized statistics layout (expected rows or columns of samples)"),
+    };
+
+    let timestamps = match columns.iter().position(|(name, _)| name == "timestamp") {
+        Some(i) => columns.remove(i).1,
+        None => Vec::new(),
+    };
+    let series = columns.into_iter().map(|(name, values)| Series { name, values }).collect();
+    Ok(Samples { timestamps, series, now: number(body.get("currentTimestamp")) })
+}
+
+fn columns_of(map: &Map<String, Value>) -> Result<Vec<(String, Vec<Option<f64>>)>> {
+    let columns: Vec<(String, Vec<Option<f64>>)> = map
+        .iter()
+        .filter_map(|(name, v)| Some((name.clone(), v.as_array()?.iter().map(|x| number(Some(x))).collect())))
+        .collect();
+    if let Some((name, len)) = columns.first().map(|(n, v)| (n, v.len()))
+        && let Some((other, values)) = columns.iter().find(|(_, v)| v.len() != len)
+    {
+        bail!("Statistics columns differ in length ({name}: {len}, {other}: {})", values.len());
+    }
+    Ok(columns)
+}
+
+fn number(v: Option<&Value>) -> Option<f64> {
+    v?.as_f64()
+}
+
+#[derive(Debug, PartialEq)]
+struct Summary {
+    min: f64,
+    avg: f64,
+    max: f64,
+}
+
+/// `None` when the series has no numbers at all
+fn summarize(values: &[Option<f64>]) -> Option<Summary> {
+    let numbers: Vec<f64> = values.iter().flatten().copied().collect();
+    if numbers.is_empty() {
+        return None;
+    }
+    let min = numbers.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    Some(Summary { min, avg: numbers.iter().sum::<f64>() / numbers.len() as f64, max })
+}
+
+/// For the columns AxeOS is known to send
+fn unit(name: &str) -> &'static str {
+    let name = name.to_ascii_lowercase();
+    if name.starts_with("hashrate") {
+        "GH/s"
+    } else if name.contains("temp") {
+        temperature::unit().symbol()
+    } else if name == "power" {
+        "W"
+    } else {
+        ""
+    }
+}
+
+fn print_summary(samples: &Samples) {
+    let count = samples.len();
+    if count == 0 {
+        println!("The miner hasn't stored any samples yet");
+        return;
+    }
+    let mut heading = format!("{count} samples");
+    if let Some((span, age)) = samples.span() {
+        heading += &format!(" over {}", short_duration(span));
+        if let Some(age) = age {
+            heading += &format!(" (latest {} ago)", short_duration(age));
+        }
+    }
+    println!("{heading}");
+
+    let width = samples.series.iter().map(|s| s.name.len()).max().unwrap_or(0).max("SERIES".len());
+    println!("{:<width$}  {:>10}  {:>10}  {:>10}", "SERIES", "MIN", "AVG", "MAX");
+    for series in &samples.series {
+        // The miner stores °C; only the summary follows --temp-unit
+        let values: Vec<Option<f64>> = if series.name.to_ascii_lowercase().contains("temp") {
+            series.values.iter().map(|v| v.map(|c| temperature::unit().of_celsius(c))).collect()
+        } else {
+            series.values.clone()
+        };
+        match summarize(&values) {
+            Some(Summary { min, avg, max }) => {
+                let unit = unit(&series.name);
+                println!("{:<width$}  {min:>10.2}  {avg:>10.2}  {max:>10.2}  {unit}", series.name);
+            }
+            None => println!("{:<width$}  {:>10}  {:>10}  {:>10}", series.name, "-", "-", "-"),
+        }
+    }
+}
+
+/// One row per sample; `timestamp_ms` leads when the firmware sent it
+fn csv(samples: &Samples) -> String {
+    let with_time = !samples.timestamps.is_empty();
+    let mut header: Vec<String> = samples.series.iter().map(|s| csv_field(&s.name)).collect();
+    if with_time {
+        header.insert(0, "timestamp_ms".to_string());
+    }
+    let mut out = header.join(",") + "\n";
+    let cell = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+    for i in 0..samples.len() {
+        let mut row: Vec<String> = samples.series.iter().map(|s| cell(s.values[i])).collect();
+        if with_time {
+            row.insert(0, cell(samples.timestamps[i]));
+        }
+        out += &(row.join(",") + "\n");
+    }
+    out
+}
+
+fn rows_json(samples: &Samples) -> Value {
+    let rows = (0..samples.len())
+        .map(|i| {
+            let mut row = Map::new();
+            if let Some(ts) = samples.timestamps.get(i) {
+                row.insert("timestamp_ms".to_string(), (*ts).into());
+            }
+            for series in &samples.series {
+                row.insert(series.name.clone(), series.values[i].into());
+            }
+            Value::Object(row)
+        })
+        .collect();
+    Value::Array(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn every_layout_reads_the_same() {
+        let labeled = json!({
+            "currentTimestamp": 70_000,
+            "labels": ["hashrate", "asicTemp", "timestamp"],
+            "statistics": [[480.5, 58.0, 10_000], [520.5, 62.0, 40_000]],
+        });
+        let objects = json!({
+            "currentTimestamp": 70_000,
+            "statistics": [{"hashrate": 480.5, "asicTemp": 58.0, "timestamp": 10_000},
+                           {"hashrate": 520.5, "asicTemp": 62.0, "timestamp": 40_000}],
+        });
+        let columns = json!({
+            "currentTimestamp": 70_000,
+            "statistics": {"asicTemp": [58.0, 62.0], "hashrate": [480.5, 520.5], "timestamp": [10_000, 40_000]},
+        });
+        let expected = parse(&labeled).unwrap();
+        assert_eq!(expected.len(), 2);
+        assert_eq!(expected.span(), Some((Duration::from_secs(30), Some(Duration::from_secs(30)))));
+        let sorted = |mut s: Samples| {
+            s.series.sort_by(|a, b| a.name.cmp(&b.name));
+            s
+        };
+        assert_eq!(sorted(parse(&objects).unwrap()), sorted(parse(&labeled).unwrap()));
+        assert_eq!(sorted(parse(&columns).unwrap()), sorted(parse(&labeled).unwrap()));
+
+        let dashboard = parse(&json!({"statistics": [[500.0, 60.0, 15.2, 1000]]})).unwrap();
+        assert_eq!(dashboard.series.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), DASHBOARD_COLUMNS[..3]);
+        assert!(parse(&json!({"statistics": [[500.0, 60.0]]})).is_err());
+        assert!(parse(&json!({"statistics": {"hashrate": [1.0, 2.0], "timestamp": [1]}})).is_err());
+        assert!(parse(&json!({"statistics": "soon"})).is_err());
+    }
+
+    #[test]
+    fn summary_and_dumps() {
+        let samples = parse(&json!({
+            "labels": ["hashrate", "power", "timestamp"],
+            "statistics": [[480.0, null, 1000], [520.0, 15.5, 2000], [500.0, 14.5, 3000]],
+        }))
+        .unwrap();
+        assert_eq!(summarize(&samples.series[0].values), Some(Summary { min: 480.0, avg: 500.0, max: 520.0 }));
+        assert_eq!(summarize(&samples.series[1].values).unwrap().avg, 15.0);
+        assert_eq!(summarize(&[None]), None);
+        assert_eq!(csv(&samples), "timestamp_ms,hashrate,power\n1000,480,\n2000,520,15.5\n3000,500,14.5\n");
+        assert_eq!(rows_json(&samples)[0], json!({"timestamp_ms": 1000.0, "hashrate": 480.0, "power": null}));
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::UdpSocket;
+use anyhow::{Context, Result, bail};
+use clap::{Args, ValueEnum};
+use serde::Deserialize;
+use serde_json::Value;
+use crate::client::Target;
+use crate::config_file::{AppConfig, StatsdConfig};
+use crate::fleet::{FleetOpts, fetch_fleet};
+use crate::get_number;
+
+const DEFAULT_ADDR: &str = "127.0.0.1:8125";
+const DEFAULT_PREFIX: &str = "bitaxe";
+
+/// Sent as gauges, under these names
+const GAUGES: &[(&str, &str)] = &[
+    ("hashRate", "hashrate"),
+    ("temp", "temp"),
+    ("vrTemp", "vr_temp"),
+    ("power", "power"),
+    ("wifiRSSI", "wifi_rssi"),
+];
+/// Sent as counters of what was added since the previous poll
+const COUNTERS: &[(&str, &str)] = &[("sharesAccepted", "shares_accepted"), ("sharesRejected", "shares_rejected")];
+
+/// Where the device name goes in each metric
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagFormat {
+    /// A name segment: bitaxe.gamma.hashrate
+    #[default]
+    Prefix,
+    /// A DogStatsD tag: bitaxe.hashrate ... |#device:gamma
+    Datadog,
+}
+
+/// `push statsd`: where to send. Flags override the [statsd] config table.
+#[derive(Args, Debug)]
+pub struct StatsdArgs {
+    /// StatsD host:port (default: `addr` in [statsd], then 127.0.0.1:8125)
+    #[arg(long)]
+    addr: Option<String>,
+
+    /// First segment of every metric name (default: `prefix` in [statsd], then "bitaxe")
+    #[arg(long)]
+    prefix: Option<String>,
+
+    /// Device name as a name segment or as DogStatsD tags
+    /// (default: `tag_format` in [statsd], then prefix)
+    #[arg(long, value_enum)]
+    tag_format: Option<TagFormat>,
+
+    #[command(flatten)]
+    pub opts: FleetOpts,
+}
+
+/// Formats polls as StatsD lines and sends one datagram per device
+pub struct Emitter {
+    socket: UdpSocket,
+    addr: String,
+    prefix: String,
+    format: TagFormat,
+    /// Share counters per device at the previous poll
+    last: HashMap<String, Vec<Option<f64>>>,
+    /// Datagrams that couldn't be sent since start
+    pub failures: u64,
+}
+
+impl Emitter {
+    /// Flags first, then [statsd], then the defaults
+    pub fn connect(
+        conf: Option<&StatsdConfig>,
+        addr: Option<&str>,
+        prefix: Option<&str>,
+        format: Option<TagFormat>,
+    ) -> Result<Self> {
+        let addr = addr.or(conf.and_then(|c| c.addr.as_deref())).unwrap_or(DEFAULT_ADDR).to_string();
+        let prefix = prefix.or(conf.and_then(|c| c.prefix.as_deref())).unwrap_or(DEFAULT_PREFIX).to_string();
+        let format = format.or(conf.and_then(|c| c.tag_format)).unwrap_or_default();
+        let socket = UdpSocket::bind(if addr.starts_with('[') { "[::]:0" } else { "0.0.0.0:0" })
+            .context("Couldn't open a UDP socket")?;
+        // Connected, so a closed port shows up as an error on the next send
+        socket.connect(&addr).with_context(|| format!("Couldn't resolve StatsD address {addr}"))?;
+        Ok(Emitter { socket, addr, prefix, format, last: HashMap::new(), failures: 0 })
+    }
+
+    /// The lines for one poll of `device`; share counters only once there
+    /// is an earlier poll to count from
+    pub fn lines(&mut self, device: &str, info: &Value) -> Vec<String> {
+        let counts: Vec<Option<f64>> = COUNTERS.iter().map(|(key, _)| get_number(info, key)).collect();
+        let before = self.last.insert(device.to_string(), counts.clone());
+        let metric = Metric { prefix: &self.prefix, device, format: self.format };
+
+        let mut lines: Vec<String> = Vec::new();
+        for (key, name) in GAUGES {
+            if let Some(v) = get_number(info, key) {
+                lines.extend(metric.gauge(name, v));
+            }
+        }
+        let Some(before) = before else { return lines };
+        for (((_, name), now), then) in COUNTERS.iter().zip(&counts).zip(before) {
+            if let (Some(now), Some(then)) = (now, then) {
+                // A lower count means the miner restarted; it counts from zero again
+                let delta = if *now < then { *now } else { now - then };
+                lines.push(metric.line(name, delta, "c"));
+            }
+        }
+        lines
+    }
+
+    /// Send one datagram; a failure is counted and returned
+    pub fn send(&mut self, lines: &[String]) -> io::Result<()> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let sent = self.socket.send(lines.join("\n").as_bytes()).map(|_| ());
+        if sent.is_err() {
+            self.failures += 1;
+        }
+        sent
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+}
+
+/// How one device's metrics are named
+struct Metric<'a> {
+    prefix: &'a str,
+    device: &'a str,
+    format: TagFormat,
+}
+
+impl Metric<'_> {
+    fn line(&self, name: &str, value: f64, kind: &str) -> String {
+        let device = sanitize(self.device);
+        let mut segments: Vec<&str> = vec![self.prefix];
+        if self.format == TagFormat::Prefix {
+            segments.push(&device);
+        }
+        segments.push(name);
+        let path = segments.into_iter().filter(|s| !s.is_empty()).collect::<Vec<_>>().join(".");
+        match self.format {
+            TagFormat::Prefix => format!("{path}:{value}|{kind}"),
+            TagFormat::Datadog => format!("{path}:{value}|{kind}|#device:{device}"),
+        }
+    }
+
+    /// Plain StatsD reads a signed gauge as a change (`-55|g` lowers it by
+    /// 55), so a negative reading is sent as a reset to 0 first
+    fn gauge(&self, name: &str, value: f64) -> Vec<String> {
+        if value < 0.0 && self.format == TagFormat::Prefix {
+            vec![self.line(name, 0.0, "g"), self.line(name, value, "g")]
+        } else {
+            vec![self.line(name, value, "g")]
+        }
+    }
+}
+
+/// Dots would add name segments, and `:|@#,` are part of the wire format
+/// (Graphite paths follow the same rules)
+pub fn sanitize(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect()
+}
+
+/// Poll every target once and send its gauges. Share counters need two
+/// polls, so they come from the `daemon` sink only.
+pub fn push(args: &StatsdArgs, targets: &[Target], cfg: &AppConfig) -> Result<()> {
+    let (addr, prefix) = (args.addr.as_deref(), args.prefix.as_deref());
+    let mut statsd = Emitter::connect(cfg.statsd.as_ref(), addr, prefix, args.tag_format)?;
+    let mut sent = 0;
+    for result in fetch_fleet(targets, &args.opts) {
+        match &result.info {
+            Ok(info) => {
+                let lines = statsd.lines(&result.name, info);
+                statsd.send(&lines).with_context(|| format!("Couldn't send to StatsD at {}", statsd.addr()))?;
+                sent += lines.len();
+            }
+            Err(e) => eprintln!("Warning: {}: {e}", result.name),
+        }
+    }
+    if sent == 0 {
+        bail!("No data to push: none of the {} device(s) answered", targets.len());
+    }
+    println!("Sent {sent} line(s) to {}", statsd.addr());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn emitter(format: TagFormat) -> Emitter {
+        Emitter::connect(None, None, None, Some(format)).unwrap()
+    }
+
+    #[test]
+    fn wire_format_per_tag_style() {
+        let info = json!({"hashRate": 512.5, "temp": 61, "wifiRSSI": -55, "sharesAccepted": 100, "hostname": "x"});
+        assert_eq!(
+            emitter(TagFormat::Prefix).lines("gamma", &info),
+            [
+                "bitaxe.gamma.hashrate:512.5|g",
+                "bitaxe.gamma.temp:61|g",
+                "bitaxe.gamma.wifi_rssi:0|g",
+                "bitaxe.gamma.wifi_rssi:-55|g"
+            ]
+        );
+        assert_eq!(
+            emitter(TagFormat::Datadog).lines("10.0.0.2", &info),
+            [
+                "bitaxe.hashrate:512.5|g|#device:10_0_0_2",
+                "bitaxe.temp:61|g|#device:10_0_0_2",
+                "bitaxe.wifi_rssi:-55|g|#device:10_0_0_2"
+            ]
+        );
+        let bare = Metric { prefix: "", device: "my.rig", format: TagFormat::Prefix };
+        assert_eq!(bare.line("power", 14.2, "g"), "my_rig.power:14.2|g");
+    }
+
+    #[test]
+    fn share_counters_are_deltas() {
+        let mut statsd = emitter(TagFormat::Prefix);
+        let counters = |lines: Vec<String>| lines.into_iter().filter(|l| l.ends_with("|c")).collect::<Vec<_>>();
+        assert!(counters(statsd.lines("gamma", &json!({"sharesAccepted": 100, "sharesRejected": 2}))).is_empty());
+        assert_eq!(
+            counters(statsd.lines("gamma", &json!({"sharesAccepted": 104, "sharesRejected": 2}))),
+            ["bitaxe.gamma.shares_accepted:4|c", "bitaxe.gamma.shares_rejected:0|c"]
+        );
+        // Rebooted: the new count is what was added
+        assert_eq!(
+            counters(statsd.lines("gamma", &json!({"sharesAccepted": 3}))),
+            ["bitaxe.gamma.shares_accepted:3|c"]
+        );
+        assert!(counters(statsd.lines("ultra", &json!({"sharesAccepted": 50}))).is_empty());
+    }
+}
@@ -0,0 +1,608 @@
+use std::time::Duration;
+use bitaxe_api::{Difficulty, SystemInfo};
+use clap::Args;
+use serde_json::Value;
+use crate::client::{Target, bare_host};
+use crate::color::{BOLD, GREEN, RED, YELLOW, paint};
+use crate::config_file::AppConfig;
+use crate::influx::FIELDS;
+use crate::output::tsv_field;
+use crate::rules::Metric;
+use crate::shares::Stats;
+use crate::temperature;
+use crate::threshold::{Level, ThresholdArgs, Verdict};
+use crate::{difficulty, get_str};
+
+/// Core/VR temperature (°C) above which readings are highlighted as a warning
+pub const TEMP_WARN: f64 = 65.0;
+/// Core/VR temperature (°C) above which readings are highlighted as critical
+pub const TEMP_CRIT: f64 = 70.0;
+
+/// Labeled groups of lines in the status block, in print order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Section {
+    Identity,
+    Hashing,
+    Thermals,
+    Power,
+    Frequency,
+    Network,
+}
+
+impl Section {
+    pub const ALL: [Section; 6] = [
+        Section::Identity,
+        Section::Hashing,
+        Section::Thermals,
+        Section::Power,
+        Section::Frequency,
+        Section::Network,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Section::Identity => "identity",
+            Section::Hashing => "hashing",
+            Section::Thermals => "thermals",
+            Section::Power => "power",
+            Section::Frequency => "frequency",
+            Section::Network => "network",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Section> {
+        Section::ALL.into_iter().find(|s| s.name().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+/// --include/--exclude flags selecting which sections are printed
+#[derive(Args, Debug, Clone, Default)]
+pub struct SectionArgs {
+    /// Only print these sections (identity,hashing,thermals,power,frequency,network)
+    #[arg(long, value_delimiter = ',', value_name = "SECTIONS")]
+    pub include: Vec<String>,
+
+    /// Skip these sections
+    #[arg(long, value_delimiter = ',', value_name = "SECTIONS")]
+    pub exclude: Vec<String>,
+}
+
+impl SectionArgs {
+    /// Resolve the flags to the sections to print. Unknown names are
+    /// reported on stderr and otherwise ignored.
+    pub fn sections(&self) -> Vec<Section> {
+        let include = parse_names(&self.include);
+        let exclude = parse_names(&self.exclude);
+
+        Section::ALL
+            .into_iter()
+            .filter(|s| self.include.is_empty() || include.contains(s))
+            .filter(|s| !exclude.contains(s))
+            .collect()
+    }
+}
+
+fn parse_names(names: &[String]) -> Vec<Section> {
+    names
+        .iter()
+        .filter_map(|n| {
+            let section = Section::parse(n);
+            if section.is_none() {
+                let known: Vec<&str> = Section::ALL.iter().map(|s| s.name()).collect();
+                eprintln!("Warning: unknown section '{n}' (known: {})", known.join(", "));
+            }
+            section
+        })
+        .collect()
+}
+
+/// How the status block is rendered
+pub struct StatusView {
+    pub colorize: bool,
+    pub sections: Vec<Section>,
+    /// Decimal places for measured values (hashrate, temps, power, volts);
+    /// None uses `decimals`
+    pub precision: Option<usize>,
+    /// Per-unit decimal places from [display]
+    pub decimals: Decimals,
+    /// --target difficulty for best-diff progress and ETA
+    pub target: Option<f64>,
+    /// Rolling hashrate average windows (`watch --avg`)
+    pub averages: Vec<Duration>,
+    /// --warn/--crit expressions to flag readings with
+    pub thresholds: ThresholdArgs,
+    /// Sparklines next to hashrate, core temp and power (`watch`)
+    pub sparklines: bool,
+}
+
+/// Decimal places per kind of reading; the defaults are what `status`
+/// has always printed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Decimals {
+    pub hashrate: usize,
+    pub temp: usize,
+    pub power: usize,
+    pub voltage: usize,
+}
+
+impl Default for Decimals {
+    fn default() -> Self {
+        Decimals { hashrate: 2, temp: 1, power: 2, voltage: 2 }
+    }
+}
+
+impl Decimals {
+    /// The defaults, with whatever [display] sets
+    pub fn from_config(cfg: &AppConfig) -> Self {
+        let mut decimals = Decimals::default();
+        if let Some(display) = &cfg.display {
+            decimals.hashrate = display.hashrate_decimals.unwrap_or(decimals.hashrate);
+            decimals.temp = display.temp_decimals.unwrap_or(decimals.temp);
+            decimals.power = display.power_decimals.unwrap_or(decimals.power);
+            decimals.voltage = display.voltage_decimals.unwrap_or(decimals.voltage);
+        }
+        decimals
+    }
+}
+
+/// What `watch` knows from earlier polls, appended to the reading lines
+#[derive(Default)]
+pub struct Trends {
+    /// Averages, sparkline and staleness after the hashrate
+    pub hashrate: Option<String>,
+    pub temp: Option<String>,
+    pub power: Option<String>,
+    /// Adds a last-share line
+    pub shares: Option<Stats>,
+}
+
+/// `text`, then `trend` if there is one
+fn with_trend(text: String, trend: Option<&str>) -> String {
+    match trend {
+        Some(trend) => format!("{text}  {trend}"),
+        None => text,
+    }
+}
+
+impl StatusView {
+    fn num(&self, value: f64, default_decimals: usize) -> String {
+        format!("{:.*}", self.precision.unwrap_or(default_decimals), value)
+    }
+
+    /// `text` for a reading of `metric`: in red/yellow with the expression
+    /// after it when a --crit/--warn one holds, otherwise in `color` (if any)
+    fn flag(&self, metric: Metric, info: &Value, text: &str, color: Option<&str>) -> String {
+        match self.thresholds.worst(metric, info) {
+            Some(breach) => {
+                let color = if breach.level == Level::Crit { RED } else { YELLOW };
+                paint(self.colorize, color, &format!("{text}  [{} {}]", breach.level.name(), breach.expr))
+            }
+            None => color.map_or_else(|| text.to_string(), |color| paint(self.colorize, color, text)),
+        }
+    }
+}
+
+/// The metrics each section has a line for, so the ones it doesn't can be
+/// listed after the block when they breach a threshold
+fn section_metrics(section: Section) -> &'static [Metric] {
+    match section {
+        Section::Identity => &[],
+        Section::Hashing => &[Metric::Hashrate, Metric::SharesRejected],
+        Section::Thermals => &[Metric::Temp, Metric::VrTemp],
+        Section::Power => &[Metric::Power, Metric::CoreVoltage],
+        Section::Frequency => &[Metric::Frequency],
+        Section::Network => &[Metric::WifiRssi],
+    }
+}
+
+/// Print the status block, with `trends` from earlier polls in watch mode.
+/// Thresholds are checked against `raw`, the answer as the miner sent it.
+pub fn print_info(raw: &Value, target: &Target, view: &StatusView, trends: &Trends) {
+    let info = SystemInfo::from(raw);
+    println!("{}", paint(view.colorize, BOLD, &title(&info, target)));
+
+    for section in &view.sections {
+        print_section(*section, &info, raw, view, trends);
+    }
+    // Breached metrics that no printed line carries get one of their own;
+    // critical breaches come first, so that is the worst per metric
+    let mut listed = Vec::new();
+    for breach in view.thresholds.breaches(raw) {
+        let metric = breach.expr.metric;
+        if listed.contains(&metric) || view.sections.iter().any(|s| section_metrics(*s).contains(&metric)) {
+            continue;
+        }
+        listed.push(metric);
+        let unit = temperature::unit().metric_unit(metric);
+        let text = format!("{}{unit}", (breach.value * 100.0).round() / 100.0);
+        println!("{:<16}: {}", metric.label(), view.flag(metric, raw, &text, None));
+    }
+
+    let verdict = verdict(&info, raw, &view.thresholds);
+    let color = match verdict.level {
+        None => GREEN,
+        Some(Level::Warn) => YELLOW,
+        Some(Level::Crit) => RED,
+    };
+    println!("Status          : {}", paint(view.colorize, color, &verdict.to_string()));
+}
+
+/// The --warn/--crit verdict, plus the temperature limits readings are
+/// highlighted at (`TEMP_WARN`, `TEMP_CRIT`) for sensors no expression is about
+pub fn verdict(info: &SystemInfo, raw: &Value, thresholds: &ThresholdArgs) -> Verdict {
+    let mut verdict = thresholds.verdict(raw);
+    for (temp, sensor, metric) in [(info.temp, "", Metric::Temp), (info.vr_temp, "VR ", Metric::VrTemp)] {
+        if thresholds.names(metric) {
+            continue;
+        }
+        match temp {
+            Some(t) if t >= TEMP_CRIT => verdict.note(Level::Crit, format!("{sensor}overheat")),
+            Some(t) if t >= TEMP_WARN => verdict.note(Level::Warn, format!("{sensor}temp high")),
+            _ => {}
+        }
+    }
+    verdict
+}
+
+/// `=== gamma (bitaxe-gamma @ 192.168.1.50) ===` for configured devices,
+/// the generic title for bare hosts
+fn title(info: &SystemInfo, target: &Target) -> String {
+    let Some(alias) = &target.alias else {
+        return "=== Bitaxe System Info ===".to_string();
+    };
+    let host = bare_host(&target.host);
+    match &info.hostname {
+        Some(hostname) => format!("=== {alias} ({hostname} @ {host}) ==="),
+        None => format!("=== {alias} ({host}) ==="),
+    }
+}
+
+fn print_section(section: Section, info: &SystemInfo, raw: &Value, view: &StatusView, trends: &Trends) {
+    match section {
+        Section::Identity => {
+            if let Some(hostname) = &info.hostname {
+                println!("Hostname        : {hostname}");
+            }
+        }
+
+        Section::Hashing => {
+            if let Some(hash) = info.hash_rate {
+                let text = format!("{} GH/s", view.num(hash, view.decimals.hashrate));
+                let text = view.flag(Metric::Hashrate, raw, &text, None);
+                println!("Hashrate        : {}", with_trend(text, trends.hashrate.as_deref()));
+                if let Some(expected) = expected_hashrate(info) {
+                    println!("Expected        : ~{expected:.0} GH/s ({:.0}% of nominal)", hash / expected * 100.0);
+                }
+            }
+            let progress = |best: &Difficulty| match (view.target, best.value) {
+                (Some(target), Some(best)) => {
+                    format!(" ({} of {})", difficulty::format_progress(best, target), difficulty::format(target))
+                }
+                _ => String::new(),
+            };
+            if let Some(best) = &info.best_diff {
+                println!("Best Diff       : {}{}", best.shown, progress(best));
+            }
+            if let Some(best_session) = &info.best_session_diff {
+                println!("Best Session    : {}{}", best_session.shown, progress(best_session));
+            }
+            if let Some(target) = view.target
+                && let Some(hash) = info.hash_rate
+                && let Some(secs) = difficulty::expected_secs(target, hash)
+            {
+                println!(
+                    "Target ETA      : ~{} for a {} share at {} GH/s",
+                    difficulty::format_eta(secs),
+                    difficulty::format(target),
+                    view.num(hash, 0)
+                );
+            }
+            if let Some(accepted) = info.shares_accepted {
+                println!("Shares Accepted : {accepted}");
+            }
+            if let Some(rejected) = info.shares_rejected {
+                let text = rejected.to_string();
+                println!("Shares Rejected : {}", view.flag(Metric::SharesRejected, raw, &text, None));
+            }
+            if let Some(shares) = trends.shares {
+                println!("Last Share      : {}", shares.describe());
+            }
+        }
+
+        Section::Thermals => {
+            let unit = temperature::unit();
+            if let Some(temp) = info.temp {
+                let text = format!("{} {}", view.num(unit.of_celsius(temp), view.decimals.temp), unit.symbol());
+                let text = view.flag(Metric::Temp, raw, &text, Some(temp_color(temp)));
+                println!("Core Temp       : {}", with_trend(text, trends.temp.as_deref()));
+            }
+            if let Some(vr) = info.vr_temp {
+                let text = format!("{} {}", view.num(unit.of_celsius(vr), view.decimals.temp), unit.symbol());
+                println!("VR Temp         : {}", view.flag(Metric::VrTemp, raw, &text, Some(temp_color(vr))));
+            }
+        }
+
+        // Input rail first, then the ASIC core rail, then efficiency
+        Section::Power => {
+            let rails = PowerRails::from_info(info);
+            if let Some((volts, millivolts)) = rails.input_volts.map(|v| (v, v * 1000.0)) {
+                println!("Input Voltage   : {} V ({:.0} mV)", view.num(volts, view.decimals.voltage), millivolts);
+            }
+            if let Some(watts) = rails.input_watts {
+                let text = format!("{} W", view.num(watts, view.decimals.power));
+                let text = view.flag(Metric::Power, raw, &text, None);
+                println!("Input Power     : {}", with_trend(text, trends.power.as_deref()));
+            }
+            if let Some(mv) = rails.core_set_mv {
+                println!("Core V (set)    : {:.0} mV", mv);
+            }
+            if let Some(mv) = rails.core_actual_mv {
+                println!("Core V (actual) : {}", view.flag(Metric::CoreVoltage, raw, &format!("{mv:.0} mV"), None));
+            }
+            if let Some(watts) = rails.core_watts() {
+                let amps = rails.core_amps.unwrap_or(0.0);
+                println!("Core Power      : {} W ({} A)", view.num(watts, view.decimals.power), view.num(amps, 2));
+            }
+            if let Some(jth) = rails.efficiency(rails.input_watts) {
+                println!("Efficiency      : {} J/TH (input)", view.num(jth, 2));
+            }
+            if let Some(jth) = rails.efficiency(rails.core_watts()) {
+                println!("ASIC Efficiency : {} J/TH (core rail)", view.num(jth, 2));
+            }
+        }
+
+        Section::Frequency => {
+            if let Some(freq) = info.frequency {
+                println!("Frequency       : {}", view.flag(Metric::Frequency, raw, &format!("{freq:.0} MHz"), None));
+            }
+        }
+
+        Section::Network => {
+            if let Some(rssi) = info.wifi_rssi {
+                println!("WiFi RSSI       : {}", view.flag(Metric::WifiRssi, raw, &format!("{rssi} dBm"), None));
+            }
+            if let Some(status) = &info.wifi_status {
+                println!("WiFi Status     : {status}");
+            }
+        }
+    }
+}
+
+/// Terse summary for status bars: `512GH 61°C 14.2W 0.3%rej`. Fields the
+/// miner didn't report are left out.
+pub fn oneline(info: &SystemInfo) -> String {
+    let mut parts = Vec::new();
+    if let Some(hash) = info.hash_rate {
+        parts.push(format!("{hash:.0}GH"));
+    }
+    let unit = temperature::unit();
+    if let Some(temp) = info.temp {
+        parts.push(format!("{:.0}{}", unit.of_celsius(temp), unit.symbol()));
+    }
+    if let Some(power) = info.power {
+        parts.push(format!("{power:.1}W"));
+    }
+    if let (Some(accepted), Some(rejected)) = (info.shares_accepted, info.shares_rejected)
+        && accepted + rejected > 0
+    {
+        parts.push(format!("{:.1}%rej", rejected as f64 / (accepted + rejected) as f64 * 100.0));
+    }
+    parts.join(" ")
+}
+
+/// Text fields `--format env` prints besides the numeric ones in FIELDS
+const ENV_STRINGS: &[(&str, &str)] = &[("hostname", "hostname"), ("ASICModel", "asic_model"), ("version", "version")];
+
+/// `--format env`: `BITAXE_HASHRATE=512.34` lines to `eval` in a shell,
+/// named after the stable field names. Numbers are printed as the miner
+/// sent them, text is single-quoted, and absent fields are left out.
+pub fn env_block(info: &Value) -> String {
+    let numbers = FIELDS
+        .iter()
+        .filter_map(|(key, field)| Some((field, info.get(key).filter(|v| v.is_number())?.to_string())));
+    let strings = ENV_STRINGS.iter().filter_map(|(key, field)| Some((field, shell_quote(get_str(info, key)?))));
+    numbers.chain(strings).map(|(field, value)| format!("BITAXE_{}={value}\n", field.to_ascii_uppercase())).collect()
+}
+
+/// `--format tsv`: `hashrate<TAB>512.34` lines for `column -t` or `cut`,
+/// with the fields and values of `--format env` but text unquoted. With
+/// `device`, each line starts with a column naming it.
+pub fn tsv_block(info: &Value, device: Option<&str>) -> String {
+    let numbers = FIELDS
+        .iter()
+        .filter_map(|(key, field)| Some((*field, info.get(key).filter(|v| v.is_number())?.to_string())));
+    let strings = ENV_STRINGS.iter().filter_map(|(key, field)| Some((*field, tsv_field(get_str(info, key)?))));
+    let prefix = device.map(|d| format!("{}\t", tsv_field(d))).unwrap_or_default();
+    numbers.chain(strings).map(|(field, value)| format!("{prefix}{field}\t{value}\n")).collect()
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Nominal hashrate in GH/s for the configured frequency, the same estimate
+/// AxeOS shows as "expected hashrate":
+///
+///   frequency (MHz) × small cores per ASIC × ASICs / 1000
+///
+/// Each small core is assumed to try one nonce per clock cycle. Boards that
+/// don't report `asicCount` (older firmware) carry a single ASIC.
+pub fn expected_hashrate(info: &SystemInfo) -> Option<f64> {
+    let freq_mhz = info.frequency?;
+    let cores = info.small_core_count? as f64;
+    let asics = info.asic_count.unwrap_or(1) as f64;
+    let expected = freq_mhz * cores * asics / 1000.0;
+    (expected > 0.0).then_some(expected)
+}
+
+/// Power readings split by rail. AxeOS reports them as flat keys in mixed
+/// units; this keeps the PSU side and the ASIC core side apart.
+struct PowerRails {
+    /// Input (PSU) rail, from `voltage`
+    input_volts: Option<f64>,
+    /// Board power draw, from `power`
+    input_watts: Option<f64>,
+    /// Core rail target and measurement, from `coreVoltage` / `coreVoltageActual`
+    core_set_mv: Option<f64>,
+    core_actual_mv: Option<f64>,
+    /// Regulator output current, from `current` (mA)
+    core_amps: Option<f64>,
+    hashrate_ghs: Option<f64>,
+}
+
+impl PowerRails {
+    fn from_info(info: &SystemInfo) -> Self {
+        PowerRails {
+            input_volts: info.voltage.map(|raw| input_voltage(raw).0),
+            input_watts: info.power,
+            core_set_mv: info.core_voltage,
+            core_actual_mv: info.core_voltage_actual,
+            core_amps: info.current.map(|ma| ma / 1000.0),
+            hashrate_ghs: info.hash_rate,
+        }
+    }
+
+    /// ASIC-domain power: core voltage times regulator current. Boards
+    /// whose `current` sensor sits on the input side give a figure at or
+    /// above the input power; that isn't core power, so it is dropped.
+    fn core_watts(&self) -> Option<f64> {
+        let watts = self.core_actual_mv? / 1000.0 * self.core_amps?;
+        match self.input_watts {
+            Some(input) if watts >= input => None,
+            _ => (watts > 0.0).then_some(watts),
+        }
+    }
+
+    /// J/TH for the given power at the current hashrate
+    fn efficiency(&self, watts: Option<f64>) -> Option<f64> {
+        let hashrate = self.hashrate_ghs.filter(|h| *h > 0.0)?;
+        Some(watts? / (hashrate / 1000.0))
+    }
+}
+
+/// AxeOS reports `voltage` as the board's input (PSU) rail in millivolts,
+/// e.g. 5023 for a 5 V supply. A reading below 100 can't be millivolts
+/// for any supply a Bitaxe runs on, so it is taken as already in volts.
+/// Returns (volts, millivolts).
+pub fn input_voltage(raw: f64) -> (f64, f64) {
+    if raw.abs() < 100.0 {
+        (raw, raw * 1000.0)
+    } else {
+        (raw / 1000.0, raw)
+    }
+}
+
+fn temp_color(temp: f64) -> &'static str {
+    if temp >= TEMP_CRIT {
+        RED
+    } else if temp >= TEMP_WARN {
+        YELLOW
+    } else {
+        GREEN
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn oneline_summary() {
+        let info = json!({"hashRate": 512.34, "temp": 61.2, "power": 14.23, "sharesAccepted": 997, "sharesRejected": 3});
+        assert_eq!(oneline(&SystemInfo::from(&info)), "512GH 61°C 14.2W 0.3%rej");
+    }
+
+    #[test]
+    fn verdict_includes_the_temperature_limits() {
+        let none = ThresholdArgs::default();
+        let verdict = |raw: Value| verdict(&SystemInfo::from(&raw), &raw, &none).to_string();
+        assert_eq!(verdict(json!({"temp": 61.0, "vrTemp": 55.0})), "HEALTHY");
+        assert_eq!(verdict(json!({"temp": 61.0, "vrTemp": 66.0})), "WARNING (VR temp high)");
+        assert_eq!(verdict(json!({"temp": 71.0, "vrTemp": 66.0})), "CRITICAL (overheat)");
+        assert_eq!(verdict(json!({"temp": 70.0, "vrTemp": 72.5})), "CRITICAL (overheat, VR overheat)");
+        // An expression on a sensor replaces its built-in limits
+        let own = ThresholdArgs { warn: Vec::new(), crit: vec![crate::rules::Expr::parse("temp>80").unwrap()] };
+        let raw = json!({"temp": 72.0, "vrTemp": 66.0});
+        assert_eq!(super::verdict(&SystemInfo::from(&raw), &raw, &own).to_string(), "WARNING (VR temp high)");
+    }
+
+    #[test]
+    fn display_table_overrides_default_decimals() {
+        let cfg: AppConfig = toml::from_str("[display]\nhashrate_decimals = 1\nvoltage_decimals = 3").unwrap();
+        let decimals = Decimals::from_config(&cfg);
+        assert_eq!(decimals, Decimals { hashrate: 1, voltage: 3, ..Decimals::default() });
+        let defaults = Decimals { hashrate: 2, temp: 1, power: 2, voltage: 2 };
+        assert_eq!(Decimals::from_config(&AppConfig::default()), defaults);
+
+        let mut view = StatusView {
+            colorize: false,
+            sections: Section::ALL.to_vec(),
+            precision: None,
+            decimals,
+            target: None,
+            averages: Vec::new(),
+            thresholds: ThresholdArgs::default(),
+            sparklines: false,
+        };
+        assert_eq!(view.num(512.345, view.decimals.hashrate), "512.3");
+        view.precision = Some(0);
+        assert_eq!(view.num(5.123, view.decimals.voltage), "5");
+    }
+
+    #[test]
+    fn env_block_for_shells() {
+        let info = json!({"hashRate": 512.34, "temp": 61.0, "sharesAccepted": 997, "hostname": "it's", "power": null});
+        assert_eq!(
+            env_block(&info),
+            "BITAXE_HASHRATE=512.34\nBITAXE_TEMP=61.0\nBITAXE_SHARES_ACCEPTED=997\nBITAXE_HOSTNAME='it'\\''s'\n"
+        );
+        assert_eq!(env_block(&json!({})), "");
+    }
+
+    #[test]
+    fn tsv_block_keeps_its_columns() {
+        let info = json!({"hashRate": 512.34, "temp": 61.0, "hostname": "rig\t2\n", "version": "v2.4"});
+        assert_eq!(tsv_block(&info, None), "hashrate\t512.34\ntemp\t61.0\nhostname\trig 2 \nversion\tv2.4\n");
+        assert_eq!(tsv_block(&json!({"power": 15}), Some("garage\trig")), "garage rig\tpower\t15\n");
+    }
+
+    #[test]
+    fn power_rails_are_kept_apart() {
+        let info = json!({"voltage": 5000, "power": 15.0, "coreVoltageActual": 1200, "current": 10000, "hashRate": 1000.0});
+        let rails = PowerRails::from_info(&SystemInfo::from(&info));
+        assert_eq!(rails.input_volts, Some(5.0));
+        assert_eq!(rails.core_watts(), Some(12.0));
+        assert_eq!(rails.efficiency(rails.input_watts), Some(15.0));
+        assert_eq!(rails.efficiency(rails.core_watts()), Some(12.0));
+    }
+
+    #[test]
+    fn input_side_current_is_not_core_power() {
+        // 1.2 V * 14 A = 16.8 W > 15 W input: the sensor measures the input
+        let info = json!({"power": 15.0, "coreVoltageActual": 1200, "current": 14000, "hashRate": 1000.0});
+        assert_eq!(PowerRails::from_info(&SystemInfo::from(&info)).core_watts(), None);
+        let idle = SystemInfo::from(&json!({"hashRate": 0.0, "power": 15.0}));
+        assert_eq!(PowerRails::from_info(&idle).efficiency(Some(15.0)), None);
+    }
+
+    #[test]
+    fn expected_hashrate_from_frequency_and_cores() {
+        // BM1370 (Gamma): 2040 small cores at 525 MHz
+        let gamma = json!({"frequency": 525, "smallCoreCount": 2040, "asicCount": 1});
+        let expected_hashrate = |info: &Value| expected_hashrate(&SystemInfo::from(info));
+        assert_eq!(expected_hashrate(&gamma), Some(1071.0));
+        let two_chips = json!({"frequency": 500, "smallCoreCount": 894, "asicCount": 2});
+        assert_eq!(expected_hashrate(&two_chips), Some(894.0));
+        assert_eq!(expected_hashrate(&json!({"frequency": 500, "smallCoreCount": 894})), Some(447.0));
+        assert_eq!(expected_hashrate(&json!({"frequency": 525})), None);
+    }
+
+    #[test]
+    fn oneline_skips_missing_fields() {
+        let oneline = |info: Value| oneline(&SystemInfo::from(&info));
+        assert_eq!(oneline(json!({"hashRate": 480.0, "power": 13.0})), "480GH 13.0W");
+        // No shares yet: no rejection rate rather than NaN
+        assert_eq!(oneline(json!({"temp": 40.0, "sharesAccepted": 0, "sharesRejected": 0})), "40°C");
+    }
+}
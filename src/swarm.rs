@@ -0,0 +1,168 @@
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use reqwest::Client;
+use serde_json::{Value, json};
+use crate::client::{Target, bare_host};
+use crate::fleet::{self, FleetOpts};
+use crate::error::BitaxeError;
+use crate::retry::{self, Policy};
+use crate::{check_response, ratelimit};
+
+#[derive(Args, Debug)]
+pub struct SwarmArgs {
+    /// Print JSON instead of a table
+    #[arg(long)]
+    pub json: bool,
+
+    /// Add a peer (IP or host) to the queried device's swarm list
+    #[arg(long, value_name = "IP", conflicts_with = "unregister")]
+    register: Option<String>,
+
+    /// Remove a peer from the queried device's swarm list
+    #[arg(long, value_name = "IP")]
+    unregister: Option<String>,
+
+    #[command(flatten)]
+    opts: FleetOpts,
+}
+
+pub async fn run(args: &SwarmArgs, target: &Target) -> Result<()> {
+    let (client, host) = (&target.client, target.host.as_str());
+    if !args.opts.tag.is_empty() {
+        bail!("--tag applies to configured devices; swarm peers come from the miner");
+    }
+    if let Some(peer) = &args.register {
+        return register(client, host, peer).await;
+    }
+    if let Some(peer) = &args.unregister {
+        return unregister(client, host, peer).await;
+    }
+
+    let peers = fetch_peers(client, host).await?;
+    if peers.is_empty() {
+        eprintln!("No swarm peers configured on {host}");
+    }
+
+    // The queried device comes first, followed by its peers in swarm order
+    let at = |url: &str| Target { alias: None, host: url.to_string(), ..target.clone() };
+    let mut targets = vec![at(host)];
+    for peer in &peers {
+        let url = peer_url(peer);
+        if !targets.iter().any(|t| same_host(&t.host, &url)) {
+            targets.push(at(&url));
+        }
+    }
+
+    let results = fleet::poll_fleet(targets, args.opts.concurrency as usize).await;
+    fleet::print_results(&results, args.json)
+}
+
+/// GET /api/swarm/info and pull out the peer addresses
+async fn fetch_peers(client: &Client, host: &str) -> Result<Vec<String>> {
+    let url = format!("{host}/api/swarm/info");
+    let resp = retry::send(host, Policy::Idempotent, || client.get(&url))
+        .await
+        .map_err(|e| BitaxeError::retried(host, e))?;
+    let resp = check_response(resp, "Swarm info").await?;
+    let body: Value = resp.json().await.context("Swarm info is not valid JSON")?;
+    Ok(parse_peers(&body))
+}
+
+/// Firmware returns either an array or `{"swarm": [...]}`, whose entries are
+/// objects with an `ip` (or `host`) field or plain strings.
+/// Empty entries and duplicates are dropped.
+fn parse_peers(body: &Value) -> Vec<String> {
+    let entries = match body {
+        Value::Array(a) => a.as_slice(),
+        Value::Object(o) => o.get("swarm").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default(),
+        _ => &[],
+    };
+
+    let mut peers: Vec<String> = Vec::new();
+    for entry in entries {
+        let addr = match entry {
+            Value::String(s) => Some(s.as_str()),
+            Value::Object(o) => o.get("ip").or_else(|| o.get("host")).and_then(Value::as_str),
+            _ => None,
+        };
+        if let Some(addr) = addr.map(str::trim).filter(|a| !a.is_empty())
+            && !peers.iter().any(|p| same_host(p, addr))
+        {
+            peers.push(addr.to_string());
+        }
+    }
+    peers
+}
+
+/// The swarm list is written back whole: PATCH /api/swarm with the new array
+async fn register(client: &Client, host: &str, peer: &str) -> Result<()> {
+    let mut peers = fetch_peers(client, host).await?;
+    if peers.iter().any(|p| same_host(p, peer)) {
+        println!("{peer} is already in the swarm of {host}");
+        return Ok(());
+    }
+    peers.push(bare_host(peer).to_string());
+    save_peers(client, host, &peers).await?;
+    println!("Registered {peer} in the swarm of {host}");
+    Ok(())
+}
+
+async fn unregister(client: &Client, host: &str, peer: &str) -> Result<()> {
+    let mut peers = fetch_peers(client, host).await?;
+    let before = peers.len();
+    peers.retain(|p| !same_host(p, peer));
+    if peers.len() == before {
+        bail!("{peer} is not in the swarm of {host}");
+    }
+    save_peers(client, host, &peers).await?;
+    println!("Unregistered {peer} from the swarm of {host}");
+    Ok(())
+}
+
+async fn save_peers(client: &Client, host: &str, peers: &[String]) -> Result<()> {
+    let body: Vec<Value> = peers.iter().map(|p| json!({ "ip": p })).collect();
+    let url = format!("{host}/api/swarm");
+    ratelimit::throttle(host).await;
+    let resp = client.patch(&url).json(&body).send().await.map_err(|e| BitaxeError::transport(host, e))?;
+    check_response(resp, "Swarm update").await?;
+    Ok(())
+}
+
+/// Peers are usually listed as bare IPs; the API is plain HTTP
+fn peer_url(peer: &str) -> String {
+    if peer.contains("://") {
+        peer.trim_end_matches('/').to_string()
+    } else {
+        format!("http://{}", peer.trim_end_matches('/'))
+    }
+}
+
+/// Compare addresses ignoring scheme and trailing slash
+fn same_host(a: &str, b: &str) -> bool {
+    bare_host(a).eq_ignore_ascii_case(bare_host(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peers_from_array_deduplicated() {
+        let body = json!([{"ip": "10.0.0.2"}, "10.0.0.3", {"ip": "http://10.0.0.2/"}, {"ip": ""}, 5]);
+        assert_eq!(parse_peers(&body), vec!["10.0.0.2", "10.0.0.3"]);
+    }
+
+    #[test]
+    fn peers_from_wrapped_or_empty() {
+        assert_eq!(parse_peers(&json!({"swarm": [{"host": "10.0.0.4"}]})), vec!["10.0.0.4"]);
+        assert!(parse_peers(&json!([])).is_empty());
+        assert!(parse_peers(&json!({})).is_empty());
+    }
+
+    #[test]
+    fn host_comparison_ignores_scheme() {
+        assert!(same_host("http://10.0.0.2/", "10.0.0.2"));
+        assert_eq!(peer_url("10.0.0.2"), "http://10.0.0.2");
+        assert!(!same_host("10.0.0.2", "10.0.0.20"));
+    }
+}
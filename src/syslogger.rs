@@ -0,0 +1,85 @@
+use anyhow::{Context, Result};
+use bitaxe_api::SystemInfo;
+use serde_json::Value;
+use syslog::{Facility, Formatter3164, Logger, LoggerBackend};
+use crate::status::{TEMP_CRIT, TEMP_WARN, oneline};
+
+/// `--syslog-facility`, ex: daemon, user, local0
+pub fn parse_facility(s: &str) -> Result<Facility, String> {
+    s.parse().map_err(|_| format!("unknown syslog facility '{s}' (ex: daemon, user, local0-local7)"))
+}
+
+#[derive(Debug, PartialEq)]
+enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Poll summaries sent to the local syslog daemon (`watch --syslog`)
+pub struct Syslog {
+    logger: Logger<LoggerBackend, Formatter3164>,
+    /// Cleared after the first failed send, so a dead socket warns once
+    working: bool,
+}
+
+impl Syslog {
+    pub fn connect(tag: &str, facility: Facility) -> Result<Self> {
+        let formatter = Formatter3164 { facility, hostname: None, process: tag.to_string(), pid: std::process::id() };
+        let logger = syslog::unix(formatter).context("Can't reach the system log (no /dev/log socket?)")?;
+        Ok(Syslog { logger, working: true })
+    }
+
+    pub fn poll(&mut self, device: &str, info: Result<&Value, String>) {
+        if !self.working {
+            return;
+        }
+        let (level, text) = message(device, info);
+        let sent = match level {
+            Level::Info => self.logger.info(text),
+            Level::Warning => self.logger.warning(text),
+            Level::Error => self.logger.err(text),
+        };
+        if let Err(e) = sent {
+            eprintln!("Warning: syslog stopped accepting messages: {e}");
+            self.working = false;
+        }
+    }
+}
+
+/// One line per poll, as `status --oneline` prints it: a warning from
+/// TEMP_WARN, an error from TEMP_CRIT and for failed polls
+fn message(device: &str, info: Result<&Value, String>) -> (Level, String) {
+    match info {
+        Ok(info) => {
+            let info = SystemInfo::from(info);
+            let temp = info.temp.unwrap_or(0.0);
+            let level = if temp >= TEMP_CRIT {
+                Level::Error
+            } else if temp >= TEMP_WARN {
+                Level::Warning
+            } else {
+                Level::Info
+            };
+            (level, format!("{device}: {}", oneline(&info)))
+        }
+        Err(e) => (Level::Error, format!("{device}: poll failed: {e}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn severity_follows_temperature() {
+        let poll = |temp: f64| json!({"hashRate": 512.0, "temp": temp});
+        assert_eq!(message("gamma", Ok(&poll(60.0))), (Level::Info, "gamma: 512GH 60°C".to_string()));
+        assert_eq!(message("gamma", Ok(&poll(66.0))).0, Level::Warning);
+        assert_eq!(message("gamma", Ok(&poll(71.0))).0, Level::Error);
+        assert_eq!(message("gamma", Err("timed out".into())), (Level::Error, "gamma: poll failed: timed out".into()));
+        assert!(parse_facility("local3").is_ok());
+        assert!(parse_facility("nope").is_err());
+    }
+}
@@ -0,0 +1,285 @@
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+/// The `alias` spellings above, by the key they stand for. serde refuses an
+/// answer carrying both, so `from_value` drops the alias then.
+const ALIASES: &[(&str, &[&str])] = &[
+    ("ASICModel", &["asicModel"]),
+    ("hashRate", &["hashrate"]),
+    ("expectedHashrate", &["expectedHashRate"]),
+    ("temptarget", &["tempTarget"]),
+    ("fanspeed", &["fanSpeed"]),
+    ("fanrpm", &["fanRpm", "fanRPM"]),
+    ("autofanspeed", &["autoFanSpeed"]),
+    ("wifiRSSI", &["wifiRssi"]),
+    ("stratumURL", &["stratumUrl"]),
+    ("fallbackStratumURL", &["fallbackStratumUrl"]),
+];
+
+/// SI suffixes AxeOS uses for difficulties, ex: "45.3M", "1.2G"
+pub const DIFFICULTY_SUFFIXES: &[(char, f64)] =
+    &[('k', 1e3), ('M', 1e6), ('G', 1e9), ('T', 1e12), ('P', 1e15), ('E', 1e18)];
+
+/// What /api/system/info reports, for the keys this crate knows about.
+/// Every field is optional: firmware versions add, drop and rename keys,
+/// and a value of the wrong type (a number sent as a string, 0/1 for a
+/// flag) is read leniently or left out rather than failing the whole
+/// answer. Keys not listed here are kept in `extra`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SystemInfo {
+    #[serde(deserialize_with = "lenient")]
+    pub hostname: Option<String>,
+    /// Firmware version, ex: "v2.4.0"
+    #[serde(deserialize_with = "lenient")]
+    pub version: Option<String>,
+    /// Version of the web UI, on builds that ship it separately
+    #[serde(rename = "axeOSVersion", deserialize_with = "lenient")]
+    pub axe_os_version: Option<String>,
+    #[serde(rename = "idfVersion", deserialize_with = "lenient")]
+    pub idf_version: Option<String>,
+    #[serde(rename = "ASICModel", alias = "asicModel", deserialize_with = "lenient")]
+    pub asic_model: Option<String>,
+    #[serde(rename = "boardVersion", deserialize_with = "lenient")]
+    pub board_version: Option<String>,
+    #[serde(rename = "asicCount", deserialize_with = "lenient")]
+    pub asic_count: Option<u64>,
+    #[serde(rename = "smallCoreCount", deserialize_with = "lenient")]
+    pub small_core_count: Option<u64>,
+
+    /// GH/s
+    #[serde(rename = "hashRate", alias = "hashrate", deserialize_with = "lenient")]
+    pub hash_rate: Option<f64>,
+    /// GH/s, as AxeOS estimates it from the frequency
+    #[serde(rename = "expectedHashrate", alias = "expectedHashRate", deserialize_with = "lenient")]
+    pub expected_hashrate: Option<f64>,
+    #[serde(rename = "bestDiff", deserialize_with = "lenient")]
+    pub best_diff: Option<Difficulty>,
+    #[serde(rename = "bestSessionDiff", deserialize_with = "lenient")]
+    pub best_session_diff: Option<Difficulty>,
+    #[serde(rename = "sharesAccepted", deserialize_with = "lenient")]
+    pub shares_accepted: Option<u64>,
+    #[serde(rename = "sharesRejected", deserialize_with = "lenient")]
+    pub shares_rejected: Option<u64>,
+
+    /// ASIC temperature, °C
+    #[serde(deserialize_with = "lenient")]
+    pub temp: Option<f64>,
+    /// Voltage regulator temperature, °C
+    #[serde(rename = "vrTemp", deserialize_with = "lenient")]
+    pub vr_temp: Option<f64>,
+    /// Target temperature of the fan controller, °C
+    #[serde(rename = "temptarget", alias = "tempTarget", deserialize_with = "lenient")]
+    pub temp_target: Option<f64>,
+    /// Percent
+    #[serde(rename = "fanspeed", alias = "fanSpeed", deserialize_with = "lenient")]
+    pub fan_speed: Option<f64>,
+    #[serde(rename = "fanrpm", alias = "fanRpm", alias = "fanRPM", deserialize_with = "lenient")]
+    pub fan_rpm: Option<u64>,
+    #[serde(rename = "autofanspeed", alias = "autoFanSpeed", deserialize_with = "lenient")]
+    pub auto_fan_speed: Option<bool>,
+
+    /// Board power draw, W
+    #[serde(deserialize_with = "lenient")]
+    pub power: Option<f64>,
+    /// Input (PSU) rail; millivolts on most builds, volts on a few
+    #[serde(deserialize_with = "lenient")]
+    pub voltage: Option<f64>,
+    /// Regulator current, mA
+    #[serde(deserialize_with = "lenient")]
+    pub current: Option<f64>,
+    /// Core voltage setting, mV
+    #[serde(rename = "coreVoltage", deserialize_with = "lenient")]
+    pub core_voltage: Option<f64>,
+    /// Core voltage measured, mV
+    #[serde(rename = "coreVoltageActual", deserialize_with = "lenient")]
+    pub core_voltage_actual: Option<f64>,
+    /// ASIC clock, MHz
+    #[serde(deserialize_with = "lenient")]
+    pub frequency: Option<f64>,
+
+    #[serde(rename = "wifiRSSI", alias = "wifiRssi", deserialize_with = "lenient")]
+    pub wifi_rssi: Option<i64>,
+    #[serde(rename = "wifiStatus", deserialize_with = "lenient")]
+    pub wifi_status: Option<String>,
+    #[serde(deserialize_with = "lenient")]
+    pub ssid: Option<String>,
+    #[serde(rename = "macAddr", deserialize_with = "lenient")]
+    pub mac_addr: Option<String>,
+    #[serde(rename = "uptimeSeconds", deserialize_with = "lenient")]
+    pub uptime_seconds: Option<u64>,
+
+    #[serde(rename = "stratumURL", alias = "stratumUrl", deserialize_with = "lenient")]
+    pub stratum_url: Option<String>,
+    #[serde(rename = "stratumPort", deserialize_with = "lenient")]
+    pub stratum_port: Option<u64>,
+    #[serde(rename = "stratumUser", deserialize_with = "lenient")]
+    pub stratum_user: Option<String>,
+    #[serde(rename = "fallbackStratumURL", alias = "fallbackStratumUrl", deserialize_with = "lenient")]
+    pub fallback_stratum_url: Option<String>,
+    #[serde(rename = "fallbackStratumPort", deserialize_with = "lenient")]
+    pub fallback_stratum_port: Option<u64>,
+    #[serde(rename = "fallbackStratumUser", deserialize_with = "lenient")]
+    pub fallback_stratum_user: Option<String>,
+    #[serde(rename = "isUsingFallbackStratum", deserialize_with = "lenient")]
+    pub using_fallback_stratum: Option<bool>,
+
+    /// Screen model, ex: "SSD1306 (128x32)"
+    #[serde(deserialize_with = "lenient")]
+    pub display: Option<String>,
+    /// Degrees, on builds that can rotate the screen
+    #[serde(deserialize_with = "lenient")]
+    pub rotation: Option<u64>,
+    /// Upside down, on builds that can only flip it
+    #[serde(deserialize_with = "lenient")]
+    pub flipscreen: Option<bool>,
+    #[serde(deserialize_with = "lenient")]
+    pub invertscreen: Option<bool>,
+    /// Minutes the screen stays on; -1 always, 0 off
+    #[serde(rename = "displayTimeout", deserialize_with = "lenient")]
+    pub display_timeout: Option<i64>,
+
+    /// Everything else the miner sent, as it sent it
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl SystemInfo {
+    /// From an answer already parsed as JSON (see `parse_info`). Only fails
+    /// when `info` isn't an object.
+    pub fn from_value(info: &Value) -> Result<Self, serde_json::Error> {
+        let Some(map) = info.as_object() else { return SystemInfo::deserialize(info) };
+        let repeated: Vec<&str> = ALIASES
+            .iter()
+            .flat_map(|(key, aliases)| {
+                let mut present = std::iter::once(key).chain(aliases.iter()).filter(|k| map.contains_key(**k));
+                present.next();
+                present.copied()
+            })
+            .collect();
+        if repeated.is_empty() {
+            return SystemInfo::deserialize(info);
+        }
+        let mut map = map.clone();
+        for key in repeated {
+            map.remove(key);
+        }
+        SystemInfo::deserialize(&Value::Object(map))
+    }
+}
+
+/// An answer that isn't an object reads as reporting nothing
+impl From<&Value> for SystemInfo {
+    fn from(info: &Value) -> Self {
+        SystemInfo::from_value(info).unwrap_or_default()
+    }
+}
+
+/// A best difficulty: older firmware sends text with a suffix ("4.29M"),
+/// newer a plain number
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difficulty {
+    /// As the miner sent it, for showing
+    pub shown: String,
+    /// The number it stands for, when that could be worked out
+    pub value: Option<f64>,
+}
+
+impl Lenient for Difficulty {
+    fn read(v: &Value) -> Option<Self> {
+        match v {
+            Value::String(s) => Some(Difficulty { shown: s.clone(), value: parse_difficulty(s) }),
+            Value::Number(n) => Some(Difficulty { shown: n.to_string(), value: n.as_f64() }),
+            _ => None,
+        }
+    }
+}
+
+/// A difficulty as AxeOS prints it, plain ("1234") or suffixed ("1.2G",
+/// "3.5 T"; suffixes are case-insensitive)
+pub fn parse_difficulty(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let last = s.chars().last()?;
+    let (num, mult) = match DIFFICULTY_SUFFIXES.iter().find(|(c, _)| c.eq_ignore_ascii_case(&last)) {
+        Some((_, mult)) => (&s[..s.len() - 1], *mult),
+        None => (s, 1.0),
+    };
+    num.trim().parse::<f64>().ok().filter(|n| n.is_finite() && *n >= 0.0).map(|n| n * mult)
+}
+
+/// Reading one JSON value as a field type, leniently
+trait Lenient: Sized {
+    fn read(v: &Value) -> Option<Self>;
+}
+
+impl Lenient for String {
+    fn read(v: &Value) -> Option<Self> {
+        v.as_str().map(str::to_string)
+    }
+}
+
+impl Lenient for f64 {
+    fn read(v: &Value) -> Option<Self> {
+        match v {
+            Value::String(s) => s.trim().parse().ok().filter(|n: &f64| n.is_finite()),
+            _ => v.as_f64(),
+        }
+    }
+}
+
+impl Lenient for u64 {
+    fn read(v: &Value) -> Option<Self> {
+        v.as_u64().or_else(|| f64::read(v).filter(|n| *n >= 0.0 && n.fract() == 0.0).map(|n| n as u64))
+    }
+}
+
+impl Lenient for i64 {
+    fn read(v: &Value) -> Option<Self> {
+        v.as_i64().or_else(|| f64::read(v).filter(|n| n.fract() == 0.0).map(|n| n as i64))
+    }
+}
+
+/// AxeOS flags are 0/1; some builds send booleans
+impl Lenient for bool {
+    fn read(v: &Value) -> Option<Self> {
+        v.as_bool().or_else(|| v.as_f64().map(|n| n != 0.0))
+    }
+}
+
+fn lenient<'de, D: Deserializer<'de>, T: Lenient>(d: D) -> Result<Option<T>, D::Error> {
+    Ok(T::read(&Value::deserialize(d)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn odd_values_are_read_leniently() {
+        let info = SystemInfo::from_value(&json!({
+            "hashrate": "512.5", "sharesAccepted": 997.0, "wifiRSSI": -61, "autofanspeed": 1,
+            "flipscreen": false, "temp": null, "fanrpm": "fast", "bestDiff": 1364728132u64,
+        }))
+        .unwrap();
+        assert_eq!(info.hash_rate, Some(512.5));
+        assert_eq!(info.shares_accepted, Some(997));
+        assert_eq!(info.wifi_rssi, Some(-61));
+        assert_eq!((info.auto_fan_speed, info.flipscreen), (Some(true), Some(false)));
+        assert_eq!((info.temp, info.fan_rpm), (None, None));
+        assert_eq!(info.best_diff, Some(Difficulty { shown: "1364728132".into(), value: Some(1364728132.0) }));
+        assert!(SystemInfo::from_value(&json!([1, 2])).is_err());
+        // Both spellings of a key: the usual one wins
+        let both = SystemInfo::from_value(&json!({"fanspeed": 40, "fanSpeed": 55, "fanRpm": 3000})).unwrap();
+        assert_eq!((both.fan_speed, both.fan_rpm), (Some(40.0), Some(3000)));
+    }
+
+    #[test]
+    fn suffixed_difficulties() {
+        assert_eq!(parse_difficulty("4.29M"), Some(4_290_000.0));
+        assert_eq!(parse_difficulty("3.5 t"), Some(3.5e12));
+        assert_eq!(parse_difficulty("1234"), Some(1234.0));
+        assert_eq!(parse_difficulty("lots"), None);
+        assert_eq!(parse_difficulty("-1G"), None);
+    }
+}
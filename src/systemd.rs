@@ -0,0 +1,140 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+use anyhow::{Context, Result};
+
+/// Talks to systemd over `$NOTIFY_SOCKET` (sd_notify). Outside a
+/// `Type=notify` service the variable isn't set and every call is a no-op.
+pub struct Notifier {
+    #[cfg(unix)]
+    socket: Option<(std::os::unix::net::UnixDatagram, String)>,
+    watchdog: Option<Duration>,
+}
+
+impl Notifier {
+    pub fn from_env() -> Self {
+        Notifier {
+            #[cfg(unix)]
+            socket: env::var("NOTIFY_SOCKET")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .and_then(|path| Some((std::os::unix::net::UnixDatagram::unbound().ok()?, path))),
+            watchdog: watchdog_timeout(env::var("WATCHDOG_USEC").ok(), env::var("WATCHDOG_PID").ok()),
+        }
+    }
+
+    /// `WatchdogSec=` of the service, when systemd expects pings from this process
+    pub fn watchdog(&self) -> Option<Duration> {
+        self.watchdog
+    }
+
+    pub fn ready(&self) {
+        self.send("READY=1");
+    }
+
+    /// Ping the watchdog, if the service has one
+    pub fn alive(&self) {
+        if self.watchdog.is_some() {
+            self.send("WATCHDOG=1");
+        }
+    }
+
+    pub fn stopping(&self) {
+        self.send("STOPPING=1");
+    }
+
+    /// One line for `systemctl status`
+    pub fn status(&self, text: &str) {
+        self.send(&format!("STATUS={text}"));
+    }
+
+    /// Best effort: a supervisor that went away shouldn't stop the daemon
+    #[cfg(unix)]
+    fn send(&self, state: &str) {
+        let Some((socket, path)) = &self.socket else { return };
+        if let Err(e) = send_to(socket, path, state) {
+            tracing::debug!(error = e.to_string(), "sd_notify failed");
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn send(&self, _state: &str) {}
+}
+
+/// `@name` is an abstract socket (Linux); anything else a path
+#[cfg(unix)]
+fn send_to(socket: &std::os::unix::net::UnixDatagram, path: &str, state: &str) -> std::io::Result<usize> {
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.strip_prefix('@') {
+        use std::os::linux::net::SocketAddrExt;
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        return socket.send_to_addr(state.as_bytes(), &addr);
+    }
+    socket.send_to(state.as_bytes(), path)
+}
+
+/// `WATCHDOG_USEC`, unless `WATCHDOG_PID` says it's meant for another process
+fn watchdog_timeout(usec: Option<String>, pid: Option<String>) -> Option<Duration> {
+    if pid.is_some_and(|pid| pid.trim() != process::id().to_string()) {
+        return None;
+    }
+    usec?.trim().parse::<u64>().ok().filter(|&us| us > 0).map(Duration::from_micros)
+}
+
+/// A service file running `daemon` from this binary with the given config.
+/// The watchdog allows three missed cycles before systemd restarts it.
+pub fn unit(config: Option<&Path>, interval: Duration) -> Result<String> {
+    let exe = env::current_exe().context("Can't tell where this binary is installed")?;
+    let mut exec = quote(&exe.display().to_string());
+    if let Some(config) = config {
+        let config = fs::canonicalize(config).unwrap_or_else(|_| config.to_path_buf());
+        exec += &format!(" --config {}", quote(&config.display().to_string()));
+    }
+    exec += " daemon";
+    let watchdog = (interval.as_secs() * 3).max(60);
+    Ok(format!(
+        "[Unit]
+Description=bitaxe-cli daemon (polling, logging, alerts)
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+Type=notify
+ExecStart={exec}
+WatchdogSec={watchdog}
+Restart=on-failure
+RestartSec=10
+# User=bitaxe
+
+[Install]
+WantedBy=multi-user.target
+"
+    ))
+}
+
+/// ExecStart splits on spaces unless the word is quoted
+fn quote(word: &str) -> String {
+    if word.contains([' ', '"', '\\']) {
+        format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        word.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watchdog_from_the_environment() {
+        let us = |s: &str| Some(s.to_string());
+        assert_eq!(watchdog_timeout(us("30000000"), None), Some(Duration::from_secs(30)));
+        assert_eq!(watchdog_timeout(us("30000000"), Some(process::id().to_string())), Some(Duration::from_secs(30)));
+        assert_eq!(watchdog_timeout(us("30000000"), us("1")), None);
+        assert_eq!(watchdog_timeout(us("0"), None), None);
+        assert_eq!(watchdog_timeout(None, None), None);
+        assert_eq!(quote("/opt/my tools/bitaxe-cli"), "\"/opt/my tools/bitaxe-cli\"");
+    }
+}
@@ -0,0 +1,109 @@
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::Value;
+use crate::get_number;
+use crate::rules::Metric;
+
+/// AxeOS keys holding temperatures, always °C on the wire
+const KEYS: [&str; 2] = ["temp", "vrTemp"];
+
+/// How temperatures are shown to people. --json, --field, the exporters,
+/// the history database and the config file stay in °C.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+pub enum TempUnit {
+    #[default]
+    #[value(name = "c", alias = "celsius")]
+    #[serde(rename = "c", alias = "celsius")]
+    Celsius,
+    #[value(name = "f", alias = "fahrenheit")]
+    #[serde(rename = "f", alias = "fahrenheit")]
+    Fahrenheit,
+}
+
+/// Set from --temp-unit or `temp_unit` before the command runs
+static FAHRENHEIT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_unit(unit: TempUnit) {
+    FAHRENHEIT.store(unit == TempUnit::Fahrenheit, Ordering::Relaxed);
+}
+
+pub fn unit() -> TempUnit {
+    if FAHRENHEIT.load(Ordering::Relaxed) { TempUnit::Fahrenheit } else { TempUnit::Celsius }
+}
+
+impl TempUnit {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            TempUnit::Celsius => "°C",
+            TempUnit::Fahrenheit => "°F",
+        }
+    }
+
+    /// A reading from the miner, in this unit
+    pub fn of_celsius(self, celsius: f64) -> f64 {
+        match self {
+            TempUnit::Celsius => celsius,
+            TempUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    /// `info[key]`, converted when it is a temperature
+    pub fn reading(self, info: &Value, key: &str) -> Option<f64> {
+        let value = get_number(info, key)?;
+        Some(if KEYS.contains(&key) { self.of_celsius(value) } else { value })
+    }
+
+    /// `info` with its temperatures converted, for thresholds typed in this unit
+    pub fn convert(self, info: &Value) -> Cow<'_, Value> {
+        if self == TempUnit::Celsius {
+            return Cow::Borrowed(info);
+        }
+        let mut info = info.clone();
+        for key in KEYS {
+            if let Some(value) = get_number(&info, key) {
+                info[key] = self.of_celsius(value).into();
+            }
+        }
+        Cow::Owned(info)
+    }
+
+    /// A `metric` reading as the miner reports it, in this unit for temperatures
+    pub fn metric_value(self, metric: Metric, value: f64) -> f64 {
+        match metric {
+            Metric::Temp | Metric::VrTemp => self.of_celsius(value),
+            _ => value,
+        }
+    }
+
+    /// `Metric::unit`, in this unit for temperatures
+    pub fn metric_unit(self, metric: Metric) -> &'static str {
+        match (metric, self) {
+            (Metric::Temp | Metric::VrTemp, TempUnit::Fahrenheit) => " °F",
+            _ => metric.unit(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn only_temperatures_are_converted() {
+        let f = TempUnit::Fahrenheit;
+        assert_eq!(f.of_celsius(70.0), 158.0);
+        assert_eq!(f.of_celsius(-40.0), -40.0);
+        let info = json!({"temp": 65.0, "vrTemp": 50, "hashRate": 512.0});
+        assert_eq!(*f.convert(&info), json!({"temp": 149.0, "vrTemp": 122.0, "hashRate": 512.0}));
+        assert_eq!(f.reading(&info, "hashRate"), Some(512.0));
+        assert_eq!(TempUnit::Celsius.reading(&info, "temp"), Some(65.0));
+        assert_eq!(f.metric_unit(Metric::VrTemp), " °F");
+        assert_eq!(f.metric_unit(Metric::Power), " W");
+        assert_eq!(f.metric_value(Metric::Temp, 70.0), 158.0);
+        assert_eq!(f.metric_value(Metric::Power, 15.5), 15.5);
+        assert_eq!(TempUnit::from_str("fahrenheit", true), Ok(f));
+    }
+}
@@ -0,0 +1,165 @@
+use std::cmp::Ordering;
+use std::fmt;
+use clap::Args;
+use serde_json::Value;
+use crate::rules::{Expr, Metric};
+use crate::temperature;
+
+/// `--warn` / `--crit` expressions, checked against every poll
+#[derive(Args, Debug, Clone, Default)]
+pub struct ThresholdArgs {
+    /// Flag a reading as a warning when this holds, ex: "reject_rate>1"
+    /// (a metric from [alerts.rules] or its AxeOS key; < > <= >= == !=).
+    /// Repeat for several.
+    #[arg(long, value_name = "EXPR", value_parser = parse_expr)]
+    pub warn: Vec<Expr>,
+
+    /// Flag a reading as critical when this holds, ex: "hashRate<400"
+    #[arg(long, value_name = "EXPR", value_parser = parse_expr)]
+    pub crit: Vec<Expr>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Warn,
+    Crit,
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Warn => "warn",
+            Level::Crit => "crit",
+        }
+    }
+}
+
+/// An expression that holds, with the reading that made it
+#[derive(Debug, PartialEq)]
+pub struct Breach<'a> {
+    pub level: Level,
+    pub expr: &'a Expr,
+    pub value: f64,
+}
+
+/// One poll at a glance: the worst level reached and what reached it.
+/// `status::verdict` adds the built-in temperature limits to this; `status`
+/// prints the result as its footer and `check` without --metric exits by it,
+/// so the two can't disagree about the same reading.
+#[derive(Debug, Default, PartialEq)]
+pub struct Verdict {
+    /// None when nothing was breached
+    pub level: Option<Level>,
+    /// What reached `level`; lesser breaches aren't kept
+    pub reasons: Vec<String>,
+}
+
+impl Verdict {
+    /// Count `reason` at `level`
+    pub fn note(&mut self, level: Level, reason: String) {
+        match self.level.cmp(&Some(level)) {
+            Ordering::Less => {
+                self.level = Some(level);
+                self.reasons = vec![reason];
+            }
+            Ordering::Equal if !self.reasons.contains(&reason) => self.reasons.push(reason),
+            _ => {}
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.level {
+            None => "HEALTHY",
+            Some(Level::Warn) => "WARNING",
+            Some(Level::Crit) => "CRITICAL",
+        }
+    }
+}
+
+/// `HEALTHY`, `WARNING (VR temp high)`
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())?;
+        if !self.reasons.is_empty() {
+            write!(f, " ({})", self.reasons.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl ThresholdArgs {
+    /// The verdict of the expressions alone, each breach named by its expression
+    pub fn verdict(&self, info: &Value) -> Verdict {
+        let mut verdict = Verdict::default();
+        for breach in self.breaches(info) {
+            verdict.note(breach.level, breach.expr.to_string());
+        }
+        verdict
+    }
+
+    /// Every expression that holds for this poll, critical ones first.
+    /// Temperatures are compared in the --temp-unit they were typed in.
+    pub fn breaches(&self, info: &Value) -> Vec<Breach<'_>> {
+        let info = temperature::unit().convert(info);
+        let crit = self.crit.iter().map(|e| (Level::Crit, e));
+        let warn = self.warn.iter().map(|e| (Level::Warn, e));
+        crit.chain(warn).filter_map(|(level, expr)| Some(Breach { level, expr, value: expr.matches(&info)? })).collect()
+    }
+
+    /// Whether an expression is about `metric`
+    pub fn names(&self, metric: Metric) -> bool {
+        self.crit.iter().chain(&self.warn).any(|e| e.metric == metric)
+    }
+
+    /// The worst breach on `metric`
+    pub fn worst(&self, metric: Metric, info: &Value) -> Option<Breach<'_>> {
+        self.breaches(info).into_iter().find(|b| b.expr.metric == metric)
+    }
+}
+
+/// Only what one poll shows: metrics worked out over several polls belong
+/// in [alerts.rules]
+fn parse_expr(s: &str) -> Result<Expr, String> {
+    let expr = Expr::parse(s).map_err(|e| format!("{e:#}"))?;
+    if expr.metric.needs_history() {
+        return Err(format!("{} takes several polls to work out; use it in [alerts.rules]", expr.metric.name()));
+    }
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn critical_wins_over_warning() {
+        let args = ThresholdArgs {
+            warn: vec![parse_expr("hashRate<450").unwrap(), parse_expr("temp>60").unwrap()],
+            crit: vec![parse_expr("hashrate < 400").unwrap()],
+        };
+        let info = json!({"hashRate": 380.0, "temp": 58.0});
+        let breaches = args.breaches(&info);
+        assert_eq!(breaches.iter().map(|b| b.level).collect::<Vec<_>>(), [Level::Crit, Level::Warn]);
+        assert_eq!(args.worst(Metric::Hashrate, &info).unwrap().expr.to_string(), "hashrate < 400");
+        assert_eq!(args.worst(Metric::Temp, &info), None);
+        assert!(parse_expr("failed_polls > 2").is_err());
+        assert!(parse_expr("hashrate => 2").unwrap_err().contains("unknown comparison"));
+    }
+
+    #[test]
+    fn verdict_keeps_the_worst_reasons() {
+        let args = ThresholdArgs {
+            warn: vec![parse_expr("temp>60").unwrap(), parse_expr("hashRate<450").unwrap()],
+            crit: vec![],
+        };
+        let verdict = args.verdict(&json!({"hashRate": 380.0, "temp": 61.0}));
+        assert_eq!(verdict.to_string(), "WARNING (temp > 60, hashrate < 450)");
+        assert_eq!(args.verdict(&json!({"hashRate": 500.0, "temp": 50.0})).to_string(), "HEALTHY");
+
+        let mut verdict = verdict;
+        verdict.note(Level::Crit, "overheat".into());
+        verdict.note(Level::Warn, "VR temp high".into());
+        assert_eq!(verdict, Verdict { level: Some(Level::Crit), reasons: vec!["overheat".into()] });
+    }
+}
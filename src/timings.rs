@@ -0,0 +1,42 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use crate::client::bare_host;
+
+/// Set from `status --timings` before the command runs
+static TIMINGS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_timings(on: bool) {
+    TIMINGS.store(on, Ordering::Relaxed);
+}
+
+/// With --timings, say on stderr how long a system info request took,
+/// retries and the JSON parse included
+pub fn report(host: &str, took: Duration) {
+    if TIMINGS.load(Ordering::Relaxed) {
+        eprintln!("{}", line(host, took));
+    }
+}
+
+/// `192.168.1.50: fetched and parsed in 83ms`
+fn line(host: &str, took: Duration) -> String {
+    format!("{}: fetched and parsed in {}", bare_host(host), millis(took))
+}
+
+/// Whole milliseconds, with a decimal below 10 ms where that is all there is
+fn millis(d: Duration) -> String {
+    let ms = d.as_secs_f64() * 1000.0;
+    if ms < 10.0 { format!("{ms:.1}ms") } else { format!("{ms:.0}ms") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_in_milliseconds() {
+        let line = line("http://192.168.1.50", Duration::from_micros(83_400));
+        assert_eq!(line, "192.168.1.50: fetched and parsed in 83ms");
+        assert_eq!(millis(Duration::from_micros(420)), "0.4ms");
+        assert_eq!(millis(Duration::from_secs(2)), "2000ms");
+    }
+}
@@ -0,0 +1,450 @@
+use std::io::{self, IsTerminal};
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use anyhow::{Context, Result, bail};
+use bitaxe_api::SystemInfo;
+use clap::Args;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::symbols::Marker;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Axis, Block, Chart, Dataset, GraphType, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use serde_json::Value;
+use crate::client::{Target, bare_host};
+use crate::fleet::{DeviceResult, FleetOpts, fetch_fleet};
+use crate::interrupt::Interrupt;
+use crate::rules::short_duration;
+use crate::shares;
+use crate::temperature;
+use crate::{logging, parse_duration, ratelimit, runtime, send_restart};
+
+/// `tui`: a full-screen dashboard of the configured devices
+#[derive(Args, Debug)]
+pub struct TuiArgs {
+    /// Time between polls (ex: 5, 10s, 1m; at least 1s)
+    #[arg(long, default_value = "5s", value_parser = parse_duration)]
+    interval: Duration,
+
+    #[command(flatten)]
+    pub opts: FleetOpts,
+}
+
+/// Points kept per chart, so a long session stays cheap to draw
+const MAX_POINTS: usize = 4096;
+/// Rows given to each device pane when several share the screen
+const PANE_HEIGHT: u16 = 10;
+/// Panes narrower than this show their readings without charts
+const CHART_MIN_WIDTH: u16 = 70;
+/// Longest wait for a key before redrawing anyway
+const TICK: Duration = Duration::from_millis(200);
+
+/// From the polling and restart threads to the UI
+enum Update {
+    Poll(Vec<DeviceResult>, SystemTime),
+    Restart { device: usize, result: Result<(), String> },
+}
+
+/// One device: its latest readings and the session's history
+struct Pane {
+    target: Target,
+    /// The last successful poll
+    info: Option<Value>,
+    /// Why the latest poll failed, if it did
+    error: Option<String>,
+    /// (seconds into the session, reading)
+    hashrate: Vec<(f64, f64)>,
+    temp: Vec<(f64, f64)>,
+    shares: Option<shares::Stats>,
+}
+
+impl Pane {
+    fn new(target: Target) -> Self {
+        Pane { target, info: None, error: None, hashrate: Vec::new(), temp: Vec::new(), shares: None }
+    }
+}
+
+fn push(points: &mut Vec<(f64, f64)>, at: f64, value: Option<f64>) {
+    let Some(value) = value else { return };
+    if points.len() == MAX_POINTS {
+        points.remove(0);
+    }
+    points.push((at, value));
+}
+
+struct App {
+    panes: Vec<Pane>,
+    selected: usize,
+    interval: Duration,
+    started: Instant,
+    last_poll: Option<Instant>,
+    /// Shared with the polling thread
+    paused: Arc<AtomicBool>,
+    /// `r` was pressed; the next key answers the prompt
+    confirm: bool,
+    /// What the last action did, shown in the footer
+    message: Option<String>,
+    tracker: shares::Tracker,
+}
+
+/// What a key press asks for
+#[derive(Debug, PartialEq)]
+enum Action {
+    Continue,
+    Restart(usize),
+    Quit,
+}
+
+impl App {
+    fn new(targets: Vec<Target>, interval: Duration) -> Self {
+        App {
+            panes: targets.into_iter().map(Pane::new).collect(),
+            selected: 0,
+            interval,
+            started: Instant::now(),
+            last_poll: None,
+            paused: Arc::new(AtomicBool::new(false)),
+            confirm: false,
+            message: None,
+            tracker: shares::Tracker::new(),
+        }
+    }
+
+    /// Results come in target order, which is pane order
+    fn observe(&mut self, results: Vec<DeviceResult>, at: SystemTime) {
+        let secs = self.started.elapsed().as_secs_f64();
+        for (pane, result) in self.panes.iter_mut().zip(results) {
+            match result.info {
+                Ok(info) => {
+                    let typed = SystemInfo::from(&info);
+                    push(&mut pane.hashrate, secs, typed.hash_rate);
+                    push(&mut pane.temp, secs, typed.temp.map(|t| temperature::unit().of_celsius(t)));
+                    pane.shares = self.tracker.observe(&result.name, &info, at);
+                    pane.info = Some(info);
+                    pane.error = None;
+                }
+                Err(e) => pane.error = Some(e),
+            }
+        }
+        self.last_poll = Some(Instant::now());
+    }
+
+    fn key(&mut self, key: KeyEvent) -> Action {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Action::Quit;
+        }
+        if self.confirm {
+            self.confirm = false;
+            let name = self.panes[self.selected].target.name().to_string();
+            if key.code == KeyCode::Char('y') {
+                self.message = Some(format!("Restarting {name}…"));
+                return Action::Restart(self.selected);
+            }
+            self.message = Some(format!("Restart of {name} cancelled"));
+            return Action::Continue;
+        }
+
+        let count = self.panes.len();
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Right | KeyCode::Down | KeyCode::Tab => self.selected = (self.selected + 1) % count,
+            KeyCode::Left | KeyCode::Up | KeyCode::BackTab => self.selected = (self.selected + count - 1) % count,
+            KeyCode::Char('p') => {
+                let paused = !self.paused.fetch_xor(true, Ordering::Relaxed);
+                self.message = Some(if paused { "Polling paused" } else { "Polling resumed" }.to_string());
+            }
+            KeyCode::Char('r') => self.confirm = true,
+            _ => {}
+        }
+        Action::Continue
+    }
+}
+
+/// Run the dashboard until `q`, Ctrl-C or SIGTERM. Polling and restarts
+/// happen on their own threads so the screen keeps answering keys.
+pub fn run(args: &TuiArgs, targets: Vec<Target>) -> Result<()> {
+    if !io::stdout().is_terminal() {
+        bail!("`tui` needs a terminal; use `fleet watch --jsonl` for piped output");
+    }
+    let interval = ratelimit::poll_interval(args.interval);
+    let interrupt = Interrupt::install()?;
+    let mut app = App::new(targets.clone(), interval);
+
+    let (tx, rx) = mpsc::channel();
+    let paused = app.paused.clone();
+    let opts = args.opts.clone();
+    thread::spawn({
+        let tx = tx.clone();
+        move || poll_loop(&targets, &opts, interval, &paused, &tx)
+    });
+
+    // Raw mode and the alternate screen; the panic hook it installs puts the
+    // terminal back before the panic message is printed
+    let mut terminal = ratatui::try_init().context("Couldn't set up the terminal")?;
+    let result = event_loop(&mut terminal, &mut app, &rx, &tx, &interrupt);
+    ratatui::restore();
+    result?;
+    if interrupt.is_set() {
+        return interrupt.finish();
+    }
+    Ok(())
+}
+
+fn poll_loop(targets: &[Target], opts: &FleetOpts, interval: Duration, paused: &AtomicBool, tx: &Sender<Update>) {
+    loop {
+        if !paused.load(Ordering::Relaxed) {
+            let results = fetch_fleet(targets, opts);
+            for r in &results {
+                logging::poll(&r.name, r.info.as_ref().map_err(String::clone));
+            }
+            // The UI has gone: nothing left to poll for
+            if tx.send(Update::Poll(results, SystemTime::now())).is_err() {
+                return;
+            }
+        }
+        thread::sleep(interval);
+    }
+}
+
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    rx: &Receiver<Update>,
+    tx: &Sender<Update>,
+    interrupt: &Interrupt,
+) -> Result<()> {
+    while !interrupt.is_set() {
+        for update in rx.try_iter() {
+            match update {
+                Update::Poll(results, at) => app.observe(results, at),
+                Update::Restart { device, result } => {
+                    let name = app.panes[device].target.name();
+                    app.message = Some(match result {
+                        Ok(()) => format!("Restart sent to {name}"),
+                        Err(e) => format!("Restart of {name} failed: {e}"),
+                    });
+                }
+            }
+        }
+        terminal.draw(|frame| draw(frame, app))?;
+
+        if !event::poll(TICK)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else { continue };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match app.key(key) {
+            Action::Continue => {}
+            Action::Quit => return Ok(()),
+            Action::Restart(device) => {
+                let target = app.panes[device].target.clone();
+                let tx = tx.clone();
+                runtime().spawn(async move {
+                    let result = send_restart(&target).await.map_err(|e| format!("{e:#}"));
+                    let _ = tx.send(Update::Restart { device, result });
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn draw(frame: &mut Frame, app: &App) {
+    let [header, body, footer] =
+        Layout::vertical([Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+
+    let mut title = format!(" bitaxe-cli · {} device(s) · every {}", app.panes.len(), short_duration(app.interval));
+    match app.last_poll {
+        _ if app.paused.load(Ordering::Relaxed) => title += " · PAUSED",
+        Some(at) => title += &format!(" · polled {} ago", short_duration(at.elapsed())),
+        None => title += " · polling…",
+    }
+    frame.render_widget(Paragraph::new(title).bold(), header);
+
+    // A single device gets the whole screen
+    let shown = visible(app.selected, app.panes.len(), usize::from((body.height / PANE_HEIGHT).max(1)));
+    let count = shown.len() as u32;
+    let areas = Layout::vertical(shown.clone().map(|_| Constraint::Ratio(1, count))).split(body);
+    for (i, area) in shown.zip(areas.iter()) {
+        draw_pane(frame, &app.panes[i], i == app.selected, *area);
+    }
+
+    let bar = if app.confirm {
+        let name = app.panes[app.selected].target.name();
+        Line::from(format!(" Restart {name}? y to confirm, any other key cancels ")).black().on_yellow()
+    } else {
+        let keys = [("←/→ Tab", "select"), ("r", "restart"), ("p", "pause"), ("q", "quit")];
+        let mut spans: Vec<Span> = Vec::new();
+        for (key, what) in keys {
+            spans.push(Span::raw(format!(" {key} ")).black().on_gray());
+            spans.push(Span::raw(format!(" {what}  ")));
+        }
+        if let Some(message) = &app.message {
+            spans.push(Span::raw(message.clone()).italic());
+        }
+        Line::from(spans)
+    };
+    frame.render_widget(Paragraph::new(bar), footer);
+}
+
+/// The panes that fit, keeping the selected one on screen
+fn visible(selected: usize, count: usize, fits: usize) -> Range<usize> {
+    let fits = fits.min(count);
+    let start = (selected + 1).saturating_sub(fits).min(count - fits);
+    start..start + fits
+}
+
+fn draw_pane(frame: &mut Frame, pane: &Pane, selected: bool, area: Rect) {
+    let title = format!(" {} ({}) ", pane.target.name(), bare_host(&pane.target.host));
+    let border = if selected { Style::new().fg(Color::Cyan).add_modifier(Modifier::BOLD) } else { Style::new() };
+    let block = Block::bordered().title(title).border_style(border);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let readings = Paragraph::new(readings(pane));
+    if inner.width < CHART_MIN_WIDTH {
+        frame.render_widget(readings, inner);
+        return;
+    }
+    let [text, hash, temp] =
+        Layout::horizontal([Constraint::Length(38), Constraint::Fill(1), Constraint::Fill(1)]).spacing(2).areas(inner);
+    frame.render_widget(readings, text);
+    frame.render_widget(chart("Hashrate (GH/s)", &pane.hashrate, Color::Green), hash);
+    let title = format!("Core temp ({})", temperature::unit().symbol());
+    frame.render_widget(chart(&title, &pane.temp, Color::Red), temp);
+}
+
+fn readings(pane: &Pane) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+    match (&pane.error, &pane.info) {
+        (Some(e), _) => lines.push(Line::from(format!("● offline: {e}")).red()),
+        (None, Some(_)) => lines.push(Line::from("● online").green()),
+        (None, None) => lines.push(Line::from("○ waiting for the first poll").dim()),
+    }
+    let Some(info) = &pane.info else { return lines };
+    let row = |label: &str, value: String| Line::from(vec![Span::raw(format!("{label:<11}")).dim(), Span::raw(value)]);
+    let info = SystemInfo::from(info);
+    let unit = temperature::unit();
+    let temp = |celsius: Option<f64>| celsius.map(|c| format!("{:.1}", unit.of_celsius(c)));
+
+    if let Some(hash) = info.hash_rate {
+        lines.push(row("Hashrate", format!("{hash:.1} GH/s")));
+    }
+    if let Some(core) = temp(info.temp) {
+        let vr = temp(info.vr_temp).map(|vr| format!(" (VR {vr})")).unwrap_or_default();
+        lines.push(row("Temp", format!("{core} {}{vr}", unit.symbol())));
+    }
+    if let Some(power) = info.power {
+        lines.push(row("Power", format!("{power:.1} W")));
+    }
+    if let (Some(accepted), Some(rejected)) = (info.shares_accepted, info.shares_rejected) {
+        lines.push(row("Shares", format!("{accepted} accepted, {rejected} rejected")));
+    }
+    if let Some(stats) = pane.shares {
+        lines.push(row("Last share", stats.describe()));
+    }
+    if let Some(url) = &info.stratum_url {
+        let port = info.stratum_port.map(|p| format!(":{p}")).unwrap_or_default();
+        let fallback = if info.using_fallback_stratum == Some(true) { " (fallback)" } else { "" };
+        lines.push(row("Pool", format!("{url}{port}{fallback}")));
+    }
+    if let Some(uptime) = info.uptime_seconds {
+        lines.push(row("Uptime", short_duration(Duration::from_secs(uptime))));
+    }
+    lines
+}
+
+fn chart<'a>(title: &'a str, points: &'a [(f64, f64)], color: Color) -> Chart<'a> {
+    let (x0, x1) = match (points.first(), points.last()) {
+        (Some(first), Some(last)) => (first.0, last.0.max(first.0 + 1.0)),
+        _ => (0.0, 1.0),
+    };
+    let min = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    let max = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+    // A flat or empty series still gets a visible band
+    let (y0, y1) = if points.is_empty() {
+        (0.0, 1.0)
+    } else if max - min < 1.0 {
+        (min - 1.0, max + 1.0)
+    } else {
+        (min, max)
+    };
+
+    let data = Dataset::default().marker(Marker::Braille).graph_type(GraphType::Line).style(color).data(points);
+    let (mut x_axis, mut y_axis) = (Axis::default().bounds([x0, x1]), Axis::default().bounds([y0, y1]));
+    if !points.is_empty() {
+        let span = format!("-{}", short_duration(Duration::from_secs_f64(x1 - x0)));
+        x_axis = x_axis.labels([span, "now".to_string()]);
+        y_axis = y_axis.labels([format!("{y0:.0}"), format!("{y1:.0}")]);
+    }
+    Chart::new(vec![data]).block(Block::new().title(title)).x_axis(x_axis).y_axis(y_axis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+    use serde_json::json;
+    use crate::fleet::result;
+
+    fn target(name: &str) -> Target {
+        Target { alias: Some(name.to_string()), host: format!("http://{name}.lan"), client: reqwest::Client::new() }
+    }
+
+    #[test]
+    fn selection_stays_on_screen() {
+        assert_eq!(visible(0, 1, 3), 0..1);
+        assert_eq!(visible(0, 5, 2), 0..2);
+        assert_eq!(visible(3, 5, 2), 2..4);
+        assert_eq!(visible(4, 5, 2), 3..5);
+        assert_eq!(visible(4, 5, 9), 0..5);
+    }
+
+    #[test]
+    fn keys_select_pause_and_confirm_restarts() {
+        let mut app = App::new(vec![target("gamma"), target("ultra")], Duration::from_secs(5));
+        let press = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        app.key(press(KeyCode::Left));
+        assert_eq!(app.selected, 1);
+        app.key(press(KeyCode::Tab));
+        assert_eq!(app.selected, 0);
+
+        app.key(press(KeyCode::Char('p')));
+        assert!(app.paused.load(Ordering::Relaxed));
+        assert_eq!(app.message.as_deref(), Some("Polling paused"));
+
+        assert_eq!(app.key(press(KeyCode::Char('r'))), Action::Continue);
+        // Anything but `y` cancels, including `q`
+        assert_eq!(app.key(press(KeyCode::Char('q'))), Action::Continue);
+        app.key(press(KeyCode::Char('r')));
+        assert_eq!(app.key(press(KeyCode::Char('y'))), Action::Restart(0));
+        assert_eq!(app.key(press(KeyCode::Char('q'))), Action::Quit);
+        assert_eq!(app.key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)), Action::Quit);
+    }
+
+    #[test]
+    fn panes_show_readings_and_failures() {
+        let mut app = App::new(vec![target("gamma"), target("ultra")], Duration::from_secs(5));
+        let info = json!({"hashRate": 512.3, "temp": 61.0, "sharesAccepted": 10, "sharesRejected": 1,
+                          "stratumURL": "public-pool.io", "stratumPort": 21496});
+        let results = vec![result("gamma", Ok(info)), result("ultra", Err("connection refused".into()))];
+        app.observe(results, SystemTime::now());
+        assert_eq!(app.panes[0].hashrate.len(), 1);
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 24)).unwrap();
+        terminal.draw(|frame| draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        let expected = ["gamma (gamma.lan)", "512.3 GH/s", "public-pool.io:21496", "offline: connection refused", "q  quit"];
+        for text in expected {
+            assert!(screen.contains(text), "{text:?} missing from the screen");
+        }
+    }
+}
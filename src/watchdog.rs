@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use clap::Args;
+use serde_json::Value;
+use crate::client::Target;
+use crate::config_file::{AppConfig, WatchdogConfig};
+use crate::fleet::{DeviceResult, FleetOpts, fetch_fleet};
+use crate::interrupt::Interrupt;
+use crate::rules::short_duration;
+use crate::{get_number, logging, parse_duration, ratelimit, runtime, send_restart};
+
+#[derive(Args, Debug)]
+pub struct WatchdogArgs {
+    /// Time between polls
+    #[arg(long, default_value = "30s", value_parser = parse_duration)]
+    interval: Duration,
+
+    /// A poll below this hashrate (GH/s) counts as bad
+    /// (default: `hashrate_below` in [watchdog], then 1)
+    #[arg(long, value_name = "GH/S")]
+    hashrate_below: Option<f64>,
+
+    /// Bad polls in a row before restarting (default: `polls` in [watchdog], then 10)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    polls: Option<u32>,
+
+    /// Least time between two restarts of one miner
+    /// (default: `cooldown` in [watchdog], then 15m)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    cooldown: Option<Duration>,
+
+    /// Only count hashrate; by default a poll where the accepted share
+    /// count hasn't moved is bad as well
+    #[arg(long)]
+    ignore_shares: bool,
+
+    /// Log the restarts it would issue without sending them
+    #[arg(long)]
+    dry_run: bool,
+
+    #[command(flatten)]
+    pub opts: FleetOpts,
+}
+
+const DEFAULT_FLOOR: f64 = 1.0;
+const DEFAULT_POLLS: u32 = 10;
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(15 * 60);
+
+/// When a miner counts as stuck, and how often it may be restarted
+#[derive(Clone, Debug, PartialEq)]
+pub struct Policy {
+    pub hashrate_below: f64,
+    pub polls: u32,
+    pub cooldown: Duration,
+    pub shares: bool,
+    pub dry_run: bool,
+}
+
+impl Policy {
+    /// [watchdog] as `daemon` uses it
+    pub fn from_config(conf: Option<&WatchdogConfig>) -> Result<Self> {
+        let cooldown = match conf.and_then(|c| c.cooldown.as_deref()) {
+            Some(s) => parse_duration(s).map_err(|e| anyhow!("cooldown in [watchdog]: {e}"))?,
+            None => DEFAULT_COOLDOWN,
+        };
+        Ok(Policy {
+            hashrate_below: conf.and_then(|c| c.hashrate_below).unwrap_or(DEFAULT_FLOOR),
+            polls: conf.and_then(|c| c.polls).unwrap_or(DEFAULT_POLLS).max(1),
+            cooldown,
+            shares: conf.and_then(|c| c.shares).unwrap_or(true),
+            dry_run: conf.is_some_and(|c| c.dry_run),
+        })
+    }
+
+    /// Flags override the [watchdog] table
+    fn from_args(args: &WatchdogArgs, conf: Option<&WatchdogConfig>) -> Result<Self> {
+        let mut policy = Policy::from_config(conf)?;
+        if let Some(floor) = args.hashrate_below {
+            policy.hashrate_below = floor;
+        }
+        if let Some(polls) = args.polls {
+            policy.polls = polls;
+        }
+        if let Some(cooldown) = args.cooldown {
+            policy.cooldown = cooldown;
+        }
+        policy.shares &= !args.ignore_shares;
+        policy.dry_run |= args.dry_run;
+        Ok(policy)
+    }
+}
+
+/// What one poll means for a device
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Healthy,
+    /// Another bad poll, not yet enough to act on
+    Bad { polls: u32, reason: String },
+    /// Enough bad polls: restart now
+    Restart { polls: u32, reason: String },
+    /// Enough bad polls, but the last restart was too recent
+    CoolingDown { polls: u32, reason: String, left: Duration },
+}
+
+#[derive(Default)]
+struct DeviceState {
+    bad: u32,
+    shares: Option<f64>,
+    restarted: Option<Instant>,
+}
+
+/// Counts bad polls per device and decides when to restart
+pub struct Watchdog {
+    policy: Policy,
+    devices: HashMap<String, DeviceState>,
+}
+
+impl Watchdog {
+    pub fn new(policy: Policy) -> Self {
+        Watchdog { policy, devices: HashMap::new() }
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Judge one poll. Unreachable polls don't count either way: a miner
+    /// that doesn't answer can't be told to restart (and `[alerts]` covers it).
+    pub fn observe(&mut self, device: &str, info: Option<&Value>, at: Instant) -> Verdict {
+        let Some(info) = info else { return Verdict::Healthy };
+        let state = self.devices.entry(device.to_string()).or_default();
+        let hashrate = get_number(info, "hashRate").unwrap_or(0.0);
+        let shares = get_number(info, "sharesAccepted");
+        // A lower count means the miner restarted by itself; start over from it
+        let stalled = self.policy.shares && shares.is_some() && state.shares.is_some_and(|prev| Some(prev) == shares);
+        state.shares = shares;
+
+        let reason = if hashrate < self.policy.hashrate_below {
+            format!("hashrate {hashrate} GH/s below {} GH/s", self.policy.hashrate_below)
+        } else if stalled {
+            format!("accepted shares stuck at {}", shares.unwrap_or_default())
+        } else {
+            state.bad = 0;
+            return Verdict::Healthy;
+        };
+        state.bad += 1;
+        let polls = state.bad;
+        if polls < self.policy.polls {
+            return Verdict::Bad { polls, reason };
+        }
+        if let Some(last) = state.restarted {
+            let since = at.saturating_duration_since(last);
+            if since < self.policy.cooldown {
+                return Verdict::CoolingDown { polls, reason, left: self.policy.cooldown - since };
+            }
+        }
+        state.restarted = Some(at);
+        state.bad = 0;
+        state.shares = None;
+        Verdict::Restart { polls, reason }
+    }
+
+    /// Judge a fleet poll and act on it: log every bad poll and restart
+    /// (or, dry-running, say it would) the devices that are due. Returns
+    /// how many restarts were due.
+    pub fn act(&mut self, targets: &[Target], results: &[DeviceResult]) -> usize {
+        let mut due = 0;
+        for r in results {
+            let hashrate = r.info.as_ref().ok().and_then(|i| get_number(i, "hashRate"));
+            let shares = r.info.as_ref().ok().and_then(|i| get_number(i, "sharesAccepted"));
+            match self.observe(&r.name, r.info.as_ref().ok(), Instant::now()) {
+                Verdict::Healthy => {}
+                Verdict::Bad { polls, reason } => {
+                    eprintln!("Watchdog: {}: {reason} ({polls}/{} bad polls)", r.name, self.policy.polls);
+                    tracing::info!(device = r.name, hashrate, shares, bad_polls = polls, reason, "watchdog bad poll");
+                }
+                Verdict::CoolingDown { polls, reason, left } => {
+                    eprintln!(
+                        "Watchdog: {}: {reason} for {polls} polls; not restarting again for {}",
+                        r.name,
+                        short_duration(left)
+                    );
+                    tracing::warn!(
+                        device = r.name,
+                        hashrate,
+                        shares,
+                        bad_polls = polls,
+                        reason,
+                        cooldown_left_secs = left.as_secs(),
+                        "watchdog cooling down"
+                    );
+                }
+                Verdict::Restart { polls, reason } => {
+                    due += 1;
+                    let action = if self.policy.dry_run { "dry-run" } else { "restart" };
+                    tracing::warn!(device = r.name, hashrate, shares, bad_polls = polls, reason, action, "watchdog");
+                    if self.policy.dry_run {
+                        eprintln!("Watchdog: {}: {reason} for {polls} polls; would restart (dry run)", r.name);
+                        continue;
+                    }
+                    eprintln!("Watchdog: {}: {reason} for {polls} polls; restarting", r.name);
+                    let Some(target) = targets.iter().find(|t| t.name() == r.name) else { continue };
+                    if let Err(e) = runtime().block_on(send_restart(target)) {
+                        eprintln!("Warning: watchdog restart of {} failed: {e:#}", r.name);
+                        tracing::error!(device = r.name, error = format!("{e:#}"), "watchdog restart failed");
+                    }
+                }
+            }
+        }
+        due
+    }
+}
+
+/// `watchdog`: poll the targets every --interval until Ctrl-C, restarting
+/// the ones that stay stuck
+pub fn run(args: &WatchdogArgs, targets: &[Target], cfg: &AppConfig) -> Result<()> {
+    let mut watchdog = Watchdog::new(Policy::from_args(args, cfg.watchdog.as_ref())?);
+    let interval = ratelimit::poll_interval(args.interval);
+    let p = watchdog.policy();
+    eprintln!(
+        "Watching {} device(s) every {}: restart after {} polls below {} GH/s{}, at most every {}{}",
+        targets.len(),
+        short_duration(interval),
+        p.polls,
+        p.hashrate_below,
+        if p.shares { " or without new shares" } else { "" },
+        short_duration(p.cooldown),
+        if p.dry_run { " (dry run)" } else { "" },
+    );
+
+    let interrupt = Interrupt::install()?;
+    loop {
+        let results = fetch_fleet(targets, &args.opts);
+        for r in &results {
+            logging::poll(&r.name, r.info.as_ref().map_err(String::clone));
+        }
+        watchdog.act(targets, &results);
+        if !interrupt.sleep(interval) {
+            return interrupt.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policy() -> Policy {
+        Policy { hashrate_below: 1.0, polls: 3, cooldown: Duration::from_secs(900), shares: true, dry_run: false }
+    }
+
+    #[test]
+    fn restarts_after_enough_bad_polls_then_cools_down() {
+        let mut dog = Watchdog::new(policy());
+        let t0 = Instant::now();
+        let at = |s: u64| t0 + Duration::from_secs(s);
+        let stuck = json!({"hashRate": 0.0, "sharesAccepted": 100});
+
+        assert!(matches!(dog.observe("gamma", Some(&stuck), at(0)), Verdict::Bad { polls: 1, .. }));
+        assert!(matches!(dog.observe("gamma", Some(&stuck), at(30)), Verdict::Bad { polls: 2, .. }));
+        let Verdict::Restart { polls: 3, reason } = dog.observe("gamma", Some(&stuck), at(60)) else { panic!() };
+        assert_eq!(reason, "hashrate 0 GH/s below 1 GH/s");
+
+        // Still stuck right after the restart: counted, but not restarted again
+        for s in [90, 120] {
+            assert!(matches!(dog.observe("gamma", Some(&stuck), at(s)), Verdict::Bad { .. }));
+        }
+        let Verdict::CoolingDown { left, .. } = dog.observe("gamma", Some(&stuck), at(150)) else { panic!() };
+        assert_eq!(left, Duration::from_secs(810));
+        assert!(matches!(dog.observe("gamma", Some(&stuck), at(960)), Verdict::Restart { .. }));
+    }
+
+    #[test]
+    fn stalled_shares_count_and_recovery_resets() {
+        let mut dog = Watchdog::new(policy());
+        let now = Instant::now();
+        let poll = |shares: u64| json!({"hashRate": 500.0, "sharesAccepted": shares});
+
+        assert_eq!(dog.observe("gamma", Some(&poll(10)), now), Verdict::Healthy);
+        let Verdict::Bad { polls: 1, reason } = dog.observe("gamma", Some(&poll(10)), now) else { panic!() };
+        assert_eq!(reason, "accepted shares stuck at 10");
+        // Unreachable polls don't count either way
+        assert_eq!(dog.observe("gamma", None, now), Verdict::Healthy);
+        assert!(matches!(dog.observe("gamma", Some(&poll(10)), now), Verdict::Bad { polls: 2, .. }));
+        assert_eq!(dog.observe("gamma", Some(&poll(11)), now), Verdict::Healthy);
+        assert!(matches!(dog.observe("gamma", Some(&poll(11)), now), Verdict::Bad { polls: 1, .. }));
+
+        let mut hashrate_only = Watchdog::new(Policy { shares: false, ..policy() });
+        hashrate_only.observe("gamma", Some(&poll(10)), now);
+        assert_eq!(hashrate_only.observe("gamma", Some(&poll(10)), now), Verdict::Healthy);
+    }
+}
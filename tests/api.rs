@@ -0,0 +1,135 @@
+use std::time::Duration;
+use bitaxe_api::{BitaxeClient, Error, INFO_PATH, RESTART_PATH, SESSION_RESET_PATH, SYSTEM_PATH};
+use httpmock::prelude::*;
+use reqwest::StatusCode;
+use serde_json::json;
+
+#[tokio::test]
+async fn system_info_is_returned_as_sent() {
+    let server = MockServer::start_async().await;
+    let info = server.mock_async(|when, then| {
+        when.method(GET).path(INFO_PATH);
+        then.status(200).json_body(json!({"hostname": "gamma", "hashRate": 1024.5, "temp": 61}));
+    }).await;
+    let miner = BitaxeClient::new(&format!("{}/", server.base_url())).unwrap();
+    assert_eq!(miner.base_url(), server.base_url());
+
+    let got = miner.system_info().await.unwrap();
+    assert_eq!((got.hostname.as_deref(), got.temp), (Some("gamma"), Some(61.0)));
+    let raw = miner.system_info_json().await.unwrap();
+    assert_eq!(bitaxe_api::get_number(&raw, "hashRate"), Some(1024.5));
+    info.assert_calls_async(2).await;
+}
+
+#[tokio::test]
+async fn http_errors_keep_the_status_and_what_the_miner_said() {
+    let server = MockServer::start_async().await;
+    server.mock_async(|when, then| {
+        when.method(PATCH).path(SYSTEM_PATH);
+        then.status(400).json_body(json!({"message": "Invalid frequency"}));
+    }).await;
+    server.mock_async(|when, then| {
+        when.method(GET).path(INFO_PATH);
+        then.status(503).body("<html><body>busy</body></html>");
+    }).await;
+    let miner = BitaxeClient::new(&server.base_url()).unwrap();
+
+    let e = miner.patch_settings(&json!({"frequency": 9999})).await.unwrap_err();
+    let detail = Some("Invalid frequency".to_string());
+    assert!(matches!(&e, Error::Status { status: StatusCode::BAD_REQUEST, detail: d } if *d == detail));
+    assert_eq!(e.to_string(), "Miner answered with status 400 Bad Request: Invalid frequency");
+    let e = miner.system_info().await.unwrap_err();
+    assert!(matches!(e, Error::Status { status: StatusCode::SERVICE_UNAVAILABLE, detail: None }));
+}
+
+#[tokio::test]
+async fn slow_miners_time_out() {
+    let server = MockServer::start_async().await;
+    server.mock_async(|when, then| {
+        when.method(GET).path(INFO_PATH);
+        then.status(200).delay(Duration::from_secs(2)).json_body(json!({"hashRate": 1.0}));
+    }).await;
+    let http = reqwest::Client::builder().timeout(Duration::from_millis(200)).build().unwrap();
+    let miner = BitaxeClient::with_client(http, &server.base_url());
+
+    let e = miner.system_info().await.unwrap_err();
+    assert!(matches!(&e, Error::Timeout { url, .. } if url.ends_with(INFO_PATH)), "{e:?}");
+}
+
+#[tokio::test]
+async fn pages_that_are_not_axeos_json_are_not_bitaxe() {
+    let server = MockServer::start_async().await;
+    server.mock_async(|when, then| {
+        when.method(GET).path(INFO_PATH);
+        then.status(200).header("content-type", "text/html").body("<!DOCTYPE html>\n<title>NAS</title>");
+    }).await;
+    let miner = BitaxeClient::new(&server.base_url()).unwrap();
+
+    match miner.system_info().await.unwrap_err() {
+        Error::NotBitaxe(got) => {
+            assert_eq!(got.content_type.as_deref(), Some("text/html"));
+            assert_eq!(got.snippet, "<!DOCTYPE html> <title>NAS</title>");
+        }
+        e => panic!("{e:?}"),
+    }
+
+    let truncated = MockServer::start_async().await;
+    truncated.mock_async(|when, then| {
+        when.method(GET).path(INFO_PATH);
+        then.status(200).header("content-type", "application/json").body(r#"{"hashRate": 10"#);
+    }).await;
+    let miner = BitaxeClient::new(&truncated.base_url()).unwrap();
+    assert!(matches!(miner.system_info().await, Err(Error::NotBitaxe(_))));
+}
+
+#[tokio::test]
+async fn commands_reach_the_right_endpoints() {
+    let server = MockServer::start_async().await;
+    let restart = server.mock_async(|when, then| {
+        when.method(POST).path(RESTART_PATH);
+        then.status(200).body("System will restart shortly.");
+    }).await;
+    let patch = server.mock_async(|when, then| {
+        when.method(PATCH).path(SYSTEM_PATH).json_body(json!({"frequency": 525, "coreVoltage": 1150}));
+        then.status(200);
+    }).await;
+    let miner = BitaxeClient::new(&server.base_url()).unwrap();
+
+    miner.restart().await.unwrap();
+    miner.patch_settings(&json!({"frequency": 525, "coreVoltage": 1150})).await.unwrap();
+    restart.assert_async().await;
+    patch.assert_async().await;
+
+    // Firmware without session reset answers 404
+    assert!(matches!(miner.reset_session().await, Err(Error::Unsupported { path: SESSION_RESET_PATH })));
+}
+
+#[tokio::test]
+async fn nothing_listening_is_a_connect_error() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let miner = BitaxeClient::new(&format!("http://127.0.0.1:{port}")).unwrap();
+    let e = miner.restart().await.unwrap_err();
+    assert!(matches!(e, Error::Connect { .. }));
+    // Still a reqwest error underneath, for callers that look for one
+    assert!(std::error::Error::source(&e).unwrap().is::<reqwest::Error>());
+}
+
+#[test]
+fn blocking_client_makes_the_same_calls() {
+    let server = MockServer::start();
+    let info = server.mock(|when, then| {
+        when.method(GET).path(INFO_PATH);
+        then.status(200).json_body(json!({"hostname": "gamma", "hashRate": 1024.5}));
+    });
+    server.mock(|when, then| {
+        when.method(PATCH).path(SYSTEM_PATH);
+        then.status(400).json_body(json!({"message": "Invalid frequency"}));
+    });
+    let miner = bitaxe_api::blocking::BitaxeClient::new(&server.base_url()).unwrap();
+
+    assert_eq!(miner.system_info().unwrap().hostname.as_deref(), Some("gamma"));
+    let e = miner.patch_settings(&json!({"frequency": 9999})).unwrap_err();
+    assert_eq!(e.to_string(), "Miner answered with status 400 Bad Request: Invalid frequency");
+    assert!(matches!(miner.reset_session(), Err(Error::Unsupported { path: SESSION_RESET_PATH })));
+    info.assert();
+}
@@ -0,0 +1,43 @@
+use bitaxe_api::{Difficulty, SystemInfo, parse_info};
+use serde_json::json;
+
+fn fixture(body: &str) -> SystemInfo {
+    SystemInfo::from_value(&parse_info(body, Some("application/json")).unwrap()).unwrap()
+}
+
+#[test]
+fn ultra_on_v2_0_reports_suffixed_difficulties_and_flips_its_screen() {
+    let info = fixture(include_str!("fixtures/axeos-v2.0.3-ultra.json"));
+    assert_eq!(info.version.as_deref(), Some("v2.0.3"));
+    assert_eq!(info.asic_model.as_deref(), Some("BM1366"));
+    assert_eq!(info.hash_rate, Some(463.2279052734375));
+    assert_eq!(info.best_diff, Some(Difficulty { shown: "4.29M".into(), value: Some(4.29e6) }));
+    assert_eq!((info.shares_accepted, info.shares_rejected), (Some(1941), Some(1)));
+    assert_eq!((info.fan_speed, info.fan_rpm, info.auto_fan_speed), (Some(100.0), Some(5230), Some(true)));
+    assert_eq!((info.flipscreen, info.rotation), (Some(true), None));
+    // Not in this build yet
+    assert_eq!((info.vr_temp, info.wifi_rssi, info.asic_count, info.small_core_count), (None, None, None, None));
+    assert_eq!(info.extra.get("fanSpeedRpm"), Some(&json!(0)));
+    assert_eq!(info.extra.get("invertfanpolarity"), Some(&json!(1)));
+    assert!(!info.extra.contains_key("hashRate"));
+}
+
+#[test]
+fn gamma_on_v2_5_reports_numbers_and_rotates_its_screen() {
+    let info = fixture(include_str!("fixtures/axeos-v2.5.0-gamma.json"));
+    assert_eq!(info.hostname.as_deref(), Some("gamma"));
+    assert_eq!(info.axe_os_version.as_deref(), Some("v2.5.0"));
+    assert_eq!(info.best_diff, Some(Difficulty { shown: "1364728132".into(), value: Some(1364728132.0) }));
+    assert_eq!(info.best_session_diff.and_then(|d| d.value), Some(27436171.0));
+    assert_eq!((info.temp, info.vr_temp), (Some(58.5), Some(49.0)));
+    assert_eq!((info.voltage, info.current, info.power), (Some(5112.5), Some(3412.5), Some(17.41866683959961)));
+    assert_eq!((info.core_voltage, info.core_voltage_actual), (Some(1150.0), Some(1148.0)));
+    assert_eq!(info.frequency, Some(575.0));
+    assert_eq!((info.small_core_count, info.expected_hashrate), (Some(2040), Some(1173.0)));
+    assert_eq!((info.wifi_rssi, info.uptime_seconds), (Some(-58), Some(396543)));
+    assert_eq!(info.fallback_stratum_url.as_deref(), Some("solo.ckpool.org"));
+    assert_eq!((info.fallback_stratum_port, info.using_fallback_stratum), (Some(3333), Some(false)));
+    assert_eq!((info.rotation, info.display_timeout, info.flipscreen), (Some(0), Some(-1), None));
+    assert_eq!(info.extra["sharesRejectedReasons"], json!([{"message": "Above target", "count": 37}]));
+    assert_eq!(info.extra["overheat_mode"], json!(0));
+}